@@ -0,0 +1,307 @@
+//! Local certificate authority for Gurted development certificates
+//!
+//! GURT requires TLS 1.3 everywhere, so testing a client against a server needs
+//! certificates the client trusts. [`LocalCa`] creates a root CA kept on disk and issues
+//! leaf certificates from it: server certificates for Gurted domains (`example.web`,
+//! `*.example.web`, `localhost` or IP addresses) and client certificates for mutual TLS.
+//! Trusting the CA's certificate in the client is then enough for a whole setup to run
+//! offline.
+//!
+//! ```rust,ignore
+//! use portal_solutions_yo_gurt_ca::LocalCa;
+//!
+//! let ca = LocalCa::generate("my dev CA")?;
+//! ca.save(".gurt-ca")?;
+//! let server = ca.issue_server(&["example.web", "*.example.web"])?;
+//! server.write(".", "example.web")?; // example.web.pem, example.web-key.pem
+//! ```
+//!
+//! The `gurt-ca` binary does the same from the command line.
+
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write as _};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+
+use rcgen::{
+    BasicConstraints, CertificateParams, DistinguishedName, DnType, ExtendedKeyUsagePurpose, IsCa,
+    KeyPair, KeyUsagePurpose,
+};
+use rustls_pki_types::pem::PemObject;
+use rustls_pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use time::{Duration, OffsetDateTime};
+
+/// File name of the CA certificate inside a CA directory
+pub const CA_CERT_FILE: &str = "ca.pem";
+/// File name of the CA private key inside a CA directory
+pub const CA_KEY_FILE: &str = "ca-key.pem";
+
+/// How long the root CA is valid
+const CA_VALIDITY_DAYS: i64 = 10 * 365;
+/// How long issued certificates are valid; clients commonly refuse longer lifetimes
+const LEAF_VALIDITY_DAYS: i64 = 825;
+
+/// Errors from creating, loading or using a [`LocalCa`]
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or writing a file failed
+    Io(io::Error),
+    /// A certificate or key could not be generated, parsed or signed
+    Certificate(rcgen::Error),
+    /// A PEM file held no usable certificate
+    InvalidPem,
+    /// A name is not a DNS name, wildcard or IP address
+    InvalidName(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{e}"),
+            Error::Certificate(e) => write!(f, "certificate error: {e}"),
+            Error::InvalidPem => f.write_str("no certificate in PEM data"),
+            Error::InvalidName(name) => write!(f, "invalid certificate name {name:?}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<rcgen::Error> for Error {
+    fn from(e: rcgen::Error) -> Self {
+        Error::Certificate(e)
+    }
+}
+
+/// A root CA that issues development certificates
+pub struct LocalCa {
+    /// Parameters and key used to sign, equivalent to the original certificate
+    issuer: rcgen::Certificate,
+    key: KeyPair,
+    /// The certificate as generated or loaded, which is what clients trust
+    der: CertificateDer<'static>,
+    pem: String,
+}
+
+impl LocalCa {
+    /// Create a new root CA with common name `name` and a fresh P-256 key
+    pub fn generate(name: &str) -> Result<Self, Error> {
+        let mut params = CertificateParams::default();
+        params.distinguished_name = DistinguishedName::new();
+        params.distinguished_name.push(DnType::CommonName, name);
+        params
+            .distinguished_name
+            .push(DnType::OrganizationName, "Gurted development");
+        params.is_ca = IsCa::Ca(BasicConstraints::Constrained(0));
+        params.key_usages = vec![
+            KeyUsagePurpose::KeyCertSign,
+            KeyUsagePurpose::CrlSign,
+            KeyUsagePurpose::DigitalSignature,
+        ];
+        validity(&mut params, CA_VALIDITY_DAYS);
+        let key = KeyPair::generate()?;
+        let issuer = params.self_signed(&key)?;
+        Ok(Self {
+            der: issuer.der().clone(),
+            pem: issuer.pem(),
+            issuer,
+            key,
+        })
+    }
+
+    /// Load a CA from its certificate and private key in PEM form
+    pub fn from_pem(cert_pem: &str, key_pem: &str) -> Result<Self, Error> {
+        let der =
+            CertificateDer::from_pem_slice(cert_pem.as_bytes()).map_err(|_| Error::InvalidPem)?;
+        let key = KeyPair::from_pem(key_pem)?;
+        // Re-signing the parsed parameters gives an issuer with the same subject and
+        // key identifier, so leaves chain to the original certificate
+        let issuer = CertificateParams::from_ca_cert_der(&der)?.self_signed(&key)?;
+        Ok(Self {
+            issuer,
+            key,
+            der,
+            pem: cert_pem.to_owned(),
+        })
+    }
+
+    /// Load a CA saved with [`save`](Self::save)
+    pub fn load(dir: impl AsRef<Path>) -> Result<Self, Error> {
+        let dir = dir.as_ref();
+        let cert = fs::read_to_string(dir.join(CA_CERT_FILE))?;
+        let key = fs::read_to_string(dir.join(CA_KEY_FILE))?;
+        Self::from_pem(&cert, &key)
+    }
+
+    /// Save the certificate and key as [`CA_CERT_FILE`] and [`CA_KEY_FILE`] in `dir`,
+    /// creating it if needed
+    ///
+    /// Fails with [`io::ErrorKind::AlreadyExists`] rather than replace an existing CA,
+    /// since certificates it issued would stop being trusted.
+    pub fn save(&self, dir: impl AsRef<Path>) -> Result<(), Error> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        write_file(&dir.join(CA_CERT_FILE), &self.pem, false, false)?;
+        write_file(
+            &dir.join(CA_KEY_FILE),
+            &self.key.serialize_pem(),
+            true,
+            false,
+        )?;
+        Ok(())
+    }
+
+    /// The CA certificate, to add to a client's trusted roots
+    pub fn cert_der(&self) -> &CertificateDer<'static> {
+        &self.der
+    }
+
+    /// The CA certificate in PEM form
+    pub fn cert_pem(&self) -> &str {
+        &self.pem
+    }
+
+    /// Issue a server certificate for `names`
+    ///
+    /// Names are DNS names such as `example.web`, wildcards such as `*.example.web`, or
+    /// IP addresses, and are lowercased. The first name also becomes the common name.
+    pub fn issue_server(&self, names: &[&str]) -> Result<Issued, Error> {
+        let names = names
+            .iter()
+            .map(|name| check_name(name))
+            .collect::<Result<Vec<_>, _>>()?;
+        let first = names
+            .first()
+            .cloned()
+            .ok_or_else(|| Error::InvalidName(String::new()))?;
+        let mut params = CertificateParams::new(names)?;
+        params.distinguished_name = DistinguishedName::new();
+        params.distinguished_name.push(DnType::CommonName, first);
+        params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ServerAuth];
+        self.issue(params)
+    }
+
+    /// Issue a client certificate with common name `common_name`, for servers that
+    /// authenticate clients by certificate
+    pub fn issue_client(&self, common_name: &str) -> Result<Issued, Error> {
+        if common_name.is_empty() || common_name.chars().any(char::is_control) {
+            return Err(Error::InvalidName(common_name.to_owned()));
+        }
+        let mut params = CertificateParams::default();
+        params.distinguished_name = DistinguishedName::new();
+        params
+            .distinguished_name
+            .push(DnType::CommonName, common_name);
+        params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+        self.issue(params)
+    }
+
+    fn issue(&self, mut params: CertificateParams) -> Result<Issued, Error> {
+        params.key_usages = vec![KeyUsagePurpose::DigitalSignature];
+        params.use_authority_key_identifier_extension = true;
+        validity(&mut params, LEAF_VALIDITY_DAYS);
+        let key = KeyPair::generate()?;
+        let cert = params.signed_by(&key, &self.issuer, &self.key)?;
+        Ok(Issued {
+            der: cert.der().clone(),
+            pem: cert.pem(),
+            key,
+        })
+    }
+}
+
+/// A certificate issued by a [`LocalCa`], with its private key
+pub struct Issued {
+    der: CertificateDer<'static>,
+    pem: String,
+    key: KeyPair,
+}
+
+impl Issued {
+    /// The certificate
+    pub fn cert_der(&self) -> &CertificateDer<'static> {
+        &self.der
+    }
+
+    /// The certificate in PEM form
+    pub fn cert_pem(&self) -> &str {
+        &self.pem
+    }
+
+    /// The private key (PKCS#8)
+    pub fn key_der(&self) -> PrivateKeyDer<'static> {
+        PrivatePkcs8KeyDer::from(self.key.serialize_der()).into()
+    }
+
+    /// The private key in PEM form (PKCS#8)
+    pub fn key_pem(&self) -> String {
+        self.key.serialize_pem()
+    }
+
+    /// Write `STEM.pem` and `STEM-key.pem` to `dir`, replacing existing files, and return
+    /// their paths
+    pub fn write(&self, dir: impl AsRef<Path>, stem: &str) -> Result<(PathBuf, PathBuf), Error> {
+        let cert = dir.as_ref().join(format!("{stem}.pem"));
+        let key = dir.as_ref().join(format!("{stem}-key.pem"));
+        write_file(&cert, &self.pem, false, true)?;
+        write_file(&key, &self.key.serialize_pem(), true, true)?;
+        Ok((cert, key))
+    }
+}
+
+/// Valid from a day ago, to tolerate clock skew, for `days` days
+fn validity(params: &mut CertificateParams, days: i64) {
+    let now = OffsetDateTime::now_utc();
+    params.not_before = now - Duration::days(1);
+    params.not_after = now + Duration::days(days);
+}
+
+/// Lowercase a DNS name, `*.` wildcard or IP address, rejecting anything else
+fn check_name(name: &str) -> Result<String, Error> {
+    if name.parse::<IpAddr>().is_ok() {
+        return Ok(name.to_owned());
+    }
+    let lower = name.to_ascii_lowercase();
+    let host = lower.strip_prefix("*.").unwrap_or(&lower);
+    let valid = !host.is_empty()
+        && host.len() <= 253
+        && host.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+        });
+    if !valid {
+        return Err(Error::InvalidName(name.to_owned()));
+    }
+    Ok(lower)
+}
+
+/// Write `contents` to `path`, readable only by the owner when `private`
+fn write_file(path: &Path, contents: &str, private: bool, replace: bool) -> io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true);
+    if replace {
+        options.create(true).truncate(true);
+    } else {
+        options.create_new(true);
+    }
+    #[cfg(unix)]
+    if private {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    #[cfg(not(unix))]
+    let _ = private;
+    options.open(path)?.write_all(contents.as_bytes())
+}
@@ -0,0 +1,102 @@
+//! `gurt-ca`: create a local root CA and issue development certificates from it
+
+use std::io;
+use std::path::PathBuf;
+use std::process::exit;
+
+use portal_solutions_yo_gurt_ca::{CA_CERT_FILE, Error, LocalCa};
+
+const USAGE: &str = "\
+usage: gurt-ca [options] <command>
+
+Creates a local root CA and issues certificates for Gurted domains, so GURT
+clients and servers can be tested offline. Trust the CA certificate in the
+client (e.g. yo-gurt-proxy --ca DIR/ca.pem).
+
+commands:
+  init [NAME]      create the CA (common name NAME, default \"yo-gurt local CA\")
+  issue NAME...    issue a server certificate for DNS names (*.example.web
+                   wildcards allowed) or IP addresses
+  client NAME      issue a client certificate with common name NAME
+  path             print the path of the CA certificate
+
+options:
+  --dir DIR        CA directory (default $GURT_CA_DIR, or ~/.gurt-ca)
+  --out STEM       write STEM.pem and STEM-key.pem (default: the first NAME,
+                   in the current directory)
+  -h, --help       show this help
+";
+
+fn main() {
+    let mut dir = std::env::var_os("GURT_CA_DIR").map(PathBuf::from);
+    let mut out = None;
+    let mut words = Vec::new();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dir" => {
+                dir = Some(
+                    args.next()
+                        .unwrap_or_else(|| usage("--dir needs a value"))
+                        .into(),
+                )
+            }
+            "--out" => out = Some(args.next().unwrap_or_else(|| usage("--out needs a value"))),
+            "-h" | "--help" => {
+                print!("{USAGE}");
+                return;
+            }
+            _ if arg.starts_with('-') => usage(&format!("unknown argument {arg:?}")),
+            _ => words.push(arg),
+        }
+    }
+    let dir = dir
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".gurt-ca")))
+        .unwrap_or_else(|| usage("no CA directory; set --dir or GURT_CA_DIR"));
+
+    let (command, names) = match words.split_first() {
+        Some((command, names)) => (command.as_str(), names),
+        None => usage("missing command"),
+    };
+    let result = match (command, names) {
+        ("init", [] | [_]) => {
+            let name = names.first().map_or("yo-gurt local CA", String::as_str);
+            match LocalCa::generate(name).and_then(|ca| ca.save(&dir)) {
+                Err(Error::Io(e)) if e.kind() == io::ErrorKind::AlreadyExists => {
+                    eprintln!("gurt-ca: a CA already exists in {}", dir.display());
+                    exit(1);
+                }
+                result => result.map(|()| println!("{}", dir.join(CA_CERT_FILE).display())),
+            }
+        }
+        ("issue", [first, ..]) => {
+            let names: Vec<&str> = names.iter().map(String::as_str).collect();
+            let stem = out.unwrap_or_else(|| first.replace('*', "wildcard"));
+            LocalCa::load(&dir)
+                .and_then(|ca| ca.issue_server(&names))
+                .and_then(|issued| issued.write(".", &stem))
+                .map(|(cert, key)| println!("{}\n{}", cert.display(), key.display()))
+        }
+        ("client", [name]) => {
+            let stem = out.unwrap_or_else(|| name.clone());
+            LocalCa::load(&dir)
+                .and_then(|ca| ca.issue_client(name))
+                .and_then(|issued| issued.write(".", &stem))
+                .map(|(cert, key)| println!("{}\n{}", cert.display(), key.display()))
+        }
+        ("path", []) => {
+            println!("{}", dir.join(CA_CERT_FILE).display());
+            Ok(())
+        }
+        _ => usage(&format!("invalid command {:?}", words.join(" "))),
+    };
+    if let Err(e) = result {
+        eprintln!("gurt-ca: {e}");
+        exit(1);
+    }
+}
+
+fn usage(message: &str) -> ! {
+    eprintln!("{message}\n\n{USAGE}");
+    exit(2);
+}
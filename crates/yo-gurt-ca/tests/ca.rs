@@ -0,0 +1,143 @@
+//! A local CA set up on disk, trusted by a client talking to a local server
+
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use portal_solutions_yo_gurt::adapters::FromTokio;
+use portal_solutions_yo_gurt::{ALPN_IDENTIFIER, GurtClient, Method, StatusCode};
+use portal_solutions_yo_gurt_ca::{CA_CERT_FILE, Error, Issued, LocalCa};
+use portal_solutions_yo_gurt_server::{
+    ClientAuth, Request, Response, Server, TlsConfig, load_roots,
+};
+use rustls::pki_types::ServerName;
+use rustls::{ClientConfig, RootCertStore};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::TlsConnector;
+
+/// A fresh directory under the system temp dir
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("gurt-ca-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Serve from PEM files, requiring client certificates from the CA in `ca_dir`
+async fn start(ca_dir: &PathBuf, server: &Issued) -> u16 {
+    let (cert, key) = server.write(ca_dir, "server").unwrap();
+    let roots = load_roots(ca_dir.join(CA_CERT_FILE)).unwrap();
+    let tls = TlsConfig::from_pem_files(cert, key)
+        .unwrap()
+        .client_auth(ClientAuth::Required(roots));
+    let server = Server::new(tls, async |request: Request| {
+        let name = request.peer().and_then(|peer| peer.common_name());
+        Response::new(StatusCode::Ok).text(format!("hello {}", name.unwrap_or("?")))
+    })
+    .unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(server.serve(listener));
+    port
+}
+
+/// Connect as `client`, trusting only `ca`, and fetch `/`
+async fn get(port: u16, host: &str, ca: &LocalCa, client: &Issued) -> io::Result<String> {
+    let mut roots = RootCertStore::empty();
+    roots.add(ca.cert_der().clone()).unwrap();
+    let mut config =
+        ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_protocol_versions(&[&rustls::version::TLS13])
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_client_auth_cert(vec![client.cert_der().clone()], client.key_der())
+            .unwrap();
+    config.alpn_protocols = vec![ALPN_IDENTIFIER.as_bytes().to_vec()];
+    let tcp = TcpStream::connect(("127.0.0.1", port)).await?;
+    let tls = TlsConnector::from(Arc::new(config))
+        .connect(ServerName::try_from(host.to_owned()).unwrap(), tcp)
+        .await?;
+
+    let mut client = GurtClient::new(FromTokio(tls));
+    let mut buf = [0u8; 512];
+    client.handshake(host, "test/1.0").await.unwrap();
+    client.read_handshake_response(&mut buf).await.unwrap();
+    client
+        .request_no_body(Method::Get, "/", host, None)
+        .await
+        .unwrap();
+    let mut response = client.response_reader();
+    assert_eq!(
+        response.read_status_line(&mut buf).await.unwrap().status,
+        StatusCode::Ok
+    );
+    while response.read_header(&mut buf).await.unwrap().is_some() {}
+    let mut body = vec![0u8; response.content_length().unwrap()];
+    response.read_body_exact(&mut body).await.unwrap();
+    Ok(String::from_utf8(body).unwrap())
+}
+
+#[tokio::test]
+async fn saved_ca_issues_trusted_certificates() {
+    let dir = temp_dir("saved");
+    let ca = LocalCa::generate("test CA").unwrap();
+    ca.save(&dir).unwrap();
+
+    // Certificates issued after reloading still chain to the saved CA certificate
+    let loaded = LocalCa::load(&dir).unwrap();
+    assert_eq!(loaded.cert_der(), ca.cert_der());
+    let server = loaded
+        .issue_server(&["Example.web", "*.example.web"])
+        .unwrap();
+    let client = loaded.issue_client("device-1").unwrap();
+
+    let port = start(&dir, &server).await;
+    for host in ["example.web", "api.example.web"] {
+        assert_eq!(
+            get(port, host, &ca, &client).await.unwrap(),
+            "hello device-1"
+        );
+    }
+    // The certificate does not cover other names
+    assert!(get(port, "other.web", &ca, &client).await.is_err());
+    // Nor is it trusted through another CA
+    let other = LocalCa::generate("other CA").unwrap();
+    assert!(get(port, "example.web", &other, &client).await.is_err());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn save_does_not_replace_a_ca() {
+    let dir = temp_dir("replace");
+    LocalCa::generate("first").unwrap().save(&dir).unwrap();
+    let second = LocalCa::generate("second").unwrap();
+    match second.save(&dir) {
+        Err(Error::Io(e)) => assert_eq!(e.kind(), io::ErrorKind::AlreadyExists),
+        _ => panic!("existing CA replaced"),
+    }
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn invalid_names() {
+    let ca = LocalCa::generate("test CA").unwrap();
+    for names in [
+        &[][..],
+        &[""],
+        &["bad name.web"],
+        &["-dash.web"],
+        &["a..web"],
+        &["*"],
+    ] {
+        assert!(
+            matches!(ca.issue_server(names), Err(Error::InvalidName(_))),
+            "{names:?}"
+        );
+    }
+    assert!(ca.issue_server(&["127.0.0.1", "::1", "localhost"]).is_ok());
+    assert!(matches!(
+        ca.issue_client("a\nb"),
+        Err(Error::InvalidName(_))
+    ));
+}
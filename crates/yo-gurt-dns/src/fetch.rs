@@ -0,0 +1,187 @@
+//! Minimal GET requests to the DNS API over GURT or plain HTTP
+
+use std::fmt;
+use std::io;
+
+use portal_solutions_yo_gurt::adapters::FromTokio;
+use portal_solutions_yo_gurt::{DEFAULT_USER_AGENT, GurtClient, Method};
+use rustls::pki_types::ServerName;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+
+use crate::DnsError;
+
+/// Largest API answer read; real answers are a few hundred bytes
+const MAX_ANSWER_SIZE: usize = 64 * 1024;
+
+/// Protocol of a [`BaseUrl`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scheme {
+    /// `gurt://`, default port 4878
+    Gurt,
+    /// `http://`, default port 80
+    Http,
+}
+
+/// Where the DNS API is served: `scheme://host[:port][/prefix]`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BaseUrl {
+    pub scheme: Scheme,
+    pub host: String,
+    pub port: u16,
+    /// Path prepended to API paths, without a trailing `/`; empty for the root
+    pub prefix: String,
+}
+
+impl BaseUrl {
+    /// Parse a `gurt://` or `http://` URL without query or fragment
+    pub fn parse(url: &str) -> Option<Self> {
+        let (scheme, rest, default_port) = if let Some(rest) = url.strip_prefix("gurt://") {
+            (Scheme::Gurt, rest, portal_solutions_yo_gurt::DEFAULT_PORT)
+        } else if let Some(rest) = url.strip_prefix("http://") {
+            (Scheme::Http, rest, 80)
+        } else {
+            return None;
+        };
+        let (authority, prefix) = match rest.find('/') {
+            Some(slash) => rest.split_at(slash),
+            None => (rest, ""),
+        };
+        let (host, port) = if let Some(rest) = authority.strip_prefix('[') {
+            let (host, rest) = rest.split_once(']')?;
+            match rest {
+                "" => (host, default_port),
+                _ => (host, rest.strip_prefix(':')?.parse().ok()?),
+            }
+        } else {
+            match authority.split_once(':') {
+                Some((host, port)) => (host, port.parse().ok()?),
+                None => (authority, default_port),
+            }
+        };
+        let prefix = prefix.trim_end_matches('/');
+        if host.is_empty()
+            || prefix.contains(['?', '#'])
+            || prefix
+                .bytes()
+                .any(|b| b.is_ascii_whitespace() || b.is_ascii_control())
+        {
+            return None;
+        }
+        Some(Self {
+            scheme,
+            host: host.to_ascii_lowercase(),
+            port,
+            prefix: prefix.to_owned(),
+        })
+    }
+}
+
+impl fmt::Display for BaseUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let scheme = match self.scheme {
+            Scheme::Gurt => "gurt",
+            Scheme::Http => "http",
+        };
+        if self.host.contains(':') {
+            write!(f, "{scheme}://[{}]:{}{}", self.host, self.port, self.prefix)
+        } else {
+            write!(f, "{scheme}://{}:{}{}", self.host, self.port, self.prefix)
+        }
+    }
+}
+
+/// GET `path` from `base`, returning the status and body
+pub(crate) async fn get(
+    base: &BaseUrl,
+    tls: &TlsConnector,
+    path: &str,
+) -> Result<(u16, Vec<u8>), DnsError> {
+    let tcp = TcpStream::connect((base.host.as_str(), base.port)).await?;
+    tcp.set_nodelay(true)?;
+    match base.scheme {
+        Scheme::Gurt => get_gurt(base, tls, tcp, path).await,
+        Scheme::Http => get_http(base, tcp, path).await,
+    }
+}
+
+async fn get_gurt(
+    base: &BaseUrl,
+    tls: &TlsConnector,
+    tcp: TcpStream,
+    path: &str,
+) -> Result<(u16, Vec<u8>), DnsError> {
+    let server_name = ServerName::try_from(base.host.clone()).map_err(|_| DnsError::InvalidUrl)?;
+    let tls = tls.connect(server_name, tcp).await?;
+    let mut client = GurtClient::new(FromTokio(tls));
+    let mut buf = [0u8; 1024];
+    client.handshake(&base.host, DEFAULT_USER_AGENT).await?;
+    client.read_handshake_response(&mut buf).await?;
+    client
+        .request_no_body(Method::Get, path, &base.host, None)
+        .await?;
+
+    let mut response = client.response_reader();
+    let status = response.read_status_line(&mut buf).await?.status;
+    while response.read_header(&mut buf).await?.is_some() {}
+    let length = response.content_length().unwrap_or(0);
+    if length > MAX_ANSWER_SIZE {
+        return Err(DnsError::InvalidResponse);
+    }
+    let mut body = vec![0u8; length];
+    response.read_body_exact(&mut body).await?;
+    let _ = client.close().await;
+    Ok((status as u16, body))
+}
+
+async fn get_http(
+    base: &BaseUrl,
+    mut tcp: TcpStream,
+    path: &str,
+) -> Result<(u16, Vec<u8>), DnsError> {
+    // HTTP/1.0 keeps the response unchunked and ends it by closing the connection
+    let host = match base.host.contains(':') {
+        true => format!("[{}]:{}", base.host, base.port),
+        false => format!("{}:{}", base.host, base.port),
+    };
+    let request = format!(
+        "GET {path} HTTP/1.0\r\nhost: {host}\r\nuser-agent: {DEFAULT_USER_AGENT}\r\naccept: application/json\r\n\r\n"
+    );
+    tcp.write_all(request.as_bytes()).await?;
+
+    let mut answer = Vec::new();
+    (&mut tcp)
+        .take(MAX_ANSWER_SIZE as u64 + 1)
+        .read_to_end(&mut answer)
+        .await?;
+    if answer.len() > MAX_ANSWER_SIZE {
+        return Err(DnsError::InvalidResponse);
+    }
+    let mut headers = [httparse::EMPTY_HEADER; 32];
+    let mut response = httparse::Response::new(&mut headers);
+    let head_len = match response.parse(&answer) {
+        Ok(httparse::Status::Complete(len)) => len,
+        _ => return Err(DnsError::InvalidResponse),
+    };
+    let status = response.code.ok_or(DnsError::InvalidResponse)?;
+    let length = response
+        .headers
+        .iter()
+        .find(|header| header.name.eq_ignore_ascii_case("content-length"))
+        .map(|header| {
+            std::str::from_utf8(header.value)
+                .ok()
+                .and_then(|value| value.trim().parse::<usize>().ok())
+                .ok_or(DnsError::InvalidResponse)
+        })
+        .transpose()?;
+    let mut body = answer.split_off(head_len);
+    if let Some(length) = length {
+        if body.len() < length {
+            return Err(DnsError::Io(io::ErrorKind::UnexpectedEof.into()));
+        }
+        body.truncate(length);
+    }
+    Ok((status, body))
+}
@@ -0,0 +1,241 @@
+//! Gurted DNS API resolver for GURT connectors
+//!
+//! Gurted sites live under their own top-level domains (`site.web`), which are published
+//! by the Gurted DNS service rather than system DNS. [`GurtedDns`] implements
+//! [`Resolver`] by querying such a service, reachable at a `gurt://` or `http://` base
+//! URL, and caches answers for their TTL. [`System`] resolves through the operating
+//! system, for everything else:
+//!
+//! ```rust,ignore
+//! use portal_solutions_yo_gurt::resolve::{Fallback, Hosts};
+//! use portal_solutions_yo_gurt_dns::{GurtedDns, System};
+//!
+//! let resolver = Fallback(
+//!     Hosts::load("gurt-hosts")?,
+//!     Fallback(GurtedDns::new("gurt://dns.example:4878")?, System),
+//! );
+//! ```
+//!
+//! The API is queried with `GET {base}/domain/{name}/{tld}`, where `tld` is the last label
+//! of the host and `name` everything before it, and answers with a JSON object holding
+//! the address as `ip` and optionally a `ttl` in seconds. `404` means the domain does
+//! not exist.
+
+mod fetch;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use portal_solutions_yo_gurt::resolve::Resolver;
+use portal_solutions_yo_gurt::{ALPN_IDENTIFIER, DEFAULT_REQUEST_TIMEOUT_SECS, GurtError};
+use rustls::{ClientConfig, RootCertStore};
+use tokio::time::timeout;
+use tokio_rustls::TlsConnector;
+
+pub use fetch::{BaseUrl, Scheme};
+pub use portal_solutions_yo_gurt::resolve::System;
+
+/// How long answers without a `ttl` are cached
+pub const DEFAULT_TTL: Duration = Duration::from_secs(300);
+/// How long nonexistent domains are cached
+pub const DEFAULT_NEGATIVE_TTL: Duration = Duration::from_secs(30);
+
+/// Errors from [`GurtedDns`]
+#[derive(Debug)]
+pub enum DnsError {
+    /// The base URL is not `gurt://` or `http://` followed by a host
+    InvalidUrl,
+    /// The host is not a name the API can be asked about
+    InvalidName,
+    /// The domain does not exist
+    NotFound,
+    /// The API answered with an unexpected status
+    Status(u16),
+    /// The API's answer could not be understood
+    InvalidResponse,
+    /// The API did not answer in time
+    Timeout,
+    /// Connecting to the API or reading its answer failed
+    Io(io::Error),
+    /// The GURT exchange with the API failed
+    Gurt(GurtError<io::Error>),
+}
+
+impl fmt::Display for DnsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DnsError::InvalidUrl => f.write_str("invalid DNS API URL, expected gurt:// or http://"),
+            DnsError::InvalidName => f.write_str("invalid domain name"),
+            DnsError::NotFound => f.write_str("domain not found"),
+            DnsError::Status(status) => write!(f, "DNS API answered with status {status}"),
+            DnsError::InvalidResponse => f.write_str("invalid DNS API response"),
+            DnsError::Timeout => f.write_str("DNS API timed out"),
+            DnsError::Io(e) => write!(f, "DNS API connection failed: {e}"),
+            DnsError::Gurt(e) => write!(f, "DNS API request failed: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for DnsError {}
+
+impl From<io::Error> for DnsError {
+    fn from(e: io::Error) -> Self {
+        DnsError::Io(e)
+    }
+}
+
+impl From<GurtError<io::Error>> for DnsError {
+    fn from(e: GurtError<io::Error>) -> Self {
+        DnsError::Gurt(e)
+    }
+}
+
+/// A cached answer: an address, or `None` for a nonexistent domain
+struct Entry {
+    address: Option<IpAddr>,
+    expires: Instant,
+}
+
+/// Resolves names through a Gurted DNS API, caching answers
+pub struct GurtedDns {
+    base: BaseUrl,
+    tls: TlsConnector,
+    ttl: Duration,
+    negative_ttl: Duration,
+    cache: Mutex<HashMap<String, Entry>>,
+}
+
+impl GurtedDns {
+    /// Query the API at `base_url` (`gurt://host[:port][/prefix]` or
+    /// `http://host[:port][/prefix]`)
+    ///
+    /// The API's own host must be an IP address or resolvable by the system. `gurt://`
+    /// servers are verified against the WebPKI roots; see [`tls_config`](Self::tls_config).
+    pub fn new(base_url: &str) -> Result<Self, DnsError> {
+        let roots = RootCertStore {
+            roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+        };
+        let config =
+            ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+                .with_protocol_versions(&[&rustls::version::TLS13])
+                .expect("ring supports TLS 1.3")
+                .with_root_certificates(roots)
+                .with_no_client_auth();
+        Ok(Self {
+            base: BaseUrl::parse(base_url).ok_or(DnsError::InvalidUrl)?,
+            tls: TlsConnector::from(Arc::new(config_with_alpn(config))),
+            ttl: DEFAULT_TTL,
+            negative_ttl: DEFAULT_NEGATIVE_TTL,
+            cache: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Use `config` for `gurt://` APIs, e.g. to trust a local development CA; ALPN is
+    /// set to `GURT/1.0`
+    pub fn tls_config(mut self, config: ClientConfig) -> Self {
+        self.tls = TlsConnector::from(Arc::new(config_with_alpn(config)));
+        self
+    }
+
+    /// Cache answers without a `ttl` for `ttl` (default [`DEFAULT_TTL`])
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Cache nonexistent domains for `ttl` (default [`DEFAULT_NEGATIVE_TTL`])
+    pub fn negative_ttl(mut self, ttl: Duration) -> Self {
+        self.negative_ttl = ttl;
+        self
+    }
+
+    /// The API's base URL
+    pub fn base_url(&self) -> &BaseUrl {
+        &self.base
+    }
+
+    /// Forget all cached answers
+    pub fn clear_cache(&self) {
+        self.cache.lock().unwrap().clear();
+    }
+
+    /// Resolve `host`, from the cache while its answer is fresh
+    pub async fn lookup(&self, host: &str) -> Result<IpAddr, DnsError> {
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        let (name, tld) = split_domain(&host).ok_or(DnsError::InvalidName)?;
+        if let Some(entry) = self.cache.lock().unwrap().get(&host)
+            && entry.expires > Instant::now()
+        {
+            return entry.address.ok_or(DnsError::NotFound);
+        }
+
+        let path = format!("{}/domain/{name}/{tld}", self.base.prefix);
+        let request_timeout = Duration::from_secs(DEFAULT_REQUEST_TIMEOUT_SECS.into());
+        let (status, body) = timeout(request_timeout, fetch::get(&self.base, &self.tls, &path))
+            .await
+            .map_err(|_| DnsError::Timeout)??;
+        let (address, ttl) = match status {
+            200..=299 => {
+                let (address, ttl) = parse_answer(&body).ok_or(DnsError::InvalidResponse)?;
+                (Some(address), ttl.unwrap_or(self.ttl))
+            }
+            404 => (None, self.negative_ttl),
+            status => return Err(DnsError::Status(status)),
+        };
+
+        let now = Instant::now();
+        let mut cache = self.cache.lock().unwrap();
+        cache.retain(|_, entry| entry.expires > now);
+        cache.insert(
+            host,
+            Entry {
+                address,
+                expires: now + ttl,
+            },
+        );
+        address.ok_or(DnsError::NotFound)
+    }
+}
+
+impl Resolver for GurtedDns {
+    type Error = DnsError;
+
+    async fn resolve(&self, host: &str) -> Result<IpAddr, DnsError> {
+        self.lookup(host).await
+    }
+}
+
+fn config_with_alpn(mut config: ClientConfig) -> ClientConfig {
+    config.alpn_protocols = vec![ALPN_IDENTIFIER.as_bytes().to_vec()];
+    config
+}
+
+/// Split `host` into the name and top-level domain the API is asked about
+fn split_domain(host: &str) -> Option<(&str, &str)> {
+    let (name, tld) = host.rsplit_once('.')?;
+    let valid = |part: &str| {
+        !part.is_empty()
+            && part.split('.').all(|label| {
+                !label.is_empty()
+                    && label
+                        .bytes()
+                        .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+            })
+    };
+    (valid(name) && valid(tld)).then_some((name, tld))
+}
+
+/// Read `ip` and the optional `ttl` from an API answer
+fn parse_answer(body: &[u8]) -> Option<(IpAddr, Option<Duration>)> {
+    let answer: serde_json::Value = serde_json::from_slice(body).ok()?;
+    let address = answer.get("ip")?.as_str()?.parse().ok()?;
+    let ttl = answer
+        .get("ttl")
+        .and_then(|ttl| ttl.as_u64())
+        .map(Duration::from_secs);
+    Some((address, ttl))
+}
@@ -0,0 +1,210 @@
+//! Resolving through fake Gurted DNS APIs served over HTTP and GURT
+
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use portal_solutions_yo_gurt::StatusCode;
+use portal_solutions_yo_gurt::resolve::{Fallback, Hosts, Resolver};
+use portal_solutions_yo_gurt_ca::LocalCa;
+use portal_solutions_yo_gurt_dns::{BaseUrl, DnsError, GurtedDns};
+use portal_solutions_yo_gurt_server::{Request, Response, Server, TlsConfig};
+use rustls::{ClientConfig, RootCertStore};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+/// The answer for `path`: `site.web` exists, everything else does not
+fn answer(path: &str) -> (u16, &'static str) {
+    match path {
+        "/api/domain/site/web" => (200, r#"{"name":"site","tld":"web","ip":"10.0.0.7"}"#),
+        "/api/domain/short/web" => (200, r#"{"ip":"10.0.0.8","ttl":0}"#),
+        "/api/domain/broken/web" => (200, r#"{"ip":"not an address"}"#),
+        _ => (404, r#"{"error":"not found"}"#),
+    }
+}
+
+/// Serve [`answer`] over HTTP/1.0, counting requests
+async fn http_api(requests: Arc<AtomicUsize>) -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            requests.fetch_add(1, Ordering::SeqCst);
+            let mut request = Vec::new();
+            let mut buf = [0u8; 512];
+            while !request.ends_with(b"\r\n\r\n") {
+                let n = stream.read(&mut buf).await.unwrap();
+                assert!(n > 0, "request cut short");
+                request.extend_from_slice(&buf[..n]);
+            }
+            let line = std::str::from_utf8(&request)
+                .unwrap()
+                .lines()
+                .next()
+                .unwrap();
+            let path = line.split(' ').nth(1).unwrap();
+            let (status, body) = answer(path);
+            let response = format!(
+                "HTTP/1.0 {status} X\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{body}",
+                body.len()
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+        }
+    });
+    port
+}
+
+#[tokio::test]
+async fn http_api_with_cache() {
+    let requests = Arc::new(AtomicUsize::new(0));
+    let port = http_api(requests.clone()).await;
+    let dns = GurtedDns::new(&format!("http://127.0.0.1:{port}/api/")).unwrap();
+    assert_eq!(dns.base_url().prefix, "/api");
+
+    let site: IpAddr = "10.0.0.7".parse().unwrap();
+    assert_eq!(dns.lookup("site.web").await.unwrap(), site);
+    // Cached, whatever the case or trailing dot
+    assert_eq!(dns.lookup("SITE.web.").await.unwrap(), site);
+    assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+    // Nonexistent domains are cached too
+    assert!(matches!(
+        dns.lookup("missing.web").await,
+        Err(DnsError::NotFound)
+    ));
+    assert!(matches!(
+        dns.lookup("missing.web").await,
+        Err(DnsError::NotFound)
+    ));
+    assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+    // A `ttl` of zero is not cached
+    for _ in 0..2 {
+        assert_eq!(
+            dns.lookup("short.web").await.unwrap(),
+            "10.0.0.8".parse::<IpAddr>().unwrap()
+        );
+    }
+    assert_eq!(requests.load(Ordering::SeqCst), 4);
+
+    assert!(matches!(
+        dns.lookup("broken.web").await,
+        Err(DnsError::InvalidResponse)
+    ));
+    for name in ["web", "", "bad name.web", "a..web"] {
+        assert!(
+            matches!(dns.lookup(name).await, Err(DnsError::InvalidName)),
+            "{name}"
+        );
+    }
+    assert_eq!(requests.load(Ordering::SeqCst), 5);
+
+    dns.clear_cache();
+    assert_eq!(dns.lookup("site.web").await.unwrap(), site);
+    assert_eq!(requests.load(Ordering::SeqCst), 6);
+}
+
+#[tokio::test]
+async fn expired_answers_are_fetched_again() {
+    let requests = Arc::new(AtomicUsize::new(0));
+    let port = http_api(requests.clone()).await;
+    let dns = GurtedDns::new(&format!("http://127.0.0.1:{port}/api"))
+        .unwrap()
+        .ttl(Duration::from_millis(50))
+        .negative_ttl(Duration::ZERO);
+
+    dns.lookup("site.web").await.unwrap();
+    dns.lookup("site.web").await.unwrap();
+    assert_eq!(requests.load(Ordering::SeqCst), 1);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    dns.lookup("site.web").await.unwrap();
+    assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+    dns.lookup("missing.web").await.unwrap_err();
+    dns.lookup("missing.web").await.unwrap_err();
+    assert_eq!(requests.load(Ordering::SeqCst), 4);
+}
+
+#[tokio::test]
+async fn gurt_api() {
+    let ca = LocalCa::generate("test CA").unwrap();
+    let issued = ca.issue_server(&["localhost"]).unwrap();
+    let tls = TlsConfig::new(vec![issued.cert_der().clone()], issued.key_der());
+    let server = Server::new(tls, async |request: Request| {
+        let (status, body) = answer(request.path());
+        let status = match status {
+            200 => StatusCode::Ok,
+            _ => StatusCode::NotFound,
+        };
+        Response::new(status)
+            .header("content-type", "application/json")
+            .body(body)
+    })
+    .unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(server.serve(listener));
+
+    let mut roots = RootCertStore::empty();
+    roots.add(ca.cert_der().clone()).unwrap();
+    let config =
+        ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_protocol_versions(&[&rustls::version::TLS13])
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+    let dns = GurtedDns::new(&format!("gurt://localhost:{port}/api"))
+        .unwrap()
+        .tls_config(config);
+
+    // Names the API does not know fall through to the hosts file
+    let resolver = Fallback(&dns, Hosts::new("127.0.0.1 local.test"));
+    assert_eq!(
+        resolver.resolve("site.web").await.unwrap(),
+        "10.0.0.7".parse::<IpAddr>().unwrap()
+    );
+    assert_eq!(
+        resolver.resolve("local.test").await.unwrap(),
+        "127.0.0.1".parse::<IpAddr>().unwrap()
+    );
+    assert!(matches!(
+        dns.lookup("missing.web").await,
+        Err(DnsError::NotFound)
+    ));
+
+    // Without the CA, the API's certificate is not trusted
+    let untrusted = GurtedDns::new(&format!("gurt://localhost:{port}/api")).unwrap();
+    assert!(matches!(
+        untrusted.lookup("site.web").await,
+        Err(DnsError::Io(_))
+    ));
+}
+
+#[test]
+fn base_urls() {
+    let url = BaseUrl::parse("gurt://DNS.web").unwrap();
+    assert_eq!(
+        (url.host.as_str(), url.port, url.prefix.as_str()),
+        ("dns.web", 4878, "")
+    );
+    let url = BaseUrl::parse("http://[::1]:8080/v1/").unwrap();
+    assert_eq!(
+        (url.host.as_str(), url.port, url.prefix.as_str()),
+        ("::1", 8080, "/v1")
+    );
+    assert_eq!(url.to_string(), "http://[::1]:8080/v1");
+    for invalid in [
+        "https://dns.web",
+        "gurt://",
+        "gurt://dns.web:x",
+        "http://dns.web/?q",
+    ] {
+        assert!(BaseUrl::parse(invalid).is_none(), "{invalid}");
+    }
+    assert!(matches!(
+        GurtedDns::new("dns.web"),
+        Err(DnsError::InvalidUrl)
+    ));
+}
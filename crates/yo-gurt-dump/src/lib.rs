@@ -0,0 +1,345 @@
+//! Dissector for GURT traffic captures
+//!
+//! Reads the records written by `portal_solutions_yo_gurt::capture` and splits the bytes
+//! sent and received back into GURT messages: the `HANDSHAKE`, request and response
+//! heads with their headers, and bodies framed by `content-length`, except for responses
+//! to `HEAD` and `204`/`304` responses, which have none. The heads are parsed
+//! with the same readers, in [`ParseMode::Strict`], that clients and servers use, so
+//! anything they would reject shows up as a [`FramingError`] at the byte where reading
+//! stopped.
+//!
+//! ```rust,ignore
+//! let data = std::fs::read("session.gcap")?;
+//! let records = capture::records(&data)?.collect::<Result<Vec<_>, _>>()?;
+//! for item in dissect(records) {
+//!     println!("{item}");
+//! }
+//! ```
+//!
+//! The `gurt-dump` binary does the same from the command line.
+
+use std::convert::Infallible;
+use std::fmt;
+
+use portal_solutions_yo_gurt::capture::{Direction, Record};
+use portal_solutions_yo_gurt::server::RequestReader;
+use portal_solutions_yo_gurt::{
+    GURT_VERSION, GurtError, HeaderResult, Method, ParseMode, ResponseReader, StatusCode,
+};
+
+/// Buffer for request, status and header lines; longer lines are framing errors
+pub const LINE_BUFFER_SIZE: usize = 16 * 1024;
+
+/// A request or response line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Head {
+    /// A request, including the `HANDSHAKE`
+    Request {
+        /// The request method
+        method: Method,
+        /// The request path
+        path: String,
+    },
+    /// A response
+    Response {
+        /// The response status
+        status: StatusCode,
+    },
+}
+
+/// One message read back from a capture
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    /// Which way the message went
+    pub direction: Direction,
+    /// Offset of the message within the bytes captured in its direction
+    pub offset: usize,
+    /// Microseconds since the capture started, when the message's first byte was captured
+    pub timestamp: u64,
+    /// The request or status line
+    pub head: Head,
+    /// The headers in order, as sent
+    pub headers: Vec<(String, String)>,
+    /// The body length declared by `content-length`, 0 without one or for a response
+    /// without a body
+    pub body_len: usize,
+    /// The captured body; shorter than `body_len` if the capture ends inside it
+    pub body: Vec<u8>,
+}
+
+/// Bytes that do not frame as a GURT message
+///
+/// Nothing after it is read in the same direction, since message boundaries are lost.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FramingError {
+    /// Which way the bytes went
+    pub direction: Direction,
+    /// Offset of the message being read, within the bytes captured in its direction
+    pub offset: usize,
+    /// Microseconds since the capture started, when the message's first byte was captured
+    pub timestamp: u64,
+    /// Why reading stopped; [`GurtError::UnexpectedEof`] if the capture ends inside a head
+    pub error: GurtError<Infallible>,
+}
+
+/// A dissected message or framing error
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Item {
+    /// A complete head, with as much body as was captured
+    Message(Message),
+    /// Where a direction stopped framing
+    Error(FramingError),
+}
+
+impl Item {
+    fn timestamp(&self) -> u64 {
+        match self {
+            Item::Message(message) => message.timestamp,
+            Item::Error(error) => error.timestamp,
+        }
+    }
+
+    fn method(&self) -> Option<Method> {
+        match self {
+            Item::Message(Message {
+                head: Head::Request { method, .. },
+                ..
+            }) => Some(*method),
+            _ => None,
+        }
+    }
+}
+
+/// Dissect the records of a capture into messages, in the order they began
+///
+/// The bytes of each direction are read as one stream. A stream starting with
+/// `GURT/` holds responses and any other holds requests, so captures taken on either
+/// side of a connection read the same way. Responses are paired with requests in order,
+/// for the method they answer.
+pub fn dissect<'a>(records: impl IntoIterator<Item = Record<'a>>) -> Vec<Item> {
+    let mut sent = Stream::default();
+    let mut received = Stream::default();
+    for record in records {
+        match record.direction {
+            Direction::Sent => sent.push(&record),
+            Direction::Received => received.push(&record),
+        }
+    }
+    // Requests first, so that each response is read knowing what it answers
+    let (first, second) = match sent.holds_responses() {
+        true => ((received, Direction::Received), (sent, Direction::Sent)),
+        false => ((sent, Direction::Sent), (received, Direction::Received)),
+    };
+    let mut items = first.0.dissect(first.1, &[]);
+    let methods: Vec<Method> = items.iter().filter_map(Item::method).collect();
+    items.extend(second.0.dissect(second.1, &methods));
+    // Stable, so a request and its response captured at once stay in stream order
+    items.sort_by_key(Item::timestamp);
+    items
+}
+
+/// The bytes captured in one direction, with the timestamp of each record's start
+#[derive(Default)]
+struct Stream {
+    bytes: Vec<u8>,
+    starts: Vec<(usize, u64)>,
+}
+
+impl Stream {
+    fn push(&mut self, record: &Record<'_>) {
+        self.starts.push((self.bytes.len(), record.timestamp));
+        self.bytes.extend_from_slice(record.bytes);
+    }
+
+    fn holds_responses(&self) -> bool {
+        self.bytes.starts_with(b"GURT/")
+    }
+
+    /// Timestamp of the record holding byte `offset`
+    fn timestamp(&self, offset: usize) -> u64 {
+        let index = self.starts.partition_point(|&(start, _)| start <= offset);
+        self.starts[index.saturating_sub(1)].1
+    }
+
+    /// Read the messages of this stream; responses answer the requests of `methods` in
+    /// turn
+    fn dissect(&self, direction: Direction, methods: &[Method]) -> Vec<Item> {
+        let responses = self.holds_responses();
+        let mut rest = self.bytes.as_slice();
+        let mut buf = vec![0u8; LINE_BUFFER_SIZE];
+        let mut items = Vec::new();
+        while !rest.is_empty() {
+            let offset = self.bytes.len() - rest.len();
+            let timestamp = self.timestamp(offset);
+            let head = match responses {
+                true => {
+                    let method = methods.get(items.len()).copied();
+                    embassy_futures::block_on(read_response(&mut rest, &mut buf, method))
+                }
+                false => embassy_futures::block_on(read_request(&mut rest, &mut buf)),
+            };
+            let (head, headers, body_len) = match head {
+                Ok(head) => head,
+                Err(error) => {
+                    items.push(Item::Error(FramingError {
+                        direction,
+                        offset,
+                        timestamp,
+                        error,
+                    }));
+                    break;
+                }
+            };
+            let (body, after) = rest.split_at(body_len.min(rest.len()));
+            rest = after;
+            items.push(Item::Message(Message {
+                direction,
+                offset,
+                timestamp,
+                head,
+                headers,
+                body_len,
+                body: body.to_vec(),
+            }));
+        }
+        items
+    }
+}
+
+type Headers = Vec<(String, String)>;
+
+async fn read_request(
+    rest: &mut &[u8],
+    buf: &mut [u8],
+) -> Result<(Head, Headers, usize), GurtError<Infallible>> {
+    let mut reader = RequestReader::with_mode(rest, ParseMode::Strict);
+    let line = reader.read_request_line(buf).await?;
+    let head = Head::Request {
+        method: line.method,
+        path: String::from_utf8_lossy(line.path(buf)).into_owned(),
+    };
+    let mut headers = Vec::new();
+    while let Some(header) = reader.read_header(buf).await? {
+        headers.push(header_pair(&header, buf));
+    }
+    Ok((head, headers, reader.content_length().unwrap_or(0)))
+}
+
+/// Read a response to a `method` request, if known
+async fn read_response(
+    rest: &mut &[u8],
+    buf: &mut [u8],
+    method: Option<Method>,
+) -> Result<(Head, Headers, usize), GurtError<Infallible>> {
+    let mut reader = ResponseReader::with_mode(rest, ParseMode::Strict);
+    let line = reader.read_status_line(buf).await?;
+    let head = Head::Response {
+        status: line.status,
+    };
+    let mut headers = Vec::new();
+    while let Some(header) = reader.read_header(buf).await? {
+        headers.push(header_pair(&header, buf));
+    }
+    let bodiless = method == Some(Method::Head)
+        || matches!(line.status, StatusCode::NoContent | StatusCode::NotModified);
+    let body_len = match bodiless {
+        true => 0,
+        false => reader.content_length().unwrap_or(0),
+    };
+    Ok((head, headers, body_len))
+}
+
+fn header_pair(header: &HeaderResult, buf: &[u8]) -> (String, String) {
+    (
+        String::from_utf8_lossy(header.name(buf)).into_owned(),
+        String::from_utf8_lossy(header.value(buf)).into_owned(),
+    )
+}
+
+/// A timestamp as seconds since the capture started
+struct Seconds(u64);
+
+impl fmt::Display for Seconds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:4}.{:06}", self.0 / 1_000_000, self.0 % 1_000_000)
+    }
+}
+
+/// `>` for sent, `<` for received
+fn arrow(direction: Direction) -> char {
+    match direction {
+        Direction::Sent => '>',
+        Direction::Received => '<',
+    }
+}
+
+/// Annotated text: the timestamp, direction and head, then the headers and body size
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{}] {} ",
+            Seconds(self.timestamp),
+            arrow(self.direction)
+        )?;
+        match &self.head {
+            Head::Request { method, path } => {
+                write!(f, "{} {path} {GURT_VERSION}", method.as_str())?
+            }
+            Head::Response { status } => write!(
+                f,
+                "{GURT_VERSION} {} {}",
+                status.as_u16(),
+                status.reason_phrase()
+            )?,
+        }
+        match self.head {
+            Head::Request {
+                method: Method::Handshake,
+                ..
+            } => f.write_str("  (handshake)")?,
+            Head::Response {
+                status: StatusCode::SwitchingProtocols,
+            } => f.write_str("  (handshake accepted)")?,
+            _ => {}
+        }
+        writeln!(f, "  @{}", self.offset)?;
+        for (name, value) in &self.headers {
+            writeln!(f, "    {name}: {value}")?;
+        }
+        match self.body.len() == self.body_len {
+            true => write!(f, "    body: {} bytes", self.body_len),
+            false => write!(
+                f,
+                "    body: {} of {} bytes captured",
+                self.body.len(),
+                self.body_len
+            ),
+        }
+    }
+}
+
+impl fmt::Display for FramingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{}] {} framing error @{}: ",
+            Seconds(self.timestamp),
+            arrow(self.direction),
+            self.offset
+        )?;
+        match &self.error {
+            GurtError::UnexpectedEof => f.write_str("capture ends inside a message head"),
+            error => write!(f, "{error}"),
+        }
+    }
+}
+
+impl fmt::Display for Item {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Item::Message(message) => message.fmt(f),
+            Item::Error(error) => error.fmt(f),
+        }
+    }
+}
@@ -0,0 +1,74 @@
+//! `gurt-dump`: print the GURT messages in a traffic capture
+
+use std::process::exit;
+
+use portal_solutions_yo_gurt::capture;
+use portal_solutions_yo_gurt_dump::{Item, dissect};
+
+const USAGE: &str = "\
+usage: gurt-dump [options] <capture>
+
+Prints the GURT messages in a capture written by portal_solutions_yo_gurt's
+capture::Writer: the HANDSHAKE, request and response heads with their headers,
+body sizes, and where bytes stop framing as GURT. '>' marks bytes the captured
+side sent and '<' bytes it received.
+
+options:
+  --bodies         also print bodies that are UTF-8 text
+  -h, --help       show this help
+";
+
+fn main() {
+    let mut bodies = false;
+    let mut path = None;
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--bodies" => bodies = true,
+            "-h" | "--help" => {
+                print!("{USAGE}");
+                return;
+            }
+            _ if arg.starts_with('-') => usage(&format!("unknown argument {arg:?}")),
+            _ if path.is_none() => path = Some(arg),
+            _ => usage("only one capture can be dumped at a time"),
+        }
+    }
+    let path = path.unwrap_or_else(|| usage("missing capture"));
+
+    let data = std::fs::read(&path).unwrap_or_else(|e| fail(&format!("{path}: {e}")));
+    let mut records = Vec::new();
+    let mut error = None;
+    for record in capture::records(&data).unwrap_or_else(|e| fail(&format!("{path}: {e}"))) {
+        match record {
+            Ok(record) => records.push(record),
+            // Dump what was captured before a record cut short by a crash
+            Err(e) => error = Some(e),
+        }
+    }
+
+    for item in dissect(records) {
+        println!("{item}");
+        if let Item::Message(message) = &item
+            && bodies
+            && !message.body.is_empty()
+            && let Ok(text) = std::str::from_utf8(&message.body)
+        {
+            for line in text.lines() {
+                println!("    | {line}");
+            }
+        }
+    }
+    if let Some(e) = error {
+        fail(&format!("{path}: {e}"));
+    }
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("gurt-dump: {message}");
+    exit(1);
+}
+
+fn usage(message: &str) -> ! {
+    eprintln!("{message}\n\n{USAGE}");
+    exit(2);
+}
@@ -0,0 +1,179 @@
+//! Dissecting captures into annotated messages
+
+use portal_solutions_yo_gurt::capture::{Direction, Record};
+use portal_solutions_yo_gurt::{GurtError, Method, ProtocolError, StatusCode};
+use portal_solutions_yo_gurt_dump::{Head, Item, dissect};
+
+fn record(direction: Direction, timestamp: u64, bytes: &[u8]) -> Record<'_> {
+    Record {
+        direction,
+        timestamp,
+        bytes,
+    }
+}
+
+#[test]
+fn client_capture() {
+    let records = [
+        record(
+            Direction::Sent,
+            10,
+            b"HANDSHAKE / GURT/1.0.0\r\nhost: gurt.test\r\n\r\n",
+        ),
+        record(
+            Direction::Received,
+            20,
+            b"GURT/1.0.0 101 SWITCHING_PROTOCOLS\r\n\r\n",
+        ),
+        record(
+            Direction::Sent,
+            30,
+            b"POST /up GURT/1.0.0\r\ncontent-length: 4\r\n\r\nab",
+        ),
+        record(Direction::Sent, 31, b"cd"),
+        record(
+            Direction::Received,
+            1_500_040,
+            b"GURT/1.0.0 200 OK\r\ncontent-length: 5\r\n\r\nhel",
+        ),
+        record(Direction::Received, 1_500_050, b"lo"),
+        record(Direction::Received, 1_500_060, b"HTTP/1.1 200 OK\r\n\r\n"),
+    ];
+    let items = dissect(records);
+    assert_eq!(items.len(), 5);
+
+    let Item::Message(handshake) = &items[0] else {
+        panic!("{items:?}")
+    };
+    assert_eq!(
+        handshake.head,
+        Head::Request {
+            method: Method::Handshake,
+            path: "/".into()
+        }
+    );
+    assert_eq!(handshake.headers, [("host".into(), "gurt.test".into())]);
+    assert_eq!(
+        handshake.to_string(),
+        "[   0.000010] > HANDSHAKE / GURT/1.0.0  (handshake)  @0\n    host: gurt.test\n    body: 0 bytes"
+    );
+
+    let Item::Message(accepted) = &items[1] else {
+        panic!("{items:?}")
+    };
+    assert_eq!(accepted.direction, Direction::Received);
+    assert!(
+        accepted
+            .to_string()
+            .contains("101 SWITCHING_PROTOCOLS  (handshake accepted)")
+    );
+
+    let Item::Message(upload) = &items[2] else {
+        panic!("{items:?}")
+    };
+    assert_eq!((upload.timestamp, upload.offset), (30, 43));
+    assert_eq!(upload.body, b"abcd");
+
+    let Item::Message(response) = &items[3] else {
+        panic!("{items:?}")
+    };
+    assert_eq!(
+        response.head,
+        Head::Response {
+            status: StatusCode::Ok
+        }
+    );
+    assert_eq!(
+        (response.body_len, response.body.as_slice()),
+        (5, &b"hello"[..])
+    );
+    assert!(
+        response
+            .to_string()
+            .starts_with("[   1.500040] < GURT/1.0.0 200 OK  @38\n")
+    );
+
+    let Item::Error(error) = &items[4] else {
+        panic!("{items:?}")
+    };
+    assert_eq!(
+        (error.direction, error.timestamp),
+        (Direction::Received, 1_500_060)
+    );
+    assert_eq!(
+        error.error,
+        GurtError::Protocol(ProtocolError::InvalidProtocol)
+    );
+    assert!(error.to_string().contains("framing error @83"));
+}
+
+#[test]
+fn server_capture_cut_short() {
+    // A server reads requests and sends responses; the capture ends inside a body and
+    // then inside a head
+    let records = [
+        record(
+            Direction::Received,
+            0,
+            b"GET / GURT/1.0.0\r\n\r\nPUT /x GURT/1.0.0\r\ncontent-length: 10\r\n\r\n12345",
+        ),
+        record(
+            Direction::Sent,
+            5,
+            b"GURT/1.0.0 404 NOT_FOUND\r\ncontent-le",
+        ),
+    ];
+    let items = dissect(records);
+    let Item::Message(put) = &items[1] else {
+        panic!("{items:?}")
+    };
+    assert_eq!((put.body_len, put.body.len()), (10, 5));
+    assert!(put.to_string().ends_with("body: 5 of 10 bytes captured"));
+    let Item::Error(cut) = &items[2] else {
+        panic!("{items:?}")
+    };
+    assert_eq!(cut.error, GurtError::UnexpectedEof);
+    assert!(
+        cut.to_string()
+            .ends_with("capture ends inside a message head")
+    );
+}
+
+#[test]
+fn bodiless_responses() {
+    let requests: &[u8] =
+        b"HEAD /a GURT/1.0.0\r\n\r\nGET /b GURT/1.0.0\r\n\r\nGET /c GURT/1.0.0\r\n\r\n";
+    let responses: &[u8] = b"GURT/1.0.0 200 OK\r\ncontent-length: 5\r\n\r\n\
+        GURT/1.0.0 304 NOT_MODIFIED\r\ncontent-length: 3\r\n\r\n\
+        GURT/1.0.0 200 OK\r\ncontent-length: 1\r\n\r\nc";
+    // Captured by the client, then by the server
+    for (requested, answered) in [
+        (Direction::Sent, Direction::Received),
+        (Direction::Received, Direction::Sent),
+    ] {
+        let items = dissect([
+            record(requested, 0, requests),
+            record(answered, 1, responses),
+        ]);
+        let lengths: Vec<(Direction, usize, &[u8])> = items
+            .iter()
+            .map(|item| match item {
+                Item::Message(message) => {
+                    (message.direction, message.body_len, message.body.as_slice())
+                }
+                Item::Error(error) => panic!("{error}"),
+            })
+            .collect();
+        assert_eq!(
+            lengths,
+            [
+                (requested, 0, &b""[..]),
+                (requested, 0, b""),
+                (requested, 0, b""),
+                (answered, 0, b""),
+                (answered, 0, b""),
+                (answered, 1, b"c"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,148 @@
+//! Command line configuration
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+
+use portal_solutions_yo_gurt::resolve::Hosts;
+use portal_solutions_yo_gurt::tls;
+use portal_solutions_yo_gurt_dns::GurtedDns;
+use rustls::pki_types::CertificateDer;
+
+use crate::connect::{Authority, ClientIdentity};
+
+/// Proxy settings
+pub struct Config {
+    /// Address to accept HTTP clients on
+    pub listen: SocketAddr,
+    /// HTTP `host` values mapped to GURT servers
+    pub mappings: HashMap<String, Authority>,
+    /// Accept any server certificate
+    pub insecure: bool,
+    /// Roots trusted in addition to the WebPKI roots
+    pub extra_roots: Vec<CertificateDer<'static>>,
+    /// Certificate presented to GURT servers that ask for one (mutual TLS)
+    pub client_identity: Option<ClientIdentity>,
+    /// User agent sent upstream when the client did not provide one
+    pub user_agent: String,
+    /// Addresses looked up before any DNS
+    pub hosts: Option<Hosts<String>>,
+    /// Gurted DNS API asked before system DNS
+    pub dns: Option<GurtedDns>,
+}
+
+pub const USAGE: &str = "\
+usage: yo-gurt-proxy [options]
+
+Accepts HTTP/1.1 requests and forwards them to GURT servers. Requests may use
+absolute-form targets (GET gurt://example.web/path HTTP/1.1) or be routed by
+their host header through --map.
+
+options:
+  --listen ADDR           address to listen on (default 127.0.0.1:8080)
+  --map HOST=gurt://AUTH  forward requests for HTTP host HOST to AUTH (repeatable)
+  --user-agent UA         default upstream user agent (default yo-gurt-proxy/0.1)
+  --insecure              accept any upstream TLS certificate
+  --ca FILE               also trust the PEM CA certificates in FILE, e.g. a
+                          gurt-ca development CA (repeatable)
+  --client-cert FILE      PEM certificate chain presented to upstream servers
+  --client-key FILE       PEM private key for --client-cert
+  --hosts FILE            resolve upstream hosts from FILE (hosts-file format)
+                          before any DNS
+  --dns URL               resolve upstream hosts through the Gurted DNS API at
+                          URL (gurt:// or http://) before system DNS
+  -h, --help              show this help
+";
+
+impl Config {
+    /// Parse command line arguments (without the program name)
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut config = Config {
+            listen: SocketAddr::from(([127, 0, 0, 1], 8080)),
+            mappings: HashMap::new(),
+            insecure: false,
+            extra_roots: Vec::new(),
+            client_identity: None,
+            user_agent: "yo-gurt-proxy/0.1".to_owned(),
+            hosts: None,
+            dns: None,
+        };
+        let mut client_cert = None;
+        let mut client_key = None;
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| args.next().ok_or_else(|| format!("{name} needs a value"));
+            match arg.as_str() {
+                "--listen" => {
+                    let listen = value("--listen")?;
+                    config.listen = listen
+                        .parse()
+                        .map_err(|_| format!("invalid listen address {listen:?}"))?;
+                }
+                "--map" => {
+                    let map = value("--map")?;
+                    let (host, target) = map.split_once('=').ok_or_else(|| {
+                        format!("invalid mapping {map:?}, expected HOST=gurt://AUTH")
+                    })?;
+                    let authority = target
+                        .strip_prefix("gurt://")
+                        .map(|s| s.trim_end_matches('/'))
+                        .and_then(Authority::parse)
+                        .ok_or_else(|| format!("invalid gurt:// target {target:?}"))?;
+                    config.mappings.insert(host.to_ascii_lowercase(), authority);
+                }
+                "--user-agent" => config.user_agent = value("--user-agent")?,
+                "--insecure" => config.insecure = true,
+                "--ca" => {
+                    let path = value("--ca")?;
+                    config.extra_roots.extend(load_certificates(&path)?);
+                }
+                "--client-cert" => client_cert = Some(value("--client-cert")?),
+                "--client-key" => client_key = Some(value("--client-key")?),
+                "--hosts" => {
+                    let path = value("--hosts")?;
+                    let hosts = Hosts::load(&path)
+                        .map_err(|e| format!("cannot read hosts file {path:?}: {e}"))?;
+                    config.hosts = Some(hosts);
+                }
+                "--dns" => {
+                    let url = value("--dns")?;
+                    let dns = GurtedDns::new(&url).map_err(|_| {
+                        format!("invalid --dns URL {url:?}, expected gurt:// or http://")
+                    })?;
+                    config.dns = Some(dns);
+                }
+                "-h" | "--help" => return Err(String::new()),
+                _ => return Err(format!("unknown argument {arg:?}")),
+            }
+        }
+        config.client_identity = match (client_cert, client_key) {
+            (Some(cert), Some(key)) => Some(load_identity(&cert, &key)?),
+            (None, None) => None,
+            _ => return Err("--client-cert and --client-key must be used together".into()),
+        };
+        Ok(config)
+    }
+
+    /// Find the GURT server mapped to an HTTP `host` value
+    pub fn mapping(&self, host: &str) -> Option<&Authority> {
+        let host = host.to_ascii_lowercase();
+        self.mappings.get(&host).or_else(|| {
+            let bare = host
+                .rsplit_once(':')
+                .map_or(host.as_str(), |(bare, _)| bare);
+            self.mappings.get(bare)
+        })
+    }
+}
+
+/// Read a client certificate chain and its private key from PEM files
+fn load_identity(cert: &str, key: &str) -> Result<ClientIdentity, String> {
+    let cert_chain = load_certificates(cert)?;
+    let key = tls::read_private_key(key)
+        .map_err(|e| format!("cannot read private key from {key:?}: {e}"))?;
+    Ok(ClientIdentity { cert_chain, key })
+}
+
+/// Read every certificate in a PEM file, failing if there are none
+fn load_certificates(path: &str) -> Result<Vec<CertificateDer<'static>>, String> {
+    tls::read_certificates(path).map_err(|e| format!("cannot read certificates from {path:?}: {e}"))
+}
@@ -0,0 +1,90 @@
+//! TLS 1.3 connections to GURT servers
+//!
+//! From spec: "All connections must use TLS 1.3 encryption" with ALPN `GURT/1.0`.
+
+use std::fmt;
+
+use portal_solutions_yo_gurt::DEFAULT_PORT;
+use portal_solutions_yo_gurt::fetch::Connector;
+use portal_solutions_yo_gurt::resolve::{Fallback, Hosts};
+use portal_solutions_yo_gurt::tls;
+use portal_solutions_yo_gurt_dns::{GurtedDns, System};
+use rustls::RootCertStore;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+
+/// Host and port of a GURT server
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Authority {
+    pub host: String,
+    pub port: u16,
+}
+
+impl Authority {
+    /// Parse `host[:port]`, defaulting to the GURT port
+    pub fn parse(s: &str) -> Option<Self> {
+        let (host, port) = if let Some(rest) = s.strip_prefix('[') {
+            let (host, rest) = rest.split_once(']')?;
+            match rest {
+                "" => (host, DEFAULT_PORT),
+                _ => (host, rest.strip_prefix(':')?.parse().ok()?),
+            }
+        } else {
+            match s.split_once(':') {
+                Some((host, port)) => (host, port.parse().ok()?),
+                None => (s, DEFAULT_PORT),
+            }
+        };
+        if host.is_empty() {
+            return None;
+        }
+        Some(Self {
+            host: host.to_ascii_lowercase(),
+            port,
+        })
+    }
+}
+
+impl fmt::Display for Authority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.host, self.port)
+    }
+}
+
+/// Certificate chain and key for servers that require client certificates
+#[derive(Debug)]
+pub struct ClientIdentity {
+    pub cert_chain: Vec<CertificateDer<'static>>,
+    pub key: PrivateKeyDer<'static>,
+}
+
+/// Hosts file, then the Gurted DNS API, then system DNS
+pub type UpstreamResolver = Fallback<Option<Hosts<String>>, Fallback<Option<GurtedDns>, System>>;
+
+/// A connector verifying servers against the WebPKI roots plus `extra_roots` (such as a
+/// local development CA), or accepting any certificate when `insecure` is set
+///
+/// `client_identity` is presented to servers that request a client certificate; it fails
+/// here if the key does not match the certificate. Hosts are looked up in `hosts`, then
+/// through `dns`, then by the system; `dns` is reached with the same TLS settings as
+/// upstream servers.
+pub fn connector(
+    insecure: bool,
+    extra_roots: Vec<CertificateDer<'static>>,
+    client_identity: Option<ClientIdentity>,
+    hosts: Option<Hosts<String>>,
+    dns: Option<GurtedDns>,
+) -> Result<Connector<UpstreamResolver>, rustls::Error> {
+    let mut roots = RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+    for root in extra_roots {
+        roots.add(root)?;
+    }
+    let identity = client_identity.map(|identity| (identity.cert_chain, identity.key));
+    let config = tls::client_config(roots, insecure, identity)?;
+    let dns = dns.map(|dns| dns.tls_config(config.clone()));
+    Ok(Connector::new(
+        config,
+        Fallback(hosts, Fallback(dns, System)),
+    ))
+}
@@ -0,0 +1,276 @@
+//! Minimal HTTP/1.1 server side: request parsing and response framing
+
+use std::fmt;
+use std::io;
+
+use portal_solutions_yo_gurt::{MAX_MESSAGE_SIZE, parse_content_length};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Largest request head accepted from HTTP clients
+const MAX_HEAD_SIZE: usize = 64 * 1024;
+
+/// Maximum number of request headers
+const MAX_HEADERS: usize = 100;
+
+/// Headers that only apply to a single HTTP hop and are never forwarded
+pub const HOP_BY_HOP: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "proxy-connection",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// A parsed HTTP request with its body fully read
+pub struct HttpRequest {
+    pub method: String,
+    pub target: String,
+    /// Header names are lowercased
+    pub headers: Vec<(String, Vec<u8>)>,
+    pub body: Vec<u8>,
+    /// Minor version of HTTP/1.x the client speaks
+    pub version: u8,
+    pub keep_alive: bool,
+}
+
+impl HttpRequest {
+    /// First value of the header `name` (lowercase)
+    pub fn header(&self, name: &str) -> Option<&[u8]> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_slice())
+    }
+}
+
+/// Errors reading an HTTP request
+#[derive(Debug)]
+pub enum HttpError {
+    Io(io::Error),
+    /// Malformed request head or body framing
+    BadRequest(&'static str),
+    /// Request head exceeds [`MAX_HEAD_SIZE`]
+    HeadTooLarge,
+    /// Request body exceeds `MAX_MESSAGE_SIZE`
+    BodyTooLarge,
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HttpError::Io(e) => write!(f, "{e}"),
+            HttpError::BadRequest(reason) => write!(f, "bad request: {reason}"),
+            HttpError::HeadTooLarge => f.write_str("request head too large"),
+            HttpError::BodyTooLarge => f.write_str("request body too large"),
+        }
+    }
+}
+
+impl From<io::Error> for HttpError {
+    fn from(e: io::Error) -> Self {
+        HttpError::Io(e)
+    }
+}
+
+/// Read the next request, or `None` if the client closed the connection
+pub async fn read_request<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+) -> Result<Option<HttpRequest>, HttpError> {
+    let mut head = Vec::new();
+    loop {
+        let n = (&mut *reader)
+            .take((MAX_HEAD_SIZE + 1 - head.len()) as u64)
+            .read_until(b'\n', &mut head)
+            .await?;
+        if n == 0 {
+            if head.is_empty() {
+                return Ok(None);
+            }
+            return Err(HttpError::BadRequest("truncated request head"));
+        }
+        if head.len() > MAX_HEAD_SIZE {
+            return Err(HttpError::HeadTooLarge);
+        }
+        // Tolerate empty lines before the request line (RFC 9112 section 2.2)
+        if head == b"\r\n" || head == b"\n" {
+            head.clear();
+            continue;
+        }
+        if head.ends_with(b"\r\n\r\n") || head.ends_with(b"\n\n") {
+            break;
+        }
+    }
+
+    let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+    let mut parsed = httparse::Request::new(&mut headers);
+    match parsed.parse(&head) {
+        Ok(httparse::Status::Complete(_)) => {}
+        Ok(httparse::Status::Partial) => return Err(HttpError::BadRequest("incomplete head")),
+        Err(httparse::Error::TooManyHeaders) => return Err(HttpError::HeadTooLarge),
+        Err(_) => return Err(HttpError::BadRequest("malformed head")),
+    }
+
+    let version = parsed.version.unwrap_or(1);
+    let mut request = HttpRequest {
+        method: parsed.method.unwrap_or_default().to_owned(),
+        target: parsed.path.unwrap_or_default().to_owned(),
+        headers: parsed
+            .headers
+            .iter()
+            .map(|h| (h.name.to_ascii_lowercase(), h.value.to_vec()))
+            .collect(),
+        body: Vec::new(),
+        version,
+        keep_alive: version == 1,
+    };
+
+    let mut keep_alive = None;
+    if let Some(connection) = request.header("connection") {
+        for token in connection.split(|&b| b == b',') {
+            let token = token.trim_ascii();
+            if token.eq_ignore_ascii_case(b"close") {
+                keep_alive = Some(false);
+            } else if token.eq_ignore_ascii_case(b"keep-alive") {
+                keep_alive = Some(true);
+            }
+        }
+    }
+    if let Some(keep_alive) = keep_alive {
+        request.keep_alive = keep_alive;
+    }
+
+    // Framing must be unambiguous, since the body boundary decides where the next
+    // request on this connection starts (RFC 9112 section 6.3)
+    let count = |name: &str| request.headers.iter().filter(|(n, _)| n == name).count();
+    let encodings = count("transfer-encoding");
+    let lengths = count("content-length");
+    if encodings > 0 && lengths > 0 {
+        return Err(HttpError::BadRequest(
+            "both transfer-encoding and content-length",
+        ));
+    }
+    if encodings > 1 || lengths > 1 {
+        return Err(HttpError::BadRequest("repeated framing header"));
+    }
+
+    if let Some(encoding) = request.header("transfer-encoding") {
+        if !encoding.trim_ascii().eq_ignore_ascii_case(b"chunked") {
+            return Err(HttpError::BadRequest("unsupported transfer-encoding"));
+        }
+        request.body = read_chunked(reader).await?;
+    } else if let Some(length) = request.header("content-length") {
+        let length = parse_content_length(length.trim_ascii())
+            .ok_or(HttpError::BadRequest("invalid content-length"))?;
+        if length > MAX_MESSAGE_SIZE {
+            return Err(HttpError::BodyTooLarge);
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).await?;
+        request.body = body;
+    }
+
+    Ok(Some(request))
+}
+
+/// Read a `transfer-encoding: chunked` body, discarding trailers
+async fn read_chunked<R: AsyncBufRead + Unpin>(reader: &mut R) -> Result<Vec<u8>, HttpError> {
+    let mut body = Vec::new();
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        (&mut *reader)
+            .take(1024)
+            .read_until(b'\n', &mut line)
+            .await?;
+        let size = line
+            .strip_suffix(b"\r\n")
+            .ok_or(HttpError::BadRequest("invalid chunk size line"))?;
+        let size = size.split(|&b| b == b';').next().unwrap_or_default();
+        let size = parse_chunk_size(size).ok_or(HttpError::BadRequest("invalid chunk size"))?;
+        if size == 0 {
+            break;
+        }
+        if body.len() + size > MAX_MESSAGE_SIZE {
+            return Err(HttpError::BodyTooLarge);
+        }
+        let start = body.len();
+        body.resize(start + size, 0);
+        reader.read_exact(&mut body[start..]).await?;
+        let mut crlf = [0u8; 2];
+        reader.read_exact(&mut crlf).await?;
+        if crlf != *b"\r\n" {
+            return Err(HttpError::BadRequest("chunk data not followed by CRLF"));
+        }
+    }
+    // Trailer section ends with an empty line
+    loop {
+        line.clear();
+        let n = (&mut *reader)
+            .take(MAX_HEAD_SIZE as u64)
+            .read_until(b'\n', &mut line)
+            .await?;
+        if n == 0 || line == b"\r\n" || line == b"\n" {
+            return Ok(body);
+        }
+    }
+}
+
+/// Parse a chunk size: hexadecimal digits only
+fn parse_chunk_size(bytes: &[u8]) -> Option<usize> {
+    if bytes.is_empty() {
+        return None;
+    }
+    bytes.iter().try_fold(0usize, |size, &b| {
+        let digit = (b as char).to_digit(16)?;
+        size.checked_mul(16)?.checked_add(digit as usize)
+    })
+}
+
+/// Write an HTTP/1.1 status line and headers
+pub async fn write_head<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    code: u16,
+    reason: &str,
+    headers: &[(String, Vec<u8>)],
+) -> io::Result<()> {
+    let mut head = format!("HTTP/1.1 {code} {reason}\r\n").into_bytes();
+    for (name, value) in headers {
+        head.extend_from_slice(name.as_bytes());
+        head.extend_from_slice(b": ");
+        head.extend_from_slice(value);
+        head.extend_from_slice(b"\r\n");
+    }
+    head.extend_from_slice(b"\r\n");
+    writer.write_all(&head).await
+}
+
+/// Write a short plain-text response generated by the proxy itself
+pub async fn write_error<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    code: u16,
+    reason: &str,
+    message: &str,
+    keep_alive: bool,
+) -> io::Result<()> {
+    let body = format!("{message}\n");
+    let mut headers = vec![
+        (
+            "content-type".to_owned(),
+            b"text/plain; charset=utf-8".to_vec(),
+        ),
+        (
+            "content-length".to_owned(),
+            body.len().to_string().into_bytes(),
+        ),
+    ];
+    if !keep_alive {
+        headers.push(("connection".to_owned(), b"close".to_vec()));
+    }
+    write_head(writer, code, reason, &headers).await?;
+    writer.write_all(body.as_bytes()).await?;
+    writer.flush().await
+}
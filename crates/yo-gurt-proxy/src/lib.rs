@@ -0,0 +1,19 @@
+//! HTTP-to-GURT forward proxy
+//!
+//! Lets ordinary HTTP tools reach `gurt://` sites. HTTP/1.1 requests are accepted locally,
+//! either in absolute form (`GET gurt://example.web/ HTTP/1.1`, as sent by
+//! `curl -x http://127.0.0.1:8080 gurt://example.web/`) or routed by their `host` header
+//! through a configured mapping. Each request is forwarded over a pooled, handshaken
+//! `GurtClient` connection and the response is relayed back with its body streamed.
+//!
+//! The binary is a thin wrapper: [`Config`](config::Config) parses its arguments,
+//! [`connect::connector`] and [`Pool`](pool::Pool) open upstream connections, and
+//! [`Proxy`](proxy::Proxy) serves each accepted client. A [`Proxy`](proxy::Proxy) works
+//! over any [`fetch::Connect`](portal_solutions_yo_gurt::fetch::Connect), so it can be
+//! tested against in-memory upstreams. Failures are reported through `tracing`.
+
+pub mod config;
+pub mod connect;
+pub mod http;
+pub mod pool;
+pub mod proxy;
@@ -0,0 +1,64 @@
+//! `yo-gurt-proxy`: accept HTTP/1.1 clients and forward their requests to GURT servers
+
+use std::sync::Arc;
+
+use portal_solutions_yo_gurt_proxy::config::{Config, USAGE};
+use portal_solutions_yo_gurt_proxy::connect;
+use portal_solutions_yo_gurt_proxy::pool::Pool;
+use portal_solutions_yo_gurt_proxy::proxy::Proxy;
+use tokio::net::TcpListener;
+use tracing::{info, warn};
+use tracing_subscriber::EnvFilter;
+
+#[tokio::main]
+async fn main() -> std::io::Result<()> {
+    let mut config = match Config::from_args(std::env::args().skip(1)) {
+        Ok(config) => config,
+        Err(e) if e.is_empty() => {
+            print!("{USAGE}");
+            return Ok(());
+        }
+        Err(e) => {
+            eprintln!("{e}\n\n{USAGE}");
+            std::process::exit(2);
+        }
+    };
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+        )
+        .with_writer(std::io::stderr)
+        .init();
+
+    let listener = TcpListener::bind(config.listen).await?;
+    info!(
+        "yo-gurt-proxy listening on http://{}",
+        listener.local_addr()?
+    );
+
+    let connector = connect::connector(
+        config.insecure,
+        std::mem::take(&mut config.extra_roots),
+        config.client_identity.take(),
+        config.hosts.take(),
+        config.dns.take(),
+    );
+    let connector = match connector {
+        Ok(connector) => connector,
+        Err(e) => {
+            eprintln!("invalid TLS settings: {e}");
+            std::process::exit(2);
+        }
+    };
+    let pool = Pool::new(connector, config.user_agent.clone());
+    let proxy = Arc::new(Proxy::new(config, pool));
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let proxy = proxy.clone();
+        tokio::spawn(async move {
+            if let Err(e) = proxy.serve(stream).await {
+                warn!(%peer, "client connection failed: {e}");
+            }
+        });
+    }
+}
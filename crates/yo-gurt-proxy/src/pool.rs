@@ -0,0 +1,125 @@
+//! Pool of idle, handshaken upstream connections
+//!
+//! From spec: "Maximum connection pool size: 10 connections" and
+//! "Pool idle timeout: 300 seconds"
+
+use std::collections::HashMap;
+use std::io;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use portal_solutions_yo_gurt::adapters::FromTokio;
+use portal_solutions_yo_gurt::fetch::Connect;
+use portal_solutions_yo_gurt::{
+    DEFAULT_CONNECTION_TIMEOUT_SECS, DEFAULT_HANDSHAKE_TIMEOUT_SECS, GurtClient, GurtError,
+    MAX_CONNECTION_POOL_SIZE, POOL_IDLE_TIMEOUT_SECS,
+};
+use tokio::io::BufReader;
+use tokio::time::timeout;
+
+use crate::connect::Authority;
+
+/// A handshaken upstream GURT connection
+pub type Upstream<T> = GurtClient<FromTokio<BufReader<T>>>;
+
+struct Idle<T> {
+    client: Upstream<T>,
+    since: Instant,
+}
+
+/// Connection checked out of the pool
+pub struct Pooled<T> {
+    pub client: Upstream<T>,
+    pub authority: Authority,
+    /// Whether the connection already served an earlier request
+    pub reused: bool,
+}
+
+/// Opens connections through `C` and keeps up to [`MAX_CONNECTION_POOL_SIZE`] idle ones
+/// per authority
+pub struct Pool<C: Connect> {
+    connector: C,
+    user_agent: String,
+    idle: Mutex<HashMap<Authority, Vec<Idle<C::Transport>>>>,
+}
+
+impl<C: Connect> Pool<C> {
+    /// A pool connecting through `connector` and sending `user_agent` in each handshake
+    pub fn new(connector: C, user_agent: String) -> Self {
+        Self {
+            connector,
+            user_agent,
+            idle: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The user agent sent in handshakes, and with requests that did not bring their own
+    pub fn user_agent(&self) -> &str {
+        &self.user_agent
+    }
+
+    /// Take an idle connection to `authority`, or open a new one
+    pub async fn checkout(
+        &self,
+        authority: &Authority,
+    ) -> Result<Pooled<C::Transport>, GurtError<io::Error>> {
+        if let Some(client) = self.take_idle(authority) {
+            return Ok(Pooled {
+                client,
+                authority: authority.clone(),
+                reused: true,
+            });
+        }
+        self.connect(authority).await
+    }
+
+    /// Always open a new connection to `authority` and complete the GURT handshake
+    pub async fn connect(
+        &self,
+        authority: &Authority,
+    ) -> Result<Pooled<C::Transport>, GurtError<io::Error>> {
+        let transport = timeout(
+            Duration::from_secs(DEFAULT_CONNECTION_TIMEOUT_SECS.into()),
+            self.connector.connect(&authority.host, authority.port),
+        )
+        .await
+        .map_err(|_| GurtError::Timeout)?
+        .map_err(GurtError::Io)?;
+        let mut client = GurtClient::new(FromTokio(BufReader::new(transport)));
+        let handshake = async {
+            client.handshake(&authority.host, &self.user_agent).await?;
+            client.read_handshake_response(&mut [0u8; 1024]).await
+        };
+        timeout(
+            Duration::from_secs(DEFAULT_HANDSHAKE_TIMEOUT_SECS.into()),
+            handshake,
+        )
+        .await
+        .map_err(|_| GurtError::Timeout)??;
+        Ok(Pooled {
+            client,
+            authority: authority.clone(),
+            reused: false,
+        })
+    }
+
+    /// Return a connection whose last response was fully read
+    pub fn checkin(&self, pooled: Pooled<C::Transport>) {
+        let mut idle = self.idle.lock().unwrap();
+        let entries = idle.entry(pooled.authority).or_default();
+        if entries.len() < MAX_CONNECTION_POOL_SIZE {
+            entries.push(Idle {
+                client: pooled.client,
+                since: Instant::now(),
+            });
+        }
+    }
+
+    fn take_idle(&self, authority: &Authority) -> Option<Upstream<C::Transport>> {
+        let timeout = Duration::from_secs(POOL_IDLE_TIMEOUT_SECS.into());
+        let mut idle = self.idle.lock().unwrap();
+        let entries = idle.get_mut(authority)?;
+        entries.retain(|entry| entry.since.elapsed() < timeout);
+        entries.pop().map(|entry| entry.client)
+    }
+}
@@ -0,0 +1,387 @@
+//! Request forwarding from HTTP clients to GURT servers
+
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+use embedded_io_async::Read;
+use portal_solutions_yo_gurt::fetch::{Connect, Connector};
+use portal_solutions_yo_gurt::{DEFAULT_REQUEST_TIMEOUT_SECS, GurtError, Method, StatusCode};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+use tracing::warn;
+
+use crate::config::Config;
+use crate::connect::{Authority, UpstreamResolver};
+use crate::http::{self, HOP_BY_HOP, HttpError, HttpRequest};
+use crate::pool::{Pool, Pooled};
+
+/// Size of the buffer used for GURT header lines and body chunks
+const BUF_SIZE: usize = 16 * 1024;
+
+/// Forwards HTTP requests to GURT servers over connections from a [`Pool`]
+pub struct Proxy<C: Connect = Connector<UpstreamResolver>> {
+    config: Config,
+    pool: Pool<C>,
+}
+
+/// Status line and headers of an upstream response
+struct UpstreamHead {
+    status: StatusCode,
+    headers: Vec<(String, Vec<u8>)>,
+    content_length: Option<usize>,
+}
+
+/// Errors talking to the upstream GURT server
+type UpstreamError = GurtError<io::Error>;
+
+/// Whether the error suggests a pooled connection was closed while idle
+fn is_stale(e: &UpstreamError) -> bool {
+    matches!(e, GurtError::Io(_) | GurtError::UnexpectedEof)
+}
+
+/// HTTP status and reason reported to the client for an upstream failure
+fn gateway_status(e: &UpstreamError) -> (u16, &'static str) {
+    match e {
+        GurtError::Timeout => (504, "Gateway Timeout"),
+        _ => (502, "Bad Gateway"),
+    }
+}
+
+/// Describe an upstream failure, showing I/O errors by their message
+fn describe(e: &UpstreamError) -> String {
+    match e {
+        GurtError::Io(e) => format!("upstream I/O error: {e}"),
+        e => e.to_string(),
+    }
+}
+
+/// Convert an upstream error met while streaming a body to the client
+fn into_io(e: UpstreamError) -> io::Error {
+    match e {
+        GurtError::Io(e) => e,
+        GurtError::UnexpectedEof => {
+            io::Error::new(io::ErrorKind::UnexpectedEof, "upstream closed mid-body")
+        }
+        e => io::Error::other(e.to_string()),
+    }
+}
+
+impl<C: Connect> Proxy<C> {
+    /// A proxy routing by `config` and forwarding through `pool`
+    pub fn new(config: Config, pool: Pool<C>) -> Self {
+        Self { config, pool }
+    }
+
+    /// Serve HTTP requests from one client connection until it closes
+    pub async fn serve(self: Arc<Self>, stream: TcpStream) -> io::Result<()> {
+        let mut stream = BufReader::new(stream);
+        loop {
+            let request = match http::read_request(&mut stream).await {
+                Ok(Some(request)) => request,
+                Ok(None) => return Ok(()),
+                Err(HttpError::Io(e)) => return Err(e),
+                Err(e) => {
+                    let (code, reason) = match e {
+                        HttpError::HeadTooLarge => (431, "Request Header Fields Too Large"),
+                        HttpError::BodyTooLarge => (413, "Payload Too Large"),
+                        _ => (400, "Bad Request"),
+                    };
+                    let out = stream.get_mut();
+                    return http::write_error(out, code, reason, &e.to_string(), false).await;
+                }
+            };
+            let keep_alive = request.keep_alive;
+            if !self.handle(request, stream.get_mut()).await? || !keep_alive {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Handle one request, writing the response to `out`; returns whether the client
+    /// connection can be reused, which it cannot after a body ended by closing it
+    ///
+    /// Routing and upstream failures are answered with an error response rather than
+    /// returned; only failures writing to `out` are.
+    pub async fn handle<W: AsyncWrite + Unpin>(
+        &self,
+        request: HttpRequest,
+        out: &mut W,
+    ) -> io::Result<bool> {
+        let keep_alive = request.keep_alive;
+        // HTTP/1.0 clients know no chunks, so a body of unknown length ends with the
+        // connection instead
+        let chunked = request.version >= 1;
+        let Some((authority, path)) = self.route(&request) else {
+            let message = "target must be a gurt:// URL or a mapped host";
+            http::write_error(out, 400, "Bad Request", message, keep_alive).await?;
+            return Ok(keep_alive);
+        };
+        let method = match Method::from_bytes(request.method.as_bytes()) {
+            Some(Method::Handshake) => {
+                let message = "HANDSHAKE is performed by the proxy";
+                http::write_error(out, 405, "Method Not Allowed", message, keep_alive).await?;
+                return Ok(keep_alive);
+            }
+            Some(method) => method,
+            None => {
+                let message = "method not supported by GURT";
+                http::write_error(out, 501, "Not Implemented", message, keep_alive).await?;
+                return Ok(keep_alive);
+            }
+        };
+
+        let (mut pooled, head) = match self.exchange(&authority, method, &path, &request).await {
+            Ok(result) => result,
+            Err(e) => {
+                let message = describe(&e);
+                warn!(
+                    method = %request.method,
+                    target = %request.target,
+                    upstream = %format_args!("gurt://{authority}{path}"),
+                    "upstream request failed: {message}"
+                );
+                let (code, reason) = gateway_status(&e);
+                http::write_error(out, code, reason, &message, keep_alive).await?;
+                return Ok(keep_alive);
+            }
+        };
+
+        let has_body = method != Method::Head
+            && head.status != StatusCode::NoContent
+            && head.status != StatusCode::NotModified
+            && head.status.as_u16() >= 200;
+        let mut headers: Vec<(String, Vec<u8>)> = head
+            .headers
+            .into_iter()
+            .filter(|(name, _)| name != "content-length" && !HOP_BY_HOP.contains(&name.as_str()))
+            .collect();
+        let until_close = has_body && head.content_length.is_none() && !chunked;
+        match head.content_length {
+            Some(length) => headers.push(("content-length".into(), length.to_string().into())),
+            None if has_body && chunked => {
+                headers.push(("transfer-encoding".into(), b"chunked".to_vec()))
+            }
+            None => {}
+        }
+        if !keep_alive || until_close {
+            headers.push(("connection".into(), b"close".to_vec()));
+        }
+        headers.push(("via".into(), b"1.1 yo-gurt-proxy".to_vec()));
+        let reason = http_reason(head.status);
+        http::write_head(out, head.status.as_u16(), &reason, &headers).await?;
+
+        let reusable = if !has_body {
+            // Bytes a content-length announced may follow anyway, and would be read as the
+            // next response
+            head.content_length.unwrap_or(0) == 0
+        } else if let Some(length) = head.content_length {
+            relay_body(&mut pooled, length, out).await?;
+            true
+        } else {
+            relay_until_eof(&mut pooled, chunked, out).await?;
+            false
+        };
+        out.flush().await?;
+        if reusable {
+            self.pool.checkin(pooled);
+        }
+        Ok(!until_close)
+    }
+
+    /// Find the GURT server and path for an HTTP request target
+    fn route(&self, request: &HttpRequest) -> Option<(Authority, String)> {
+        let target = request.target.as_str();
+        if let Some(rest) = target.strip_prefix("gurt://") {
+            let (authority, path) = split_authority(rest);
+            return Some((Authority::parse(authority)?, path));
+        }
+        if let Some(rest) = target.strip_prefix("http://") {
+            let (host, path) = split_authority(rest);
+            return Some((self.config.mapping(host)?.clone(), path));
+        }
+        if target.starts_with('/') {
+            let host = std::str::from_utf8(request.header("host")?).ok()?;
+            return Some((self.config.mapping(host)?.clone(), target.to_owned()));
+        }
+        None
+    }
+
+    /// Send the request upstream and read the response head, retrying once on a fresh
+    /// connection if a pooled one turns out to be closed
+    ///
+    /// Only idempotent methods are retried: the server may have read the whole request
+    /// before the connection failed, and a `POST` must not reach it twice.
+    async fn exchange(
+        &self,
+        authority: &Authority,
+        method: Method,
+        path: &str,
+        request: &HttpRequest,
+    ) -> Result<(Pooled<C::Transport>, UpstreamHead), UpstreamError> {
+        let mut pooled = self.pool.checkout(authority).await?;
+        let result = self.forward(&mut pooled, method, path, request).await;
+        let head = match result {
+            Err(e) if pooled.reused && method.is_idempotent() && is_stale(&e) => {
+                pooled = self.pool.connect(authority).await?;
+                self.forward(&mut pooled, method, path, request).await?
+            }
+            result => result?,
+        };
+        Ok((pooled, head))
+    }
+
+    async fn forward(
+        &self,
+        pooled: &mut Pooled<C::Transport>,
+        method: Method,
+        path: &str,
+        request: &HttpRequest,
+    ) -> Result<UpstreamHead, UpstreamError> {
+        let connection_tokens: Vec<String> = request
+            .header("connection")
+            .map(|v| {
+                String::from_utf8_lossy(v)
+                    .split(',')
+                    .map(|t| t.trim().to_ascii_lowercase())
+                    .collect()
+            })
+            .unwrap_or_default();
+        let headers: Vec<(&str, &str)> = request
+            .headers
+            .iter()
+            .filter(|(name, _)| {
+                !matches!(name.as_str(), "host" | "content-length" | "user-agent")
+                    && !HOP_BY_HOP.contains(&name.as_str())
+                    && !connection_tokens.contains(name)
+            })
+            .filter_map(|(name, value)| Some((name.as_str(), std::str::from_utf8(value).ok()?)))
+            .collect();
+        let user_agent = request
+            .header("user-agent")
+            .and_then(|ua| std::str::from_utf8(ua).ok())
+            .unwrap_or(self.pool.user_agent());
+        let content_length = match method {
+            Method::Post | Method::Put | Method::Patch => Some(request.body.len()),
+            _ if !request.body.is_empty() => Some(request.body.len()),
+            _ => None,
+        };
+
+        let host = pooled.authority.host.clone();
+        let client = &mut pooled.client;
+        let exchange = async {
+            let mut writer = client
+                .request(
+                    method,
+                    path,
+                    &host,
+                    Some(user_agent),
+                    &headers,
+                    content_length,
+                )
+                .await?;
+            writer.write(&request.body).await?;
+            writer.finish().await?;
+
+            let mut response = client.response_reader();
+            let mut buf = vec![0; BUF_SIZE];
+            let status = response.read_status_line(&mut buf).await?.status;
+            let mut headers = Vec::new();
+            while let Some(header) = response.read_header(&mut buf).await? {
+                let name = String::from_utf8_lossy(header.name(&buf)).to_ascii_lowercase();
+                headers.push((name, header.value(&buf).to_vec()));
+            }
+            Ok(UpstreamHead {
+                status,
+                headers,
+                content_length: response.content_length(),
+            })
+        };
+        timeout(
+            Duration::from_secs(DEFAULT_REQUEST_TIMEOUT_SECS.into()),
+            exchange,
+        )
+        .await
+        .map_err(|_| GurtError::Timeout)?
+    }
+}
+
+/// Stream a `content-length` delimited body
+async fn relay_body<T: AsyncRead + AsyncWrite + Unpin, W: AsyncWrite + Unpin>(
+    pooled: &mut Pooled<T>,
+    length: usize,
+    out: &mut W,
+) -> io::Result<()> {
+    let mut body = pooled.client.response_reader().body(length);
+    let mut buf = vec![0; BUF_SIZE];
+    while !body.is_done() {
+        let n = read_timeout(body.read(&mut buf)).await?;
+        out.write_all(&buf[..n]).await?;
+    }
+    Ok(())
+}
+
+/// Stream a body without `content-length` until the upstream closes, as HTTP chunks if
+/// `chunked` and as is otherwise
+async fn relay_until_eof<T: AsyncRead + AsyncWrite + Unpin, W: AsyncWrite + Unpin>(
+    pooled: &mut Pooled<T>,
+    chunked: bool,
+    out: &mut W,
+) -> io::Result<()> {
+    let mut response = pooled.client.response_reader();
+    let mut buf = vec![0; BUF_SIZE];
+    loop {
+        let n = read_timeout(response.read_body(&mut buf)).await?;
+        if n == 0 && chunked {
+            return out.write_all(b"0\r\n\r\n").await;
+        } else if n == 0 {
+            return Ok(());
+        }
+        if chunked {
+            out.write_all(format!("{n:x}\r\n").as_bytes()).await?;
+        }
+        out.write_all(&buf[..n]).await?;
+        if chunked {
+            out.write_all(b"\r\n").await?;
+        }
+    }
+}
+
+async fn read_timeout(
+    read: impl Future<Output = Result<usize, UpstreamError>>,
+) -> io::Result<usize> {
+    timeout(
+        Duration::from_secs(DEFAULT_REQUEST_TIMEOUT_SECS.into()),
+        read,
+    )
+    .await
+    .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "upstream body timed out"))?
+    .map_err(into_io)
+}
+
+/// Split `authority/path?query` into the authority and an origin-form path
+fn split_authority(rest: &str) -> (&str, String) {
+    match rest.find(['/', '?']) {
+        Some(i) if rest.as_bytes()[i] == b'?' => (&rest[..i], format!("/{}", &rest[i..])),
+        Some(i) => (&rest[..i], rest[i..].to_owned()),
+        None => (rest, "/".to_owned()),
+    }
+}
+
+/// HTTP reason phrase from a GURT one, e.g. `NOT_FOUND` becomes `Not Found`
+fn http_reason(status: StatusCode) -> String {
+    if status == StatusCode::Ok {
+        return "OK".to_owned();
+    }
+    status
+        .reason_phrase()
+        .split('_')
+        .map(|word| {
+            let mut word = word.to_ascii_lowercase();
+            word[..1].make_ascii_uppercase();
+            word
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -0,0 +1,346 @@
+//! Routing, translation and upstream handling, against scripted in-memory GURT servers
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io;
+use std::rc::Rc;
+
+use portal_solutions_yo_gurt::fetch::Connect;
+use portal_solutions_yo_gurt::testing::{DuplexStream, MockPeer, Reply, duplex};
+use portal_solutions_yo_gurt::{DEFAULT_PORT, Method, StatusCode};
+use portal_solutions_yo_gurt_proxy::config::Config;
+use portal_solutions_yo_gurt_proxy::http;
+use portal_solutions_yo_gurt_proxy::pool::Pool;
+use portal_solutions_yo_gurt_proxy::proxy::Proxy;
+
+const USER_AGENT: &str = "yo-gurt-proxy/0.1";
+
+/// Hands out queued in-memory connections in order, recording where each one was for
+#[derive(Clone, Default)]
+struct Upstreams {
+    ends: Rc<RefCell<VecDeque<DuplexStream>>>,
+    connects: Rc<RefCell<Vec<String>>>,
+}
+
+impl Upstreams {
+    /// Queue a connection for the next connect, returning the server's end
+    fn queue(&self) -> DuplexStream {
+        let (client, server) = duplex(4096);
+        self.ends.borrow_mut().push_back(client);
+        server
+    }
+
+    fn connects(&self) -> Vec<String> {
+        self.connects.borrow().clone()
+    }
+}
+
+impl Connect for Upstreams {
+    type Transport = DuplexStream;
+
+    async fn connect(&self, host: &str, port: u16) -> io::Result<DuplexStream> {
+        self.connects.borrow_mut().push(format!("{host}:{port}"));
+        self.ends
+            .borrow_mut()
+            .pop_front()
+            .ok_or_else(|| io::Error::from(io::ErrorKind::ConnectionRefused))
+    }
+}
+
+fn proxy(args: &[&str]) -> (Proxy<Upstreams>, Upstreams) {
+    let config = Config::from_args(args.iter().map(|arg| arg.to_string())).unwrap();
+    let upstreams = Upstreams::default();
+    let pool = Pool::new(upstreams.clone(), USER_AGENT.into());
+    (Proxy::new(config, pool), upstreams)
+}
+
+/// Read `raw` as an HTTP request and handle it, returning what the proxy wrote back
+async fn send(proxy: &Proxy<Upstreams>, raw: &str) -> String {
+    let request = http::read_request(&mut raw.as_bytes())
+        .await
+        .unwrap()
+        .unwrap();
+    let mut out = Vec::new();
+    assert!(proxy.handle(request, &mut out).await.unwrap());
+    String::from_utf8(out).unwrap()
+}
+
+fn handshake(host: &str) -> MockPeer {
+    MockPeer::new()
+        .expect_handshake(host, USER_AGENT)
+        .reply(Reply::new(StatusCode::SwitchingProtocols))
+}
+
+#[tokio::test]
+async fn absolute_targets_and_header_filtering() {
+    let (proxy, upstreams) = proxy(&[]);
+    let server = handshake("example.web")
+        .expect_request(
+            Method::Get,
+            "/?q=1",
+            &[
+                ("host", "example.web"),
+                ("accept", "text/plain"),
+                ("user-agent", "curl/8"),
+            ],
+            b"",
+        )
+        .reply(
+            Reply::new(StatusCode::NotFound)
+                .header("keep-alive", "timeout=5")
+                .header("x-up", "1")
+                .body(b"missing"),
+        )
+        .expect_request(
+            Method::Post,
+            "/items",
+            &[
+                ("host", "example.web"),
+                ("content-length", "4"),
+                ("user-agent", USER_AGENT),
+            ],
+            b"data",
+        )
+        .reply(Reply::new(StatusCode::Created).body(b"ok"))
+        .run(upstreams.queue());
+    let client = async {
+        // Hop-by-hop headers, and those the connection header names, stay on this hop
+        let response = send(
+            &proxy,
+            "GET gurt://Example.Web?q=1 HTTP/1.1\r\nhost: ignored\r\n\
+             connection: keep-alive, x-hop\r\nx-hop: 1\r\nkeep-alive: 5\r\n\
+             proxy-authorization: secret\r\naccept: text/plain\r\nuser-agent: curl/8\r\n\r\n",
+        )
+        .await;
+        assert_eq!(
+            response,
+            "HTTP/1.1 404 Not Found\r\nx-up: 1\r\ncontent-length: 7\r\n\
+             via: 1.1 yo-gurt-proxy\r\n\r\nmissing"
+        );
+        // A chunked body is forwarded with content-length, on the pooled connection
+        let response = send(
+            &proxy,
+            "POST gurt://example.web/items HTTP/1.1\r\ntransfer-encoding: chunked\r\n\
+             connection: close\r\n\r\n4\r\ndata\r\n0\r\n\r\n",
+        )
+        .await;
+        assert_eq!(
+            response,
+            "HTTP/1.1 201 Created\r\ncontent-length: 2\r\nconnection: close\r\n\
+             via: 1.1 yo-gurt-proxy\r\n\r\nok"
+        );
+    };
+    tokio::join!(client, server);
+    assert_eq!(
+        upstreams.connects(),
+        [format!("example.web:{DEFAULT_PORT}")]
+    );
+}
+
+#[tokio::test]
+async fn mapped_hosts_and_streamed_bodies() {
+    let (proxy, upstreams) = proxy(&["--map", "site.local=gurt://example.web:4000"]);
+    // Without content-length the body runs to the end of the connection
+    let streamed = handshake("example.web")
+        .expect_request(
+            Method::Get,
+            "/page",
+            &[("host", "example.web"), ("user-agent", USER_AGENT)],
+            b"",
+        )
+        .send(b"GURT/1.0.0 200 OK\r\ncontent-type: text/plain\r\n\r\nstreamed")
+        .run(upstreams.queue());
+    let next = handshake("example.web")
+        .expect_request(
+            Method::Head,
+            "/x",
+            &[("host", "example.web"), ("user-agent", USER_AGENT)],
+            b"",
+        )
+        .reply(Reply::new(StatusCode::Ok).header("content-length", "100"))
+        .run(upstreams.queue());
+    let client = async {
+        let response = send(
+            &proxy,
+            "GET /page HTTP/1.1\r\nhost: site.local:8080\r\n\r\n",
+        )
+        .await;
+        assert_eq!(
+            response,
+            "HTTP/1.1 200 OK\r\ncontent-type: text/plain\r\ntransfer-encoding: chunked\r\n\
+             via: 1.1 yo-gurt-proxy\r\n\r\n8\r\nstreamed\r\n0\r\n\r\n"
+        );
+        // That connection was used up, so this one is new; HEAD has no body to relay
+        let response = send(&proxy, "HEAD http://SITE.local/x HTTP/1.1\r\n\r\n").await;
+        assert_eq!(
+            response,
+            "HTTP/1.1 200 OK\r\ncontent-length: 100\r\nvia: 1.1 yo-gurt-proxy\r\n\r\n"
+        );
+    };
+    tokio::join!(client, streamed, next);
+    assert_eq!(
+        upstreams.connects(),
+        ["example.web:4000", "example.web:4000"]
+    );
+
+    // Requests the proxy answers itself, without connecting
+    for (request, status) in [
+        (
+            "GET /page HTTP/1.1\r\nhost: other.local\r\n\r\n",
+            "400 Bad Request",
+        ),
+        ("GET ftp://site.local/ HTTP/1.1\r\n\r\n", "400 Bad Request"),
+        (
+            "HANDSHAKE gurt://example.web/ HTTP/1.1\r\n\r\n",
+            "405 Method Not Allowed",
+        ),
+        (
+            "TRACE gurt://example.web/ HTTP/1.1\r\n\r\n",
+            "501 Not Implemented",
+        ),
+    ] {
+        let response = send(&proxy, request).await;
+        assert!(
+            response.starts_with(&format!("HTTP/1.1 {status}\r\n")),
+            "{response}"
+        );
+    }
+    assert_eq!(upstreams.connects().len(), 2);
+}
+
+#[tokio::test]
+async fn http_1_0_clients() {
+    let (proxy, upstreams) = proxy(&[]);
+    let server = handshake("example.web")
+        .expect_request(
+            Method::Get,
+            "/page",
+            &[("host", "example.web"), ("user-agent", USER_AGENT)],
+            b"",
+        )
+        .send(b"GURT/1.0.0 200 OK\r\n\r\nstreamed")
+        .run(upstreams.queue());
+    let client = async {
+        let raw = "GET gurt://example.web/page HTTP/1.0\r\nconnection: keep-alive\r\n\r\n";
+        let request = http::read_request(&mut raw.as_bytes())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(request.version, 0);
+        let mut out = Vec::new();
+        // Without chunks the body can only end with the connection
+        assert!(!proxy.handle(request, &mut out).await.unwrap());
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "HTTP/1.1 200 OK\r\nconnection: close\r\nvia: 1.1 yo-gurt-proxy\r\n\r\nstreamed"
+        );
+    };
+    tokio::join!(client, server);
+}
+
+#[tokio::test]
+async fn closed_connections_and_bad_gateways() {
+    let (proxy, upstreams) = proxy(&[]);
+    let first = handshake("example.web")
+        .expect_request(
+            Method::Get,
+            "/a",
+            &[("host", "example.web"), ("user-agent", USER_AGENT)],
+            b"",
+        )
+        .reply(Reply::new(StatusCode::Ok).body(b"a"))
+        .run(upstreams.queue());
+    let second = handshake("example.web")
+        .expect_request(
+            Method::Get,
+            "/b",
+            &[("host", "example.web"), ("user-agent", USER_AGENT)],
+            b"",
+        )
+        .reply(Reply::new(StatusCode::Ok).body(b"b"))
+        .run(upstreams.queue());
+    let client = async {
+        assert!(
+            send(&proxy, "GET gurt://example.web/a HTTP/1.1\r\n\r\n")
+                .await
+                .ends_with("\r\n\r\na")
+        );
+        // The pooled connection was closed by the server: GET is sent again on a new one
+        assert!(
+            send(&proxy, "GET gurt://example.web/b HTTP/1.1\r\n\r\n")
+                .await
+                .ends_with("\r\n\r\nb")
+        );
+        // but POST is not, since the server may already have acted on it
+        let response = send(
+            &proxy,
+            "POST gurt://example.web/c HTTP/1.1\r\ncontent-length: 1\r\n\r\nx",
+        )
+        .await;
+        assert!(
+            response.starts_with("HTTP/1.1 502 Bad Gateway\r\n"),
+            "{response}"
+        );
+    };
+    tokio::join!(client, first, second);
+    assert_eq!(upstreams.connects().len(), 2);
+
+    // Nothing listening
+    let response = send(&proxy, "GET gurt://example.web/d HTTP/1.1\r\n\r\n").await;
+    assert!(
+        response.starts_with("HTTP/1.1 502 Bad Gateway\r\n"),
+        "{response}"
+    );
+}
+
+#[tokio::test]
+async fn bodiless_responses_with_a_length() {
+    let (proxy, upstreams) = proxy(&[]);
+    // Kept open, so that a reused connection would not be retried as closed
+    let mut end = upstreams.queue();
+    let padded = handshake("example.web")
+        .expect_request(
+            Method::Get,
+            "/a",
+            &[("host", "example.web"), ("user-agent", USER_AGENT)],
+            b"",
+        )
+        .send(b"GURT/1.0.0 304 NOT_MODIFIED\r\ncontent-length: 5\r\n\r\nstray")
+        .run(&mut end);
+    let next = handshake("example.web")
+        .expect_request(
+            Method::Get,
+            "/b",
+            &[("host", "example.web"), ("user-agent", USER_AGENT)],
+            b"",
+        )
+        .reply(Reply::new(StatusCode::Ok).body(b"b"))
+        .run(upstreams.queue());
+    let client = async {
+        let response = send(&proxy, "GET gurt://example.web/a HTTP/1.1\r\n\r\n").await;
+        assert!(
+            response.starts_with("HTTP/1.1 304 Not Modified\r\n") && response.ends_with("\r\n\r\n"),
+            "{response}"
+        );
+        // The connection may still hold the unread body, so it is not reused
+        let response = send(&proxy, "GET gurt://example.web/b HTTP/1.1\r\n\r\n").await;
+        assert!(response.ends_with("\r\n\r\nb"), "{response}");
+    };
+    tokio::join!(client, padded, next);
+    assert_eq!(upstreams.connects().len(), 2);
+}
+
+#[tokio::test(start_paused = true)]
+async fn upstream_timeout() {
+    let (proxy, upstreams) = proxy(&[]);
+    let mut server = upstreams.queue();
+    let client = async {
+        let response = send(&proxy, "GET gurt://example.web/slow HTTP/1.1\r\n\r\n").await;
+        assert!(
+            response.starts_with("HTTP/1.1 504 Gateway Timeout\r\n"),
+            "{response}"
+        );
+    };
+    // The server reads the request but never answers
+    tokio::join!(client, handshake("example.web").run(&mut server));
+}
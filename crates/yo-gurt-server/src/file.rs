@@ -0,0 +1,98 @@
+//! Serving files, whole or by byte range
+
+use std::io::{self, SeekFrom};
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use portal_solutions_yo_gurt::range::{ByteRange, ContentRange};
+use portal_solutions_yo_gurt::{MAX_MESSAGE_SIZE, StatusCode};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+use crate::{Request, Response};
+
+impl Response {
+    /// The file at `path`, or the byte range of it that `request` asks for
+    ///
+    /// Answers `200 OK` with the whole file, `206 PARTIAL_CONTENT` with a `content-range`
+    /// for a satisfiable `range`, and `416 RANGE_NOT_SATISFIABLE` with `content-range:
+    /// bytes */<length>` otherwise. Every answer carries `accept-ranges: bytes` and an
+    /// `etag` made from the file's length and modification time; a `range` with an
+    /// `if-range` naming another `etag` gets the whole file, and an `if-none-match` naming
+    /// this one gets `304 NOT_MODIFIED`.
+    ///
+    /// A message holds at most [`MAX_MESSAGE_SIZE`] bytes, so longer ranges are cut short
+    /// (the `content-range` says so) and larger files can only be fetched by range: asked
+    /// for whole, they are answered with `413 TOO_LARGE`. A missing file is `404
+    /// NOT_FOUND`, and one that cannot be read `500 INTERNAL_SERVER_ERROR`.
+    pub async fn file(request: &Request, path: impl AsRef<Path>) -> Self {
+        match serve_file(request, path.as_ref()).await {
+            Ok(response) => response,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Response::new(StatusCode::NotFound),
+            Err(_) => Response::new(StatusCode::InternalServerError),
+        }
+    }
+}
+
+async fn serve_file(request: &Request, path: &Path) -> io::Result<Response> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let metadata = file.metadata().await?;
+    if !metadata.is_file() {
+        return Err(io::ErrorKind::NotFound.into());
+    }
+    let length = metadata.len();
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |time| time.as_nanos());
+    let etag = format!("\"{length:x}-{modified:x}\"");
+    let response = |status| {
+        Response::new(status)
+            .header("accept-ranges", "bytes")
+            .header("etag", etag.clone())
+    };
+
+    let not_modified = request.header("if-none-match").is_some_and(|tags| {
+        tags.split(',')
+            .any(|tag| tag.trim() == "*" || tag.trim() == etag)
+    });
+    if not_modified {
+        return Ok(response(StatusCode::NotModified));
+    }
+    let range = request
+        .header("range")
+        .and_then(ByteRange::parse)
+        .filter(|_| request.header("if-range").is_none_or(|tag| tag == etag));
+    let (status, first, last) = match range.map(|range| range.resolve(length)) {
+        Some(None) => {
+            let unsatisfiable = ContentRange {
+                range: None,
+                complete_length: Some(length),
+            };
+            return Ok(response(StatusCode::RangeNotSatisfiable)
+                .header("content-range", unsatisfiable.to_string()));
+        }
+        Some(Some((first, last))) => {
+            let last = last.min(first + MAX_MESSAGE_SIZE as u64 - 1);
+            (StatusCode::PartialContent, first, last)
+        }
+        None if length > MAX_MESSAGE_SIZE as u64 => return Ok(response(StatusCode::TooLarge)),
+        None if length == 0 => return Ok(response(StatusCode::Ok)),
+        None => (StatusCode::Ok, 0, length - 1),
+    };
+
+    let mut body = vec![0; (last - first + 1) as usize];
+    file.seek(SeekFrom::Start(first)).await?;
+    file.read_exact(&mut body).await?;
+    let response = match status {
+        StatusCode::PartialContent => {
+            let content_range = ContentRange {
+                range: Some((first, last)),
+                complete_length: Some(length),
+            };
+            response(status).header("content-range", content_range.to_string())
+        }
+        _ => response(status),
+    };
+    Ok(response.body(body))
+}
@@ -0,0 +1,227 @@
+//! Requests whose body is read from the connection as the handler consumes it
+
+use std::io;
+
+use embedded_io_async::{ErrorType, Read};
+use portal_solutions_yo_gurt::encoding::DecodedBody;
+use portal_solutions_yo_gurt::form::{MultipartReader, UrlEncodedReader, multipart_boundary};
+use portal_solutions_yo_gurt::{GurtError, Method, StatusCode};
+use tokio::time::{Instant, timeout_at};
+
+use crate::tls::PeerIdentity;
+use crate::{Request, Response, Transport};
+
+/// A request as passed to a [`Handler`](crate::Handler): the head has been read, the body
+/// has not
+///
+/// The body is read with [`body`](Self::body), or parsed as it arrives with
+/// [`form`](Self::form) and [`multipart`](Self::multipart). [`buffer`](Self::buffer)
+/// reads it whole into a [`Request`] instead.
+///
+/// ```rust,ignore
+/// let mut buf = [0u8; 4096];
+/// let mut parts = match request.multipart(&mut buf) {
+///     Ok(parts) => parts,
+///     Err(response) => return response,
+/// };
+/// while let Ok(Some(part)) = parts.next_part().await {
+///     let mut file = File::create(part.filename().unwrap_or("upload")).await.unwrap();
+///     let mut chunk = [0u8; 4096];
+///     while let Ok(n @ 1..) = parts.read(&mut chunk).await {
+///         file.write_all(&chunk[..n]).await.unwrap();
+///     }
+/// }
+/// ```
+pub struct Incoming<'a> {
+    head: Request,
+    body: RequestBody<'a>,
+}
+
+impl<'a> Incoming<'a> {
+    pub(crate) fn new(head: Request, body: DecodedBody<'a, Transport>, deadline: Instant) -> Self {
+        Self {
+            head,
+            body: RequestBody(Source::Connection {
+                body,
+                deadline,
+                error: None,
+            }),
+        }
+    }
+
+    pub(crate) fn buffered(head: Request, body: &'a [u8]) -> Self {
+        Self {
+            head,
+            body: RequestBody(Source::Buffered(body)),
+        }
+    }
+
+    /// The request method
+    pub fn method(&self) -> Method {
+        self.head.method()
+    }
+
+    /// The request path, as sent on the method line
+    pub fn path(&self) -> &str {
+        self.head.path()
+    }
+
+    /// All headers in the order received, with lowercase names
+    pub fn headers(&self) -> &[(String, String)] {
+        self.head.headers()
+    }
+
+    /// The first value of header `name` (lowercase)
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.head.header(name)
+    }
+
+    /// The verified client certificate, if the client presented one
+    pub fn peer(&self) -> Option<&PeerIdentity> {
+        self.head.peer()
+    }
+
+    /// Check the client certificate before handling the request; see
+    /// [`Request::authorize`]
+    pub fn authorize(
+        &self,
+        allow: impl FnOnce(&PeerIdentity) -> bool,
+    ) -> Result<&PeerIdentity, Response> {
+        self.head.authorize(allow)
+    }
+
+    /// The body, decoded according to its `content-encoding`
+    pub fn body(&mut self) -> &mut RequestBody<'a> {
+        &mut self.body
+    }
+
+    /// The body as a URL-encoded form, parsed one field at a time into `buf`
+    ///
+    /// Returns the response to send instead when the `content-type` is not
+    /// `application/x-www-form-urlencoded`: `415 UNSUPPORTED_MEDIA_TYPE`.
+    pub fn form<'b>(
+        &'b mut self,
+        buf: &'b mut [u8],
+    ) -> Result<UrlEncodedReader<'b, &'b mut RequestBody<'a>>, Response> {
+        self.head.expect_urlencoded()?;
+        Ok(UrlEncodedReader::new(&mut self.body, buf))
+    }
+
+    /// The body as a `multipart/form-data` form, parsed one part at a time with each
+    /// part's head held in `buf`
+    ///
+    /// Returns the response to send instead when the `content-type` is not a multipart
+    /// form: `415 UNSUPPORTED_MEDIA_TYPE`.
+    pub fn multipart<'b>(
+        &'b mut self,
+        buf: &'b mut [u8],
+    ) -> Result<MultipartReader<'b, &'b mut RequestBody<'a>>, Response> {
+        let boundary = self
+            .head
+            .header("content-type")
+            .and_then(multipart_boundary)
+            .ok_or_else(|| Response::new(StatusCode::UnsupportedMediaType))?;
+        Ok(MultipartReader::new(&mut self.body, boundary, buf))
+    }
+
+    /// Read the rest of the body into a buffered [`Request`]
+    pub async fn buffer(&mut self) -> Result<Request, GurtError<io::Error>> {
+        let mut body = Vec::new();
+        self.body.read_to_end(&mut body).await?;
+        Ok(self.head.clone().with_body(body))
+    }
+
+    /// Discard what the handler left of the body, so the connection is ready for the next
+    /// request
+    ///
+    /// Fails with the error the body failed with, if it did, for the server to answer.
+    pub(crate) async fn finish(&mut self) -> Result<(), GurtError<io::Error>> {
+        if let Source::Connection { error, .. } = &mut self.body.0
+            && let Some(e) = error.take()
+        {
+            return Err(e);
+        }
+        let mut chunk = [0u8; 1024];
+        while self.body.read(&mut chunk).await? > 0 {}
+        Ok(())
+    }
+}
+
+/// The body of an [`Incoming`] request
+///
+/// Reads return the decoded body and `Ok(0)` at its end. They fail with
+/// [`GurtError::Timeout`] once the request timeout has passed, and with the protocol
+/// error when the body is malformed; the server then answers the client itself,
+/// whatever the handler returns.
+pub struct RequestBody<'a>(Source<'a>);
+
+enum Source<'a> {
+    Connection {
+        body: DecodedBody<'a, Transport>,
+        deadline: Instant,
+        /// The error the body failed with, kept for the server to answer
+        error: Option<GurtError<io::Error>>,
+    },
+    Buffered(&'a [u8]),
+}
+
+impl RequestBody<'_> {
+    /// Read the rest of the body, appending it to `out`
+    pub async fn read_to_end(&mut self, out: &mut Vec<u8>) -> Result<usize, GurtError<io::Error>> {
+        let mut total = 0;
+        let mut chunk = [0u8; 4096];
+        loop {
+            let n = self.read(&mut chunk).await?;
+            if n == 0 {
+                return Ok(total);
+            }
+            out.extend_from_slice(&chunk[..n]);
+            total += n;
+        }
+    }
+}
+
+impl ErrorType for RequestBody<'_> {
+    type Error = GurtError<io::Error>;
+}
+
+impl Read for RequestBody<'_> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        match &mut self.0 {
+            Source::Buffered(data) => {
+                let n = buf.len().min(data.len());
+                buf[..n].copy_from_slice(&data[..n]);
+                *data = &data[n..];
+                Ok(n)
+            }
+            Source::Connection {
+                body,
+                deadline,
+                error,
+            } => {
+                if let Some(e) = error {
+                    return Err(duplicate(e));
+                }
+                let result = timeout_at(*deadline, body.read(buf))
+                    .await
+                    .unwrap_or(Err(GurtError::Timeout));
+                result.inspect_err(|e| *error = Some(duplicate(e)))
+            }
+        }
+    }
+}
+
+/// A copy of a body error, as `io::Error` cannot be cloned
+fn duplicate(e: &GurtError<io::Error>) -> GurtError<io::Error> {
+    match e {
+        GurtError::Io(e) => GurtError::Io(io::Error::new(e.kind(), e.to_string())),
+        GurtError::UnexpectedEof => GurtError::UnexpectedEof,
+        GurtError::Protocol(e) => GurtError::Protocol(*e),
+        GurtError::LimitExceeded(limit) => GurtError::LimitExceeded(*limit),
+        GurtError::Timeout => GurtError::Timeout,
+        GurtError::InvalidRequest(e) => GurtError::InvalidRequest(*e),
+        GurtError::InvalidResponse(e) => GurtError::InvalidResponse(*e),
+        GurtError::HandshakeRejected(status) => GurtError::HandshakeRejected(*status),
+        GurtError::PinMismatch(pin) => GurtError::PinMismatch(*pin),
+    }
+}
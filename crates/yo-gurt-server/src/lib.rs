@@ -0,0 +1,283 @@
+//! TLS GURT server built on `portal-solutions-yo-gurt`
+//!
+//! [`Server`] accepts TCP connections, performs the TLS 1.3 handshake (ALPN `GURT/1.0`),
+//! answers the GURT `HANDSHAKE` and then passes each request to a [`Handler`] as an
+//! [`Incoming`] request, whose body the handler reads as it arrives. Async closures taking
+//! a buffered [`Request`] are handlers too. Connections are kept open for further requests
+//! until the client closes them or they sit idle for the spec's pool idle timeout.
+//!
+//! Clients can be authenticated by certificate (mutual TLS) with [`ClientAuth`]. The
+//! verified certificate is available to handlers as [`Request::peer`], and
+//! [`Request::authorize`] turns a missing or rejected certificate into `401 UNAUTHORIZED`
+//! or `403 FORBIDDEN`:
+//!
+//! ```rust,ignore
+//! use portal_solutions_yo_gurt_server::{ClientAuth, Response, Server, TlsConfig};
+//!
+//! let tls = TlsConfig::new(cert_chain, key).client_auth(ClientAuth::Optional(roots));
+//! let server = Server::new(tls, async |request: Request| {
+//!     match request.authorize(|peer| peer.common_name() == Some("deploy-bot")) {
+//!         Ok(peer) => Response::new(StatusCode::Ok).text(format!("hello {}\n", peer.subject())),
+//!         Err(response) => response,
+//!     }
+//! })?;
+//! server.serve(TcpListener::bind(("0.0.0.0", DEFAULT_PORT)).await?).await?;
+//! ```
+//!
+//! [`Response::file`] serves a file from disk, honoring `range` requests so that clients
+//! can resume downloads and fetch files larger than one message.
+
+mod file;
+mod incoming;
+#[cfg(feature = "metrics")]
+mod metrics;
+mod request;
+mod tls;
+
+pub use incoming::{Incoming, RequestBody};
+#[cfg(feature = "metrics")]
+pub use metrics::{DEFAULT_METRICS_PATH, Metrics};
+pub use request::{FormPart, Handler, Request, Response};
+pub use tls::{ClientAuth, PeerIdentity, TlsConfig, load_roots};
+
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+#[cfg(feature = "metrics")]
+use std::time::Instant;
+
+use embedded_io_async::Write;
+use portal_solutions_yo_gurt::adapters::FromTokio;
+use portal_solutions_yo_gurt::encoding::ContentEncoding;
+use portal_solutions_yo_gurt::server::{GurtServer, RequestReader};
+use portal_solutions_yo_gurt::{
+    DEFAULT_HANDSHAKE_TIMEOUT_SECS, DEFAULT_REQUEST_TIMEOUT_SECS, GurtError, Method,
+    POOL_IDLE_TIMEOUT_SECS, ProtocolError, StatusCode,
+};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::{self as clock, timeout, timeout_at};
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::server::TlsStream;
+
+/// An accepted, handshaken GURT connection
+pub type Connection = GurtServer<Transport>;
+
+/// The TLS stream a [`Connection`] reads and writes
+type Transport = FromTokio<BufReader<TlsStream<TcpStream>>>;
+
+/// Buffer for request lines and headers; longer lines are answered with `413 TOO_LARGE`
+pub const LINE_BUFFER_SIZE: usize = 8 * 1024;
+
+/// Serves GURT over TLS, passing requests to a [`Handler`]
+pub struct Server<H> {
+    acceptor: TlsAcceptor,
+    handler: Arc<H>,
+}
+
+impl<H: Handler> Server<H> {
+    /// Create a server with the given TLS settings
+    pub fn new(tls: TlsConfig, handler: H) -> Result<Self, rustls::Error> {
+        Ok(Self {
+            acceptor: TlsAcceptor::from(Arc::new(tls.into_server_config()?)),
+            handler: Arc::new(handler),
+        })
+    }
+
+    /// Accept connections until the listener fails, serving each on its own task
+    ///
+    /// Errors on individual connections end only that connection.
+    pub async fn serve(self, listener: TcpListener) -> io::Result<()> {
+        let server = Arc::new(self);
+        loop {
+            let (stream, _peer) = listener.accept().await?;
+            let server = server.clone();
+            let connection = async move {
+                let _ = server.serve_connection(stream).await;
+            };
+            #[cfg(feature = "tracing")]
+            let connection = tracing::Instrument::instrument(
+                connection,
+                tracing::debug_span!("gurt.connection", peer = %_peer),
+            );
+            tokio::spawn(connection);
+        }
+    }
+
+    /// Serve one connection until the client closes it
+    ///
+    /// From spec: "Every GURT session must begin with a `HANDSHAKE` request"
+    pub async fn serve_connection(&self, stream: TcpStream) -> Result<(), GurtError<io::Error>> {
+        #[cfg(feature = "metrics")]
+        let _open = metrics::OpenConnection::new();
+        #[cfg(feature = "metrics")]
+        let started = Instant::now();
+        let mut buf = vec![0u8; LINE_BUFFER_SIZE];
+        let accepted = self.accept(stream, &mut buf).await;
+        #[cfg(feature = "metrics")]
+        metrics::handshake(started, accepted.is_ok());
+        let (mut connection, peer) = accepted?;
+
+        let idle_timeout = Duration::from_secs(POOL_IDLE_TIMEOUT_SECS.into());
+        let request_timeout = Duration::from_secs(DEFAULT_REQUEST_TIMEOUT_SECS.into());
+        loop {
+            // Wait for the next request, ending quietly on close or idle timeout
+            match timeout(idle_timeout, connection.transport.0.fill_buf()).await {
+                Ok(Ok([])) | Err(_) => break,
+                Ok(Ok(_)) => {}
+                Ok(Err(e)) => return Err(GurtError::Io(e)),
+            }
+            #[cfg(feature = "metrics")]
+            let started = Instant::now();
+            // The head and the body must both arrive within the request timeout
+            let deadline = clock::Instant::now() + request_timeout;
+            let mut reader = connection.request_reader();
+            let head = match timeout_at(deadline, read_head(&mut reader, &mut buf, &peer)).await {
+                Ok(Ok(head)) => head,
+                Ok(Err(e)) => return Err(reject(&mut connection, e).await),
+                Err(_) => return Err(reject(&mut connection, GurtError::Timeout).await),
+            };
+            let method = head.method();
+            let length = reader.content_length().unwrap_or(0);
+            let body = match reader.decoded_body(length) {
+                Ok(body) => body,
+                Err(e) => return Err(reject(&mut connection, e).await),
+            };
+            let mut request = Incoming::new(head, body, deadline);
+            let response = self.handler.handle(&mut request).await;
+            if let Err(e) = request.finish().await {
+                return Err(reject(&mut connection, e).await);
+            }
+            let sent = send(&mut connection, method, &response).await;
+            #[cfg(feature = "metrics")]
+            metrics::request(method, started, &response, sent.is_ok());
+            sent?;
+        }
+        connection.close().await
+    }
+
+    /// Complete the TLS handshake and the GURT `HANDSHAKE` within the handshake timeout
+    async fn accept(
+        &self,
+        stream: TcpStream,
+        buf: &mut [u8],
+    ) -> Result<(Connection, Option<Arc<PeerIdentity>>), GurtError<io::Error>> {
+        let handshake_timeout = Duration::from_secs(DEFAULT_HANDSHAKE_TIMEOUT_SECS.into());
+        let tls = timeout(handshake_timeout, self.acceptor.accept(stream))
+            .await
+            .map_err(|_| GurtError::Timeout)?
+            .map_err(GurtError::Io)?;
+        let peer = match tls.get_ref().1.peer_certificates() {
+            Some(chain) => Some(Arc::new(PeerIdentity::from_chain(chain).ok_or_else(
+                || {
+                    GurtError::Io(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "bad certificate",
+                    ))
+                },
+            )?)),
+            None => None,
+        };
+
+        let mut connection = GurtServer::new(FromTokio(BufReader::new(tls)));
+        let handshake = connection.accept_handshake(buf);
+        match timeout(handshake_timeout, handshake).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => return Err(reject(&mut connection, e).await),
+            Err(_) => return Err(reject(&mut connection, GurtError::Timeout).await),
+        }
+        Ok((connection, peer))
+    }
+}
+
+/// Read a request's method line and headers, leaving its body on the connection
+async fn read_head(
+    reader: &mut RequestReader<'_, Transport>,
+    buf: &mut [u8],
+    peer: &Option<Arc<PeerIdentity>>,
+) -> Result<Request, GurtError<io::Error>> {
+    let line = reader.read_request_line(buf).await?;
+    let path = String::from_utf8(line.path(buf).to_vec())
+        .map_err(|_| ProtocolError::InvalidRequestLine)?;
+    let mut headers = Vec::new();
+    while let Some(header) = reader.read_header(buf).await? {
+        let value = std::str::from_utf8(header.value(buf))
+            .map_err(|_| ProtocolError::InvalidHeaderValue)?;
+        headers.push((
+            String::from_utf8_lossy(header.name(buf)).to_ascii_lowercase(),
+            value.to_owned(),
+        ));
+    }
+    let length = reader.content_length().unwrap_or(0);
+    #[cfg(feature = "metrics")]
+    metrics::received(length);
+    if reader.content_encoding() != Some(ContentEncoding::Identity) && length > 0 {
+        // Handlers see the decoded body, which these no longer describe
+        headers.retain(|(name, _)| name != "content-encoding" && name != "content-length");
+    }
+    Ok(Request::from_parts(
+        line.method,
+        path,
+        headers,
+        peer.clone(),
+    ))
+}
+
+/// Send a handler's response to a `method` request
+///
+/// A response the handler built with invalid headers, or with a body over
+/// [`MAX_MESSAGE_SIZE`](portal_solutions_yo_gurt::MAX_MESSAGE_SIZE), is replaced by
+/// `500 INTERNAL_SERVER_ERROR`; nothing has been written at that point. A response to
+/// `HEAD` carries the body's `content-length` but not the body, which the client does not
+/// read.
+async fn send(
+    connection: &mut Connection,
+    method: Method,
+    response: &Response,
+) -> Result<(), GurtError<io::Error>> {
+    let headers: Vec<(&str, &str)> = response
+        .headers
+        .iter()
+        .map(|(name, value)| (name.as_str(), value.as_str()))
+        .collect();
+    let body = match connection
+        .respond(response.status, &headers, response.body.len())
+        .await
+    {
+        Err(GurtError::InvalidResponse(_) | GurtError::LimitExceeded(_)) => {
+            connection
+                .respond(StatusCode::InternalServerError, &[], 0)
+                .await?
+        }
+        Ok(_) if method == Method::Head => {
+            // Flushed here, as the body writer would only flush once the body was written
+            return Write::flush(&mut connection.transport)
+                .await
+                .map_err(GurtError::Io);
+        }
+        result => {
+            let mut body = result?;
+            body.write(&response.body).await?;
+            body
+        }
+    };
+    body.finish().await
+}
+
+/// Answer a request that could not be read, where a reply still makes sense, and pass
+/// the error on
+async fn reject(connection: &mut Connection, e: GurtError<io::Error>) -> GurtError<io::Error> {
+    let status = match e {
+        GurtError::Protocol(ProtocolError::UnsupportedContentEncoding) => {
+            StatusCode::UnsupportedMediaType
+        }
+        GurtError::Protocol(_) => StatusCode::BadRequest,
+        GurtError::LimitExceeded(_) => StatusCode::TooLarge,
+        GurtError::Timeout => StatusCode::Timeout,
+        _ => return e,
+    };
+    if connection.respond(status, &[], 0).await.is_ok() {
+        let _ = connection.close().await;
+    }
+    e
+}
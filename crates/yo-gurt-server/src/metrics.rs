@@ -0,0 +1,122 @@
+//! Server metrics and the Prometheus `/metrics` endpoint
+
+use std::time::Instant;
+
+use metrics::{counter, gauge, histogram};
+use metrics_exporter_prometheus::{BuildError, PrometheusBuilder, PrometheusHandle};
+use portal_solutions_yo_gurt::metrics::{
+    SERVER_CONNECTIONS, SERVER_HANDSHAKE_DURATION, SERVER_HANDSHAKE_FAILURES,
+    SERVER_RECEIVED_BYTES, SERVER_REQUEST_DURATION, SERVER_REQUESTS, SERVER_SENT_BYTES, describe,
+    status_class,
+};
+use portal_solutions_yo_gurt::{Method, StatusCode};
+
+use crate::{Handler, Incoming, Response};
+
+/// Where [`Metrics`] serves the metrics unless told otherwise
+pub const DEFAULT_METRICS_PATH: &str = "/metrics";
+
+/// Content type of the Prometheus text exposition format
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// A [`Handler`] answering `GET /metrics` with the recorded metrics in the Prometheus
+/// text format, and passing every other request to an inner handler
+///
+/// ```rust,ignore
+/// let server = Server::new(tls, Metrics::install(handler)?)?;
+/// ```
+pub struct Metrics<H> {
+    prometheus: PrometheusHandle,
+    path: String,
+    handler: H,
+}
+
+impl<H: Handler> Metrics<H> {
+    /// Install a Prometheus recorder as the global recorder and serve it in front of
+    /// `handler`
+    ///
+    /// Fails if the application already installed a recorder; use [`Metrics::new`] with
+    /// its handle then.
+    pub fn install(handler: H) -> Result<Self, BuildError> {
+        Ok(Self::new(
+            PrometheusBuilder::new().install_recorder()?,
+            handler,
+        ))
+    }
+
+    /// Serve the metrics of an installed Prometheus recorder in front of `handler`
+    pub fn new(prometheus: PrometheusHandle, handler: H) -> Self {
+        describe();
+        Self {
+            prometheus,
+            path: DEFAULT_METRICS_PATH.to_owned(),
+            handler,
+        }
+    }
+
+    /// Serve the metrics at `path` instead of [`DEFAULT_METRICS_PATH`]
+    pub fn path(mut self, path: impl Into<String>) -> Self {
+        self.path = path.into();
+        self
+    }
+}
+
+impl<H: Handler> Handler for Metrics<H> {
+    async fn handle(&self, request: &mut Incoming<'_>) -> Response {
+        if request.path() != self.path {
+            return self.handler.handle(request).await;
+        }
+        match request.method() {
+            Method::Get => Response::new(StatusCode::Ok)
+                .header("content-type", CONTENT_TYPE)
+                .body(self.prometheus.render()),
+            _ => Response::new(StatusCode::MethodNotAllowed),
+        }
+    }
+}
+
+/// Counts a connection in [`SERVER_CONNECTIONS`] while alive
+pub(crate) struct OpenConnection(());
+
+impl OpenConnection {
+    pub(crate) fn new() -> Self {
+        gauge!(SERVER_CONNECTIONS).increment(1);
+        Self(())
+    }
+}
+
+impl Drop for OpenConnection {
+    fn drop(&mut self) {
+        gauge!(SERVER_CONNECTIONS).decrement(1);
+    }
+}
+
+/// Record a handshake begun at `started`
+pub(crate) fn handshake(started: Instant, succeeded: bool) {
+    match succeeded {
+        true => histogram!(SERVER_HANDSHAKE_DURATION).record(started.elapsed()),
+        false => counter!(SERVER_HANDSHAKE_FAILURES).increment(1),
+    }
+}
+
+/// Record a request body of `length` bytes as received
+pub(crate) fn received(length: usize) {
+    counter!(SERVER_RECEIVED_BYTES).increment(length as u64);
+}
+
+/// Record a request read at `started` and answered with `response`, or not if it could
+/// not be `sent`
+pub(crate) fn request(method: Method, started: Instant, response: &Response, sent: bool) {
+    let status = match sent {
+        true => {
+            histogram!(SERVER_REQUEST_DURATION, "method" => method.as_str())
+                .record(started.elapsed());
+            if method != Method::Head {
+                counter!(SERVER_SENT_BYTES).increment(response.body.len() as u64);
+            }
+            status_class(response.status)
+        }
+        false => "error",
+    };
+    counter!(SERVER_REQUESTS, "method" => method.as_str(), "status" => status).increment(1);
+}
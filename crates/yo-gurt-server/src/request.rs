@@ -0,0 +1,282 @@
+//! Buffered requests, and the responses and [`Handler`]s that answer requests
+
+use std::future::Future;
+use std::sync::Arc;
+
+use embedded_io_async::Read;
+use portal_solutions_yo_gurt::form::{URLENCODED, decode_urlencoded};
+use portal_solutions_yo_gurt::{Method, StatusCode};
+
+use crate::Incoming;
+use crate::tls::PeerIdentity;
+
+/// Buffer for the header block of one multipart part
+const MULTIPART_BUFFER_SIZE: usize = 16 * 1024;
+
+/// One part of a multipart form, from [`Request::multipart`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormPart {
+    /// The form field name
+    pub name: String,
+    /// The file name, for file uploads
+    pub filename: Option<String>,
+    /// The part's `content-type`, if given
+    pub content_type: Option<String>,
+    /// The part's contents
+    pub data: Vec<u8>,
+}
+
+/// A complete request with its body buffered, with the client's certificate identity
+/// when it presented one
+///
+/// Handlers written as closures over a `Request` have the body read in full before they
+/// are called; see [`Incoming`] for reading it as it arrives.
+#[derive(Debug, Clone)]
+pub struct Request {
+    method: Method,
+    path: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+    peer: Option<Arc<PeerIdentity>>,
+}
+
+impl Request {
+    /// A request without headers, body or peer identity, for calling handlers directly
+    pub fn new(method: Method, path: impl Into<String>) -> Self {
+        Self {
+            method,
+            path: path.into(),
+            headers: Vec::new(),
+            body: Vec::new(),
+            peer: None,
+        }
+    }
+
+    /// Add a header; names are stored lowercase
+    pub fn with_header(mut self, name: &str, value: impl Into<String>) -> Self {
+        self.headers.push((name.to_ascii_lowercase(), value.into()));
+        self
+    }
+
+    /// Set the body
+    pub fn with_body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Set the client certificate identity
+    pub fn with_peer(mut self, peer: impl Into<Arc<PeerIdentity>>) -> Self {
+        self.peer = Some(peer.into());
+        self
+    }
+
+    pub(crate) fn from_parts(
+        method: Method,
+        path: String,
+        headers: Vec<(String, String)>,
+        peer: Option<Arc<PeerIdentity>>,
+    ) -> Self {
+        Self {
+            method,
+            path,
+            headers,
+            body: Vec::new(),
+            peer,
+        }
+    }
+
+    /// This request with its body read from the buffer, for calling handlers that take
+    /// an [`Incoming`] request directly
+    pub fn incoming(&self) -> Incoming<'_> {
+        let head = Self::from_parts(
+            self.method,
+            self.path.clone(),
+            self.headers.clone(),
+            self.peer.clone(),
+        );
+        Incoming::buffered(head, &self.body)
+    }
+
+    /// The request method
+    pub fn method(&self) -> Method {
+        self.method
+    }
+
+    /// The request path, as sent on the method line
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// All headers in the order received, with lowercase names
+    pub fn headers(&self) -> &[(String, String)] {
+        &self.headers
+    }
+
+    /// The first value of header `name` (lowercase)
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// The request body
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// The body's fields, for a URL-encoded form as submitted by an HTML form
+    ///
+    /// Otherwise returns the response to send: `415 UNSUPPORTED_MEDIA_TYPE` when the
+    /// `content-type` is not `application/x-www-form-urlencoded`, `400 BAD_REQUEST` when
+    /// the body is not valid form data.
+    pub fn form(&self) -> Result<Vec<(String, String)>, Response> {
+        self.expect_urlencoded()?;
+        decode_urlencoded(&self.body).map_err(|_| Response::new(StatusCode::BadRequest))
+    }
+
+    /// The body's parts, for a `multipart/form-data` form such as a file upload
+    ///
+    /// Otherwise returns the response to send, as for [`form`](Self::form).
+    pub async fn multipart(&self) -> Result<Vec<FormPart>, Response> {
+        let mut incoming = self.incoming();
+        let mut buf = vec![0u8; MULTIPART_BUFFER_SIZE];
+        let mut reader = incoming.multipart(&mut buf)?;
+        let mut parts = Vec::new();
+        let bad_request = |_| Response::new(StatusCode::BadRequest);
+        while let Some(head) = reader.next_part().await.map_err(bad_request)? {
+            let mut part = FormPart {
+                name: head.name().to_owned(),
+                filename: head.filename().map(str::to_owned),
+                content_type: head.content_type().map(str::to_owned),
+                data: Vec::new(),
+            };
+            let mut chunk = [0u8; 4096];
+            loop {
+                match reader.read(&mut chunk).await.map_err(bad_request)? {
+                    0 => break,
+                    n => part.data.extend_from_slice(&chunk[..n]),
+                }
+            }
+            parts.push(part);
+        }
+        Ok(parts)
+    }
+
+    /// `415 UNSUPPORTED_MEDIA_TYPE` unless the `content-type` is
+    /// `application/x-www-form-urlencoded`
+    pub(crate) fn expect_urlencoded(&self) -> Result<(), Response> {
+        let content_type = self.header("content-type").unwrap_or_default();
+        let media_type = content_type.split(';').next().unwrap_or_default().trim();
+        if !media_type.eq_ignore_ascii_case(URLENCODED) {
+            return Err(Response::new(StatusCode::UnsupportedMediaType));
+        }
+        Ok(())
+    }
+
+    /// The verified client certificate, if the client presented one
+    pub fn peer(&self) -> Option<&PeerIdentity> {
+        self.peer.as_deref()
+    }
+
+    /// Check the client certificate before handling the request
+    ///
+    /// Returns the identity if `allow` accepts it. Otherwise returns the response to send:
+    /// `401 UNAUTHORIZED` when no certificate was presented, `403 FORBIDDEN` when `allow`
+    /// rejected it.
+    ///
+    /// ```rust,ignore
+    /// let peer = match request.authorize(|peer| peer.common_name() == Some("admin")) {
+    ///     Ok(peer) => peer,
+    ///     Err(response) => return response,
+    /// };
+    /// ```
+    pub fn authorize(
+        &self,
+        allow: impl FnOnce(&PeerIdentity) -> bool,
+    ) -> Result<&PeerIdentity, Response> {
+        let peer = self.peer().ok_or_else(Response::unauthorized)?;
+        if !allow(peer) {
+            return Err(Response::forbidden());
+        }
+        Ok(peer)
+    }
+}
+
+/// A complete response; `content-length` is added when it is sent
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    pub status: StatusCode,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    /// An empty response with `status`
+    pub fn new(status: StatusCode) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    /// `401 UNAUTHORIZED`: the request needs a client certificate
+    ///
+    /// From spec: "401 UNAUTHORIZED - Authentication required"
+    pub fn unauthorized() -> Self {
+        Self::new(StatusCode::Unauthorized).text("client certificate required\n")
+    }
+
+    /// `403 FORBIDDEN`: the client certificate is not allowed here
+    ///
+    /// From spec: "403 FORBIDDEN - Access denied"
+    pub fn forbidden() -> Self {
+        Self::new(StatusCode::Forbidden).text("client certificate not allowed\n")
+    }
+
+    /// Add a header; names must be lowercase
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Set the body
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Set a `text/plain` body
+    pub fn text(self, text: impl Into<String>) -> Self {
+        self.header("content-type", "text/plain; charset=utf-8")
+            .body(text.into())
+    }
+}
+
+/// Handles requests for a [`Server`](crate::Server)
+///
+/// Implement it to read request bodies as they arrive. Async closures and functions
+/// taking a buffered [`Request`] are handlers too, and get the body read in full first.
+pub trait Handler: Send + Sync + 'static {
+    /// Produce the response to `request`
+    ///
+    /// Whatever the handler leaves of the body is read and discarded before the response
+    /// is sent. When the body cannot be read, the server answers with the error's status
+    /// instead and closes the connection.
+    fn handle(&self, request: &mut Incoming<'_>) -> impl Future<Output = Response> + Send;
+}
+
+impl<F, Fut> Handler for F
+where
+    F: Fn(Request) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Response> + Send,
+{
+    async fn handle(&self, request: &mut Incoming<'_>) -> Response {
+        match request.buffer().await {
+            Ok(request) => self(request).await,
+            // Answered by the server with the body's error
+            Err(_) => Response::new(StatusCode::BadRequest),
+        }
+    }
+}
@@ -0,0 +1,180 @@
+//! TLS 1.3 server configuration and client certificate identities
+//!
+//! From spec: "All connections must use TLS 1.3 encryption" with ALPN `GURT/1.0`.
+
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use portal_solutions_yo_gurt::{ALPN_IDENTIFIER, tls};
+use rustls::RootCertStore;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use x509_parser::extensions::GeneralName;
+use x509_parser::prelude::{FromDer, X509Certificate};
+
+/// Whether clients must present a certificate (mutual TLS)
+#[derive(Debug, Clone, Default)]
+pub enum ClientAuth {
+    /// Do not ask for client certificates
+    #[default]
+    None,
+    /// Ask for a certificate issued by one of these roots, but accept clients without one
+    ///
+    /// Handlers decide what anonymous clients may do; see [`Request::authorize`](crate::Request::authorize).
+    Optional(Arc<RootCertStore>),
+    /// Refuse the TLS handshake unless the client presents a certificate issued by one of
+    /// these roots
+    Required(Arc<RootCertStore>),
+}
+
+/// Certificate, key and client authentication settings for a [`Server`](crate::Server)
+#[derive(Debug)]
+pub struct TlsConfig {
+    cert_chain: Vec<CertificateDer<'static>>,
+    key: PrivateKeyDer<'static>,
+    client_auth: ClientAuth,
+    key_log: bool,
+}
+
+impl TlsConfig {
+    /// Serve `cert_chain` (leaf first) with its private key, without client certificates
+    pub fn new(cert_chain: Vec<CertificateDer<'static>>, key: PrivateKeyDer<'static>) -> Self {
+        Self {
+            cert_chain,
+            key,
+            client_auth: ClientAuth::None,
+            key_log: false,
+        }
+    }
+
+    /// Load the certificate chain and private key from PEM files, such as those written
+    /// by `gurt-ca issue`
+    pub fn from_pem_files(cert: impl AsRef<Path>, key: impl AsRef<Path>) -> io::Result<Self> {
+        let cert_chain = tls::read_certificates(cert)?;
+        let key = tls::read_private_key(key)?;
+        Ok(Self::new(cert_chain, key))
+    }
+
+    /// Set how client certificates are requested and verified
+    pub fn client_auth(mut self, client_auth: ClientAuth) -> Self {
+        self.client_auth = client_auth;
+        self
+    }
+
+    /// Append TLS session secrets to the file named by the `SSLKEYLOGFILE` environment
+    /// variable, so captured traffic can be decrypted locally; nothing is written while
+    /// it is unset
+    pub fn key_log(mut self, enabled: bool) -> Self {
+        self.key_log = enabled;
+        self
+    }
+
+    /// Build the rustls configuration: TLS 1.3 only, ALPN `GURT/1.0`
+    pub fn into_server_config(self) -> Result<rustls::ServerConfig, rustls::Error> {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let builder = rustls::ServerConfig::builder_with_provider(provider.clone())
+            .with_protocol_versions(&[&rustls::version::TLS13])?;
+        let builder = match self.client_auth {
+            ClientAuth::None => builder.with_no_client_auth(),
+            ClientAuth::Optional(roots) => builder.with_client_cert_verifier(
+                WebPkiClientVerifier::builder_with_provider(roots, provider)
+                    .allow_unauthenticated()
+                    .build()
+                    .map_err(|e| rustls::Error::General(e.to_string()))?,
+            ),
+            ClientAuth::Required(roots) => builder.with_client_cert_verifier(
+                WebPkiClientVerifier::builder_with_provider(roots, provider)
+                    .build()
+                    .map_err(|e| rustls::Error::General(e.to_string()))?,
+            ),
+        };
+        let mut config = builder.with_single_cert(self.cert_chain, self.key)?;
+        config.alpn_protocols = vec![ALPN_IDENTIFIER.as_bytes().to_vec()];
+        if self.key_log {
+            config.key_log = Arc::new(rustls::KeyLogFile::new());
+        }
+        Ok(config)
+    }
+}
+
+/// Load trusted roots for [`ClientAuth`] from a PEM file, such as a `gurt-ca` CA
+/// certificate
+pub fn load_roots(path: impl AsRef<Path>) -> io::Result<Arc<RootCertStore>> {
+    let mut roots = RootCertStore::empty();
+    for cert in tls::read_certificates(path)? {
+        roots
+            .add(cert)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    }
+    Ok(Arc::new(roots))
+}
+
+/// The verified certificate a client presented during the TLS handshake
+///
+/// Only built from chains that passed the configured [`ClientAuth`] roots.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerIdentity {
+    chain: Vec<CertificateDer<'static>>,
+    subject: String,
+    common_name: Option<String>,
+    dns_names: Vec<String>,
+}
+
+impl PeerIdentity {
+    /// Read the identity from a verified chain (leaf first)
+    ///
+    /// Returns `None` for an empty chain or a leaf that is not valid X.509.
+    pub fn from_chain(chain: &[CertificateDer<'_>]) -> Option<Self> {
+        let (_, leaf) = X509Certificate::from_der(chain.first()?).ok()?;
+        let common_name = leaf
+            .subject()
+            .iter_common_name()
+            .next()
+            .and_then(|cn| cn.as_str().ok())
+            .map(str::to_owned);
+        let dns_names = match leaf.subject_alternative_name() {
+            Ok(Some(san)) => san
+                .value
+                .general_names
+                .iter()
+                .filter_map(|name| match name {
+                    GeneralName::DNSName(name) => Some((*name).to_owned()),
+                    _ => None,
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        Some(Self {
+            subject: leaf.subject().to_string(),
+            common_name,
+            dns_names,
+            chain: chain.iter().map(|cert| cert.clone().into_owned()).collect(),
+        })
+    }
+
+    /// The client's own certificate
+    pub fn certificate(&self) -> &CertificateDer<'static> {
+        &self.chain[0]
+    }
+
+    /// The full chain as presented, leaf first
+    pub fn chain(&self) -> &[CertificateDer<'static>] {
+        &self.chain
+    }
+
+    /// The certificate subject as an RFC 4514 string, e.g. `CN=device-1, O=Example`
+    pub fn subject(&self) -> &str {
+        &self.subject
+    }
+
+    /// The subject common name, if any
+    pub fn common_name(&self) -> Option<&str> {
+        self.common_name.as_deref()
+    }
+
+    /// DNS names from the subject alternative name extension
+    pub fn dns_names(&self) -> &[String] {
+        &self.dns_names
+    }
+}
@@ -0,0 +1,254 @@
+//! Request and response bodies against a local server: compressed, streamed, and left
+//! out for HEAD
+
+use std::sync::Arc;
+
+use embedded_io_async::Read;
+use portal_solutions_yo_gurt::adapters::FromTokio;
+use portal_solutions_yo_gurt::encoding::{ContentEncoding, SUPPORTED, encode};
+use portal_solutions_yo_gurt::form::{Boundary, MultipartBody};
+use portal_solutions_yo_gurt::{
+    ALPN_IDENTIFIER, DEFAULT_USER_AGENT, GurtClient, MAX_MESSAGE_SIZE, Method, StatusCode,
+};
+use portal_solutions_yo_gurt_server::{Handler, Incoming, Request, Response, Server, TlsConfig};
+use rcgen::{CertifiedKey, generate_simple_self_signed};
+use rustls::pki_types::{PrivateKeyDer, ServerName};
+use rustls::{ClientConfig, RootCertStore};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::TlsConnector;
+use tokio_rustls::client::TlsStream;
+
+type Client = GurtClient<FromTokio<TlsStream<TcpStream>>>;
+
+/// Echoes each request body after its headers
+async fn echo(request: Request) -> Response {
+    let mut body = Vec::new();
+    for (name, value) in request.headers() {
+        body.extend_from_slice(format!("{name}: {value}\n").as_bytes());
+    }
+    body.extend_from_slice(request.body());
+    Response::new(StatusCode::Ok).body(body)
+}
+
+/// Lists the parts of a multipart upload with their sizes, reading them as they arrive;
+/// other requests are answered without reading their body
+struct Uploads;
+
+impl Handler for Uploads {
+    async fn handle(&self, request: &mut Incoming<'_>) -> Response {
+        let mut buf = [0u8; 256];
+        let mut parts = match request.multipart(&mut buf) {
+            Ok(parts) => parts,
+            Err(response) => return response,
+        };
+        let mut listing = String::new();
+        while let Ok(Some(part)) = parts.next_part().await {
+            listing.push_str(part.name());
+            let mut size = 0;
+            let mut chunk = [0u8; 100];
+            loop {
+                match parts.read(&mut chunk).await {
+                    Ok(0) => break,
+                    Ok(n) => size += n,
+                    Err(_) => return Response::new(StatusCode::BadRequest),
+                }
+            }
+            listing.push_str(&format!(": {size}\n"));
+        }
+        Response::new(StatusCode::Ok).text(listing)
+    }
+}
+
+/// Start a server with `handler`, and connect to it
+async fn start(handler: impl Handler) -> Client {
+    let CertifiedKey { cert, key_pair } =
+        generate_simple_self_signed(vec!["gurt.test".to_string()]).unwrap();
+    let key = PrivateKeyDer::Pkcs8(key_pair.serialize_der().into());
+    let server = Server::new(TlsConfig::new(vec![cert.der().clone()], key), handler).unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(server.serve(listener));
+
+    let mut roots = RootCertStore::empty();
+    roots.add(cert.der().clone()).unwrap();
+    let mut config =
+        ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_protocol_versions(&[&rustls::version::TLS13])
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+    config.alpn_protocols = vec![ALPN_IDENTIFIER.as_bytes().to_vec()];
+    let tcp = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    let tls = TlsConnector::from(Arc::new(config))
+        .connect(ServerName::try_from("gurt.test").unwrap(), tcp)
+        .await
+        .unwrap();
+    let mut client = GurtClient::new(FromTokio(tls));
+    client.handshake("gurt.test", "test/1.0").await.unwrap();
+    client
+        .read_handshake_response(&mut [0u8; 512])
+        .await
+        .unwrap();
+    client
+}
+
+async fn post(client: &mut Client, encoding: &str, body: &[u8]) -> (StatusCode, String) {
+    post_with(client, &[("content-encoding", encoding)], body).await
+}
+
+async fn post_with(
+    client: &mut Client,
+    headers: &[(&str, &str)],
+    body: &[u8],
+) -> (StatusCode, String) {
+    let mut writer = client
+        .request(
+            Method::Post,
+            "/",
+            "gurt.test",
+            None,
+            headers,
+            Some(body.len()),
+        )
+        .await
+        .unwrap();
+    writer.write(body).await.unwrap();
+    writer.finish().await.unwrap();
+    let mut buf = [0u8; 512];
+    let mut response = client.response_reader();
+    let status = response.read_status_line(&mut buf).await.unwrap().status;
+    while response.read_header(&mut buf).await.unwrap().is_some() {}
+    let length = response.content_length().unwrap_or(0);
+    let mut body = vec![0u8; length];
+    response.read_body_exact(&mut body).await.unwrap();
+    (status, String::from_utf8(body).unwrap())
+}
+
+#[tokio::test]
+async fn compressed_request_bodies() {
+    let mut client = start(echo).await;
+    let text = "compressed request body\n".repeat(100);
+    for &encoding in SUPPORTED {
+        let body = encode(encoding, text.as_bytes()).unwrap();
+        let (status, echo) = post(&mut client, encoding.as_str(), &body).await;
+        assert_eq!(status, StatusCode::Ok);
+        // Handlers get the decoded body without the headers describing the encoded one
+        assert_eq!(
+            echo,
+            format!("host: gurt.test\nuser-agent: {DEFAULT_USER_AGENT}\n{text}")
+        );
+    }
+    let (status, echo) = post(&mut client, "identity", b"plain").await;
+    assert_eq!(status, StatusCode::Ok);
+    assert!(
+        echo.contains("content-length: 5\n") && echo.ends_with("plain"),
+        "{echo}"
+    );
+}
+
+#[tokio::test]
+async fn unsupported_and_invalid_encodings() {
+    let mut client = start(echo).await;
+    let (status, _) = post(&mut client, "compress", b"data").await;
+    assert_eq!(status, StatusCode::UnsupportedMediaType);
+
+    let mut client = start(echo).await;
+    let mut body = encode(ContentEncoding::Gzip, b"data").unwrap();
+    body.truncate(body.len() - 2);
+    let (status, _) = post(&mut client, "gzip", &body).await;
+    assert_eq!(status, StatusCode::BadRequest);
+}
+
+#[tokio::test]
+async fn streamed_bodies() {
+    let mut client = start(Uploads).await;
+    let boundary = Boundary::random();
+    let mut form = MultipartBody::new(boundary.clone());
+    form.field("title", b"holiday")
+        .file("photo", "beach.png", "image/png", &[7; 5000])
+        .unwrap();
+    let form = form.finish();
+    let compressed = encode(ContentEncoding::Gzip, &form).unwrap();
+    let headers = [
+        ("content-type", boundary.content_type()),
+        ("content-encoding", "gzip"),
+    ];
+    let (status, listing) = post_with(&mut client, &headers, &compressed).await;
+    assert_eq!(status, StatusCode::Ok);
+    assert_eq!(listing, "title: 7\nphoto: 5000\n");
+
+    // A body the handler leaves unread is discarded, and the connection stays usable
+    let (status, _) = post_with(&mut client, &[("content-type", "text/plain")], &form).await;
+    assert_eq!(status, StatusCode::UnsupportedMediaType);
+    let headers = [("content-type", boundary.content_type())];
+    let (status, listing) = post_with(&mut client, &headers, &form).await;
+    assert_eq!(status, StatusCode::Ok);
+    assert_eq!(listing, "title: 7\nphoto: 5000\n");
+
+    // A body that fails to decode is answered by the server, whatever the handler returns
+    let mut truncated = compressed.clone();
+    truncated.truncate(truncated.len() - 2);
+    let headers = [
+        ("content-type", boundary.content_type()),
+        ("content-encoding", "gzip"),
+    ];
+    let (status, _) = post_with(&mut client, &headers, &truncated).await;
+    assert_eq!(status, StatusCode::BadRequest);
+}
+
+#[tokio::test]
+async fn head_responses_leave_out_the_body() {
+    let mut client = start(async |request: Request| {
+        Response::new(StatusCode::Ok).text(format!("{} body", request.path()))
+    })
+    .await;
+    let mut buf = [0u8; 512];
+    client
+        .request_no_body(Method::Head, "/a", "gurt.test", None)
+        .await
+        .unwrap();
+    let mut response = client.response_reader();
+    assert_eq!(
+        response.read_status_line(&mut buf).await.unwrap().status,
+        StatusCode::Ok
+    );
+    while response.read_header(&mut buf).await.unwrap().is_some() {}
+    // The length of the body a GET would get, without the body itself
+    assert_eq!(response.content_length(), Some(7));
+
+    // So the next response on the connection starts right after the head
+    client
+        .request_no_body(Method::Get, "/b", "gurt.test", None)
+        .await
+        .unwrap();
+    let mut response = client.response_reader();
+    assert_eq!(
+        response.read_status_line(&mut buf).await.unwrap().status,
+        StatusCode::Ok
+    );
+    while response.read_header(&mut buf).await.unwrap().is_some() {}
+    let mut body = vec![0u8; response.content_length().unwrap()];
+    response.read_body_exact(&mut body).await.unwrap();
+    assert_eq!(body, b"/b body");
+}
+
+#[tokio::test]
+async fn invalid_responses_become_server_errors() {
+    let mut client = start(async |request: Request| match request.path() {
+        "/header" => Response::new(StatusCode::Ok).header("Bad Name", "x"),
+        _ => Response::new(StatusCode::Ok).body(vec![0; MAX_MESSAGE_SIZE + 1]),
+    })
+    .await;
+    for path in ["/header", "/large"] {
+        client
+            .request_no_body(Method::Get, path, "gurt.test", None)
+            .await
+            .unwrap();
+        let mut buf = [0u8; 512];
+        let mut response = client.response_reader();
+        let status = response.read_status_line(&mut buf).await.unwrap().status;
+        assert_eq!(status, StatusCode::InternalServerError, "{path}");
+        while response.read_header(&mut buf).await.unwrap().is_some() {}
+        assert_eq!(response.content_length(), Some(0));
+    }
+}
@@ -0,0 +1,100 @@
+//! Files served whole and by byte range
+
+use portal_solutions_yo_gurt::range::ContentRange;
+use portal_solutions_yo_gurt::{MAX_MESSAGE_SIZE, Method, StatusCode};
+use portal_solutions_yo_gurt_server::{Request, Response};
+
+fn header<'a>(response: &'a Response, name: &str) -> Option<&'a str> {
+    response
+        .headers
+        .iter()
+        .find(|(n, _)| n == name)
+        .map(|(_, value)| value.as_str())
+}
+
+fn get(range: Option<&str>) -> Request {
+    let request = Request::new(Method::Get, "/file");
+    match range {
+        Some(range) => request.with_header("range", range),
+        None => request,
+    }
+}
+
+#[tokio::test]
+async fn ranges() {
+    let dir = std::env::temp_dir().join(format!("yo-gurt-server-file-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("digits.txt");
+    std::fs::write(&path, "0123456789").unwrap();
+
+    let whole = Response::file(&get(None), &path).await;
+    assert_eq!(whole.status, StatusCode::Ok);
+    assert_eq!(whole.body, b"0123456789");
+    assert_eq!(header(&whole, "accept-ranges"), Some("bytes"));
+    assert_eq!(header(&whole, "content-range"), None);
+    let etag = header(&whole, "etag").unwrap().to_owned();
+
+    for (range, body, content_range) in [
+        ("bytes=2-4", "234", "bytes 2-4/10"),
+        ("bytes=7-", "789", "bytes 7-9/10"),
+        ("bytes=-2", "89", "bytes 8-9/10"),
+        ("bytes=5-100", "56789", "bytes 5-9/10"),
+        ("bytes=-50", "0123456789", "bytes 0-9/10"),
+    ] {
+        let response = Response::file(&get(Some(range)), &path).await;
+        assert_eq!(response.status, StatusCode::PartialContent, "{range}");
+        assert_eq!(response.body, body.as_bytes(), "{range}");
+        assert_eq!(header(&response, "content-range"), Some(content_range));
+    }
+    for range in ["bytes=10-", "bytes=-0", "bytes=20-30"] {
+        let response = Response::file(&get(Some(range)), &path).await;
+        assert_eq!(response.status, StatusCode::RangeNotSatisfiable, "{range}");
+        assert_eq!(header(&response, "content-range"), Some("bytes */10"));
+        assert!(response.body.is_empty());
+    }
+    // Ranges the server does not support are ignored
+    let response = Response::file(&get(Some("bytes=0-1,4-5")), &path).await;
+    assert_eq!(response.status, StatusCode::Ok);
+
+    // if-range sends the whole file when it has changed; if-none-match is answered with 304
+    let current = Response::file(
+        &get(Some("bytes=0-0")).with_header("if-range", &etag),
+        &path,
+    )
+    .await;
+    assert_eq!(current.status, StatusCode::PartialContent);
+    let changed = get(Some("bytes=0-0")).with_header("if-range", "\"other\"");
+    assert_eq!(Response::file(&changed, &path).await.status, StatusCode::Ok);
+    let cached = get(None).with_header("if-none-match", format!("\"x\", {etag}"));
+    let response = Response::file(&cached, &path).await;
+    assert_eq!(response.status, StatusCode::NotModified);
+    assert!(response.body.is_empty());
+
+    let missing = Response::file(&get(None), dir.join("missing")).await;
+    assert_eq!(missing.status, StatusCode::NotFound);
+    assert_eq!(
+        Response::file(&get(None), &dir).await.status,
+        StatusCode::NotFound
+    );
+    let empty = dir.join("empty");
+    std::fs::write(&empty, "").unwrap();
+    let response = Response::file(&get(None), &empty).await;
+    assert_eq!((response.status, response.body.len()), (StatusCode::Ok, 0));
+    let response = Response::file(&get(Some("bytes=0-")), &empty).await;
+    assert_eq!(response.status, StatusCode::RangeNotSatisfiable);
+
+    // Files larger than one message are served a message at a time
+    let large = dir.join("large");
+    let file = std::fs::File::create(&large).unwrap();
+    file.set_len(MAX_MESSAGE_SIZE as u64 + 10).unwrap();
+    drop(file);
+    assert_eq!(
+        Response::file(&get(None), &large).await.status,
+        StatusCode::TooLarge
+    );
+    let response = Response::file(&get(Some("bytes=5-")), &large).await;
+    assert_eq!(response.body.len(), MAX_MESSAGE_SIZE);
+    let content_range = ContentRange::parse(header(&response, "content-range").unwrap()).unwrap();
+    assert_eq!(content_range.range, Some((5, MAX_MESSAGE_SIZE as u64 + 4)));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
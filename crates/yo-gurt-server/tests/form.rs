@@ -0,0 +1,131 @@
+//! Form bodies parsed from buffered requests, and as they are read
+
+use embedded_io_async::Read;
+use portal_solutions_yo_gurt::form::{Boundary, MultipartBody, URLENCODED};
+use portal_solutions_yo_gurt::{Method, StatusCode};
+use portal_solutions_yo_gurt_server::{FormPart, Request};
+
+#[test]
+fn urlencoded_forms() {
+    let request = Request::new(Method::Post, "/search")
+        .with_header("content-type", format!("{URLENCODED}; charset=utf-8"))
+        .with_body("q=gurt+protocol&page=2");
+    assert_eq!(
+        request.form().unwrap(),
+        vec![
+            ("q".to_owned(), "gurt protocol".to_owned()),
+            ("page".to_owned(), "2".to_owned())
+        ]
+    );
+
+    let json = Request::new(Method::Post, "/").with_header("content-type", "application/json");
+    assert_eq!(
+        json.form().unwrap_err().status,
+        StatusCode::UnsupportedMediaType
+    );
+    let invalid = Request::new(Method::Post, "/")
+        .with_header("content-type", URLENCODED)
+        .with_body(b"name=%FF".to_vec());
+    assert_eq!(invalid.form().unwrap_err().status, StatusCode::BadRequest);
+}
+
+#[tokio::test]
+async fn multipart_forms() {
+    let boundary = Boundary::random();
+    let mut form = MultipartBody::new(boundary.clone());
+    form.field("title", b"holiday")
+        .file("photo", "beach.png", "image/png", &[0x89, b'P', b'N', b'G'])
+        .unwrap();
+    let request = Request::new(Method::Post, "/upload")
+        .with_header("content-type", boundary.content_type())
+        .with_body(form.finish());
+    assert_eq!(
+        request.multipart().await.unwrap(),
+        vec![
+            FormPart {
+                name: "title".to_owned(),
+                filename: None,
+                content_type: None,
+                data: b"holiday".to_vec(),
+            },
+            FormPart {
+                name: "photo".to_owned(),
+                filename: Some("beach.png".to_owned()),
+                content_type: Some("image/png".to_owned()),
+                data: vec![0x89, b'P', b'N', b'G'],
+            },
+        ]
+    );
+
+    let truncated = Request::new(Method::Post, "/upload")
+        .with_header("content-type", boundary.content_type())
+        .with_body(format!(
+            "--{}\r\ncontent-disposition: form-data; name=a\r\n\r\nx",
+            boundary.as_str()
+        ));
+    assert_eq!(
+        truncated.multipart().await.unwrap_err().status,
+        StatusCode::BadRequest
+    );
+    let plain = Request::new(Method::Post, "/upload").with_header("content-type", URLENCODED);
+    assert_eq!(
+        plain.multipart().await.unwrap_err().status,
+        StatusCode::UnsupportedMediaType
+    );
+}
+
+#[tokio::test]
+async fn streamed_forms() {
+    let request = Request::new(Method::Post, "/search")
+        .with_header("content-type", URLENCODED)
+        .with_body("q=gurt+protocol&&page=2");
+    let mut incoming = request.incoming();
+    let mut buf = [0u8; 32];
+    let mut fields = incoming.form(&mut buf).unwrap();
+    assert_eq!(
+        fields.next_field().await.unwrap(),
+        Some(("q", "gurt protocol"))
+    );
+    assert_eq!(fields.next_field().await.unwrap(), Some(("page", "2")));
+    assert_eq!(fields.next_field().await.unwrap(), None);
+    assert!(matches!(
+        request.incoming().multipart(&mut buf),
+        Err(response) if response.status == StatusCode::UnsupportedMediaType
+    ));
+
+    let boundary = Boundary::random();
+    let mut form = MultipartBody::new(boundary.clone());
+    form.field("title", b"holiday")
+        .file("photo", "beach.png", "image/png", &[1; 1000])
+        .unwrap();
+    let request = Request::new(Method::Post, "/upload")
+        .with_header("content-type", boundary.content_type())
+        .with_body(form.finish());
+    let mut incoming = request.incoming();
+    // Only one part's head needs to fit, not the body
+    let mut buf = [0u8; 256];
+    let mut parts = incoming.multipart(&mut buf).unwrap();
+    let mut seen = Vec::new();
+    while let Some(part) = parts.next_part().await.unwrap() {
+        let name = part.name().to_owned();
+        let mut size = 0;
+        let mut chunk = [0u8; 64];
+        loop {
+            match parts.read(&mut chunk).await.unwrap() {
+                0 => break,
+                n => size += n,
+            }
+        }
+        seen.push((name, size));
+    }
+    assert_eq!(seen, [("title".to_owned(), 7), ("photo".to_owned(), 1000)]);
+    assert!(matches!(
+        request.incoming().form(&mut buf),
+        Err(response) if response.status == StatusCode::UnsupportedMediaType
+    ));
+
+    // The buffered request can be had back whole
+    let buffered = request.incoming().buffer().await.unwrap();
+    assert_eq!(buffered.body(), request.body());
+    assert_eq!(buffered.headers(), request.headers());
+}
@@ -0,0 +1,64 @@
+//! Client and server metrics, read back from the `/metrics` endpoint
+
+use portal_solutions_yo_gurt::StatusCode;
+use portal_solutions_yo_gurt::fetch::Client;
+use portal_solutions_yo_gurt::resolve::Hosts;
+use portal_solutions_yo_gurt_ca::LocalCa;
+use portal_solutions_yo_gurt_server::{Metrics, Request, Response, Server, TlsConfig};
+use tokio::net::TcpListener;
+
+#[tokio::test]
+async fn metrics_endpoint() {
+    let ca = LocalCa::generate("test CA").unwrap();
+    let issued = ca.issue_server(&["gurt.test"]).unwrap();
+    let tls = TlsConfig::new(vec![issued.cert_der().clone()], issued.key_der());
+    let handler = Metrics::install(async |request: Request| match request.path() {
+        "/hello" => Response::new(StatusCode::Ok).text("hello"),
+        _ => Response::new(StatusCode::NotFound),
+    })
+    .unwrap();
+    let server = Server::new(tls, handler).unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(server.serve(listener));
+
+    let client = Client::builder()
+        .webpki_roots(false)
+        .add_root_certificate(ca.cert_der().clone())
+        .resolver(Hosts::new("127.0.0.1 gurt.test"))
+        .build()
+        .unwrap();
+    let url = |path: &str| format!("gurt://gurt.test:{port}{path}");
+    let response = client.get(&url("/hello")).send().await.unwrap();
+    assert_eq!(response.text().await.unwrap(), "hello");
+    let response = client.get(&url("/missing")).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::NotFound);
+    let response = client.post(&url("/metrics")).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::MethodNotAllowed);
+
+    let response = client.get(&url("/metrics")).send().await.unwrap();
+    assert!(
+        response
+            .header("content-type")
+            .unwrap()
+            .starts_with("text/plain; version=0.0.4")
+    );
+    let text = response.text().await.unwrap();
+    for line in [
+        "# TYPE gurt_server_requests_total counter",
+        r#"gurt_server_requests_total{method="GET",status="2xx"} 1"#,
+        r#"gurt_server_requests_total{method="GET",status="4xx"} 1"#,
+        r#"gurt_server_requests_total{method="POST",status="4xx"} 1"#,
+        "gurt_server_sent_bytes_total 5",
+        "gurt_server_open_connections 1",
+        r#"gurt_client_requests_total{method="GET",status="2xx"} 1"#,
+        "gurt_client_received_bytes_total 5",
+        r#"gurt_client_pool_idle_connections{host="gurt.test:"#,
+        "gurt_client_pool_max_idle_connections 10",
+        "gurt_client_handshake_duration_seconds_count 1",
+        "gurt_server_handshake_duration_seconds_count 1",
+    ] {
+        assert!(text.contains(line), "{line:?} missing from\n{text}");
+    }
+    assert!(!text.contains("handshake_failures_total"));
+}
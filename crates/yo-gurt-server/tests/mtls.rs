@@ -0,0 +1,181 @@
+//! Client certificate authentication against a local server
+
+use std::io;
+use std::sync::Arc;
+
+use portal_solutions_yo_gurt::adapters::FromTokio;
+use portal_solutions_yo_gurt::{ALPN_IDENTIFIER, GurtClient, GurtError, Method, StatusCode};
+use portal_solutions_yo_gurt_server::{ClientAuth, Request, Response, Server, TlsConfig};
+use rcgen::{BasicConstraints, CertificateParams, DnType, IsCa, KeyPair};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rustls::{ClientConfig, RootCertStore};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::TlsConnector;
+use tokio_rustls::client::TlsStream;
+
+type Client = GurtClient<FromTokio<TlsStream<TcpStream>>>;
+
+/// A certificate and its key
+struct Identity {
+    cert: CertificateDer<'static>,
+    key: KeyPair,
+}
+
+impl Identity {
+    fn key_der(&self) -> PrivateKeyDer<'static> {
+        PrivateKeyDer::Pkcs8(self.key.serialize_der().into())
+    }
+}
+
+/// A CA issuing a server certificate for `gurt.test` and client certificates
+struct Pki {
+    ca: rcgen::Certificate,
+    ca_key: KeyPair,
+}
+
+impl Pki {
+    fn new() -> Self {
+        let ca_key = KeyPair::generate().unwrap();
+        let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        params
+            .distinguished_name
+            .push(DnType::CommonName, "test CA");
+        let ca = params.self_signed(&ca_key).unwrap();
+        Self { ca, ca_key }
+    }
+
+    fn issue(&self, common_name: &str, dns_names: &[&str]) -> Identity {
+        let key = KeyPair::generate().unwrap();
+        let names: Vec<String> = dns_names.iter().map(|name| name.to_string()).collect();
+        let mut params = CertificateParams::new(names).unwrap();
+        params
+            .distinguished_name
+            .push(DnType::CommonName, common_name);
+        let cert = params.signed_by(&key, &self.ca, &self.ca_key).unwrap();
+        Identity {
+            cert: cert.der().clone(),
+            key,
+        }
+    }
+
+    fn roots(&self) -> Arc<RootCertStore> {
+        let mut roots = RootCertStore::empty();
+        roots.add(self.ca.der().clone()).unwrap();
+        Arc::new(roots)
+    }
+}
+
+/// Start a server whose handler only lets `device-1` in
+async fn start(pki: &Pki, client_auth: impl FnOnce(Arc<RootCertStore>) -> ClientAuth) -> u16 {
+    let server_identity = pki.issue("gurt.test", &["gurt.test"]);
+    let tls = TlsConfig::new(
+        vec![server_identity.cert.clone()],
+        server_identity.key_der(),
+    )
+    .client_auth(client_auth(pki.roots()));
+    let server = Server::new(tls, async |request: Request| {
+        match request.authorize(|peer| peer.common_name() == Some("device-1")) {
+            Ok(peer) => Response::new(StatusCode::Ok).text(format!(
+                "{} {} {:?}",
+                request.path(),
+                peer.subject(),
+                peer.dns_names()
+            )),
+            Err(response) => response,
+        }
+    })
+    .unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(server.serve(listener));
+    port
+}
+
+async fn connect(
+    port: u16,
+    roots: Arc<RootCertStore>,
+    identity: Option<&Identity>,
+) -> Result<Client, GurtError<io::Error>> {
+    let builder =
+        ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_protocol_versions(&[&rustls::version::TLS13])
+            .unwrap()
+            .with_root_certificates(roots);
+    let mut config = match identity {
+        Some(identity) => builder
+            .with_client_auth_cert(vec![identity.cert.clone()], identity.key_der())
+            .unwrap(),
+        None => builder.with_no_client_auth(),
+    };
+    config.alpn_protocols = vec![ALPN_IDENTIFIER.as_bytes().to_vec()];
+    let tcp = TcpStream::connect(("127.0.0.1", port))
+        .await
+        .map_err(GurtError::Io)?;
+    let tls = TlsConnector::from(Arc::new(config))
+        .connect(ServerName::try_from("gurt.test").unwrap(), tcp)
+        .await
+        .map_err(GurtError::Io)?;
+    let mut client = GurtClient::new(FromTokio(tls));
+    client.handshake("gurt.test", "test/1.0").await?;
+    client.read_handshake_response(&mut [0u8; 512]).await?;
+    Ok(client)
+}
+
+async fn get(client: &mut Client, path: &str) -> (StatusCode, String) {
+    client
+        .request_no_body(Method::Get, path, "gurt.test", None)
+        .await
+        .unwrap();
+    let mut buf = [0u8; 512];
+    let mut response = client.response_reader();
+    let status = response.read_status_line(&mut buf).await.unwrap().status;
+    while response.read_header(&mut buf).await.unwrap().is_some() {}
+    let length = response.content_length().unwrap();
+    let mut body = vec![0u8; length];
+    response.read_body_exact(&mut body).await.unwrap();
+    (status, String::from_utf8(body).unwrap())
+}
+
+#[tokio::test]
+async fn optional_client_certificates() {
+    let pki = Pki::new();
+    let port = start(&pki, ClientAuth::Optional).await;
+
+    let device = pki.issue("device-1", &["device-1.internal"]);
+    let mut client = connect(port, pki.roots(), Some(&device)).await.unwrap();
+    let (status, body) = get(&mut client, "/status").await;
+    assert_eq!(status, StatusCode::Ok);
+    assert_eq!(body, "/status CN=device-1 [\"device-1.internal\"]");
+    // Later requests on the same connection carry the same identity
+    assert_eq!(get(&mut client, "/again").await.0, StatusCode::Ok);
+    client.close().await.unwrap();
+
+    let mut anonymous = connect(port, pki.roots(), None).await.unwrap();
+    assert_eq!(
+        get(&mut anonymous, "/status").await,
+        (
+            StatusCode::Unauthorized,
+            "client certificate required\n".into()
+        )
+    );
+
+    let other = pki.issue("device-2", &[]);
+    let mut client = connect(port, pki.roots(), Some(&other)).await.unwrap();
+    assert_eq!(get(&mut client, "/status").await.0, StatusCode::Forbidden);
+}
+
+#[tokio::test]
+async fn required_client_certificates() {
+    let pki = Pki::new();
+    let port = start(&pki, ClientAuth::Required).await;
+
+    let device = pki.issue("device-1", &[]);
+    let mut client = connect(port, pki.roots(), Some(&device)).await.unwrap();
+    assert_eq!(get(&mut client, "/").await.0, StatusCode::Ok);
+
+    // TLS 1.3 clients learn of the rejection on their first read
+    assert!(connect(port, pki.roots(), None).await.is_err());
+    let untrusted = Pki::new().issue("device-1", &[]);
+    assert!(connect(port, pki.roots(), Some(&untrusted)).await.is_err());
+}
@@ -0,0 +1,33 @@
+#![no_main]
+
+//! `set-cookie` headers, cookie dates and the jar's scoping rules
+
+use libfuzzer_sys::fuzz_target;
+use portal_solutions_yo_gurt::cookie::{CookieJar, FixedStore, MAX_COOKIE_SIZE, SetCookie};
+
+const HOSTS: [&str; 4] = ["example.web", "www.example.web", "localhost", "10.0.0.1"];
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(text) = core::str::from_utf8(data) else {
+        return;
+    };
+    let mut jar = CookieJar::new(FixedStore::<4, 96>::new());
+    let now = 1_445_412_480;
+    for (i, line) in text.lines().enumerate() {
+        let host = HOSTS[i % HOSTS.len()];
+        if let Some(cookie) = SetCookie::parse(line) {
+            assert!(!cookie.name.is_empty());
+            assert!(cookie.path.is_none_or(|path| path.starts_with('/')));
+        }
+        if jar.set_cookie(host, "/a/b", line, now) {
+            let cookie = SetCookie::parse(line).unwrap();
+            assert!(cookie.name.len() + cookie.value.len() <= MAX_COOKIE_SIZE);
+        }
+    }
+    let mut buf = [0u8; 512];
+    for host in HOSTS {
+        if let Ok(Some(header)) = jar.cookie_header(host, "/a/b/c", now, &mut buf) {
+            assert!(!header.starts_with(';') && !header.ends_with(' '));
+        }
+    }
+});
@@ -0,0 +1,45 @@
+#![no_main]
+
+//! Content-encoding decoders over arbitrary encoded bodies
+
+use libfuzzer_sys::fuzz_target;
+use portal_solutions_yo_gurt::encoding::{ContentEncoding, Decoder};
+
+/// Decoded output allowed per input; small enough to reach the limit quickly
+const LIMIT: usize = 1024 * 1024;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&selector, input)) = data.split_first() else {
+        return;
+    };
+    let encoding = match selector % 5 {
+        0 => ContentEncoding::Identity,
+        1 => ContentEncoding::Gzip,
+        2 => ContentEncoding::Deflate,
+        3 => ContentEncoding::Brotli,
+        _ => ContentEncoding::Zstd,
+    };
+    // Vary how input and output are split up
+    let step = usize::from(selector >> 3) + 1;
+    let mut output = vec![0u8; usize::from(selector & 0x38) * 16 + 1];
+    let mut decoder = Decoder::with_limit(encoding, LIMIT).unwrap();
+    let mut pos = 0;
+    loop {
+        let end = (pos + step).min(input.len());
+        let last = end == input.len();
+        let Ok(progress) = decoder.decode(&input[pos..end], &mut output, last) else {
+            break;
+        };
+        assert!(progress.consumed <= end - pos);
+        assert!(progress.produced <= output.len());
+        pos += progress.consumed;
+        if progress.done {
+            // Data after the end of the encoded body is never accepted
+            if pos < input.len() {
+                assert!(decoder.decode(&input[pos..], &mut output, true).is_err());
+            }
+            break;
+        }
+    }
+    assert!(decoder.total_out() <= LIMIT + output.len());
+});
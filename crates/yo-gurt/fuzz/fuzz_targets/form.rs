@@ -0,0 +1,37 @@
+#![no_main]
+
+//! URL-encoded and multipart form bodies, parsed incrementally
+
+use embassy_futures::block_on;
+use embedded_io_async::Read;
+use libfuzzer_sys::fuzz_target;
+use portal_solutions_yo_gurt::ResponseReader;
+use portal_solutions_yo_gurt::form::{MultipartReader, UrlEncodedReader};
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&selector, input)) = data.split_first() else {
+        return;
+    };
+    let mut transport = input;
+    let body = ResponseReader::new(&mut transport).body(input.len());
+    let mut buf = [0u8; 128];
+    block_on(async {
+        if selector & 1 == 0 {
+            let mut reader = UrlEncodedReader::new(body, &mut buf);
+            while let Ok(Some((name, value))) = reader.next_field().await {
+                assert!(name.len() + value.len() < 128);
+            }
+        } else {
+            // A short boundary makes delimiters easy to hit
+            let boundary = ["b", "--", "xy"][usize::from(selector >> 1) % 3];
+            let mut reader = MultipartReader::new(body, boundary, &mut buf);
+            let mut chunk = [0u8; 13];
+            while let Ok(Some(head)) = reader.next_part().await {
+                assert!(head.name().len() < 128);
+                while let Ok(n @ 1..) = reader.read(&mut chunk).await {
+                    assert!(n <= chunk.len());
+                }
+            }
+        }
+    });
+});
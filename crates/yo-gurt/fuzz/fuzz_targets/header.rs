@@ -0,0 +1,18 @@
+#![no_main]
+
+use embassy_futures::block_on;
+use libfuzzer_sys::fuzz_target;
+use portal_solutions_yo_gurt::ResponseReader;
+
+fuzz_target!(|data: &[u8]| {
+    let mut transport = data;
+    let mut buf = [0u8; 256];
+    if let Ok(Some(header)) = block_on(ResponseReader::new(&mut transport).read_header(&mut buf)) {
+        let line = &buf[..header.total_bytes - 2];
+        assert!(!header.name(&buf).is_empty());
+        assert!(header.value_start + header.value_len <= line.len());
+        assert!(!line.contains(&b'\r') && !line.contains(&b'\n'));
+        let value = header.value(&buf);
+        assert!(!value.starts_with(b" ") && !value.ends_with(b" "));
+    }
+});
@@ -0,0 +1,43 @@
+#![no_main]
+
+//! Full request as read by a server: request line, headers, then the body
+
+use embassy_futures::block_on;
+use embedded_io_async::Read;
+use libfuzzer_sys::fuzz_target;
+use portal_solutions_yo_gurt::GurtError;
+use portal_solutions_yo_gurt::server::RequestReader;
+
+fuzz_target!(|data: &[u8]| {
+    let mut transport = data;
+    let mut buf = [0u8; 256];
+    block_on(async {
+        let mut request = RequestReader::new(&mut transport);
+        let Ok(line) = request.read_request_line(&mut buf).await else {
+            return;
+        };
+        let path = line.path(&buf);
+        assert!(path.starts_with(b"/"));
+        assert!(!path.contains(&b' '));
+        assert_eq!(line.bytes_read, line.path_start + path.len() + " GURT/1.0.0\r\n".len());
+        loop {
+            match request.read_header(&mut buf).await {
+                Ok(Some(_)) => {}
+                Ok(None) => break,
+                Err(_) => return,
+            }
+        }
+        let content_length = request.content_length().unwrap_or(0);
+        let mut body = request.body(content_length);
+        let mut read = 0;
+        loop {
+            match body.read(&mut buf).await {
+                Ok(0) => break,
+                Ok(n) => read += n,
+                Err(GurtError::UnexpectedEof) => break,
+                Err(e) => panic!("unexpected body error: {e:?}"),
+            }
+        }
+        assert_eq!(read + body.remaining(), content_length);
+    });
+});
@@ -0,0 +1,45 @@
+#![no_main]
+
+//! Full response: status line, headers, then a `content-length` delimited body
+
+use embassy_futures::block_on;
+use embedded_io_async::Read;
+use libfuzzer_sys::fuzz_target;
+use portal_solutions_yo_gurt::{GurtError, MAX_MESSAGE_SIZE, ResponseReader, parse_content_length};
+
+fuzz_target!(|data: &[u8]| {
+    let mut transport = data;
+    let mut buf = [0u8; 256];
+    block_on(async {
+        let mut response = ResponseReader::new(&mut transport);
+        if response.read_status_line(&mut buf).await.is_err() {
+            return;
+        }
+        let mut content_length = 0;
+        loop {
+            match response.read_header(&mut buf).await {
+                Ok(Some(header)) => {
+                    if header.name(&buf) == b"content-length" {
+                        match parse_content_length(header.value(&buf)) {
+                            Some(length) if length <= MAX_MESSAGE_SIZE => content_length = length,
+                            _ => return,
+                        }
+                    }
+                }
+                Ok(None) => break,
+                Err(_) => return,
+            }
+        }
+        let mut body = response.body(content_length);
+        let mut read = 0;
+        loop {
+            match body.read(&mut buf).await {
+                Ok(0) => break,
+                Ok(n) => read += n,
+                Err(GurtError::UnexpectedEof) => break,
+                Err(e) => panic!("unexpected body error: {e:?}"),
+            }
+        }
+        assert_eq!(read + body.remaining(), content_length);
+    });
+});
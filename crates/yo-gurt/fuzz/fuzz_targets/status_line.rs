@@ -0,0 +1,16 @@
+#![no_main]
+
+use embassy_futures::block_on;
+use libfuzzer_sys::fuzz_target;
+use portal_solutions_yo_gurt::ResponseReader;
+
+fuzz_target!(|data: &[u8]| {
+    let mut transport = data;
+    let mut buf = [0u8; 256];
+    if let Ok(result) = block_on(ResponseReader::new(&mut transport).read_status_line(&mut buf)) {
+        // An accepted line is exactly the bytes consumed, ending in CRLF
+        assert!(result.bytes_read <= data.len());
+        assert_eq!(&data[result.bytes_read - 2..result.bytes_read], b"\r\n");
+        assert_eq!(transport.len(), data.len() - result.bytes_read);
+    }
+});
@@ -0,0 +1,268 @@
+//! Adapters between `embedded-io-async` and the std, tokio and futures I/O traits
+//!
+//! [`GurtClient`](crate::GurtClient) works over `embedded_io_async::Read + Write`. These
+//! wrappers let it run over other I/O types, each behind a feature:
+//!
+//! - [`FromStd`] (`std`): blocking `std::io::Read + Write`, for use with a simple
+//!   `block_on` executor
+//! - [`FromTokio`] (`tokio`): `tokio::io::AsyncRead + AsyncWrite`
+//! - [`FromFutures`] (`futures`): `futures_io::AsyncRead + AsyncWrite`
+//!
+//! Errors are `std::io::Error` throughout, which implements `embedded_io_async::Error`.
+//!
+//! In the other direction, a [`BodyReader`] over one of these adapters implements the
+//! matching read trait (`std::io::Read`, `tokio::io::AsyncRead` or
+//! `futures_io::AsyncRead`), so response bodies can be passed to `std::io::copy`,
+//! `tokio::io::copy` and the like. Reading stops at the end of the body; a connection
+//! closed before then is reported as [`std::io::ErrorKind::UnexpectedEof`].
+//!
+//! With `tokio`, [`GurtClient::send_file`](crate::GurtClient::send_file) streams a file
+//! into a request body, reporting [progress](crate::progress) as it goes.
+
+use std::io;
+
+/// Bytes of a file read at a time by [`GurtClient::send_file`](crate::GurtClient::send_file)
+#[cfg(feature = "tokio")]
+pub const FILE_CHUNK_SIZE: usize = 16 * 1024;
+
+/// The error for a body that ended before its `content-length`
+fn truncated() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::UnexpectedEof,
+        "connection closed before end of body",
+    )
+}
+
+/// Wraps a blocking `std::io` stream as a GURT transport
+///
+/// Every operation blocks the calling thread, so drive the client with a minimal executor
+/// such as `embassy_futures::block_on` rather than inside an async runtime.
+#[derive(Debug, Default)]
+pub struct FromStd<T>(pub T);
+
+mod from_std {
+    use std::io;
+
+    use embedded_io_async::{ErrorType, Read, Write};
+
+    use super::{FromStd, truncated};
+    use crate::progress::Progress;
+    use crate::{BodyReader, Close};
+
+    impl<T> ErrorType for FromStd<T> {
+        type Error = io::Error;
+    }
+
+    impl<T: io::Read> Read for FromStd<T> {
+        async fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.0.read(buf)
+        }
+    }
+
+    impl<T: io::Write> Write for FromStd<T> {
+        async fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.write(buf)
+        }
+
+        async fn flush(&mut self) -> io::Result<()> {
+            self.0.flush()
+        }
+    }
+
+    impl Close for FromStd<std::net::TcpStream> {
+        async fn close(&mut self) -> io::Result<()> {
+            io::Write::flush(&mut self.0)?;
+            self.0.shutdown(std::net::Shutdown::Write)
+        }
+    }
+
+    impl<T: io::Read, P: Progress> io::Read for BodyReader<'_, FromStd<T>, P> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = buf.len().min(self.remaining);
+            if len == 0 {
+                return Ok(0);
+            }
+            let n = self.transport.0.read(&mut buf[..len])?;
+            if n == 0 {
+                return Err(truncated());
+            }
+            self.advance(n);
+            Ok(n)
+        }
+    }
+}
+
+/// Wraps a tokio stream as a GURT transport
+#[cfg(feature = "tokio")]
+#[derive(Debug, Default)]
+pub struct FromTokio<T>(pub T);
+
+#[cfg(feature = "tokio")]
+mod from_tokio {
+    use core::future::poll_fn;
+    use core::pin::Pin;
+    use core::task::{Context, Poll};
+    use std::io;
+    use std::path::Path;
+    use std::vec;
+
+    use embedded_io_async::{ErrorType, Read, Write};
+    use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+    use super::{FILE_CHUNK_SIZE, FromTokio, truncated};
+    use crate::progress::Progress;
+    use crate::{BodyReader, Close, GurtClient, GurtError, HEAD_BUFFER_SIZE, RequestHead};
+
+    impl<T> ErrorType for FromTokio<T> {
+        type Error = io::Error;
+    }
+
+    impl<T: AsyncRead + Unpin> Read for FromTokio<T> {
+        async fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let mut buf = ReadBuf::new(buf);
+            poll_fn(|cx| Pin::new(&mut self.0).poll_read(cx, &mut buf)).await?;
+            Ok(buf.filled().len())
+        }
+    }
+
+    impl<T: AsyncWrite + Unpin> Write for FromTokio<T> {
+        async fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            poll_fn(|cx| Pin::new(&mut self.0).poll_write(cx, buf)).await
+        }
+
+        async fn flush(&mut self) -> io::Result<()> {
+            poll_fn(|cx| Pin::new(&mut self.0).poll_flush(cx)).await
+        }
+    }
+
+    impl<T: AsyncWrite + Unpin> Close for FromTokio<T> {
+        /// Shut down the write side, which sends `close_notify` on a TLS stream
+        async fn close(&mut self) -> io::Result<()> {
+            poll_fn(|cx| Pin::new(&mut self.0).poll_shutdown(cx)).await
+        }
+    }
+
+    impl<T: AsyncRead + AsyncWrite + Unpin> GurtClient<FromTokio<T>> {
+        /// Send a request with the file at `path` as its body, telling `progress` how
+        /// much of it has been sent
+        ///
+        /// `head`'s `content-length` is set to the file's length, so a file larger than
+        /// [`MAX_MESSAGE_SIZE`](crate::MAX_MESSAGE_SIZE) fails with
+        /// [`Limit::MessageTooLarge`](crate::Limit::MessageTooLarge) before anything is
+        /// sent. The file is streamed [`FILE_CHUNK_SIZE`] bytes at a time rather than read
+        /// into memory; one that shrinks while being sent fails with
+        /// [`RequestError::BodyIncomplete`](crate::RequestError::BodyIncomplete). Read the
+        /// response with [`response_reader`](GurtClient::response_reader) as usual.
+        pub async fn send_file(
+            &mut self,
+            head: RequestHead<'_>,
+            path: impl AsRef<Path>,
+            progress: impl Progress,
+        ) -> Result<(), GurtError<io::Error>> {
+            let file = tokio::fs::File::open(path).await.map_err(GurtError::Io)?;
+            let length = file.metadata().await.map_err(GurtError::Io)?.len();
+            let head = head.content_length(usize::try_from(length).unwrap_or(usize::MAX));
+            let mut body = self
+                .send_head(&head, &mut [0; HEAD_BUFFER_SIZE])
+                .await?
+                .with_progress(progress);
+            body.write_from(&mut FromTokio(file), &mut vec![0; FILE_CHUNK_SIZE])
+                .await?;
+            body.finish().await
+        }
+    }
+
+    impl<T: AsyncRead + Unpin, P: Progress + Unpin> AsyncRead for BodyReader<'_, FromTokio<T>, P> {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            let this = self.get_mut();
+            let len = buf.remaining().min(this.remaining);
+            if len == 0 {
+                return Poll::Ready(Ok(()));
+            }
+            let mut limited = ReadBuf::new(buf.initialize_unfilled_to(len));
+            match Pin::new(&mut this.transport.0).poll_read(cx, &mut limited) {
+                Poll::Ready(Ok(())) => {}
+                other => return other,
+            }
+            let n = limited.filled().len();
+            if n == 0 {
+                return Poll::Ready(Err(truncated()));
+            }
+            buf.advance(n);
+            this.advance(n);
+            Poll::Ready(Ok(()))
+        }
+    }
+}
+
+/// Wraps a `futures-io` stream as a GURT transport
+#[cfg(feature = "futures")]
+#[derive(Debug, Default)]
+pub struct FromFutures<T>(pub T);
+
+#[cfg(feature = "futures")]
+mod from_futures {
+    use core::future::poll_fn;
+    use core::pin::Pin;
+    use core::task::{Context, Poll};
+    use std::io;
+
+    use embedded_io_async::{ErrorType, Read, Write};
+    use futures_io::{AsyncRead, AsyncWrite};
+
+    use super::{FromFutures, truncated};
+    use crate::progress::Progress;
+    use crate::{BodyReader, Close};
+
+    impl<T> ErrorType for FromFutures<T> {
+        type Error = io::Error;
+    }
+
+    impl<T: AsyncRead + Unpin> Read for FromFutures<T> {
+        async fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            poll_fn(|cx| Pin::new(&mut self.0).poll_read(cx, buf)).await
+        }
+    }
+
+    impl<T: AsyncWrite + Unpin> Write for FromFutures<T> {
+        async fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            poll_fn(|cx| Pin::new(&mut self.0).poll_write(cx, buf)).await
+        }
+
+        async fn flush(&mut self) -> io::Result<()> {
+            poll_fn(|cx| Pin::new(&mut self.0).poll_flush(cx)).await
+        }
+    }
+
+    impl<T: AsyncWrite + Unpin> Close for FromFutures<T> {
+        async fn close(&mut self) -> io::Result<()> {
+            poll_fn(|cx| Pin::new(&mut self.0).poll_close(cx)).await
+        }
+    }
+
+    impl<T: AsyncRead + Unpin, P: Progress + Unpin> AsyncRead for BodyReader<'_, FromFutures<T>, P> {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut [u8],
+        ) -> Poll<io::Result<usize>> {
+            let this = self.get_mut();
+            let len = buf.len().min(this.remaining);
+            if len == 0 {
+                return Poll::Ready(Ok(0));
+            }
+            match Pin::new(&mut this.transport.0).poll_read(cx, &mut buf[..len]) {
+                Poll::Ready(Ok(0)) => Poll::Ready(Err(truncated())),
+                Poll::Ready(Ok(n)) => {
+                    this.advance(n);
+                    Poll::Ready(Ok(n))
+                }
+                other => other,
+            }
+        }
+    }
+}
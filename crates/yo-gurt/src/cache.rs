@@ -0,0 +1,88 @@
+//! Response caching: `cache-control`, freshness and revalidation
+//!
+//! A private cache in the manner of RFC 9111, as a browser keeps one. A response to `GET`
+//! may be stored when it is `200 OK`, neither the request nor the response says
+//! `no-store`, and it either states how long it stays fresh or carries a validator
+//! (`etag` or `last-modified`):
+//!
+//! ```rust,ignore
+//! use portal_solutions_yo_gurt::cache::{CacheStore, CachedResponse, MemoryCache};
+//!
+//! let mut cache = MemoryCache::new(16 * 1024 * 1024);
+//! match cache.get("gurt://site.web/logo.png") {
+//!     Some(stored) if stored.is_fresh(now) => return Ok(stored.body),
+//!     Some(stored) => conditional.extend(stored.validators()),
+//!     None => {}
+//! }
+//! // ...send the request with `conditional` headers; on 304, stored.update(...)
+//! ```
+//!
+//! A response is fresh for its `max-age`, or else until its `expires` date, or else for
+//! a tenth of the time since its `last-modified` date, and never with `no-cache`. A stale
+//! response is revalidated with `if-none-match` and `if-modified-since`; a `304 NOT_MODIFIED`
+//! answer refreshes it with [`CachedResponse::update`]. Responses that vary on a request
+//! header other than `accept-encoding` are not stored, since the cache keys on the URL
+//! alone. Times are seconds since the Unix epoch, as in [`cookie`](crate::cookie).
+//!
+//! [`fetch::Client`](crate::fetch::Client) does all of this given a store. [`MemoryCache`]
+//! (`alloc` feature) keeps responses on the heap up to a size limit, and [`DiskCache`]
+//! (`std` feature) keeps one file per response in a directory.
+
+#[cfg(feature = "alloc")]
+mod store;
+
+#[cfg(feature = "std")]
+pub use store::DiskCache;
+#[cfg(feature = "alloc")]
+pub use store::{CacheStore, CachedResponse, MemoryCache};
+
+/// The `cache-control` directives this crate acts on (RFC 9111 section 5.2)
+///
+/// Unknown directives and invalid values are skipped. Several headers, or several
+/// directives of the same name, combine: flags are set by any of them and the smallest
+/// `max-age` wins.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheControl {
+    /// `max-age`: seconds the response stays fresh, or, on a request, the oldest
+    /// stored response it accepts
+    pub max_age: Option<u64>,
+    /// `no-cache`: revalidate before every use
+    pub no_cache: bool,
+    /// `no-store`: do not store the response at all
+    pub no_store: bool,
+}
+
+impl CacheControl {
+    /// Parse a `cache-control` header value
+    pub fn parse(value: &str) -> Self {
+        let mut directives = Self::default();
+        directives.add(value);
+        directives
+    }
+
+    /// Add the directives of another `cache-control` header value
+    pub fn add(&mut self, value: &str) {
+        for directive in value.split(',') {
+            let (name, argument) = directive.split_once('=').unwrap_or((directive, ""));
+            let name = name.trim();
+            let argument = argument.trim().trim_matches('"');
+            if name.eq_ignore_ascii_case("max-age") {
+                if let Ok(max_age) = argument.parse::<u64>() {
+                    self.max_age = Some(self.max_age.map_or(max_age, |old| old.min(max_age)));
+                }
+            } else if name.eq_ignore_ascii_case("no-cache") {
+                self.no_cache = true;
+            } else if name.eq_ignore_ascii_case("no-store") {
+                self.no_store = true;
+            }
+        }
+    }
+}
+
+/// Parse an HTTP date, as in `expires` and `last-modified`, into seconds since the Unix
+/// epoch
+///
+/// Accepts the RFC 9110 formats and the variants cookies use.
+pub fn parse_date(date: &str) -> Option<u64> {
+    crate::cookie::date::parse(date)
+}
@@ -0,0 +1,384 @@
+//! Stored responses and where they are kept
+
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use super::{CacheControl, parse_date};
+use crate::StatusCode;
+
+/// A stored response: status, headers and the decoded body
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedResponse {
+    /// Response status
+    pub status: StatusCode,
+    /// Headers in the order received, with lowercase names
+    ///
+    /// The body is stored decoded, so `content-encoding` and `content-length` are left
+    /// out.
+    pub headers: Vec<(String, String)>,
+    /// The body after any `content-encoding` is decoded
+    pub body: Vec<u8>,
+    /// When the response was generated: the time it was received, less its `age` header
+    pub stored_at: u64,
+}
+
+impl CachedResponse {
+    /// A response received at `now`
+    ///
+    /// `content-encoding` and `content-length` are dropped from `headers`, as `body` must
+    /// already be decoded.
+    pub fn new(status: StatusCode, headers: &[(String, String)], body: Vec<u8>, now: u64) -> Self {
+        let mut response = Self {
+            status,
+            headers: Vec::new(),
+            body,
+            stored_at: now,
+        };
+        response.update(headers, now);
+        response
+    }
+
+    /// The first value of the header `name` (lowercase)
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The response's `cache-control` directives
+    pub fn cache_control(&self) -> CacheControl {
+        let mut directives = CacheControl::default();
+        for (_, value) in self.headers.iter().filter(|(n, _)| n == "cache-control") {
+            directives.add(value);
+        }
+        directives
+    }
+
+    /// Seconds the response stays fresh after it was generated
+    pub fn freshness_lifetime(&self) -> u64 {
+        if let Some(max_age) = self.cache_control().max_age {
+            return max_age;
+        }
+        let date = self
+            .header("date")
+            .and_then(parse_date)
+            .unwrap_or(self.stored_at);
+        if let Some(expires) = self.header("expires") {
+            // An invalid date, such as "0", means already expired
+            return parse_date(expires).map_or(0, |expires| expires.saturating_sub(date));
+        }
+        match self.header("last-modified").and_then(parse_date) {
+            Some(modified) => date.saturating_sub(modified) / 10,
+            None => 0,
+        }
+    }
+
+    /// Seconds since the response was generated
+    pub fn age(&self, now: u64) -> u64 {
+        now.saturating_sub(self.stored_at)
+    }
+
+    /// Whether the response can be used at `now` without asking the server
+    pub fn is_fresh(&self, now: u64) -> bool {
+        !self.cache_control().no_cache && self.age(now) < self.freshness_lifetime()
+    }
+
+    /// Whether the response is worth storing for a request with `request` directives
+    pub fn is_storable(&self, request: &CacheControl) -> bool {
+        let directives = self.cache_control();
+        let varies = self
+            .headers
+            .iter()
+            .filter(|(name, _)| name == "vary")
+            .flat_map(|(_, value)| value.split(','))
+            .any(|name| !name.trim().eq_ignore_ascii_case("accept-encoding"));
+        self.status == StatusCode::Ok
+            && !request.no_store
+            && !directives.no_store
+            && !varies
+            && (self.freshness_lifetime() > 0
+                || self.header("etag").is_some()
+                || self.header("last-modified").is_some())
+    }
+
+    /// The `if-none-match` and `if-modified-since` headers that revalidate this response
+    pub fn validators(&self) -> Vec<(String, String)> {
+        let mut headers = Vec::new();
+        if let Some(etag) = self.header("etag") {
+            headers.push(("if-none-match".to_string(), etag.to_string()));
+        }
+        if let Some(modified) = self.header("last-modified") {
+            headers.push(("if-modified-since".to_string(), modified.to_string()));
+        }
+        headers
+    }
+
+    /// Take the headers of a `304 NOT_MODIFIED` received at `now`, replacing stored
+    /// headers of the same names
+    pub fn update(&mut self, headers: &[(String, String)], now: u64) {
+        let headers: Vec<&(String, String)> = headers
+            .iter()
+            .filter(|(name, _)| name != "content-encoding" && name != "content-length")
+            .collect();
+        self.headers
+            .retain(|(name, _)| !headers.iter().any(|(n, _)| n == name));
+        self.headers.extend(headers.into_iter().cloned());
+        let age = self.header("age").and_then(|age| age.trim().parse().ok());
+        self.stored_at = now.saturating_sub(age.unwrap_or(0));
+    }
+
+    /// Bytes the response takes up, roughly
+    fn size(&self) -> usize {
+        self.body.len()
+            + self
+                .headers
+                .iter()
+                .map(|(name, value)| name.len() + value.len())
+                .sum::<usize>()
+    }
+}
+
+/// Storage for cached responses, keyed by URL
+///
+/// A store may drop responses at any time; the cache then fetches them again.
+pub trait CacheStore {
+    /// The response stored under `key`
+    fn get(&mut self, key: &str) -> Option<CachedResponse>;
+
+    /// Store `response` under `key`, replacing any response stored there
+    fn put(&mut self, key: &str, response: CachedResponse);
+
+    /// Remove the response stored under `key`
+    fn remove(&mut self, key: &str);
+}
+
+impl<S: CacheStore + ?Sized> CacheStore for &mut S {
+    fn get(&mut self, key: &str) -> Option<CachedResponse> {
+        S::get(self, key)
+    }
+
+    fn put(&mut self, key: &str, response: CachedResponse) {
+        S::put(self, key, response)
+    }
+
+    fn remove(&mut self, key: &str) {
+        S::remove(self, key)
+    }
+}
+
+impl<S: CacheStore + ?Sized> CacheStore for alloc::boxed::Box<S> {
+    fn get(&mut self, key: &str) -> Option<CachedResponse> {
+        S::get(self, key)
+    }
+
+    fn put(&mut self, key: &str, response: CachedResponse) {
+        S::put(self, key, response)
+    }
+
+    fn remove(&mut self, key: &str) {
+        S::remove(self, key)
+    }
+}
+
+/// Cached responses on the heap, up to a total size
+///
+/// When full, the least recently used responses make room for new ones; a response
+/// larger than the whole cache is not stored.
+#[derive(Debug, Clone)]
+pub struct MemoryCache {
+    entries: BTreeMap<String, (u64, CachedResponse)>,
+    capacity: usize,
+    size: usize,
+    /// Use counter, for finding the least recently used entry
+    clock: u64,
+}
+
+impl MemoryCache {
+    /// An empty cache holding up to `capacity` bytes of bodies and headers
+    pub const fn new(capacity: usize) -> Self {
+        Self {
+            entries: BTreeMap::new(),
+            capacity,
+            size: 0,
+            clock: 0,
+        }
+    }
+
+    /// Number of responses held
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether the cache holds no responses
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Bytes of bodies and headers held
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Remove every response
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.size = 0;
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+}
+
+impl CacheStore for MemoryCache {
+    fn get(&mut self, key: &str) -> Option<CachedResponse> {
+        let now = self.tick();
+        let (used, response) = self.entries.get_mut(key)?;
+        *used = now;
+        Some(response.clone())
+    }
+
+    fn put(&mut self, key: &str, response: CachedResponse) {
+        self.remove(key);
+        let size = response.size();
+        if size > self.capacity {
+            return;
+        }
+        while self.size + size > self.capacity {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (used, _))| *used)
+                .map(|(key, _)| key.clone());
+            match oldest {
+                Some(oldest) => self.remove(&oldest),
+                None => break,
+            }
+        }
+        let now = self.tick();
+        self.size += size;
+        self.entries.insert(key.to_string(), (now, response));
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some((_, response)) = self.entries.remove(key) {
+            self.size -= response.size();
+        }
+    }
+}
+
+/// Cached responses as files in a directory, one per URL
+///
+/// Files are named after a hash of the URL and hold the URL, status, time, headers and
+/// body. Unreadable or foreign files count as missing, and write failures are ignored:
+/// the response is fetched again next time.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
+pub struct DiskCache {
+    dir: std::path::PathBuf,
+}
+
+#[cfg(feature = "std")]
+impl DiskCache {
+    /// First line of every cache file
+    const MAGIC: &'static str = "gurt-cache 1";
+
+    /// A cache in `dir`, which is created if missing
+    pub fn open(dir: impl Into<std::path::PathBuf>) -> std::io::Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    /// The directory holding the cache files
+    pub fn dir(&self) -> &std::path::Path {
+        &self.dir
+    }
+
+    fn path(&self, key: &str) -> std::path::PathBuf {
+        // FNV-1a, which stays the same across builds and processes
+        let hash = key.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, b| {
+            (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+        });
+        self.dir.join(alloc::format!("{hash:016x}.cache"))
+    }
+
+    fn read(&self, key: &str) -> Option<CachedResponse> {
+        let data = std::fs::read(self.path(key)).ok()?;
+        let mut rest = data.as_slice();
+        let mut line = || {
+            let end = rest.iter().position(|&b| b == b'\n')?;
+            let line = core::str::from_utf8(&rest[..end]).ok()?;
+            rest = &rest[end + 1..];
+            Some(line)
+        };
+        if line()? != Self::MAGIC || line()? != key {
+            return None;
+        }
+        let status = StatusCode::from_u16(line()?.parse().ok()?)?;
+        let stored_at = line()?.parse().ok()?;
+        let mut headers = Vec::new();
+        loop {
+            match line()? {
+                "" => break,
+                header => {
+                    let (name, value) = header.split_once(": ")?;
+                    headers.push((name.to_string(), value.to_string()));
+                }
+            }
+        }
+        Some(CachedResponse {
+            status,
+            headers,
+            body: rest.to_vec(),
+            stored_at,
+        })
+    }
+
+    fn write(&self, key: &str, response: &CachedResponse) -> std::io::Result<()> {
+        use std::io::Write;
+
+        let path = self.path(key);
+        let temporary = path.with_extension("tmp");
+        let mut file = std::io::BufWriter::new(std::fs::File::create(&temporary)?);
+        writeln!(file, "{}\n{key}", Self::MAGIC)?;
+        writeln!(file, "{}\n{}", response.status.as_u16(), response.stored_at)?;
+        for (name, value) in &response.headers {
+            writeln!(file, "{name}: {value}")?;
+        }
+        writeln!(file)?;
+        file.write_all(&response.body)?;
+        file.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        std::fs::rename(temporary, path)
+    }
+}
+
+#[cfg(feature = "std")]
+impl CacheStore for DiskCache {
+    fn get(&mut self, key: &str) -> Option<CachedResponse> {
+        self.read(key)
+    }
+
+    fn put(&mut self, key: &str, response: CachedResponse) {
+        // Keys and headers with line breaks cannot be written back
+        let multiline = |s: &str| s.contains(['\r', '\n']);
+        if multiline(key)
+            || response
+                .headers
+                .iter()
+                .any(|(name, value)| multiline(name) || multiline(value))
+        {
+            return;
+        }
+        if self.write(key, &response).is_err() {
+            let _ = std::fs::remove_file(self.path(key));
+        }
+    }
+
+    fn remove(&mut self, key: &str) {
+        let _ = std::fs::remove_file(self.path(key));
+    }
+}
@@ -0,0 +1,334 @@
+//! Traffic capture: a transport wrapper that tees every byte into a [`Sink`]
+//!
+//! GURT runs inside TLS, so packet captures show nothing without the session keys.
+//! [`Capture`] sits between a [`GurtClient`](crate::GurtClient) or
+//! [`GurtServer`](crate::server::GurtServer) and its transport and hands each chunk read
+//! or written, in order, to a sink. Any `FnMut(Direction, &[u8])` closure is a sink; with
+//! `std`, [`Writer`] stores the chunks in the capture format below, which the `gurt-dump`
+//! tool in `yo-gurt-dump` turns back into annotated messages.
+//!
+//! ```rust,ignore
+//! let file = capture::Writer::create("session.gcap")?;
+//! let mut client = GurtClient::new(Capture::new(transport, file));
+//! // ... requests ...
+//! client.transport.into_sink().finish()?;
+//! ```
+//!
+//! A capture starts with [`MAGIC`], followed by one record per chunk:
+//!
+//! ```text
+//! direction: u8        0 = received, 1 = sent
+//! timestamp: u64 BE    microseconds since the capture started
+//! length: u32 BE
+//! bytes: [u8; length]
+//! ```
+//!
+//! [`records`] reads the records back, without allocating.
+
+use core::fmt;
+
+use embedded_io_async::{ErrorType, Read, Write};
+
+use crate::Close;
+
+/// Bytes every capture starts with
+pub const MAGIC: &[u8; 8] = b"GURTCAP1";
+
+/// Length of a record header: direction, timestamp and length
+pub const RECORD_HEADER_LEN: usize = 1 + 8 + 4;
+
+/// Which way a captured chunk went, seen from the captured side of the connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Direction {
+    /// Read from the transport
+    Received,
+    /// Written to the transport
+    Sent,
+}
+
+impl Direction {
+    /// The direction byte of a capture record
+    pub const fn as_u8(self) -> u8 {
+        match self {
+            Direction::Received => 0,
+            Direction::Sent => 1,
+        }
+    }
+
+    /// Parse a direction byte
+    pub const fn from_u8(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(Direction::Received),
+            1 => Some(Direction::Sent),
+            _ => None,
+        }
+    }
+}
+
+/// Receiver of captured traffic
+pub trait Sink {
+    /// Called with every chunk read from or written to the transport, in order
+    fn record(&mut self, direction: Direction, bytes: &[u8]);
+}
+
+impl<F: FnMut(Direction, &[u8])> Sink for F {
+    fn record(&mut self, direction: Direction, bytes: &[u8]) {
+        self(direction, bytes)
+    }
+}
+
+/// A transport that passes everything through and tees it into a [`Sink`]
+///
+/// Writes are recorded as far as the transport accepted them, and nothing is recorded for
+/// failed reads or writes.
+pub struct Capture<T, S> {
+    transport: T,
+    sink: S,
+}
+
+impl<T, S: Sink> Capture<T, S> {
+    /// Capture the traffic of `transport` into `sink`
+    pub fn new(transport: T, sink: S) -> Self {
+        Self { transport, sink }
+    }
+}
+
+impl<T, S> Capture<T, S> {
+    /// The wrapped transport
+    pub fn get_ref(&self) -> &T {
+        &self.transport
+    }
+
+    /// The wrapped transport; traffic through it directly is not captured
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.transport
+    }
+
+    /// The sink
+    pub fn sink(&self) -> &S {
+        &self.sink
+    }
+
+    /// The sink
+    pub fn sink_mut(&mut self) -> &mut S {
+        &mut self.sink
+    }
+
+    /// Stop capturing, returning the sink
+    pub fn into_sink(self) -> S {
+        self.sink
+    }
+
+    /// Stop capturing, returning the transport and the sink
+    pub fn into_parts(self) -> (T, S) {
+        (self.transport, self.sink)
+    }
+}
+
+impl<T: ErrorType, S> ErrorType for Capture<T, S> {
+    type Error = T::Error;
+}
+
+impl<T: Read, S: Sink> Read for Capture<T, S> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let n = self.transport.read(buf).await?;
+        if n > 0 {
+            self.sink.record(Direction::Received, &buf[..n]);
+        }
+        Ok(n)
+    }
+}
+
+impl<T: Write, S: Sink> Write for Capture<T, S> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        let n = self.transport.write(buf).await?;
+        if n > 0 {
+            self.sink.record(Direction::Sent, &buf[..n]);
+        }
+        Ok(n)
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.transport.flush().await
+    }
+}
+
+impl<T: Close, S: Sink> Close for Capture<T, S> {
+    async fn close(&mut self) -> Result<(), Self::Error> {
+        self.transport.close().await
+    }
+}
+
+/// One captured chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Record<'a> {
+    /// Which way the chunk went
+    pub direction: Direction,
+    /// Microseconds since the capture started
+    pub timestamp: u64,
+    /// The bytes read or written
+    pub bytes: &'a [u8],
+}
+
+/// Why a capture could not be read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CaptureError {
+    /// The data does not start with [`MAGIC`]
+    NotACapture,
+    /// The record at this offset has an unknown direction byte
+    InvalidDirection(usize),
+    /// The record at this offset is cut short
+    Truncated(usize),
+}
+
+impl fmt::Display for CaptureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CaptureError::NotACapture => f.write_str("not a GURT capture"),
+            CaptureError::InvalidDirection(offset) => {
+                write!(f, "invalid record direction at offset {offset}")
+            }
+            CaptureError::Truncated(offset) => write!(f, "truncated record at offset {offset}"),
+        }
+    }
+}
+
+impl core::error::Error for CaptureError {}
+
+/// The records of a capture, in the order they were captured
+pub fn records(capture: &[u8]) -> Result<Records<'_>, CaptureError> {
+    match capture.strip_prefix(MAGIC.as_slice()) {
+        Some(rest) => Ok(Records {
+            rest,
+            offset: MAGIC.len(),
+        }),
+        None => Err(CaptureError::NotACapture),
+    }
+}
+
+/// Iterator over the records of a capture, from [`records`]
+///
+/// Ends after the first error.
+#[derive(Debug, Clone)]
+pub struct Records<'a> {
+    rest: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Records<'a> {
+    /// Offset of the next record in the capture
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    fn next_record(&mut self) -> Result<Record<'a>, CaptureError> {
+        let Some((header, rest)) = self.rest.split_first_chunk::<RECORD_HEADER_LEN>() else {
+            return Err(CaptureError::Truncated(self.offset));
+        };
+        let direction =
+            Direction::from_u8(header[0]).ok_or(CaptureError::InvalidDirection(self.offset))?;
+        let timestamp = u64::from_be_bytes(header[1..9].try_into().unwrap());
+        let length = u32::from_be_bytes(header[9..].try_into().unwrap()) as usize;
+        let Some((bytes, rest)) = rest.split_at_checked(length) else {
+            return Err(CaptureError::Truncated(self.offset));
+        };
+        self.rest = rest;
+        self.offset += RECORD_HEADER_LEN + length;
+        Ok(Record {
+            direction,
+            timestamp,
+            bytes,
+        })
+    }
+}
+
+impl<'a> Iterator for Records<'a> {
+    type Item = Result<Record<'a>, CaptureError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.rest.is_empty() {
+            return None;
+        }
+        let record = self.next_record();
+        if record.is_err() {
+            self.rest = &[];
+        }
+        Some(record)
+    }
+}
+
+#[cfg(feature = "std")]
+pub use writer::Writer;
+
+#[cfg(feature = "std")]
+mod writer {
+    use std::fs::File;
+    use std::io::{self, BufWriter};
+    use std::path::Path;
+    use std::time::Instant;
+
+    use super::{Direction, MAGIC, Sink};
+
+    /// A [`Sink`] writing the capture format to `W`, such as a file
+    ///
+    /// Sinks cannot fail, so the first write error is kept and returned by
+    /// [`finish`](Self::finish); nothing more is written after it.
+    #[derive(Debug)]
+    pub struct Writer<W: io::Write> {
+        out: W,
+        started: Instant,
+        error: Option<io::Error>,
+    }
+
+    impl Writer<BufWriter<File>> {
+        /// Start a capture in a new file at `path`
+        pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+            Self::new(BufWriter::new(File::create(path)?))
+        }
+    }
+
+    impl<W: io::Write> Writer<W> {
+        /// Start a capture in `out`; timestamps count from now
+        pub fn new(mut out: W) -> io::Result<Self> {
+            out.write_all(MAGIC)?;
+            Ok(Self {
+                out,
+                started: Instant::now(),
+                error: None,
+            })
+        }
+
+        /// Flush the capture and return `out`, or the first write error
+        pub fn finish(mut self) -> io::Result<W> {
+            if let Some(e) = self.error.take() {
+                return Err(e);
+            }
+            self.out.flush()?;
+            Ok(self.out)
+        }
+
+        fn write(&mut self, direction: Direction, bytes: &[u8]) -> io::Result<()> {
+            let timestamp = self.started.elapsed().as_micros() as u64;
+            for chunk in bytes.chunks(u32::MAX as usize) {
+                self.out.write_all(&[direction.as_u8()])?;
+                self.out.write_all(&timestamp.to_be_bytes())?;
+                self.out.write_all(&(chunk.len() as u32).to_be_bytes())?;
+                self.out.write_all(chunk)?;
+            }
+            Ok(())
+        }
+    }
+
+    impl<W: io::Write> Sink for Writer<W> {
+        fn record(&mut self, direction: Direction, bytes: &[u8]) {
+            if self.error.is_none()
+                && let Err(e) = self.write(direction, bytes)
+            {
+                self.error = Some(e);
+            }
+        }
+    }
+}
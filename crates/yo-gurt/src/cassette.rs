@@ -0,0 +1,579 @@
+//! Record-and-replay cassettes for deterministic client tests
+//!
+//! Enabled by the `testing` feature. In record mode a [`Recording`] sink under a
+//! [`Capture`](crate::capture::Capture) keeps everything a client sends and receives
+//! over a real transport, and [`Recording::cassette`] splits it into request/response
+//! [`Exchange`]s. In replay mode [`Replay`] is the transport: it reads each request the
+//! client writes, finds the recorded exchange with the same method, path and selected
+//! headers, and serves its response back through `embedded_io_async`, so the test runs
+//! offline and gets the same bytes every time.
+//!
+//! ```rust,ignore
+//! use portal_solutions_yo_gurt::capture::Capture;
+//! use portal_solutions_yo_gurt::cassette::{Cassette, Recording, Replay};
+//!
+//! if std::env::var_os("GURT_RECORD").is_some() {
+//!     let mut client = GurtClient::new(Capture::new(connect().await?, Recording::new()));
+//!     exercise(&mut client).await;
+//!     client.transport.into_sink().cassette()?.save("tests/cassettes/api.cassette")?;
+//! } else {
+//!     let cassette = Cassette::load("tests/cassettes/api.cassette")?;
+//!     let mut client = GurtClient::new(Replay::new(cassette).match_header("host"));
+//!     exercise(&mut client).await;
+//!     assert!(client.transport.is_finished());
+//! }
+//! ```
+//!
+//! A cassette file is text where the messages are: a `GURT-CASSETTE/1` line, then for
+//! each exchange a `request <length>` line, the request bytes and a newline, and a
+//! `response <length>` line, the response bytes and a newline. Recorded messages can be
+//! edited by hand as long as the lengths are kept in step.
+
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::convert::Infallible;
+use core::fmt;
+use core::pin::pin;
+use core::task::{Context, Poll, Waker};
+
+use embedded_io_async::{ErrorKind, ErrorType, Read, Write};
+
+use crate::capture::{Direction, Sink};
+use crate::server::RequestReader;
+use crate::{Close, GurtError, Method, ProtocolError, ResponseReader, StatusCode};
+
+/// First line of every cassette file
+pub const CASSETTE_HEADER: &str = "GURT-CASSETTE/1";
+
+/// One recorded request and the response it got
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Exchange {
+    method: Method,
+    path: String,
+    headers: Vec<(String, String)>,
+    request: Vec<u8>,
+    response: Vec<u8>,
+}
+
+impl Exchange {
+    /// An exchange of complete `request` and `response` messages, as sent on the wire
+    ///
+    /// Fails if `request` is not exactly one complete GURT request.
+    pub fn new(request: Vec<u8>, response: Vec<u8>) -> Result<Self, CassetteError> {
+        match parse_request(&request).map_err(CassetteError::InvalidRequest)? {
+            Some((head, len)) if len == request.len() => Ok(Self {
+                method: head.method,
+                path: head.path,
+                headers: head.headers,
+                request,
+                response,
+            }),
+            // Bytes after the body, which would have to start another request
+            Some(_) => Err(CassetteError::InvalidRequest(
+                ProtocolError::InvalidRequestLine.into(),
+            )),
+            None => Err(CassetteError::InvalidRequest(GurtError::UnexpectedEof)),
+        }
+    }
+
+    /// The request method
+    pub fn method(&self) -> Method {
+        self.method
+    }
+
+    /// The request path
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// The first value of request header `name` (lowercase)
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// The request as sent
+    pub fn request(&self) -> &[u8] {
+        &self.request
+    }
+
+    /// The response as received
+    pub fn response(&self) -> &[u8] {
+        &self.response
+    }
+}
+
+/// Recorded exchanges, in the order they happened
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Cassette {
+    exchanges: Vec<Exchange>,
+}
+
+impl Cassette {
+    /// An empty cassette
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The recorded exchanges
+    pub fn exchanges(&self) -> &[Exchange] {
+        &self.exchanges
+    }
+
+    /// Add an exchange at the end, for example one recorded on another connection
+    pub fn push(&mut self, exchange: Exchange) {
+        self.exchanges.push(exchange);
+    }
+
+    /// The cassette in the file format described in the [module docs](self)
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(CASSETTE_HEADER.as_bytes());
+        out.push(b'\n');
+        for exchange in &self.exchanges {
+            for (label, message) in [
+                ("request", &exchange.request),
+                ("response", &exchange.response),
+            ] {
+                out.extend_from_slice(label.as_bytes());
+                out.push(b' ');
+                out.extend_from_slice(message.len().to_string().as_bytes());
+                out.push(b'\n');
+                out.extend_from_slice(message);
+                out.push(b'\n');
+            }
+        }
+        out
+    }
+
+    /// Read a cassette written by [`to_bytes`](Self::to_bytes)
+    pub fn from_bytes(data: &[u8]) -> Result<Self, CassetteError> {
+        let mut rest = data
+            .strip_prefix(CASSETTE_HEADER.as_bytes())
+            .and_then(|rest| rest.strip_prefix(b"\n"))
+            .ok_or(CassetteError::NotACassette)?;
+        let mut cassette = Self::new();
+        while !rest.is_empty() {
+            let request = take_message(data, &mut rest, "request")?;
+            let response = take_message(data, &mut rest, "response")?;
+            cassette.push(Exchange::new(request.to_vec(), response.to_vec())?);
+        }
+        Ok(cassette)
+    }
+
+    /// Read a cassette file
+    #[cfg(feature = "std")]
+    pub fn load(path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
+        let data = std::fs::read(path)?;
+        Self::from_bytes(&data).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+
+    /// Write the cassette to a file, replacing it
+    #[cfg(feature = "std")]
+    pub fn save(&self, path: impl AsRef<std::path::Path>) -> std::io::Result<()> {
+        std::fs::write(path, self.to_bytes())
+    }
+}
+
+/// Take one `<label> <length>` line and the message after it from `rest`
+fn take_message<'a>(
+    data: &[u8],
+    rest: &mut &'a [u8],
+    label: &str,
+) -> Result<&'a [u8], CassetteError> {
+    let malformed = || CassetteError::Malformed(data.len() - rest.len());
+    let line_end = rest
+        .iter()
+        .position(|&b| b == b'\n')
+        .ok_or_else(malformed)?;
+    let length = rest[..line_end]
+        .strip_prefix(label.as_bytes())
+        .and_then(|length| length.strip_prefix(b" "))
+        .and_then(|length| core::str::from_utf8(length).ok())
+        .and_then(|length| length.parse::<usize>().ok())
+        .ok_or_else(malformed)?;
+    let message = &rest[line_end + 1..];
+    if message.len() <= length || message[length] != b'\n' {
+        return Err(malformed());
+    }
+    *rest = &message[length + 1..];
+    Ok(&message[..length])
+}
+
+/// Why a cassette could not be read or recorded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CassetteError {
+    /// The data does not start with [`CASSETTE_HEADER`]
+    NotACassette,
+    /// The cassette is malformed at this offset
+    Malformed(usize),
+    /// A recorded request is not one valid GURT request
+    InvalidRequest(GurtError<Infallible>),
+    /// A recorded response is not a valid GURT response
+    InvalidResponse(GurtError<Infallible>),
+    /// A recorded response that may have a body has no `content-length`, so where it
+    /// ends is unknown
+    UnframedResponse,
+}
+
+impl fmt::Display for CassetteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CassetteError::NotACassette => f.write_str("not a GURT cassette"),
+            CassetteError::Malformed(offset) => write!(f, "malformed cassette at offset {offset}"),
+            CassetteError::InvalidRequest(e) => write!(f, "invalid recorded request: {e}"),
+            CassetteError::InvalidResponse(e) => write!(f, "invalid recorded response: {e}"),
+            CassetteError::UnframedResponse => {
+                f.write_str("recorded response without content-length")
+            }
+        }
+    }
+}
+
+impl core::error::Error for CassetteError {}
+
+/// A [`Sink`] keeping a connection's traffic, for recording a [`Cassette`]
+#[derive(Debug, Clone, Default)]
+pub struct Recording {
+    sent: Vec<u8>,
+    received: Vec<u8>,
+}
+
+impl Recording {
+    /// An empty recording
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The exchanges recorded so far
+    ///
+    /// A last request without a complete response is left out. Fails with
+    /// [`CassetteError::UnframedResponse`] for a response whose end cannot be told.
+    pub fn cassette(&self) -> Result<Cassette, CassetteError> {
+        let mut cassette = Cassette::new();
+        let mut requests = self.sent.as_slice();
+        let mut responses = self.received.as_slice();
+        while let Some((head, len)) =
+            parse_request(requests).map_err(CassetteError::InvalidRequest)?
+        {
+            let Some(response_len) = parse_response(responses, head.method)? else {
+                break;
+            };
+            cassette.push(Exchange {
+                method: head.method,
+                path: head.path,
+                headers: head.headers,
+                request: requests[..len].to_vec(),
+                response: responses[..response_len].to_vec(),
+            });
+            requests = &requests[len..];
+            responses = &responses[response_len..];
+        }
+        Ok(cassette)
+    }
+}
+
+impl Sink for Recording {
+    fn record(&mut self, direction: Direction, bytes: &[u8]) {
+        match direction {
+            Direction::Sent => self.sent.extend_from_slice(bytes),
+            Direction::Received => self.received.extend_from_slice(bytes),
+        }
+    }
+}
+
+/// A transport serving the responses of a [`Cassette`]
+///
+/// Each request written is matched against the exchanges not yet served, in recorded
+/// order, by method, path and the headers named with [`match_header`](Self::match_header);
+/// the first match's response is then read back. An exchange is served once, so a
+/// request made twice needs two recordings.
+#[derive(Debug, Clone)]
+pub struct Replay {
+    exchanges: Vec<(Exchange, bool)>,
+    headers: Vec<String>,
+    /// Bytes of the request head being written
+    request: Vec<u8>,
+    /// The exchange matched by the last head written, and the bytes of its body still to
+    /// come before it is served
+    body: Option<(usize, usize)>,
+    /// Response bytes not yet read
+    response: Vec<u8>,
+    read: usize,
+}
+
+impl Replay {
+    /// Serve the exchanges in `cassette`, matching on method and path
+    pub fn new(cassette: Cassette) -> Self {
+        Self {
+            exchanges: cassette
+                .exchanges
+                .into_iter()
+                .map(|exchange| (exchange, false))
+                .collect(),
+            headers: Vec::new(),
+            request: Vec::new(),
+            body: None,
+            response: Vec::new(),
+            read: 0,
+        }
+    }
+
+    /// Also require request header `name` to have the recorded value, or to be missing
+    /// from both
+    pub fn match_header(mut self, name: &str) -> Self {
+        self.headers.push(name.to_ascii_lowercase());
+        self
+    }
+
+    /// Whether every recorded exchange was served
+    pub fn is_finished(&self) -> bool {
+        self.exchanges.iter().all(|(_, served)| *served)
+    }
+
+    /// The exchanges not served yet
+    pub fn unused(&self) -> impl Iterator<Item = &Exchange> {
+        self.exchanges
+            .iter()
+            .filter(|(_, served)| !served)
+            .map(|(exchange, _)| exchange)
+    }
+
+    /// Take `bytes` written, serving each request once its body is complete
+    ///
+    /// Heads are matched as soon as they end, so each is parsed once, and bodies are only
+    /// counted.
+    fn serve(&mut self, mut bytes: &[u8]) -> Result<(), ReplayError> {
+        loop {
+            if let Some((index, remaining)) = &mut self.body {
+                let n = bytes.len().min(*remaining);
+                *remaining -= n;
+                bytes = &bytes[n..];
+                if *remaining > 0 {
+                    return Ok(());
+                }
+                let index = *index;
+                self.body = None;
+                self.exchanges[index].1 = true;
+                self.response.drain(..self.read);
+                self.read = 0;
+                self.response
+                    .extend_from_slice(&self.exchanges[index].0.response);
+            }
+            if bytes.is_empty() {
+                return Ok(());
+            }
+            // Only the new bytes, and the three before them, can end the head
+            let written = self.request.len();
+            let start = written.saturating_sub(3);
+            self.request.extend_from_slice(bytes);
+            let Some(at) = self.request[start..]
+                .windows(4)
+                .position(|w| w == b"\r\n\r\n")
+            else {
+                return Ok(());
+            };
+            let end = start + at + 4;
+            bytes = &bytes[end - written..];
+            self.request.truncate(end);
+            let head = parse_head(&self.request).map_err(ReplayError::Invalid)?;
+            self.request.clear();
+            let Some((head, _, length)) = head else {
+                return Err(ReplayError::Invalid(GurtError::UnexpectedEof));
+            };
+            let index = self
+                .exchanges
+                .iter()
+                .position(|(exchange, served)| {
+                    !served
+                        && exchange.method == head.method
+                        && exchange.path == head.path
+                        && self
+                            .headers
+                            .iter()
+                            .all(|name| exchange.header(name) == header(&head.headers, name))
+                })
+                .ok_or(ReplayError::Unmatched)?;
+            self.body = Some((index, length));
+        }
+    }
+}
+
+impl ErrorType for Replay {
+    type Error = ReplayError;
+}
+
+impl Read for Replay {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, ReplayError> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let available = &self.response[self.read..];
+        if available.is_empty() {
+            return Err(ReplayError::NoResponse);
+        }
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.read += n;
+        Ok(n)
+    }
+}
+
+impl Write for Replay {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, ReplayError> {
+        self.serve(buf)?;
+        Ok(buf.len())
+    }
+
+    async fn flush(&mut self) -> Result<(), ReplayError> {
+        Ok(())
+    }
+}
+
+impl Close for Replay {
+    async fn close(&mut self) -> Result<(), ReplayError> {
+        Ok(())
+    }
+}
+
+/// Errors from a [`Replay`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplayError {
+    /// No unserved exchange matches the request written
+    Unmatched,
+    /// A read came with no response to serve: no request was written, or all of it was
+    /// already read
+    NoResponse,
+    /// The bytes written are not a valid GURT request
+    Invalid(GurtError<Infallible>),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Unmatched => f.write_str("no recorded exchange matches the request"),
+            ReplayError::NoResponse => f.write_str("no recorded response to read"),
+            ReplayError::Invalid(e) => write!(f, "invalid request: {e}"),
+        }
+    }
+}
+
+impl core::error::Error for ReplayError {}
+
+impl embedded_io_async::Error for ReplayError {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            ReplayError::Unmatched => ErrorKind::NotFound,
+            ReplayError::NoResponse => ErrorKind::BrokenPipe,
+            ReplayError::Invalid(_) => ErrorKind::InvalidData,
+        }
+    }
+}
+
+/// The parts of a request head matched on
+struct RequestHead {
+    method: Method,
+    path: String,
+    headers: Vec<(String, String)>,
+}
+
+fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(n, _)| n == name)
+        .map(|(_, v)| v.as_str())
+}
+
+/// Parse the request at the start of `bytes` and its length with the body, or `None`
+/// if it is not complete yet
+fn parse_request(bytes: &[u8]) -> Result<Option<(RequestHead, usize)>, GurtError<Infallible>> {
+    let Some((head, head_len, length)) = parse_head(bytes)? else {
+        return Ok(None);
+    };
+    let len = head_len + length;
+    Ok((len <= bytes.len()).then_some((head, len)))
+}
+
+/// Parse the request head at the start of `bytes`, with its length and the body's, or
+/// `None` if it is not complete yet
+fn parse_head(bytes: &[u8]) -> Result<Option<(RequestHead, usize, usize)>, GurtError<Infallible>> {
+    if bytes.is_empty() {
+        return Ok(None);
+    }
+    let mut rest = bytes;
+    let mut buf = vec![0u8; bytes.len() + 1];
+    let head = complete(async {
+        let mut reader = RequestReader::new(&mut rest);
+        let line = reader.read_request_line(&mut buf).await?;
+        let path = String::from_utf8_lossy(line.path(&buf)).into_owned();
+        let mut headers = Vec::new();
+        while let Some(header) = reader.read_header(&mut buf).await? {
+            headers.push((
+                String::from_utf8_lossy(header.name(&buf)).into_owned(),
+                String::from_utf8_lossy(header.value(&buf)).into_owned(),
+            ));
+        }
+        let length = reader.content_length().unwrap_or(0);
+        Ok((line.method, path, headers, length))
+    });
+    let (method, path, headers, length) = match head {
+        Ok(head) => head,
+        Err(GurtError::UnexpectedEof) => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let head = RequestHead {
+        method,
+        path,
+        headers,
+    };
+    Ok(Some((head, bytes.len() - rest.len(), length)))
+}
+
+/// Parse the length of the response to a `method` request at the start of `bytes`, or
+/// `None` if it is not complete yet
+///
+/// A response that may have a body must declare its length: without `content-length`
+/// its body runs to the end of the connection, which a recording does not mark.
+fn parse_response(bytes: &[u8], method: Method) -> Result<Option<usize>, CassetteError> {
+    if bytes.is_empty() {
+        return Ok(None);
+    }
+    let mut rest = bytes;
+    let mut buf = vec![0u8; bytes.len() + 1];
+    let length = complete(async {
+        let mut reader = ResponseReader::new(&mut rest);
+        let status = reader.read_status_line(&mut buf).await?.status;
+        while reader.read_header(&mut buf).await?.is_some() {}
+        // From spec: HEAD "Get headers only (no body allowed)"
+        let bodiless = method == Method::Head
+            || status.as_u16() < 200
+            || matches!(status, StatusCode::NoContent | StatusCode::NotModified);
+        Ok(match bodiless {
+            true => Some(0),
+            false => reader.content_length(),
+        })
+    });
+    let length = match length {
+        Ok(Some(length)) => length,
+        Ok(None) => return Err(CassetteError::UnframedResponse),
+        Err(GurtError::UnexpectedEof) => return Ok(None),
+        Err(e) => return Err(CassetteError::InvalidResponse(e)),
+    };
+    let len = bytes.len() - rest.len() + length;
+    Ok((len <= bytes.len()).then_some(len))
+}
+
+/// Run a read from memory, which never waits, to completion
+fn complete<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    match future
+        .as_mut()
+        .poll(&mut Context::from_waker(Waker::noop()))
+    {
+        Poll::Ready(output) => output,
+        Poll::Pending => unreachable!("reads from memory never wait"),
+    }
+}
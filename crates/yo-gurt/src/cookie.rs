@@ -0,0 +1,432 @@
+//! Cookies: `set-cookie` parsing, `cookie` headers and a cookie jar
+//!
+//! A [`CookieJar`] follows RFC 6265 for the hosts of `gurt://` URLs. Each `set-cookie`
+//! response header is handed to [`CookieJar::set_cookie`] together with the host and
+//! path the request went to, and [`CookieJar::cookie_header`] produces the `cookie`
+//! header for the next request:
+//!
+//! ```rust,ignore
+//! use portal_solutions_yo_gurt::cookie::{CookieJar, FixedStore};
+//!
+//! let mut jar = CookieJar::new(FixedStore::<8, 256>::new());
+//! let mut response = client.response_reader();
+//! response.read_status_line(&mut buf).await?;
+//! while let Some(header) = response.read_header(&mut buf).await? {
+//!     if header.name(&buf) == b"set-cookie" {
+//!         if let Ok(value) = core::str::from_utf8(header.value(&buf)) {
+//!             jar.set_cookie(host, "/login", value, now);
+//!         }
+//!     }
+//! }
+//!
+//! let mut cookies = [0u8; 512];
+//! let header = jar.cookie_header(host, "/account", now, &mut cookies)?;
+//! let headers: &[(&str, &str)] = match header {
+//!     Some(cookie) => &[("cookie", cookie)],
+//!     None => &[],
+//! };
+//! client.request_no_body(Method::Get, "/account", host, None, headers).await?;
+//! ```
+//!
+//! Times are seconds since the Unix epoch and supplied by the caller, since `no_std`
+//! targets have no clock of their own; [`unix_time`] reads the system clock under `std`.
+//!
+//! Where cookies live is up to a [`CookieStore`]: [`FixedStore`] keeps a fixed number of
+//! cookies inline without allocating, [`MemoryStore`] (`alloc` feature) keeps any number
+//! on the heap, and [`FileStore`] (`std` feature) loads and saves them in the Netscape
+//! `cookies.txt` format that curl and wget use.
+//!
+//! GURT always runs over TLS, so every connection counts as secure and cookies with the
+//! `Secure` attribute are sent like any other. Cookies are scoped by host and path; as in
+//! HTTP, the port plays no part. There is no public suffix list, but a `Domain` attribute
+//! naming a single label such as `web` is refused, so a site cannot set cookies for a
+//! whole top-level domain.
+
+pub(crate) mod date;
+mod store;
+
+#[cfg(feature = "std")]
+pub use store::FileStore;
+#[cfg(feature = "alloc")]
+pub use store::MemoryStore;
+pub use store::{CookieStore, FixedStore};
+
+#[cfg(feature = "alloc")]
+use alloc::string::String;
+
+use crate::Limit;
+
+/// Longest name and value, together, that a jar accepts
+///
+/// RFC 6265 asks user agents to support cookies of at least this size, and browsers
+/// refuse larger ones.
+pub const MAX_COOKIE_SIZE: usize = 4096;
+
+/// A `set-cookie` header, parsed
+///
+/// Attributes this crate has no use for, such as `SameSite`, are skipped, as are
+/// attributes with invalid values. When an attribute appears more than once, the last
+/// one wins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SetCookie<'a> {
+    /// Cookie name
+    pub name: &'a str,
+    /// Cookie value, quotes included
+    pub value: &'a str,
+    /// `Domain` attribute without its leading dot
+    pub domain: Option<&'a str>,
+    /// `Path` attribute, if it starts with `/`
+    pub path: Option<&'a str>,
+    /// `Expires` attribute in seconds since the Unix epoch
+    pub expires: Option<u64>,
+    /// `Max-Age` attribute in seconds; zero or less expires the cookie immediately
+    pub max_age: Option<i64>,
+    /// `Secure` attribute
+    pub secure: bool,
+    /// `HttpOnly` attribute
+    pub http_only: bool,
+}
+
+impl<'a> SetCookie<'a> {
+    /// Parse a `set-cookie` header value
+    ///
+    /// Returns `None` for a header without a `name=value` pair, with an empty name, or
+    /// with control characters in the name or value.
+    pub fn parse(header: &'a str) -> Option<Self> {
+        let mut attributes = header.split(';');
+        let (name, value) = attributes.next()?.split_once('=')?;
+        let (name, value) = (name.trim_matches(is_space), value.trim_matches(is_space));
+        if name.is_empty() || name.chars().chain(value.chars()).any(is_control) {
+            return None;
+        }
+        let mut cookie = Self {
+            name,
+            value,
+            domain: None,
+            path: None,
+            expires: None,
+            max_age: None,
+            secure: false,
+            http_only: false,
+        };
+        for attribute in attributes {
+            let (key, value) = attribute.split_once('=').unwrap_or((attribute, ""));
+            let (key, value) = (key.trim_matches(is_space), value.trim_matches(is_space));
+            if key.eq_ignore_ascii_case("expires") {
+                if let Some(expires) = date::parse(value) {
+                    cookie.expires = Some(expires);
+                }
+            } else if key.eq_ignore_ascii_case("max-age") {
+                if let Some(max_age) = parse_max_age(value) {
+                    cookie.max_age = Some(max_age);
+                }
+            } else if key.eq_ignore_ascii_case("domain") {
+                let domain = value.strip_prefix('.').unwrap_or(value);
+                cookie.domain = (!domain.is_empty()).then_some(domain);
+            } else if key.eq_ignore_ascii_case("path") {
+                cookie.path = value.starts_with('/').then_some(value);
+            } else if key.eq_ignore_ascii_case("secure") {
+                cookie.secure = true;
+            } else if key.eq_ignore_ascii_case("httponly") {
+                cookie.http_only = true;
+            }
+        }
+        Some(cookie)
+    }
+}
+
+fn is_space(c: char) -> bool {
+    c == ' ' || c == '\t'
+}
+
+fn is_control(c: char) -> bool {
+    c != '\t' && c.is_ascii_control()
+}
+
+/// An optional `-` and digits; values beyond `i64` saturate
+fn parse_max_age(value: &str) -> Option<i64> {
+    let digits = value.strip_prefix('-').unwrap_or(value);
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some(value.parse().unwrap_or(if digits.len() < value.len() {
+        i64::MIN
+    } else {
+        i64::MAX
+    }))
+}
+
+/// A cookie as held in a [`CookieStore`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cookie<'a> {
+    /// Cookie name
+    pub name: &'a str,
+    /// Cookie value
+    pub value: &'a str,
+    /// The host that set the cookie, or the domain from its `Domain` attribute
+    pub domain: &'a str,
+    /// Paths the cookie is sent for: this one and those below it
+    pub path: &'a str,
+    /// Sent to `domain` only, not to its subdomains (no `Domain` attribute was given)
+    pub host_only: bool,
+    /// `Secure` attribute
+    pub secure: bool,
+    /// `HttpOnly` attribute
+    pub http_only: bool,
+    /// Expiry in seconds since the Unix epoch; `None` for a session cookie, which lasts
+    /// as long as the store holding it
+    pub expires: Option<u64>,
+}
+
+impl Cookie<'_> {
+    /// Whether the cookie has expired at `now`
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+
+    /// Whether the cookie is sent with requests for `path` on `host`
+    ///
+    /// Does not check expiry.
+    pub fn matches(&self, host: &str, path: &str) -> bool {
+        let host = host.strip_suffix('.').unwrap_or(host);
+        let host_matches = if self.host_only {
+            host.eq_ignore_ascii_case(self.domain)
+        } else {
+            domain_match(host, self.domain)
+        };
+        host_matches && path_match(request_path(path), self.path)
+    }
+
+    /// Whether this cookie and `other` are the same cookie: same name, domain and path
+    pub fn same_as(&self, other: &Cookie<'_>) -> bool {
+        self.name == other.name
+            && self.domain.eq_ignore_ascii_case(other.domain)
+            && self.path == other.path
+    }
+}
+
+/// RFC 6265 section 5.1.3: `host` is `domain` or a subdomain of it
+fn domain_match(host: &str, domain: &str) -> bool {
+    if host.eq_ignore_ascii_case(domain) {
+        return true;
+    }
+    host.len() > domain.len()
+        && host.as_bytes()[host.len() - domain.len() - 1] == b'.'
+        && host.as_bytes()[host.len() - domain.len()..].eq_ignore_ascii_case(domain.as_bytes())
+        && !is_ip_address(host)
+}
+
+/// RFC 6265 section 5.1.4: `path` is `cookie_path` or below it
+fn path_match(path: &str, cookie_path: &str) -> bool {
+    path == cookie_path
+        || path.starts_with(cookie_path)
+            && (cookie_path.ends_with('/') || path.as_bytes()[cookie_path.len()] == b'/')
+}
+
+fn is_ip_address(host: &str) -> bool {
+    host.parse::<core::net::IpAddr>().is_ok() || host.starts_with('[')
+}
+
+/// The path of a request target, without its query or fragment
+fn request_path(target: &str) -> &str {
+    let end = target.find(['?', '#']).unwrap_or(target.len());
+    &target[..end]
+}
+
+/// RFC 6265 section 5.1.4: the directory of the request path
+fn default_path(target: &str) -> &str {
+    let path = request_path(target);
+    match path.rfind('/') {
+        Some(0) | None => "/",
+        Some(end) => &path[..end],
+    }
+}
+
+/// Cookies for a client, kept in a [`CookieStore`]
+#[derive(Debug, Clone, Default)]
+pub struct CookieJar<S> {
+    store: S,
+}
+
+impl<S: CookieStore> CookieJar<S> {
+    /// A jar keeping its cookies in `store`
+    pub const fn new(store: S) -> Self {
+        Self { store }
+    }
+
+    /// The store holding the cookies
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// The store holding the cookies, for adding or removing them directly
+    pub fn store_mut(&mut self) -> &mut S {
+        &mut self.store
+    }
+
+    /// The store, giving up the jar
+    pub fn into_store(self) -> S {
+        self.store
+    }
+
+    /// Handle a `set-cookie` header from a response to a request for `path` on `host`
+    ///
+    /// Returns whether the header was accepted: a cookie was stored or, for an expired
+    /// one, removed. Headers are refused when they do not parse, when their `Domain` does
+    /// not cover `host` or is a single label, when name and value exceed
+    /// [`MAX_COOKIE_SIZE`], when a `__Secure-` or `__Host-` name lacks what its prefix
+    /// promises, or when the store cannot hold the cookie. Expired cookies are dropped
+    /// from the store first.
+    pub fn set_cookie(&mut self, host: &str, path: &str, header: &str, now: u64) -> bool {
+        let Some(set) = SetCookie::parse(header) else {
+            return false;
+        };
+        if set.name.len() + set.value.len() > MAX_COOKIE_SIZE {
+            return false;
+        }
+        let host = host.strip_suffix('.').unwrap_or(host);
+        let (domain, host_only) = match set.domain {
+            None => (host, true),
+            // A single label or an IP address only ever names the host itself
+            Some(domain) if !domain.contains('.') || is_ip_address(host) => {
+                if !domain.eq_ignore_ascii_case(host) {
+                    return false;
+                }
+                (host, true)
+            }
+            Some(domain) if domain_match(host, domain) => (domain, false),
+            Some(_) => return false,
+        };
+        let path = set.path.unwrap_or_else(|| default_path(path));
+        let prefixed = |prefix: &str| {
+            set.name
+                .get(..prefix.len())
+                .is_some_and(|p| p.eq_ignore_ascii_case(prefix))
+        };
+        if (prefixed("__Secure-") || prefixed("__Host-")) && !set.secure
+            || prefixed("__Host-") && (set.domain.is_some() || path != "/")
+        {
+            return false;
+        }
+        let expires = match set.max_age {
+            Some(max_age) if max_age <= 0 => Some(0),
+            Some(max_age) => Some(now.saturating_add(max_age as u64)),
+            None => set.expires,
+        };
+        let cookie = Cookie {
+            name: set.name,
+            value: set.value,
+            domain,
+            path,
+            host_only,
+            secure: set.secure,
+            http_only: set.http_only,
+            expires,
+        };
+        self.store.remove_expired(now);
+        if cookie.is_expired(now) {
+            self.store.remove(cookie.name, cookie.domain, cookie.path);
+            return true;
+        }
+        self.store.insert(&cookie)
+    }
+
+    /// The `cookie` header for a request for `path` on `host`, written into `buf`
+    ///
+    /// Cookies with longer paths come first, as RFC 6265 recommends. Returns `None` when
+    /// no cookie applies, and fails with [`Limit::BufferTooSmall`] when the header does
+    /// not fit in `buf`.
+    pub fn cookie_header<'b>(
+        &self,
+        host: &str,
+        path: &str,
+        now: u64,
+        buf: &'b mut [u8],
+    ) -> Result<Option<&'b str>, Limit> {
+        let mut len = 0;
+        let mut fits = true;
+        self.for_each_matching(host, path, now, |cookie| {
+            let separator: &[u8] = if len == 0 { b"" } else { b"; " };
+            for part in [
+                separator,
+                cookie.name.as_bytes(),
+                b"=",
+                cookie.value.as_bytes(),
+            ] {
+                let Some(dest) = buf.get_mut(len..len + part.len()) else {
+                    fits = false;
+                    return;
+                };
+                dest.copy_from_slice(part);
+                len += part.len();
+            }
+        });
+        if !fits {
+            return Err(Limit::BufferTooSmall);
+        }
+        if len == 0 {
+            return Ok(None);
+        }
+        // Only whole `&str`s were copied in
+        Ok(core::str::from_utf8(&buf[..len]).ok())
+    }
+
+    /// The `cookie` header for a request for `path` on `host`, or `None` when no cookie
+    /// applies
+    #[cfg(feature = "alloc")]
+    pub fn cookie_string(&self, host: &str, path: &str, now: u64) -> Option<String> {
+        let mut header = String::new();
+        self.for_each_matching(host, path, now, |cookie| {
+            if !header.is_empty() {
+                header.push_str("; ");
+            }
+            header.push_str(cookie.name);
+            header.push('=');
+            header.push_str(cookie.value);
+        });
+        (!header.is_empty()).then_some(header)
+    }
+
+    /// Drop the cookies that have expired at `now`
+    pub fn remove_expired(&mut self, now: u64) {
+        self.store.remove_expired(now);
+    }
+
+    /// Visit the unexpired cookies for `path` on `host`, longest path first
+    ///
+    /// Without a heap to sort in, each distinct path length takes one pass over the store.
+    fn for_each_matching(&self, host: &str, path: &str, now: u64, mut f: impl FnMut(&Cookie<'_>)) {
+        let mut below = usize::MAX;
+        loop {
+            let mut longest = None;
+            self.store.for_each(&mut |cookie| {
+                if cookie.path.len() < below
+                    && !cookie.is_expired(now)
+                    && cookie.matches(host, path)
+                {
+                    longest = longest.max(Some(cookie.path.len()));
+                }
+            });
+            let Some(length) = longest else {
+                return;
+            };
+            self.store.for_each(&mut |cookie| {
+                if cookie.path.len() == length
+                    && !cookie.is_expired(now)
+                    && cookie.matches(host, path)
+                {
+                    f(cookie);
+                }
+            });
+            below = length;
+        }
+    }
+}
+
+/// The system clock in seconds since the Unix epoch, as the jar expects
+#[cfg(feature = "std")]
+pub fn unix_time() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
@@ -0,0 +1,91 @@
+//! The lenient cookie date format of RFC 6265 section 5.1.1
+
+/// Parse an `Expires` date into seconds since the Unix epoch
+///
+/// Accepts the formats found in the wild (`Wed, 21 Oct 2015 07:28:00 GMT`,
+/// `Wednesday, 21-Oct-15 07:28:00 GMT`, `Wed Oct 21 07:28:00 2015`) by picking out the
+/// time, day, month and year tokens in any order. Dates before 1970 come out as 0.
+pub(crate) fn parse(date: &str) -> Option<u64> {
+    let mut time = None;
+    let mut day = None;
+    let mut month = None;
+    let mut year = None;
+    for token in date.split(is_delimiter).filter(|t| !t.is_empty()) {
+        if time.is_none()
+            && let Some(t) = parse_time(token)
+        {
+            time = Some(t);
+        } else if day.is_none()
+            && let Some(d) = leading_digits(token, 1, 2)
+        {
+            day = Some(d);
+        } else if month.is_none()
+            && let Some(m) = parse_month(token)
+        {
+            month = Some(m);
+        } else if year.is_none()
+            && let Some(y) = leading_digits(token, 2, 4)
+        {
+            year = Some(y);
+        }
+    }
+    let (hour, minute, second) = time?;
+    let (day, month, mut year) = (day?, month?, year?);
+    year += match year {
+        70..=99 => 1900,
+        0..=69 => 2000,
+        _ => 0,
+    };
+    if !(1..=31).contains(&day) || year < 1601 || hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+    let days = days_from_civil(year, month, day);
+    let seconds = days * 86400 + i64::from(hour * 3600 + minute * 60 + second);
+    Some(seconds.max(0) as u64)
+}
+
+fn is_delimiter(c: char) -> bool {
+    matches!(c, '\t' | ' '..='/' | ';'..='@' | '['..='`' | '{'..='~')
+}
+
+/// `min..=max` digits, optionally followed by non-digits
+fn leading_digits(token: &str, min: usize, max: usize) -> Option<u32> {
+    let digits = token.bytes().take_while(u8::is_ascii_digit).count();
+    if !(min..=max).contains(&digits) {
+        return None;
+    }
+    token[..digits].parse().ok()
+}
+
+/// `h:m:s` with one or two digits each, optionally followed by non-digits
+fn parse_time(token: &str) -> Option<(u32, u32, u32)> {
+    let mut fields = token.splitn(3, ':');
+    let hour = fields.next()?;
+    let minute = fields.next()?;
+    let second = fields.next()?;
+    let exact = |field: &str| (field.len() <= 2).then(|| field.parse().ok()).flatten();
+    Some((exact(hour)?, exact(minute)?, leading_digits(second, 1, 2)?))
+}
+
+fn parse_month(token: &str) -> Option<u32> {
+    const MONTHS: [&[u8; 3]; 12] = [
+        b"jan", b"feb", b"mar", b"apr", b"may", b"jun", b"jul", b"aug", b"sep", b"oct", b"nov",
+        b"dec",
+    ];
+    let prefix = token.as_bytes().get(..3)?;
+    let index = MONTHS.iter().position(|m| m.eq_ignore_ascii_case(prefix))?;
+    Some(index as u32 + 1)
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date
+///
+/// Howard Hinnant's `days_from_civil`; days past the end of a short month roll over.
+fn days_from_civil(year: u32, month: u32, day: u32) -> i64 {
+    let year = i64::from(year) - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = i64::from(month);
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
@@ -0,0 +1,485 @@
+//! Where a [`CookieJar`](super::CookieJar) keeps its cookies
+
+#[cfg(feature = "alloc")]
+use alloc::{string::String, vec::Vec};
+
+use super::Cookie;
+
+/// Storage for cookies
+///
+/// Stores hold cookies as given; the jar decides which to keep and which to send.
+/// Domains compare case-insensitively, names and paths exactly.
+pub trait CookieStore {
+    /// Store `cookie`, replacing any cookie with the same name, domain and path
+    ///
+    /// Returns `false` if the store cannot hold it.
+    fn insert(&mut self, cookie: &Cookie<'_>) -> bool;
+
+    /// Remove the cookie with this name, domain and path, returning whether there was one
+    fn remove(&mut self, name: &str, domain: &str, path: &str) -> bool;
+
+    /// Call `f` with every stored cookie, expired or not
+    fn for_each(&self, f: &mut dyn FnMut(&Cookie<'_>));
+
+    /// Remove the cookies that have expired at `now`
+    fn remove_expired(&mut self, now: u64);
+}
+
+impl<S: CookieStore + ?Sized> CookieStore for &mut S {
+    fn insert(&mut self, cookie: &Cookie<'_>) -> bool {
+        S::insert(self, cookie)
+    }
+
+    fn remove(&mut self, name: &str, domain: &str, path: &str) -> bool {
+        S::remove(self, name, domain, path)
+    }
+
+    fn for_each(&self, f: &mut dyn FnMut(&Cookie<'_>)) {
+        S::for_each(self, f)
+    }
+
+    fn remove_expired(&mut self, now: u64) {
+        S::remove_expired(self, now)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<S: CookieStore + ?Sized> CookieStore for alloc::boxed::Box<S> {
+    fn insert(&mut self, cookie: &Cookie<'_>) -> bool {
+        S::insert(self, cookie)
+    }
+
+    fn remove(&mut self, name: &str, domain: &str, path: &str) -> bool {
+        S::remove(self, name, domain, path)
+    }
+
+    fn for_each(&self, f: &mut dyn FnMut(&Cookie<'_>)) {
+        S::for_each(self, f)
+    }
+
+    fn remove_expired(&mut self, now: u64) {
+        S::remove_expired(self, now)
+    }
+}
+
+/// Up to `N` cookies held inline, each with at most `SIZE` bytes of name, value, domain
+/// and path together
+///
+/// Needs no allocator. When all slots are taken, a new cookie replaces the one stored
+/// longest ago; cookies that do not fit in `SIZE` bytes are refused.
+#[derive(Debug, Clone)]
+pub struct FixedStore<const N: usize, const SIZE: usize> {
+    slots: [Option<Slot<SIZE>>; N],
+    /// Insertion counter, for finding the oldest cookie
+    inserted: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Slot<const SIZE: usize> {
+    /// Name, value, domain and path, back to back
+    text: [u8; SIZE],
+    /// Ends of the name, value and domain in `text`, then the length used
+    ends: [usize; 4],
+    host_only: bool,
+    secure: bool,
+    http_only: bool,
+    expires: Option<u64>,
+    inserted: u64,
+}
+
+impl<const SIZE: usize> Slot<SIZE> {
+    fn new(cookie: &Cookie<'_>, inserted: u64) -> Option<Self> {
+        let mut text = [0; SIZE];
+        let mut ends = [0; 4];
+        let mut len = 0;
+        for (end, part) in
+            ends.iter_mut()
+                .zip([cookie.name, cookie.value, cookie.domain, cookie.path])
+        {
+            text.get_mut(len..len + part.len())?
+                .copy_from_slice(part.as_bytes());
+            len += part.len();
+            *end = len;
+        }
+        Some(Self {
+            text,
+            ends,
+            host_only: cookie.host_only,
+            secure: cookie.secure,
+            http_only: cookie.http_only,
+            expires: cookie.expires,
+            inserted,
+        })
+    }
+
+    fn cookie(&self) -> Cookie<'_> {
+        // Each part was copied from a `&str`
+        let part = |start, end| core::str::from_utf8(&self.text[start..end]).unwrap_or_default();
+        let [name, value, domain, path] = self.ends;
+        Cookie {
+            name: part(0, name),
+            value: part(name, value),
+            domain: part(value, domain),
+            path: part(domain, path),
+            host_only: self.host_only,
+            secure: self.secure,
+            http_only: self.http_only,
+            expires: self.expires,
+        }
+    }
+}
+
+impl<const N: usize, const SIZE: usize> FixedStore<N, SIZE> {
+    /// An empty store
+    pub const fn new() -> Self {
+        Self {
+            slots: [None; N],
+            inserted: 0,
+        }
+    }
+
+    /// Number of cookies held
+    pub fn len(&self) -> usize {
+        self.slots.iter().flatten().count()
+    }
+
+    /// Whether no cookies are held
+    pub fn is_empty(&self) -> bool {
+        self.slots.iter().all(Option::is_none)
+    }
+
+    fn position(&self, name: &str, domain: &str, path: &str) -> Option<usize> {
+        self.slots.iter().position(|slot| {
+            slot.as_ref().is_some_and(|slot| {
+                let cookie = slot.cookie();
+                cookie.name == name
+                    && cookie.domain.eq_ignore_ascii_case(domain)
+                    && cookie.path == path
+            })
+        })
+    }
+}
+
+impl<const N: usize, const SIZE: usize> Default for FixedStore<N, SIZE> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize, const SIZE: usize> CookieStore for FixedStore<N, SIZE> {
+    fn insert(&mut self, cookie: &Cookie<'_>) -> bool {
+        let existing = self.position(cookie.name, cookie.domain, cookie.path);
+        // A replaced cookie keeps its age
+        let inserted = existing
+            .and_then(|i| self.slots[i].as_ref())
+            .map_or(self.inserted, |slot| slot.inserted);
+        let Some(slot) = Slot::new(cookie, inserted) else {
+            return false;
+        };
+        let index = existing
+            .or_else(|| self.slots.iter().position(Option::is_none))
+            .or_else(|| {
+                (0..N).min_by_key(|&i| self.slots[i].as_ref().map_or(0, |slot| slot.inserted))
+            });
+        let Some(index) = index else {
+            return false;
+        };
+        if existing.is_none() {
+            self.inserted += 1;
+        }
+        self.slots[index] = Some(slot);
+        true
+    }
+
+    fn remove(&mut self, name: &str, domain: &str, path: &str) -> bool {
+        let Some(index) = self.position(name, domain, path) else {
+            return false;
+        };
+        self.slots[index] = None;
+        true
+    }
+
+    fn for_each(&self, f: &mut dyn FnMut(&Cookie<'_>)) {
+        for slot in self.slots.iter().flatten() {
+            f(&slot.cookie());
+        }
+    }
+
+    fn remove_expired(&mut self, now: u64) {
+        for slot in &mut self.slots {
+            if slot
+                .as_ref()
+                .is_some_and(|slot| slot.cookie().is_expired(now))
+            {
+                *slot = None;
+            }
+        }
+    }
+}
+
+/// Any number of cookies on the heap, in the order they were first stored
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStore {
+    cookies: Vec<OwnedCookie>,
+}
+
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone)]
+struct OwnedCookie {
+    name: String,
+    value: String,
+    domain: String,
+    path: String,
+    host_only: bool,
+    secure: bool,
+    http_only: bool,
+    expires: Option<u64>,
+}
+
+#[cfg(feature = "alloc")]
+impl OwnedCookie {
+    fn new(cookie: &Cookie<'_>) -> Self {
+        Self {
+            name: cookie.name.into(),
+            value: cookie.value.into(),
+            domain: cookie.domain.into(),
+            path: cookie.path.into(),
+            host_only: cookie.host_only,
+            secure: cookie.secure,
+            http_only: cookie.http_only,
+            expires: cookie.expires,
+        }
+    }
+
+    fn cookie(&self) -> Cookie<'_> {
+        Cookie {
+            name: &self.name,
+            value: &self.value,
+            domain: &self.domain,
+            path: &self.path,
+            host_only: self.host_only,
+            secure: self.secure,
+            http_only: self.http_only,
+            expires: self.expires,
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl MemoryStore {
+    /// An empty store
+    pub const fn new() -> Self {
+        Self {
+            cookies: Vec::new(),
+        }
+    }
+
+    /// Number of cookies held
+    pub fn len(&self) -> usize {
+        self.cookies.len()
+    }
+
+    /// Whether no cookies are held
+    pub fn is_empty(&self) -> bool {
+        self.cookies.is_empty()
+    }
+
+    /// Drop every cookie
+    pub fn clear(&mut self) {
+        self.cookies.clear();
+    }
+
+    fn position(&self, name: &str, domain: &str, path: &str) -> Option<usize> {
+        self.cookies.iter().position(|cookie| {
+            cookie.name == name && cookie.domain.eq_ignore_ascii_case(domain) && cookie.path == path
+        })
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl CookieStore for MemoryStore {
+    fn insert(&mut self, cookie: &Cookie<'_>) -> bool {
+        let owned = OwnedCookie::new(cookie);
+        match self.position(cookie.name, cookie.domain, cookie.path) {
+            Some(index) => self.cookies[index] = owned,
+            None => self.cookies.push(owned),
+        }
+        true
+    }
+
+    fn remove(&mut self, name: &str, domain: &str, path: &str) -> bool {
+        let Some(index) = self.position(name, domain, path) else {
+            return false;
+        };
+        self.cookies.remove(index);
+        true
+    }
+
+    fn for_each(&self, f: &mut dyn FnMut(&Cookie<'_>)) {
+        for cookie in &self.cookies {
+            f(&cookie.cookie());
+        }
+    }
+
+    fn remove_expired(&mut self, now: u64) {
+        self.cookies
+            .retain(|cookie| !cookie.cookie().is_expired(now));
+    }
+}
+
+/// Cookies kept in a Netscape `cookies.txt` file, the format curl (`-b`/`-c`) and wget
+/// read and write
+///
+/// Cookies are loaded by [`open`](Self::open) and held in memory; [`save`](Self::save)
+/// writes them back, and dropping a store that changed saves it too, ignoring errors.
+/// Session cookies, those without an expiry, end with the store and are not saved.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct FileStore {
+    path: std::path::PathBuf,
+    cookies: MemoryStore,
+    modified: bool,
+}
+
+#[cfg(feature = "std")]
+impl FileStore {
+    /// Load cookies from `path`; a missing file holds no cookies and is created on save
+    ///
+    /// Lines that are not `DOMAIN SUBDOMAINS PATH SECURE EXPIRES NAME VALUE`, tab
+    /// separated, are skipped.
+    pub fn open(path: impl Into<std::path::PathBuf>) -> std::io::Result<Self> {
+        let path = path.into();
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        let mut cookies = MemoryStore::new();
+        for (number, line) in text.lines().enumerate() {
+            let (line, http_only) = match line.strip_prefix("#HttpOnly_") {
+                Some(line) => (line, true),
+                None => (line, false),
+            };
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match parse_line(line, http_only) {
+                Some(cookie) => {
+                    cookies.insert(&cookie);
+                }
+                None => warn!("skipping malformed cookie file line {}", number + 1),
+            }
+        }
+        Ok(Self {
+            path,
+            cookies,
+            modified: false,
+        })
+    }
+
+    /// Write the cookies that have an expiry to the file, replacing its contents
+    ///
+    /// Cookies with a tab or line break in a field, which the format cannot hold, are left
+    /// out like session cookies.
+    pub fn save(&mut self) -> std::io::Result<()> {
+        use core::fmt::Write;
+
+        let mut text = String::from("# Netscape HTTP Cookie File\n");
+        self.cookies.for_each(&mut |cookie| {
+            let Some(expires) = cookie.expires else {
+                return;
+            };
+            let fields = [cookie.domain, cookie.path, cookie.name, cookie.value];
+            if fields
+                .iter()
+                .any(|field| field.contains(['\t', '\r', '\n']))
+            {
+                return;
+            }
+            let flag = |set| if set { "TRUE" } else { "FALSE" };
+            let _ = writeln!(
+                text,
+                "{}{}{}\t{}\t{}\t{}\t{expires}\t{}\t{}",
+                if cookie.http_only { "#HttpOnly_" } else { "" },
+                if cookie.host_only { "" } else { "." },
+                cookie.domain,
+                flag(!cookie.host_only),
+                cookie.path,
+                flag(cookie.secure),
+                cookie.name,
+                cookie.value,
+            );
+        });
+        std::fs::write(&self.path, text)?;
+        self.modified = false;
+        Ok(())
+    }
+
+    /// The cookies as held in memory
+    pub fn cookies(&self) -> &MemoryStore {
+        &self.cookies
+    }
+}
+
+/// A `cookies.txt` line, without its `#HttpOnly_` prefix
+#[cfg(feature = "std")]
+fn parse_line(line: &str, http_only: bool) -> Option<Cookie<'_>> {
+    let fields: Vec<&str> = line.splitn(7, '\t').collect();
+    let &[domain, subdomains, path, secure, expires, name, value] = &fields[..] else {
+        return None;
+    };
+    let flag = |field: &str| match field {
+        "TRUE" => Some(true),
+        "FALSE" => Some(false),
+        _ => None,
+    };
+    let expires: u64 = expires.parse().ok()?;
+    let domain = domain.strip_prefix('.').unwrap_or(domain);
+    if domain.is_empty() || name.is_empty() {
+        return None;
+    }
+    Some(Cookie {
+        name,
+        value: value.trim_end_matches('\r'),
+        domain,
+        path,
+        host_only: !flag(subdomains)?,
+        secure: flag(secure)?,
+        http_only,
+        expires: (expires != 0).then_some(expires),
+    })
+}
+
+#[cfg(feature = "std")]
+impl CookieStore for FileStore {
+    fn insert(&mut self, cookie: &Cookie<'_>) -> bool {
+        self.modified = true;
+        self.cookies.insert(cookie)
+    }
+
+    fn remove(&mut self, name: &str, domain: &str, path: &str) -> bool {
+        let removed = self.cookies.remove(name, domain, path);
+        self.modified |= removed;
+        removed
+    }
+
+    fn for_each(&self, f: &mut dyn FnMut(&Cookie<'_>)) {
+        self.cookies.for_each(f)
+    }
+
+    fn remove_expired(&mut self, now: u64) {
+        let before = self.cookies.len();
+        self.cookies.remove_expired(now);
+        self.modified |= self.cookies.len() != before;
+    }
+}
+
+#[cfg(feature = "std")]
+impl Drop for FileStore {
+    fn drop(&mut self) {
+        if self.modified {
+            let _ = self.save();
+        }
+    }
+}
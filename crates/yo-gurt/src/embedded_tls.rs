@@ -0,0 +1,386 @@
+//! Connector for `no_std` targets built on `embedded-nal-async` and `embedded-tls`
+//!
+//! Enabled by the `embedded-tls` feature. [`Connector::connect`] resolves a `gurt://`
+//! host with a [`Resolver`], opens a TCP connection with [`TcpConnect`], performs a TLS 1.3
+//! handshake advertising ALPN `GURT/1.0`, and finally the GURT `HANDSHAKE`, returning a
+//! ready [`GurtClient`].
+//!
+//! ```rust,ignore
+//! use embedded_tls::{Aes128GcmSha256, UnsecureProvider};
+//! use portal_solutions_yo_gurt::embedded_tls::Connector;
+//! use portal_solutions_yo_gurt::resolve::{Fallback, Hosts, NalDns};
+//!
+//! // Gurted names from a hosts list, anything else from the stack's DNS
+//! let resolver = Fallback(Hosts::new("192.168.1.20 site.web"), NalDns(&stack));
+//! let connector = Connector::new(&stack, resolver);
+//! let mut read_buf = [0u8; 16640];
+//! let mut write_buf = [0u8; 4096];
+//! let provider = UnsecureProvider::new::<Aes128GcmSha256>(rng);
+//! let mut client = connector
+//!     .connect("gurt://example.web", provider, &mut read_buf, &mut write_buf)
+//!     .await?;
+//! client.request_no_body(Method::Get, "/", "example.web", None).await?;
+//! // ...
+//! client.transport.close().await?; // sends close_notify
+//! ```
+//!
+//! Certificates are checked by the [`CryptoProvider`]'s verifier; `UnsecureProvider`
+//! skips verification, which suits self-issued Gurted certificates but not much else.
+//! [`TrustCa`] wraps a provider to verify the server against one CA instead, such as a
+//! local development CA from `gurt-ca`:
+//!
+//! ```rust,ignore
+//! use portal_solutions_yo_gurt::embedded_tls::TrustCa;
+//!
+//! let provider = TrustCa::new(UnsecureProvider::new::<Aes128GcmSha256>(rng), CA_CERT_DER);
+//! ```
+//!
+//! For servers that authenticate clients by certificate (mutual TLS), the client
+//! certificate and signing key also come from the provider, through
+//! [`CryptoProvider::client_cert`] and [`CryptoProvider::signer`]:
+//!
+//! ```rust,ignore
+//! use embedded_tls::Certificate;
+//!
+//! let provider = UnsecureProvider::new::<Aes128GcmSha256>(rng)
+//!     .with_cert(Certificate::X509(CLIENT_CERT_DER))
+//!     .with_priv_key(CLIENT_KEY_SEC1_DER); // P-256
+//! ```
+//!
+//! Self-issued certificates can instead be pinned by public key, with trust on first use
+//! if desired; see [`pin`] and [`Connector::connect_pinned`].
+//!
+//! There is no timer in `no_std`, so wrap `connect` in your executor's timeout to apply
+//! [`DEFAULT_CONNECTION_TIMEOUT_SECS`](crate::DEFAULT_CONNECTION_TIMEOUT_SECS).
+
+use core::fmt;
+use core::net::{IpAddr, SocketAddr};
+
+use embedded_nal_async::TcpConnect;
+use embedded_tls::pki::CertVerifier;
+use embedded_tls::{
+    Certificate, CryptoProvider, CryptoRngCore, NoClock, SignatureScheme, TlsClock, TlsConfig,
+    TlsConnection, TlsContext, TlsError, TlsVerifier,
+};
+
+use crate::resolve::Resolver;
+use crate::{ALPN_IDENTIFIER, DEFAULT_PORT, DEFAULT_USER_AGENT, GurtClient, GurtError};
+
+pub mod pin;
+
+use pin::{PinFailure, PinStore, PinVerifier, SpkiHash};
+
+/// A handshaken client over an `embedded-tls` connection
+pub type TlsClient<'a, C, CipherSuite> = GurtClient<TlsConnection<'a, C, CipherSuite>>;
+
+/// Errors from [`Connector::connect`]
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ConnectError<D, T> {
+    /// The address is not `[gurt://]host[:port]`
+    InvalidAddress,
+    /// Resolving the host failed
+    Dns(D),
+    /// Opening the TCP connection failed
+    Tcp(T),
+    /// The TLS handshake failed
+    Tls(TlsError),
+    /// The server's key does not match the keys pinned for the host
+    PinMismatch(SpkiHash),
+    /// The host has no pinned keys and the store did not trust this one on first use
+    Unpinned(SpkiHash),
+    /// The GURT handshake failed or was rejected
+    Gurt(GurtError<TlsError>),
+}
+
+impl<D: fmt::Debug, T: fmt::Debug> fmt::Display for ConnectError<D, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectError::InvalidAddress => f.write_str("invalid gurt:// address"),
+            ConnectError::Dns(e) => write!(f, "DNS lookup failed: {e:?}"),
+            ConnectError::Tcp(e) => write!(f, "TCP connection failed: {e:?}"),
+            ConnectError::Tls(e) => write!(f, "TLS handshake failed: {e:?}"),
+            ConnectError::PinMismatch(pin) => {
+                write!(f, "server key {pin} does not match the pinned key")
+            }
+            ConnectError::Unpinned(pin) => write!(f, "server key {pin} is not pinned"),
+            ConnectError::Gurt(e) => write!(f, "GURT handshake failed: {e}"),
+        }
+    }
+}
+
+impl<D: fmt::Debug, T: fmt::Debug> core::error::Error for ConnectError<D, T> {}
+
+/// A failed connection as the client's error type, for callers handling one error type
+///
+/// Both pin failures become [`GurtError::PinMismatch`] and GURT failures stay as they
+/// are. The rest become [`GurtError::Io`]: TLS failures as they are, TCP failures by
+/// their kind, and DNS failures, whose error has no kind, as
+/// [`ErrorKind::NotFound`](embedded_io_async::ErrorKind::NotFound). An invalid address
+/// is [`RequestError::InvalidHost`](crate::RequestError::InvalidHost).
+impl<D, T: embedded_io_async::Error> From<ConnectError<D, T>> for GurtError<TlsError> {
+    fn from(e: ConnectError<D, T>) -> Self {
+        match e {
+            ConnectError::InvalidAddress => {
+                GurtError::InvalidRequest(crate::RequestError::InvalidHost)
+            }
+            ConnectError::Dns(_) => {
+                GurtError::Io(TlsError::Io(embedded_io_async::ErrorKind::NotFound))
+            }
+            ConnectError::Tcp(e) => GurtError::Io(TlsError::Io(e.kind())),
+            ConnectError::Tls(e) => GurtError::Io(e),
+            ConnectError::PinMismatch(pin) | ConnectError::Unpinned(pin) => {
+                GurtError::PinMismatch(pin.0)
+            }
+            ConnectError::Gurt(e) => e,
+        }
+    }
+}
+
+/// Opens GURT connections over a network stack
+pub struct Connector<'u, S, D> {
+    stack: S,
+    dns: D,
+    user_agent: &'u str,
+}
+
+impl<S: TcpConnect, D: Resolver> Connector<'static, S, D> {
+    /// Create a connector using `stack` for TCP and `dns` for name resolution
+    pub fn new(stack: S, dns: D) -> Self {
+        Self {
+            stack,
+            dns,
+            user_agent: DEFAULT_USER_AGENT,
+        }
+    }
+}
+
+impl<'u, S: TcpConnect, D: Resolver> Connector<'u, S, D> {
+    /// Set the user agent sent with the HANDSHAKE
+    pub fn user_agent<'v>(self, user_agent: &'v str) -> Connector<'v, S, D> {
+        Connector {
+            stack: self.stack,
+            dns: self.dns,
+            user_agent,
+        }
+    }
+
+    /// Connect to `address` (`gurt://host[:port]` or `host[:port]`) and complete the
+    /// TLS and GURT handshakes
+    ///
+    /// From spec: "All connections must use TLS 1.3 encryption" and "Every GURT session
+    /// must begin with a `HANDSHAKE` request"
+    ///
+    /// `read_buf` must fit a full TLS record (16640 bytes is always enough); see
+    /// [`TlsConnection::new`] for sizing `write_buf`.
+    pub async fn connect<'a, P>(
+        &'a self,
+        address: &str,
+        provider: P,
+        read_buf: &'a mut [u8],
+        write_buf: &'a mut [u8],
+    ) -> Result<TlsClient<'a, S::Connection<'a>, P::CipherSuite>, ConnectError<D::Error, S::Error>>
+    where
+        P: CryptoProvider,
+        P::CipherSuite: 'static,
+    {
+        let (host, port) = parse_address(address).ok_or(ConnectError::InvalidAddress)?;
+        let literal = host.parse::<IpAddr>().ok();
+        let ip = match literal {
+            Some(ip) => ip,
+            None => self.dns.resolve(host).await.map_err(ConnectError::Dns)?,
+        };
+        let tcp = self
+            .stack
+            .connect(SocketAddr::new(ip, port))
+            .await
+            .map_err(ConnectError::Tcp)?;
+        debug!("connected to {}:{}", host, port);
+
+        // From spec: "ALPN identifier: `GURT/1.0`"
+        let alpn = [ALPN_IDENTIFIER.as_bytes()];
+        let mut config = TlsConfig::new().with_alpn(&alpn);
+        if literal.is_none() {
+            // SNI carries DNS names only
+            config = config.with_server_name(host);
+        }
+        let mut tls = TlsConnection::new(tcp, read_buf, write_buf);
+        tls.open(TlsContext::new(&config, provider))
+            .await
+            .map_err(|e| {
+                warn!("TLS handshake with {} failed: {:?}", host, e);
+                ConnectError::Tls(e)
+            })?;
+
+        let mut client = GurtClient::new(tls);
+        client
+            .handshake(host, self.user_agent)
+            .await
+            .map_err(ConnectError::Gurt)?;
+        client
+            .read_handshake_response(&mut [0; 512])
+            .await
+            .map_err(ConnectError::Gurt)?;
+        Ok(client)
+    }
+
+    /// Like [`connect`](Self::connect), but trust the server if its public key is pinned
+    /// for the host in `pins`, instead of asking `provider` to verify the certificate
+    ///
+    /// Pins are looked up by the host as written in `address`, so IP addresses can be
+    /// pinned too. A host whose pins do not include the key fails with
+    /// [`ConnectError::PinMismatch`]; a host without pins is passed to
+    /// [`PinStore::first_use`] and fails with [`ConnectError::Unpinned`] if the store
+    /// does not trust it. `provider` still supplies randomness and any client
+    /// certificate.
+    pub async fn connect_pinned<'a, P, Pins>(
+        &'a self,
+        address: &str,
+        provider: P,
+        pins: Pins,
+        read_buf: &'a mut [u8],
+        write_buf: &'a mut [u8],
+    ) -> Result<TlsClient<'a, S::Connection<'a>, P::CipherSuite>, ConnectError<D::Error, S::Error>>
+    where
+        P: CryptoProvider,
+        P::CipherSuite: 'static,
+        Pins: PinStore,
+    {
+        let (host, _) = parse_address(address).ok_or(ConnectError::InvalidAddress)?;
+        let mut pinned = Pinned {
+            inner: provider,
+            verifier: PinVerifier::new(host, pins),
+        };
+        match self
+            .connect(address, &mut pinned, read_buf, write_buf)
+            .await
+        {
+            Err(ConnectError::Tls(e)) => Err(match pinned.verifier.failure {
+                Some(PinFailure::Mismatch(pin)) => {
+                    warn!("key {:?} of {} is not one pinned for it", pin, host);
+                    ConnectError::PinMismatch(pin)
+                }
+                Some(PinFailure::Unpinned(pin)) => {
+                    warn!("key {:?} of {} is not pinned", pin, host);
+                    ConnectError::Unpinned(pin)
+                }
+                None => ConnectError::Tls(e),
+            }),
+            result => result,
+        }
+    }
+}
+
+/// A [`CryptoProvider`] that verifies the server's certificate chain against one CA
+///
+/// Everything else (randomness, client certificate and signing key) comes from the
+/// wrapped provider. The chain must lead to `ca` and, when connecting by DNS name, name
+/// the host in its subject alternative names or common name; when connecting by IP
+/// address, only certificates without names are accepted. Validity periods are checked
+/// against `Clock`, and not at all with the default [`NoClock`]. Supports ECDSA P-256
+/// certificates, as issued by `gurt-ca`; `CERT_SIZE` bounds the stored server
+/// certificate.
+pub struct TrustCa<'a, P: CryptoProvider, Clock: TlsClock = NoClock, const CERT_SIZE: usize = 4096>
+{
+    inner: P,
+    verifier: CertVerifier<'a, P::CipherSuite, Clock, CERT_SIZE>,
+}
+
+impl<'a, P: CryptoProvider> TrustCa<'a, P> {
+    /// Trust servers with certificates issued by `ca` (DER)
+    pub fn new(inner: P, ca: &'a [u8]) -> Self {
+        Self::with_clock(inner, ca)
+    }
+}
+
+impl<'a, P: CryptoProvider, Clock: TlsClock, const CERT_SIZE: usize>
+    TrustCa<'a, P, Clock, CERT_SIZE>
+{
+    /// Like [`new`](TrustCa::new), with a clock for validity checks or a different
+    /// certificate size bound
+    pub fn with_clock(inner: P, ca: &'a [u8]) -> Self {
+        Self {
+            inner,
+            verifier: CertVerifier::new(Certificate::X509(ca)),
+        }
+    }
+}
+
+impl<P: CryptoProvider, Clock: TlsClock, const CERT_SIZE: usize> CryptoProvider
+    for TrustCa<'_, P, Clock, CERT_SIZE>
+{
+    type CipherSuite = P::CipherSuite;
+    type Signature = P::Signature;
+
+    fn rng(&mut self) -> impl CryptoRngCore {
+        self.inner.rng()
+    }
+
+    fn verifier(&mut self) -> Result<&mut impl TlsVerifier<Self::CipherSuite>, TlsError> {
+        Ok(&mut self.verifier)
+    }
+
+    fn signer(
+        &mut self,
+    ) -> Result<(impl signature::SignerMut<Self::Signature>, SignatureScheme), TlsError> {
+        self.inner.signer()
+    }
+
+    fn client_cert(&mut self) -> Option<Certificate<impl AsRef<[u8]>>> {
+        self.inner.client_cert()
+    }
+}
+
+/// Provider for [`Connector::connect_pinned`]: pin checks, with everything else from
+/// the caller's provider
+struct Pinned<'h, P: CryptoProvider, S> {
+    inner: P,
+    verifier: PinVerifier<'h, S, P::CipherSuite>,
+}
+
+impl<P: CryptoProvider, S: PinStore> CryptoProvider for Pinned<'_, P, S> {
+    type CipherSuite = P::CipherSuite;
+    type Signature = P::Signature;
+
+    fn rng(&mut self) -> impl CryptoRngCore {
+        self.inner.rng()
+    }
+
+    fn verifier(&mut self) -> Result<&mut impl TlsVerifier<Self::CipherSuite>, TlsError> {
+        Ok(&mut self.verifier)
+    }
+
+    fn signer(
+        &mut self,
+    ) -> Result<(impl signature::SignerMut<Self::Signature>, SignatureScheme), TlsError> {
+        self.inner.signer()
+    }
+
+    fn client_cert(&mut self) -> Option<Certificate<impl AsRef<[u8]>>> {
+        self.inner.client_cert()
+    }
+}
+
+/// Split `[gurt://]host[:port][/...]` into host and port, defaulting to the GURT port
+///
+/// From spec: "Default port: 4878"
+fn parse_address(address: &str) -> Option<(&str, u16)> {
+    let address = address.strip_prefix("gurt://").unwrap_or(address);
+    let authority = address.split('/').next()?;
+    let (host, port) = if let Some(rest) = authority.strip_prefix('[') {
+        let (host, rest) = rest.split_once(']')?;
+        match rest {
+            "" => (host, DEFAULT_PORT),
+            _ => (host, rest.strip_prefix(':')?.parse().ok()?),
+        }
+    } else {
+        match authority.split_once(':') {
+            Some((host, port)) => (host, port.parse().ok()?),
+            None => (authority, DEFAULT_PORT),
+        }
+    };
+    if host.is_empty() {
+        return None;
+    }
+    Some((host, port))
+}
@@ -0,0 +1,405 @@
+//! Public key pinning and trust on first use
+//!
+//! Gurted sites commonly serve self-issued certificates, which no CA vouches for. Rather
+//! than accept any certificate, [`Connector::connect_pinned`](super::Connector::connect_pinned)
+//! checks the server's public key against a [`PinStore`]: the SHA-256 hash of its
+//! SubjectPublicKeyInfo must match a pin recorded for the host. [`StaticPins`] holds a
+//! fixed list; [`FilePins`] (`std` feature) also trusts a host's key the first time the
+//! host is seen and records it in a file for later connections.
+//!
+//! A failed check is reported as [`ConnectError::PinMismatch`](super::ConnectError::PinMismatch)
+//! or [`ConnectError::Unpinned`](super::ConnectError::Unpinned), carrying the key the
+//! server presented. Only ECDSA P-256 keys are supported.
+
+use core::fmt;
+use core::str::FromStr;
+
+use embedded_tls::{
+    CertificateEntryRef, CertificateRef, CertificateVerifyRef, SignatureScheme, TlsCipherSuite,
+    TlsError, TlsVerifier,
+};
+use p256::ecdsa::signature::Verifier;
+use p256::ecdsa::{Signature, VerifyingKey};
+use sha2::{Digest, Sha256};
+
+/// The SHA-256 hash of a certificate's SubjectPublicKeyInfo
+///
+/// Written and parsed as `sha256//` followed by the base64 hash, the format curl's
+/// `--pinnedpubkey` uses. The same value can be computed from a certificate with
+/// `openssl x509 -pubkey -noout | openssl pkey -pubin -outform der | openssl dgst -sha256 -binary | base64`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SpkiHash(pub [u8; 32]);
+
+impl SpkiHash {
+    /// Hash a DER SubjectPublicKeyInfo
+    pub fn of_spki(spki: &[u8]) -> Self {
+        Self(Sha256::digest(spki).into())
+    }
+
+    /// Hash the public key of a DER X.509 certificate
+    pub fn of_certificate(certificate: &[u8]) -> Option<Self> {
+        Some(Self::of_spki(subject_public_key_info(certificate)?))
+    }
+}
+
+impl fmt::Display for SpkiHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("sha256//")?;
+        for chunk in self.0.chunks(3) {
+            let mut group = [0u8; 3];
+            group[..chunk.len()].copy_from_slice(chunk);
+            let bits = u32::from_be_bytes([0, group[0], group[1], group[2]]);
+            for i in 0..4 {
+                if i <= chunk.len() {
+                    let sextet = (bits >> (18 - 6 * i)) & 0x3f;
+                    write!(f, "{}", BASE64[sextet as usize] as char)?;
+                } else {
+                    f.write_str("=")?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A pin that is not `sha256//` followed by 32 base64 encoded bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct InvalidPin;
+
+impl fmt::Display for InvalidPin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("invalid pin, expected sha256//<base64>")
+    }
+}
+
+impl core::error::Error for InvalidPin {}
+
+impl FromStr for SpkiHash {
+    type Err = InvalidPin;
+
+    fn from_str(s: &str) -> Result<Self, InvalidPin> {
+        let encoded = s.strip_prefix("sha256//").ok_or(InvalidPin)?.as_bytes();
+        // 32 bytes are 43 base64 characters and one `=`
+        if encoded.len() != 44 || encoded[43] != b'=' {
+            return Err(InvalidPin);
+        }
+        let mut hash = [0u8; 32];
+        let mut filled = 0;
+        let mut bits = 0u32;
+        let mut count = 0;
+        for &c in &encoded[..43] {
+            let sextet = BASE64.iter().position(|&b| b == c).ok_or(InvalidPin)?;
+            bits = (bits << 6 | sextet as u32) & 0xffff;
+            count += 6;
+            if count >= 8 {
+                count -= 8;
+                hash[filled] = (bits >> count) as u8;
+                filled += 1;
+            }
+        }
+        // The last character carries two unused bits, which must be zero
+        if bits & 0b11 != 0 {
+            return Err(InvalidPin);
+        }
+        Ok(Self(hash))
+    }
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Where pinned keys come from
+pub trait PinStore {
+    /// Whether `host` has any pins, and if so whether `pin` is one of them
+    ///
+    /// Host names are compared case-insensitively.
+    fn check(&self, host: &str, pin: &SpkiHash) -> Option<bool>;
+
+    /// Called when `host` has no pins and presented `pin` with a valid handshake
+    /// signature; return `true` to trust it (and remember it for next time)
+    ///
+    /// The default never trusts unpinned hosts.
+    fn first_use(&mut self, host: &str, pin: &SpkiHash) -> bool {
+        let _ = (host, pin);
+        false
+    }
+}
+
+impl<S: PinStore + ?Sized> PinStore for &mut S {
+    fn check(&self, host: &str, pin: &SpkiHash) -> Option<bool> {
+        S::check(self, host, pin)
+    }
+
+    fn first_use(&mut self, host: &str, pin: &SpkiHash) -> bool {
+        S::first_use(self, host, pin)
+    }
+}
+
+/// A fixed list of `(host, pin)` pairs; hosts may have several pins, e.g. during a key
+/// rollover, and hosts without pins are refused
+#[derive(Debug, Clone, Copy)]
+pub struct StaticPins<'a>(pub &'a [(&'a str, SpkiHash)]);
+
+impl PinStore for StaticPins<'_> {
+    fn check(&self, host: &str, pin: &SpkiHash) -> Option<bool> {
+        let mut pins = self
+            .0
+            .iter()
+            .filter(|(h, _)| h.eq_ignore_ascii_case(host))
+            .peekable();
+        pins.peek()?;
+        Some(pins.any(|(_, p)| p == pin))
+    }
+}
+
+/// Pins kept in a text file, trusting and recording each host's key on first use
+///
+/// Each line holds a host and a pin separated by whitespace, as in
+/// `example.web sha256//...`; blank lines and lines starting with `#` are ignored. Pins
+/// can be added by hand, and removing a host's lines makes the next connection trust
+/// whatever key it presents.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct FilePins {
+    path: std::path::PathBuf,
+    pins: alloc::vec::Vec<(alloc::string::String, SpkiHash)>,
+}
+
+#[cfg(feature = "std")]
+impl FilePins {
+    /// Load pins from `path`; a missing file holds no pins and is created on first use
+    pub fn open(path: impl Into<std::path::PathBuf>) -> std::io::Result<Self> {
+        use std::io::{Error, ErrorKind};
+
+        let path = path.into();
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::NotFound => alloc::string::String::new(),
+            Err(e) => return Err(e),
+        };
+        let mut pins = alloc::vec::Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    alloc::format!(
+                        "{}:{}: expected HOST sha256//...",
+                        path.display(),
+                        number + 1
+                    ),
+                )
+            };
+            let mut fields = line.split_whitespace();
+            let (Some(host), Some(pin), None) = (fields.next(), fields.next(), fields.next())
+            else {
+                return Err(invalid());
+            };
+            let pin = pin.parse().map_err(|_| invalid())?;
+            pins.push((host.to_ascii_lowercase(), pin));
+        }
+        Ok(Self { path, pins })
+    }
+
+    /// All `(host, pin)` pairs, in file order
+    pub fn pins(&self) -> &[(alloc::string::String, SpkiHash)] {
+        &self.pins
+    }
+}
+
+#[cfg(feature = "std")]
+impl PinStore for FilePins {
+    fn check(&self, host: &str, pin: &SpkiHash) -> Option<bool> {
+        let mut pins = self
+            .pins
+            .iter()
+            .filter(|(h, _)| h.eq_ignore_ascii_case(host))
+            .peekable();
+        pins.peek()?;
+        Some(pins.any(|(_, p)| p == pin))
+    }
+
+    /// Append the pin to the file; fails closed if it cannot be written
+    fn first_use(&mut self, host: &str, pin: &SpkiHash) -> bool {
+        use std::io::Write;
+
+        let host = host.to_ascii_lowercase();
+        let written = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| writeln!(file, "{host} {pin}"));
+        if written.is_err() {
+            return false;
+        }
+        self.pins.push((host, *pin));
+        true
+    }
+}
+
+/// Why a pinned connection was refused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum PinFailure {
+    /// The host has pins, and the key is not one of them
+    Mismatch(SpkiHash),
+    /// The host has no pins, and the store did not trust it on first use
+    Unpinned(SpkiHash),
+}
+
+/// Verifies the server's key against a [`PinStore`] for a fixed host
+///
+/// The handshake signature is checked with the pinned key, so only a server holding the
+/// private key passes. First use is recorded only after that check.
+pub(super) struct PinVerifier<'h, S, CipherSuite: TlsCipherSuite> {
+    host: &'h str,
+    store: S,
+    /// Pin, public key and transcript from `verify_certificate`
+    pending: Option<(SpkiHash, VerifyingKey, CipherSuite::Hash)>,
+    first_use: bool,
+    pub(super) failure: Option<PinFailure>,
+}
+
+impl<'h, S: PinStore, CipherSuite: TlsCipherSuite> PinVerifier<'h, S, CipherSuite> {
+    pub(super) fn new(host: &'h str, store: S) -> Self {
+        Self {
+            host,
+            store,
+            pending: None,
+            first_use: false,
+            failure: None,
+        }
+    }
+}
+
+impl<S: PinStore, CipherSuite: TlsCipherSuite> TlsVerifier<CipherSuite>
+    for PinVerifier<'_, S, CipherSuite>
+{
+    /// Pins are looked up by the host given to `connect_pinned`, which also covers IP
+    /// addresses, so the SNI name is not needed
+    fn set_hostname_verification(&mut self, _hostname: &str) -> Result<(), TlsError> {
+        Ok(())
+    }
+
+    fn verify_certificate(
+        &mut self,
+        transcript: &CipherSuite::Hash,
+        cert: CertificateRef,
+    ) -> Result<(), TlsError> {
+        let Some(CertificateEntryRef::X509(leaf)) = cert.entries.first() else {
+            return Err(TlsError::InvalidCertificate);
+        };
+        let spki = subject_public_key_info(leaf).ok_or(TlsError::DecodeError)?;
+        let pin = SpkiHash::of_spki(spki);
+        match self.store.check(self.host, &pin) {
+            Some(true) => {}
+            Some(false) => {
+                self.failure = Some(PinFailure::Mismatch(pin));
+                return Err(TlsError::InvalidCertificate);
+            }
+            None => self.first_use = true,
+        }
+        let key = p256_key(spki).ok_or(TlsError::InvalidCertificate)?;
+        self.pending = Some((pin, key, transcript.clone()));
+        Ok(())
+    }
+
+    fn verify_signature(&mut self, verify: CertificateVerifyRef) -> Result<(), TlsError> {
+        let (pin, key, transcript) = self.pending.take().ok_or(TlsError::InvalidHandshake)?;
+        if verify.signature_scheme != SignatureScheme::EcdsaSecp256r1Sha256 {
+            return Err(TlsError::InvalidSignatureScheme);
+        }
+        // RFC 8446 section 4.4.3: 64 spaces, the context string, then the transcript hash
+        let context = b"TLS 1.3, server CertificateVerify\x00";
+        let hash = transcript.finalize();
+        let mut message = [0x20u8; 64 + 34 + 64];
+        let len = 64 + context.len() + hash.len();
+        if len > message.len() {
+            return Err(TlsError::EncodeError);
+        }
+        message[64..64 + context.len()].copy_from_slice(context);
+        message[64 + context.len()..len].copy_from_slice(&hash);
+        let signature = Signature::from_der(verify.signature).map_err(|_| TlsError::DecodeError)?;
+        key.verify(&message[..len], &signature)
+            .map_err(|_| TlsError::InvalidSignature)?;
+
+        if self.first_use && !self.store.first_use(self.host, &pin) {
+            self.failure = Some(PinFailure::Unpinned(pin));
+            return Err(TlsError::InvalidCertificate);
+        }
+        Ok(())
+    }
+}
+
+/// The P-256 public key in a SubjectPublicKeyInfo
+fn p256_key(spki: &[u8]) -> Option<VerifyingKey> {
+    // AlgorithmIdentifier: id-ecPublicKey, prime256v1
+    const EC_P256: &[u8] = &[
+        0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01, 0x06, 0x08, 0x2a, 0x86, 0x48, 0xce,
+        0x3d, 0x03, 0x01, 0x07,
+    ];
+    let (0x30, spki, _) = der_element(spki)? else {
+        return None;
+    };
+    let (0x30, algorithm, rest) = der_element(spki)? else {
+        return None;
+    };
+    let (0x03, [0, point @ ..], _) = der_element(rest)? else {
+        return None;
+    };
+    if algorithm != EC_P256 {
+        return None;
+    }
+    VerifyingKey::from_sec1_bytes(point).ok()
+}
+
+/// The DER SubjectPublicKeyInfo inside a DER certificate, header included
+fn subject_public_key_info(certificate: &[u8]) -> Option<&[u8]> {
+    // Certificate ::= SEQUENCE { tbsCertificate SEQUENCE { [0] version OPTIONAL,
+    //   serialNumber, signature, issuer, validity, subject, subjectPublicKeyInfo, ... } ... }
+    let (0x30, certificate, _) = der_element(certificate)? else {
+        return None;
+    };
+    let (0x30, mut tbs, _) = der_element(certificate)? else {
+        return None;
+    };
+    if tbs.first() == Some(&0xa0) {
+        tbs = der_element(tbs)?.2;
+    }
+    for _ in 0..5 {
+        tbs = der_element(tbs)?.2;
+    }
+    let (0x30, _, rest) = der_element(tbs)? else {
+        return None;
+    };
+    Some(&tbs[..tbs.len() - rest.len()])
+}
+
+/// Split one DER element off `input`: its tag, its contents and what follows
+fn der_element(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = input.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    let (len, rest) = match first {
+        0..=0x7f => (usize::from(first), rest),
+        0x81..=0x84 => {
+            let count = usize::from(first & 0x7f);
+            if rest.len() < count {
+                return None;
+            }
+            let (bytes, rest) = rest.split_at(count);
+            let len = bytes
+                .iter()
+                .fold(0usize, |len, &b| len << 8 | usize::from(b));
+            (len, rest)
+        }
+        _ => return None,
+    };
+    if rest.len() < len {
+        return None;
+    }
+    let (contents, rest) = rest.split_at(len);
+    Some((tag, contents, rest))
+}
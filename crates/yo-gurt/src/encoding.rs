@@ -0,0 +1,473 @@
+//! `content-encoding` support: compressed request and response bodies
+//!
+//! Each codec sits behind its own feature: `gzip`, `deflate`, `brotli` (`br`) and `zstd`.
+//! A client advertises what it can decode with [`ACCEPT_ENCODING`] and reads the response
+//! through [`ResponseReader::decoded_body`](crate::ResponseReader::decoded_body), which
+//! decodes according to the response's `content-encoding`. Request bodies are compressed
+//! up front with [`encode`], since GURT declares the body length before the body:
+//!
+//! ```rust,ignore
+//! use portal_solutions_yo_gurt::encoding::{ACCEPT_ENCODING, ContentEncoding, encode};
+//!
+//! let body = encode(ContentEncoding::Gzip, json.as_bytes()).unwrap();
+//! let headers = [
+//!     ("accept-encoding", ACCEPT_ENCODING),
+//!     ("content-encoding", ContentEncoding::Gzip.as_str()),
+//!     ("content-type", "application/json"),
+//! ];
+//! let mut writer = client
+//!     .request(Method::Post, "/api", host, None, &headers, Some(body.len()))
+//!     .await?;
+//! writer.write(&body).await?;
+//! writer.finish().await?;
+//!
+//! let mut response = client.response_reader();
+//! // ... status line and headers
+//! let length = response.content_length().unwrap_or(0);
+//! let mut body = response.decoded_body(length)?;
+//! let mut json = Vec::new();
+//! body.read_to_end(&mut json).await?;
+//! ```
+//!
+//! Decoding is bounded: a body that decodes to more than [`MAX_MESSAGE_SIZE`] fails with
+//! [`Limit::MessageTooLarge`](crate::Limit::MessageTooLarge), however small it was on the
+//! wire, so a compressed bomb cannot exhaust memory.
+
+use core::fmt;
+
+#[cfg(feature = "alloc")]
+use alloc::{boxed::Box, vec::Vec};
+#[cfg(feature = "alloc")]
+use embedded_io_async::{ErrorType, Read};
+
+#[cfg(feature = "alloc")]
+use crate::progress::{self, NoProgress};
+#[cfg(feature = "alloc")]
+use crate::{BodyReader, MAX_MESSAGE_SIZE};
+use crate::{GurtError, Limit, ProtocolError};
+
+#[cfg(feature = "brotli")]
+mod br;
+#[cfg(any(feature = "gzip", feature = "deflate"))]
+mod gzip;
+#[cfg(feature = "zstd")]
+mod zstd;
+
+/// A `content-encoding` a body can be sent with
+///
+/// All encodings can be named and parsed; [`is_supported`](Self::is_supported) tells
+/// whether this build can encode and decode one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ContentEncoding {
+    /// No encoding
+    Identity,
+    /// gzip (RFC 1952)
+    Gzip,
+    /// zlib-wrapped DEFLATE (RFC 1950); raw DEFLATE is accepted when decoding
+    Deflate,
+    /// Brotli (RFC 7932), `br` on the wire
+    Brotli,
+    /// Zstandard (RFC 8878)
+    Zstd,
+}
+
+impl ContentEncoding {
+    /// The encoding's name in `content-encoding` and `accept-encoding`
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            ContentEncoding::Identity => "identity",
+            ContentEncoding::Gzip => "gzip",
+            ContentEncoding::Deflate => "deflate",
+            ContentEncoding::Brotli => "br",
+            ContentEncoding::Zstd => "zstd",
+        }
+    }
+
+    /// Parse a `content-encoding` value naming a single encoding, ignoring case
+    ///
+    /// Lists of encodings applied in turn (`gzip, br`) are not supported and give `None`.
+    pub fn from_bytes(value: &[u8]) -> Option<Self> {
+        let value = value.trim_ascii();
+        [
+            (&b"identity"[..], ContentEncoding::Identity),
+            (b"gzip", ContentEncoding::Gzip),
+            (b"x-gzip", ContentEncoding::Gzip),
+            (b"deflate", ContentEncoding::Deflate),
+            (b"br", ContentEncoding::Brotli),
+            (b"zstd", ContentEncoding::Zstd),
+        ]
+        .into_iter()
+        .find(|(name, _)| value.eq_ignore_ascii_case(name))
+        .map(|(_, encoding)| encoding)
+    }
+
+    /// Whether this build can encode and decode bodies in this encoding
+    pub const fn is_supported(&self) -> bool {
+        match self {
+            ContentEncoding::Identity => true,
+            ContentEncoding::Gzip => cfg!(feature = "gzip"),
+            ContentEncoding::Deflate => cfg!(feature = "deflate"),
+            ContentEncoding::Brotli => cfg!(feature = "brotli"),
+            ContentEncoding::Zstd => cfg!(feature = "zstd"),
+        }
+    }
+}
+
+impl fmt::Display for ContentEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Encodings this build supports, most preferred first (`identity` excluded)
+pub const SUPPORTED: &[ContentEncoding] = &[
+    #[cfg(feature = "zstd")]
+    ContentEncoding::Zstd,
+    #[cfg(feature = "brotli")]
+    ContentEncoding::Brotli,
+    #[cfg(feature = "gzip")]
+    ContentEncoding::Gzip,
+    #[cfg(feature = "deflate")]
+    ContentEncoding::Deflate,
+];
+
+/// `accept-encoding` value listing [`SUPPORTED`], e.g. `zstd, br, gzip, deflate`, or
+/// `identity` when no codec is enabled
+pub const ACCEPT_ENCODING: &str = {
+    const LEN: usize = accept_encoding_len();
+    const BYTES: [u8; LEN] = accept_encoding_bytes();
+    match core::str::from_utf8(&BYTES) {
+        Ok(value) => value,
+        Err(_) => panic!("encoding names are ASCII"),
+    }
+};
+
+const fn accept_encoding_len() -> usize {
+    if SUPPORTED.is_empty() {
+        return "identity".len();
+    }
+    let mut len = 0;
+    let mut i = 0;
+    while i < SUPPORTED.len() {
+        if i > 0 {
+            len += 2;
+        }
+        len += SUPPORTED[i].as_str().len();
+        i += 1;
+    }
+    len
+}
+
+const fn accept_encoding_bytes<const N: usize>() -> [u8; N] {
+    const fn copy<const N: usize>(bytes: &mut [u8; N], at: usize, name: &str) -> usize {
+        let name = name.as_bytes();
+        let mut i = 0;
+        while i < name.len() {
+            bytes[at + i] = name[i];
+            i += 1;
+        }
+        at + i
+    }
+
+    let mut bytes = [0u8; N];
+    if SUPPORTED.is_empty() {
+        copy(&mut bytes, 0, "identity");
+        return bytes;
+    }
+    let mut len = 0;
+    let mut i = 0;
+    while i < SUPPORTED.len() {
+        if i > 0 {
+            len = copy(&mut bytes, len, ", ");
+        }
+        len = copy(&mut bytes, len, SUPPORTED[i].as_str());
+        i += 1;
+    }
+    bytes
+}
+
+/// Errors from decoding a body
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DecodeError {
+    /// The body is not valid in its encoding
+    Invalid,
+    /// The body ended in the middle of the encoded data
+    Truncated,
+    /// The decoded body exceeds the decoder's limit
+    TooLarge,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DecodeError::Invalid => "invalid encoded body",
+            DecodeError::Truncated => "encoded body ends early",
+            DecodeError::TooLarge => "decoded body exceeds limit",
+        })
+    }
+}
+
+impl core::error::Error for DecodeError {}
+
+impl<E> From<DecodeError> for GurtError<E> {
+    fn from(e: DecodeError) -> Self {
+        match e {
+            DecodeError::Invalid | DecodeError::Truncated => {
+                ProtocolError::InvalidEncodedBody.into()
+            }
+            DecodeError::TooLarge => Limit::MessageTooLarge.into(),
+        }
+    }
+}
+
+/// Compress `data` with `encoding`, or `None` if this build does not support it
+#[cfg(feature = "alloc")]
+pub fn encode(encoding: ContentEncoding, data: &[u8]) -> Option<Vec<u8>> {
+    match encoding {
+        ContentEncoding::Identity => Some(data.to_vec()),
+        #[cfg(feature = "gzip")]
+        ContentEncoding::Gzip => Some(gzip::encode_gzip(data)),
+        #[cfg(feature = "deflate")]
+        ContentEncoding::Deflate => Some(gzip::encode_deflate(data)),
+        #[cfg(feature = "brotli")]
+        ContentEncoding::Brotli => Some(br::encode(data)),
+        #[cfg(feature = "zstd")]
+        ContentEncoding::Zstd => Some(zstd::encode(data)),
+        #[allow(unreachable_patterns)]
+        _ => None,
+    }
+}
+
+/// Progress of one [`Decoder::decode`] call
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Progress {
+    /// Input bytes used; the rest must be passed again
+    pub consumed: usize,
+    /// Decoded bytes written to the output
+    pub produced: usize,
+    /// The encoded body is complete and all its output has been written
+    pub done: bool,
+}
+
+/// The decoder for each encoding
+#[cfg(feature = "alloc")]
+enum Codec {
+    Identity,
+    #[cfg(feature = "gzip")]
+    Gzip(Box<gzip::GzipDecoder>),
+    #[cfg(feature = "deflate")]
+    Deflate(Box<gzip::DeflateDecoder>),
+    #[cfg(feature = "brotli")]
+    Brotli(Box<br::Decoder>),
+    #[cfg(feature = "zstd")]
+    Zstd(Box<zstd::Decoder>),
+}
+
+/// Incremental decoder for one encoded body
+///
+/// Input is pushed in pieces as it arrives; output is pulled into the caller's buffer.
+/// The total output is capped at a limit, [`MAX_MESSAGE_SIZE`] unless set otherwise.
+#[cfg(feature = "alloc")]
+pub struct Decoder {
+    codec: Codec,
+    limit: usize,
+    produced: usize,
+}
+
+#[cfg(feature = "alloc")]
+impl Decoder {
+    /// A decoder for `encoding`, or `None` if this build does not support it
+    pub fn new(encoding: ContentEncoding) -> Option<Self> {
+        Self::with_limit(encoding, MAX_MESSAGE_SIZE)
+    }
+
+    /// A decoder whose output may not exceed `limit` bytes
+    pub fn with_limit(encoding: ContentEncoding, limit: usize) -> Option<Self> {
+        let codec = match encoding {
+            ContentEncoding::Identity => Codec::Identity,
+            #[cfg(feature = "gzip")]
+            ContentEncoding::Gzip => Codec::Gzip(Box::default()),
+            #[cfg(feature = "deflate")]
+            ContentEncoding::Deflate => Codec::Deflate(Box::default()),
+            #[cfg(feature = "brotli")]
+            ContentEncoding::Brotli => Codec::Brotli(Box::default()),
+            #[cfg(feature = "zstd")]
+            ContentEncoding::Zstd => Codec::Zstd(Box::new(zstd::Decoder::new(limit))),
+            #[allow(unreachable_patterns)]
+            _ => return None,
+        };
+        Some(Self {
+            codec,
+            limit,
+            produced: 0,
+        })
+    }
+
+    /// Total decoded bytes so far
+    pub fn total_out(&self) -> usize {
+        self.produced
+    }
+
+    /// Decode from `input` into `output`
+    ///
+    /// `end` tells the decoder that `input` holds the last of the encoded body; an
+    /// encoded body that is incomplete at that point fails with
+    /// [`DecodeError::Truncated`]. Call again with the unconsumed input, or new input,
+    /// until [`Progress::done`].
+    pub fn decode(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+        end: bool,
+    ) -> Result<Progress, DecodeError> {
+        if output.is_empty() {
+            return Ok(Progress::default());
+        }
+        let progress = match &mut self.codec {
+            Codec::Identity => {
+                let n = input.len().min(output.len());
+                output[..n].copy_from_slice(&input[..n]);
+                Progress {
+                    consumed: n,
+                    produced: n,
+                    done: end && n == input.len(),
+                }
+            }
+            #[cfg(feature = "gzip")]
+            Codec::Gzip(decoder) => decoder.decode(input, output, end)?,
+            #[cfg(feature = "deflate")]
+            Codec::Deflate(decoder) => decoder.decode(input, output)?,
+            #[cfg(feature = "brotli")]
+            Codec::Brotli(decoder) => decoder.decode(input, output)?,
+            #[cfg(feature = "zstd")]
+            Codec::Zstd(decoder) => decoder.decode(input, output, end)?,
+        };
+        self.produced += progress.produced;
+        if self.produced > self.limit {
+            return Err(DecodeError::TooLarge);
+        }
+        if !progress.done && progress.produced == 0 {
+            if end && progress.consumed == input.len() {
+                return Err(DecodeError::Truncated);
+            }
+            // Every decoder takes input while it has room for output
+            if progress.consumed == 0 && !input.is_empty() {
+                return Err(DecodeError::Invalid);
+            }
+        }
+        Ok(progress)
+    }
+}
+
+/// Size of the buffer [`DecodedBody`] reads encoded data into
+#[cfg(feature = "alloc")]
+const INPUT_BUFFER_SIZE: usize = 8 * 1024;
+
+/// Reader for a body decoded according to its `content-encoding`
+///
+/// Created by [`ResponseReader::decoded_body`](crate::ResponseReader::decoded_body) and
+/// [`RequestReader::decoded_body`](crate::server::RequestReader::decoded_body). Like
+/// [`BodyReader`], it never reads past the end of the body.
+#[cfg(feature = "alloc")]
+pub struct DecodedBody<'a, T, P = NoProgress> {
+    body: BodyReader<'a, T, P>,
+    decoder: Decoder,
+    input: Box<[u8]>,
+    start: usize,
+    end: usize,
+    done: bool,
+}
+
+#[cfg(feature = "alloc")]
+impl<'a, T: Read, P: progress::Progress> DecodedBody<'a, T, P> {
+    pub(crate) fn new(body: BodyReader<'a, T, P>, decoder: Decoder) -> Self {
+        Self {
+            body,
+            decoder,
+            input: alloc::vec![0; INPUT_BUFFER_SIZE].into_boxed_slice(),
+            start: 0,
+            end: 0,
+            done: false,
+        }
+    }
+
+    /// Report the encoded body bytes read so far to `progress` after each read
+    pub fn with_progress<Q: progress::Progress>(self, progress: Q) -> DecodedBody<'a, T, Q> {
+        DecodedBody {
+            body: self.body.with_progress(progress),
+            decoder: self.decoder,
+            input: self.input,
+            start: self.start,
+            end: self.end,
+            done: self.done,
+        }
+    }
+
+    /// Whether the whole body has been read and decoded
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Read the rest of the decoded body, appending it to `out`
+    pub async fn read_to_end(&mut self, out: &mut Vec<u8>) -> Result<usize, GurtError<T::Error>>
+    where
+        T::Error: 'static,
+    {
+        let mut total = 0;
+        let mut chunk = [0u8; 1024];
+        loop {
+            let n = self.read(&mut chunk).await?;
+            if n == 0 {
+                return Ok(total);
+            }
+            out.extend_from_slice(&chunk[..n]);
+            total += n;
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<'a, T: ErrorType, P> ErrorType for DecodedBody<'a, T, P>
+where
+    T::Error: 'static,
+{
+    type Error = GurtError<T::Error>;
+}
+
+#[cfg(feature = "alloc")]
+impl<'a, T: Read, P: progress::Progress> Read for DecodedBody<'a, T, P>
+where
+    T::Error: 'static,
+{
+    /// Read decoded body data
+    ///
+    /// Returns `Ok(0)` at the end of the body. Data after the end of the encoded stream
+    /// fails with [`ProtocolError::InvalidEncodedBody`].
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        if buf.is_empty() || self.done {
+            return Ok(0);
+        }
+        loop {
+            if self.start == self.end && !self.body.is_done() {
+                self.start = 0;
+                self.end = self.body.read(&mut self.input).await?;
+            }
+            let end = self.body.is_done();
+            let progress = self
+                .decoder
+                .decode(&self.input[self.start..self.end], buf, end)?;
+            self.start += progress.consumed;
+            if progress.done {
+                if self.start != self.end || !self.body.is_done() {
+                    return Err(ProtocolError::InvalidEncodedBody.into());
+                }
+                self.done = true;
+            }
+            if progress.produced > 0 || self.done {
+                return Ok(progress.produced);
+            }
+        }
+    }
+}
@@ -0,0 +1,86 @@
+//! Brotli over the `brotli` crate
+
+use alloc::vec::Vec;
+
+use brotli::enc::{BrotliEncoderParams, StandardAlloc};
+use brotli::{BrotliDecompressStream, BrotliResult, BrotliState};
+
+use super::{DecodeError, Progress};
+
+/// Quality for [`encode`]: well compressed without the cost of the top levels
+const QUALITY: i32 = 5;
+
+pub(super) fn encode(data: &[u8]) -> Vec<u8> {
+    let params = BrotliEncoderParams {
+        quality: QUALITY,
+        ..BrotliEncoderParams::default()
+    };
+    let mut out = Vec::new();
+    brotli::BrotliCompress(&mut &data[..], &mut out, &params)
+        .expect("writing to a Vec cannot fail");
+    out
+}
+
+pub(super) struct Decoder {
+    state: BrotliState<StandardAlloc, StandardAlloc, StandardAlloc>,
+    done: bool,
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Self {
+            state: BrotliState::new(
+                StandardAlloc::default(),
+                StandardAlloc::default(),
+                StandardAlloc::default(),
+            ),
+            done: false,
+        }
+    }
+}
+
+impl Decoder {
+    pub(super) fn decode(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+    ) -> Result<Progress, DecodeError> {
+        if self.done {
+            return match input.is_empty() {
+                true => Ok(Progress {
+                    done: true,
+                    ..Progress::default()
+                }),
+                false => Err(DecodeError::Invalid),
+            };
+        }
+        let mut available_in = input.len();
+        let mut consumed = 0;
+        let mut available_out = output.len();
+        let mut produced = 0;
+        let mut total_out = 0;
+        let result = BrotliDecompressStream(
+            &mut available_in,
+            &mut consumed,
+            input,
+            &mut available_out,
+            &mut produced,
+            output,
+            &mut total_out,
+            &mut self.state,
+        );
+        self.done = match result {
+            BrotliResult::ResultSuccess => true,
+            BrotliResult::NeedsMoreInput | BrotliResult::NeedsMoreOutput => false,
+            BrotliResult::ResultFailure => return Err(DecodeError::Invalid),
+        };
+        if self.done && consumed != input.len() {
+            return Err(DecodeError::Invalid);
+        }
+        Ok(Progress {
+            consumed,
+            produced,
+            done: self.done,
+        })
+    }
+}
@@ -0,0 +1,288 @@
+//! gzip and deflate over `miniz_oxide`
+
+// Both share inflate; either may be built alone
+#![cfg_attr(not(all(feature = "gzip", feature = "deflate")), allow(dead_code))]
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use miniz_oxide::inflate::stream::{InflateState, inflate};
+use miniz_oxide::{DataFormat, MZError, MZFlush, MZStatus};
+
+use super::{DecodeError, Progress};
+
+/// Compression level for [`encode_gzip`] and [`encode_deflate`], miniz's default
+const LEVEL: u8 = 6;
+
+/// Header fields beyond this are not worth buffering
+const MAX_HEADER_SIZE: usize = 64 * 1024;
+
+/// gzip member flags (RFC 1952 section 2.3.1)
+const FHCRC: u8 = 0x02;
+const FEXTRA: u8 = 0x04;
+const FNAME: u8 = 0x08;
+const FCOMMENT: u8 = 0x10;
+const FRESERVED: u8 = 0xe0;
+
+#[cfg(feature = "gzip")]
+pub(super) fn encode_gzip(data: &[u8]) -> Vec<u8> {
+    // Magic, CM = deflate, no flags, no mtime, XFL = 0, OS = unknown
+    let mut out = Vec::from([0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff]);
+    out.extend_from_slice(&miniz_oxide::deflate::compress_to_vec(data, LEVEL));
+    out.extend_from_slice(&crc32(0, data).to_le_bytes());
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out
+}
+
+#[cfg(feature = "deflate")]
+pub(super) fn encode_deflate(data: &[u8]) -> Vec<u8> {
+    miniz_oxide::deflate::compress_to_vec_zlib(data, LEVEL)
+}
+
+/// Run `state` over `input`, returning input used, output written and whether the
+/// DEFLATE stream ended
+fn inflate_step(
+    state: &mut InflateState,
+    input: &[u8],
+    output: &mut [u8],
+) -> Result<(usize, usize, bool), DecodeError> {
+    let result = inflate(state, input, output, MZFlush::None);
+    let done = match result.status {
+        Ok(MZStatus::StreamEnd) => true,
+        // `Buf` only means no progress could be made with this input
+        Ok(_) | Err(MZError::Buf) => false,
+        Err(_) => return Err(DecodeError::Invalid),
+    };
+    Ok((result.bytes_consumed, result.bytes_written, done))
+}
+
+/// Where a gzip decoder is within the current member
+enum Stage {
+    Header,
+    Body,
+    Trailer,
+    /// A member ended; another may follow
+    End,
+}
+
+/// Decoder for gzip, including several members in a row
+pub(super) struct GzipDecoder {
+    stage: Stage,
+    /// Header or trailer bytes gathered so far
+    pending: Vec<u8>,
+    state: Box<InflateState>,
+    crc: u32,
+    size: u32,
+}
+
+impl Default for GzipDecoder {
+    fn default() -> Self {
+        Self {
+            stage: Stage::Header,
+            pending: Vec::new(),
+            state: InflateState::new_boxed(DataFormat::Raw),
+            crc: 0,
+            size: 0,
+        }
+    }
+}
+
+impl GzipDecoder {
+    pub(super) fn decode(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+        end: bool,
+    ) -> Result<Progress, DecodeError> {
+        let mut consumed = 0;
+        let mut produced = 0;
+        loop {
+            let rest = &input[consumed..];
+            match self.stage {
+                Stage::Header => {
+                    let before = self.pending.len();
+                    self.pending.extend_from_slice(rest);
+                    match header_len(&self.pending)? {
+                        Some(len) => {
+                            consumed += len - before;
+                            self.pending.clear();
+                            self.stage = Stage::Body;
+                        }
+                        None if self.pending.len() > MAX_HEADER_SIZE => {
+                            return Err(DecodeError::Invalid);
+                        }
+                        None => {
+                            consumed += rest.len();
+                            break;
+                        }
+                    }
+                }
+                Stage::Body => {
+                    let (used, written, done) =
+                        inflate_step(&mut self.state, rest, &mut output[produced..])?;
+                    let out = &output[produced..produced + written];
+                    self.crc = crc32(self.crc, out);
+                    self.size = self.size.wrapping_add(written as u32);
+                    consumed += used;
+                    produced += written;
+                    if done {
+                        self.stage = Stage::Trailer;
+                    } else {
+                        break;
+                    }
+                }
+                Stage::Trailer => {
+                    let take = rest.len().min(8 - self.pending.len());
+                    self.pending.extend_from_slice(&rest[..take]);
+                    consumed += take;
+                    if self.pending.len() < 8 {
+                        break;
+                    }
+                    let crc = u32::from_le_bytes(self.pending[..4].try_into().unwrap());
+                    let size = u32::from_le_bytes(self.pending[4..].try_into().unwrap());
+                    if crc != self.crc || size != self.size {
+                        return Err(DecodeError::Invalid);
+                    }
+                    self.stage = Stage::End;
+                }
+                Stage::End if rest.is_empty() => {
+                    return Ok(Progress {
+                        consumed,
+                        produced,
+                        done: end,
+                    });
+                }
+                Stage::End => {
+                    // Another member follows
+                    self.pending.clear();
+                    self.state.reset(DataFormat::Raw);
+                    self.crc = 0;
+                    self.size = 0;
+                    self.stage = Stage::Header;
+                }
+            }
+        }
+        Ok(Progress {
+            consumed,
+            produced,
+            done: false,
+        })
+    }
+}
+
+/// Length of the gzip member header at the start of `data`, or `None` if more is needed
+fn header_len(data: &[u8]) -> Result<Option<usize>, DecodeError> {
+    let Some(fixed) = data.get(..10) else {
+        // Reject a wrong magic number as soon as it arrives
+        return match data.iter().zip([0x1f, 0x8b, 8]).all(|(a, b)| *a == b) {
+            true => Ok(None),
+            false => Err(DecodeError::Invalid),
+        };
+    };
+    if fixed[..3] != [0x1f, 0x8b, 8] || fixed[3] & FRESERVED != 0 {
+        return Err(DecodeError::Invalid);
+    }
+    let flags = fixed[3];
+    let mut len = 10;
+    if flags & FEXTRA != 0 {
+        let Some(xlen) = data.get(len..len + 2) else {
+            return Ok(None);
+        };
+        len += 2 + usize::from(u16::from_le_bytes([xlen[0], xlen[1]]));
+    }
+    for flag in [FNAME, FCOMMENT] {
+        if flags & flag != 0 {
+            let Some(zero) = data
+                .get(len..)
+                .and_then(|rest| rest.iter().position(|&b| b == 0))
+            else {
+                return Ok(None);
+            };
+            len += zero + 1;
+        }
+    }
+    if flags & FHCRC != 0 {
+        len += 2;
+    }
+    Ok((data.len() >= len).then_some(len))
+}
+
+/// Decoder for deflate: zlib-wrapped, or raw DEFLATE as some servers send
+#[derive(Default)]
+pub(super) struct DeflateDecoder {
+    state: Option<Box<InflateState>>,
+    done: bool,
+}
+
+impl DeflateDecoder {
+    pub(super) fn decode(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+    ) -> Result<Progress, DecodeError> {
+        if self.done {
+            return match input.is_empty() {
+                true => Ok(Progress {
+                    done: true,
+                    ..Progress::default()
+                }),
+                false => Err(DecodeError::Invalid),
+            };
+        }
+        let state = match &mut self.state {
+            Some(state) => state,
+            None => {
+                let Some(&first) = input.first() else {
+                    return Ok(Progress::default());
+                };
+                // RFC 1950 CMF byte: CM = 8 with a window of at most 32K. A raw stream
+                // would have to start with a stored block with padding bits set.
+                let format = match first & 0x0f == 8 && first >> 4 <= 7 {
+                    true => DataFormat::Zlib,
+                    false => DataFormat::Raw,
+                };
+                self.state.insert(InflateState::new_boxed(format))
+            }
+        };
+        let (consumed, produced, done) = inflate_step(state, input, output)?;
+        self.done = done;
+        if done && consumed != input.len() {
+            return Err(DecodeError::Invalid);
+        }
+        Ok(Progress {
+            consumed,
+            produced,
+            done,
+        })
+    }
+}
+
+/// CRC-32 (IEEE) lookup table
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                0xedb8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// Continue the gzip CRC-32 `crc` over `data`
+fn crc32(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for &b in data {
+        crc = CRC_TABLE[((crc ^ u32::from(b)) & 0xff) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
@@ -0,0 +1,149 @@
+//! Zstandard over `ruzstd`
+//!
+//! The decoder hands `ruzstd` one block at a time and drains its output before the next,
+//! so a block that expands enormously is caught by the size limit before the one after it
+//! is decoded.
+
+use alloc::vec::Vec;
+
+use ruzstd::decoding::FrameDecoder;
+use ruzstd::encoding::{CompressionLevel, compress_to_vec};
+use ruzstd::io::Read;
+
+use super::{DecodeError, Progress};
+
+/// Largest frame header: magic, descriptor, window, dictionary ID and content size
+const MAX_FRAME_HEADER_SIZE: usize = 18;
+/// Largest block content (RFC 8878 section 3.1.1.2.3)
+const MAX_BLOCK_SIZE: usize = 128 * 1024;
+/// Windows up to this size are always accepted, whatever the decoder's limit
+const MIN_WINDOW_LIMIT: usize = 1 << 20;
+
+pub(super) fn encode(data: &[u8]) -> Vec<u8> {
+    compress_to_vec(data, CompressionLevel::Fastest)
+}
+
+/// Where the decoder is within the current frame
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    Header,
+    Blocks,
+    Checksum,
+    /// A frame ended; another may follow
+    End,
+}
+
+pub(super) struct Decoder {
+    frame: FrameDecoder,
+    stage: Stage,
+    /// Input not yet handed to `frame`
+    pending: Vec<u8>,
+}
+
+impl Decoder {
+    pub(super) fn new(limit: usize) -> Self {
+        let mut frame = FrameDecoder::new();
+        // The window stays buffered until the frame ends, so keep it near the limit
+        frame.set_max_window_size(limit.max(MIN_WINDOW_LIMIT) as u64);
+        Self {
+            frame,
+            stage: Stage::Header,
+            pending: Vec::new(),
+        }
+    }
+
+    pub(super) fn decode(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+        end: bool,
+    ) -> Result<Progress, DecodeError> {
+        self.pending.extend_from_slice(input);
+        let consumed = input.len();
+        let mut produced = 0;
+        loop {
+            if self.stage != Stage::Header {
+                produced += self
+                    .frame
+                    .read(&mut output[produced..])
+                    .map_err(|_| DecodeError::Invalid)?;
+                if produced == output.len() {
+                    break;
+                }
+            }
+            match self.stage {
+                Stage::Header => {
+                    let mut header = &self.pending[..];
+                    match self.frame.reset(&mut header) {
+                        Ok(()) => {
+                            let len = self.pending.len() - header.len();
+                            self.pending.drain(..len);
+                            self.stage = Stage::Blocks;
+                        }
+                        Err(_) if self.pending.len() < MAX_FRAME_HEADER_SIZE => break,
+                        Err(_) => return Err(DecodeError::Invalid),
+                    }
+                }
+                Stage::Blocks => {
+                    let Some(&[a, b, c]) = self.pending.get(..3) else {
+                        break;
+                    };
+                    let header = u32::from_le_bytes([a, b, c, 0]);
+                    let last = header & 1 != 0;
+                    let size = (header >> 3) as usize;
+                    let content = match (header >> 1) & 3 {
+                        // Raw and compressed blocks carry `size` bytes, RLE blocks one
+                        0 | 2 => size,
+                        1 => 1,
+                        _ => return Err(DecodeError::Invalid),
+                    };
+                    if size > MAX_BLOCK_SIZE {
+                        return Err(DecodeError::Invalid);
+                    }
+                    let len = 3 + content;
+                    if self.pending.len() < len {
+                        break;
+                    }
+                    let (used, written) = self
+                        .frame
+                        .decode_from_to(&self.pending[..len], &mut output[produced..])
+                        .map_err(|_| DecodeError::Invalid)?;
+                    if used != len {
+                        return Err(DecodeError::Invalid);
+                    }
+                    self.pending.drain(..len);
+                    produced += written;
+                    if last {
+                        self.stage = match self.frame.is_finished() {
+                            true => Stage::End,
+                            false => Stage::Checksum,
+                        };
+                    }
+                }
+                Stage::Checksum => {
+                    if self.pending.len() < 4 {
+                        break;
+                    }
+                    self.frame
+                        .decode_from_to(&self.pending[..4], &mut [])
+                        .map_err(|_| DecodeError::Invalid)?;
+                    self.pending.drain(..4);
+                    self.stage = Stage::End;
+                }
+                Stage::End if self.pending.is_empty() => {
+                    return Ok(Progress {
+                        consumed,
+                        produced,
+                        done: end,
+                    });
+                }
+                Stage::End => self.stage = Stage::Header,
+            }
+        }
+        Ok(Progress {
+            consumed,
+            produced,
+            done: false,
+        })
+    }
+}
@@ -0,0 +1,259 @@
+//! Error type shared by all client and server operations
+
+use core::fmt;
+
+use embedded_io_async::{ErrorKind, ReadExactError};
+
+use crate::StatusCode;
+
+/// Errors from sending requests and reading responses, or reading requests and sending
+/// responses on the server side
+///
+/// `E` is the transport's error type, kept as-is in [`GurtError::Io`]; it is shown by its
+/// `Display` and returned as the [`source`](core::error::Error::source) of the error.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum GurtError<E> {
+    /// IO error from transport
+    Io(E),
+    /// The connection closed before a complete message was read
+    UnexpectedEof,
+    /// The peer sent something that is not valid GURT
+    Protocol(ProtocolError),
+    /// A size limit was exceeded
+    LimitExceeded(Limit),
+    /// The operation took longer than allowed
+    ///
+    /// From spec: "Default request timeout: 30 seconds"
+    Timeout,
+    /// The request was rejected before anything was written
+    InvalidRequest(RequestError),
+    /// The response was rejected before anything was written, or its body did not match
+    /// its `content-length`
+    InvalidResponse(RequestError),
+    /// The server answered HANDSHAKE with something other than `101 SWITCHING_PROTOCOLS`
+    ///
+    /// From spec: "Every GURT session must begin with a `HANDSHAKE` request"
+    HandshakeRejected(StatusCode),
+    /// The server's public key is not pinned for the host: the host has other pins, or
+    /// none and the pin store did not trust the key on first use
+    ///
+    /// Holds the SHA-256 hash of the key's SubjectPublicKeyInfo, as the `embedded-tls`
+    /// connector's `SpkiHash` does.
+    PinMismatch([u8; 32]),
+}
+
+/// Protocol violations in a received message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ProtocolError {
+    /// Invalid protocol version
+    InvalidProtocol,
+    /// Invalid status line
+    InvalidStatusLine,
+    /// Request line that is not `METHOD /path GURT/1.0.0`
+    InvalidRequestLine,
+    /// Request line naming a method GURT does not define
+    InvalidMethod,
+    /// A session that did not begin with `HANDSHAKE`
+    ///
+    /// From spec: "Every GURT session must begin with a `HANDSHAKE` request"
+    HandshakeRequired,
+    /// Invalid header format (no colon)
+    InvalidHeader,
+    /// A CR or LF that is not part of a CRLF line ending
+    InvalidLineEnding,
+    /// Header line with nothing before the colon
+    EmptyHeaderName,
+    /// Header name containing characters other than token characters
+    InvalidHeaderName,
+    /// Header value containing control characters
+    InvalidHeaderValue,
+    /// Header line starting with whitespace (obsolete line folding)
+    ObsoleteLineFolding,
+    /// Whitespace between a header name and the colon ([`ParseMode::Strict`](crate::ParseMode::Strict) only)
+    WhitespaceBeforeColon,
+    /// `content-length` that is not a plain decimal number
+    InvalidContentLength,
+    /// More than one `content-length` value ([`ParseMode::Strict`](crate::ParseMode::Strict) only)
+    DuplicateContentLength,
+    /// `content-length` values that disagree
+    ConflictingContentLength,
+    /// A `transfer-encoding` header, which GURT does not define
+    TransferEncodingNotAllowed,
+    /// A body in a `content-encoding` that is unknown or whose feature is not enabled
+    UnsupportedContentEncoding,
+    /// A body that is not valid in its `content-encoding`
+    InvalidEncodedBody,
+    /// A malformed URL-encoded or multipart form body
+    InvalidFormData,
+    /// A partial response whose `content-range` is missing, malformed or not the range
+    /// asked for
+    InvalidContentRange,
+}
+
+/// Size limits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Limit {
+    /// A status or header line does not fit in the caller's buffer
+    BufferTooSmall,
+    /// A message body is larger than [`MAX_MESSAGE_SIZE`](crate::MAX_MESSAGE_SIZE)
+    ///
+    /// From spec: "Maximum message size: 10 MB"
+    MessageTooLarge,
+}
+
+/// Request or response input that cannot be sent safely
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RequestError {
+    /// Empty path, or a path containing whitespace or control characters
+    InvalidPath,
+    /// Empty host, or a host containing whitespace or control characters
+    InvalidHost,
+    /// Header name that is empty, not a token, or not lowercase
+    ///
+    /// From spec: "Headers must be lowercase"
+    InvalidHeaderName,
+    /// Header value containing CR, LF or other control characters, which would let it
+    /// inject extra header lines
+    InvalidHeaderValue,
+    /// A header written from the head's own fields (`host`, `user-agent`,
+    /// `content-length`) or one GURT does not define (`transfer-encoding`)
+    ReservedHeader,
+    /// More body data than the declared `content-length`
+    BodyTooLong,
+    /// A message finished before its declared `content-length` was written
+    BodyIncomplete,
+}
+
+impl<E> From<ReadExactError<E>> for GurtError<E> {
+    fn from(e: ReadExactError<E>) -> Self {
+        match e {
+            ReadExactError::UnexpectedEof => GurtError::UnexpectedEof,
+            ReadExactError::Other(e) => GurtError::Io(e),
+        }
+    }
+}
+
+impl<E> From<ProtocolError> for GurtError<E> {
+    fn from(e: ProtocolError) -> Self {
+        GurtError::Protocol(e)
+    }
+}
+
+impl<E> From<Limit> for GurtError<E> {
+    fn from(e: Limit) -> Self {
+        GurtError::LimitExceeded(e)
+    }
+}
+
+impl<E> From<RequestError> for GurtError<E> {
+    fn from(e: RequestError) -> Self {
+        GurtError::InvalidRequest(e)
+    }
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ProtocolError::InvalidProtocol => "invalid protocol version",
+            ProtocolError::InvalidStatusLine => "invalid status line",
+            ProtocolError::InvalidRequestLine => "invalid request line",
+            ProtocolError::InvalidMethod => "unknown method",
+            ProtocolError::HandshakeRequired => "session did not begin with HANDSHAKE",
+            ProtocolError::InvalidHeader => "header line without a colon",
+            ProtocolError::InvalidLineEnding => "CR or LF outside a CRLF line ending",
+            ProtocolError::EmptyHeaderName => "empty header name",
+            ProtocolError::InvalidHeaderName => "invalid character in header name",
+            ProtocolError::InvalidHeaderValue => "control character in header value",
+            ProtocolError::ObsoleteLineFolding => "obsolete header line folding",
+            ProtocolError::WhitespaceBeforeColon => "whitespace before header colon",
+            ProtocolError::InvalidContentLength => "invalid content-length",
+            ProtocolError::DuplicateContentLength => "duplicate content-length",
+            ProtocolError::ConflictingContentLength => "conflicting content-length values",
+            ProtocolError::TransferEncodingNotAllowed => "transfer-encoding is not allowed",
+            ProtocolError::UnsupportedContentEncoding => "unsupported content-encoding",
+            ProtocolError::InvalidEncodedBody => "body does not match its content-encoding",
+            ProtocolError::InvalidFormData => "malformed form body",
+            ProtocolError::InvalidContentRange => {
+                "content-range does not match the range requested"
+            }
+        })
+    }
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Limit::BufferTooSmall => "line does not fit in buffer",
+            Limit::MessageTooLarge => "message exceeds maximum size",
+        })
+    }
+}
+
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            RequestError::InvalidPath => "invalid request path",
+            RequestError::InvalidHost => "invalid host",
+            RequestError::InvalidHeaderName => "invalid header name",
+            RequestError::InvalidHeaderValue => "invalid header value",
+            RequestError::ReservedHeader => "header cannot be set directly",
+            RequestError::BodyTooLong => "body longer than content-length",
+            RequestError::BodyIncomplete => "body shorter than content-length",
+        })
+    }
+}
+
+impl<E: fmt::Display> fmt::Display for GurtError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GurtError::Io(e) => write!(f, "transport error: {e}"),
+            GurtError::UnexpectedEof => f.write_str("connection closed unexpectedly"),
+            GurtError::Protocol(e) => write!(f, "protocol error: {e}"),
+            GurtError::LimitExceeded(e) => write!(f, "limit exceeded: {e}"),
+            GurtError::Timeout => f.write_str("timed out"),
+            GurtError::InvalidRequest(e) => write!(f, "invalid request: {e}"),
+            GurtError::InvalidResponse(e) => write!(f, "invalid response: {e}"),
+            GurtError::HandshakeRejected(status) => write!(
+                f,
+                "handshake rejected: {} {}",
+                status.as_u16(),
+                status.reason_phrase()
+            ),
+            GurtError::PinMismatch(_) => f.write_str("server key is not pinned for the host"),
+        }
+    }
+}
+
+impl core::error::Error for ProtocolError {}
+
+impl core::error::Error for Limit {}
+
+impl core::error::Error for RequestError {}
+
+/// The transport error is the source of [`GurtError::Io`]
+impl<E: core::error::Error + 'static> core::error::Error for GurtError<E> {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            GurtError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl<E: embedded_io_async::Error + 'static> embedded_io_async::Error for GurtError<E> {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            GurtError::Io(e) => e.kind(),
+            GurtError::UnexpectedEof => ErrorKind::ConnectionAborted,
+            GurtError::Protocol(_) | GurtError::LimitExceeded(_) => ErrorKind::InvalidData,
+            GurtError::Timeout => ErrorKind::TimedOut,
+            GurtError::InvalidRequest(_) | GurtError::InvalidResponse(_) => ErrorKind::InvalidInput,
+            GurtError::HandshakeRejected(_) => ErrorKind::ConnectionRefused,
+            GurtError::PinMismatch(_) => ErrorKind::PermissionDenied,
+        }
+    }
+}
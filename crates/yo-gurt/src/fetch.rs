@@ -0,0 +1,976 @@
+//! High-level client: pooled connections, cookies and whole-body responses
+//!
+//! [`Client`] takes care of what [`GurtClient`] leaves to the caller: connecting, the
+//! handshake, reusing connections, `set-cookie`, decoding compressed bodies and timeouts.
+//!
+//! ```rust,ignore
+//! use portal_solutions_yo_gurt::fetch::Client;
+//!
+//! let client = Client::new();
+//! let response = client
+//!     .get("gurt://example.web/api/items?page=2")
+//!     .header("accept", "application/json")
+//!     .timeout(Duration::from_secs(5))
+//!     .send()
+//!     .await?
+//!     .error_for_status()?;
+//! let items: Vec<Item> = response.json().await?;
+//! ```
+//!
+//! Connections are opened by a [`Connect`] implementation, [`Connector`] by default:
+//! TLS 1.3 verified against the WebPKI roots, with hosts looked up by the operating
+//! system. [`ClientBuilder`] adds roots (such as a `gurt-ca` development CA), a client
+//! certificate or another [`Resolver`], and [`ClientBuilder::build_with`] takes any other
+//! connector. Each connection is handshaken once and kept for later requests to the same
+//! host and port: up to [`MAX_CONNECTION_POOL_SIZE`] idle connections per host, each for
+//! up to [`POOL_IDLE_TIMEOUT_SECS`]. A request that fails on a reused connection before a
+//! response arrives, as when the server closed it while idle, is sent again once on a
+//! new connection if [`Retry`] allows its method to be repeated.
+//!
+//! Requests advertise [`ACCEPT_ENCODING`] and response bodies are decoded accordingly.
+//! Cookies are kept in a [`MemoryStore`] unless the builder is given another
+//! [`CookieStore`] or told not to keep them. The whole exchange, from connecting to the
+//! last body byte, must finish within the timeout: [`DEFAULT_REQUEST_TIMEOUT_SECS`] unless
+//! set on the builder or the request.
+//!
+//! Failed idempotent requests, and `503` and `504` responses to them, are retried with
+//! exponential backoff as described by [`Retry`], within the same timeout.
+//!
+//! [`RequestBuilder::download`] saves a resource to a file a range at a time, continuing
+//! from whatever an earlier, interrupted download left in the file.
+//!
+//! Given a [`CacheStore`], the client caches `GET` responses as described in
+//! [`cache`](crate::cache): fresh responses are served without asking the server and
+//! stale ones are revalidated, turning a `304 NOT_MODIFIED` back into the stored
+//! response. A request with `cache-control: no-cache` or `max-age=0` always revalidates, one with
+//! `no-store` bypasses the cache, and `POST`, `PUT`, `PATCH` and `DELETE` remove the
+//! stored response for their URL.
+
+mod connect;
+mod download;
+mod response;
+mod retry;
+mod url;
+
+pub use connect::{Connect, Connector};
+pub use download::DOWNLOAD_CHUNK_SIZE;
+pub use response::Response;
+pub use retry::Retry;
+pub use url::Url;
+
+use core::fmt;
+use std::boxed::Box;
+use std::collections::HashMap;
+use std::io;
+use std::string::{String, ToString};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use std::vec::Vec;
+
+use rustls::RootCertStore;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio::io::BufReader;
+
+use crate::adapters::FromTokio;
+use crate::cache::{CacheControl, CacheStore, CachedResponse};
+use crate::cookie::{CookieJar, CookieStore, MemoryStore, unix_time};
+use crate::encoding::{ACCEPT_ENCODING, ContentEncoding};
+use crate::form::{URLENCODED, UrlEncoded};
+#[cfg(feature = "metrics")]
+use crate::metrics;
+use crate::range::ByteRange;
+use crate::resolve::{Resolver, System};
+use crate::tls;
+use crate::{
+    DEFAULT_CONNECTION_TIMEOUT_SECS, DEFAULT_HANDSHAKE_TIMEOUT_SECS, DEFAULT_REQUEST_TIMEOUT_SECS,
+    DEFAULT_USER_AGENT, GurtClient, GurtError, MAX_CONNECTION_POOL_SIZE, Method,
+    POOL_IDLE_TIMEOUT_SECS, RequestHead, StatusCode,
+};
+
+/// Buffer for the handshake response and each response header line
+const HEADER_BUFFER_SIZE: usize = 8 * 1024;
+
+/// A handshaken connection as kept in the pool
+type Connection<T> = GurtClient<FromTokio<BufReader<T>>>;
+
+/// Idle connections by host and port
+type Pool<T> = HashMap<(String, u16), Vec<Idle<T>>>;
+
+/// Errors from [`Client`] requests and [`Response`] bodies
+#[derive(Debug)]
+pub enum Error {
+    /// Not a `gurt://` URL
+    InvalidUrl,
+    /// Connecting, sending the request or reading the response failed
+    Gurt(GurtError<io::Error>),
+    /// The response has a 4xx or 5xx status; see [`Response::error_for_status`]
+    Status(StatusCode),
+    /// A JSON body could not be serialized or deserialized
+    #[cfg(feature = "json")]
+    Json(serde_json::Error),
+}
+
+impl Error {
+    /// Whether the request or response timed out
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, Error::Gurt(GurtError::Timeout))
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidUrl => f.write_str("invalid URL, expected gurt://host[:port][/path]"),
+            Error::Gurt(GurtError::Io(e)) => write!(f, "transport error: {e}"),
+            Error::Gurt(e) => write!(f, "{e}"),
+            Error::Status(status) => {
+                write!(f, "status {} {}", status.as_u16(), status.reason_phrase())
+            }
+            #[cfg(feature = "json")]
+            Error::Json(e) => write!(f, "JSON error: {e}"),
+        }
+    }
+}
+
+impl core::error::Error for Error {}
+
+impl From<GurtError<io::Error>> for Error {
+    fn from(e: GurtError<io::Error>) -> Self {
+        Error::Gurt(e)
+    }
+}
+
+/// Run `future` until `deadline`, failing with [`GurtError::Timeout`] after it
+async fn within<T>(
+    deadline: Option<Instant>,
+    future: impl Future<Output = Result<T, Error>>,
+) -> Result<T, Error> {
+    match deadline {
+        Some(deadline) => tokio::time::timeout_at(deadline.into(), future)
+            .await
+            .map_err(|_| Error::Gurt(GurtError::Timeout))?,
+        None => future.await,
+    }
+}
+
+/// Settings for a [`Client`]
+///
+/// TLS settings (roots, certificate verification, client identity) and the resolver
+/// apply to [`build`](Self::build) only; [`build_with`](Self::build_with) takes a
+/// connector that brings its own.
+pub struct ClientBuilder<R = System> {
+    user_agent: String,
+    timeout: Option<Duration>,
+    headers: Vec<(String, String)>,
+    cookies: Option<Box<dyn CookieStore + Send>>,
+    cache: Option<Box<dyn CacheStore + Send>>,
+    roots: Vec<CertificateDer<'static>>,
+    webpki_roots: bool,
+    insecure: bool,
+    identity: Option<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)>,
+    key_log: bool,
+    retry: Retry,
+    resolver: R,
+}
+
+impl ClientBuilder {
+    /// Default settings: WebPKI roots, system DNS, an in-memory cookie store and the
+    /// default request timeout
+    pub fn new() -> Self {
+        Self {
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            timeout: Some(Duration::from_secs(DEFAULT_REQUEST_TIMEOUT_SECS.into())),
+            headers: Vec::new(),
+            cookies: Some(Box::new(MemoryStore::new())),
+            cache: None,
+            roots: Vec::new(),
+            webpki_roots: true,
+            insecure: false,
+            identity: None,
+            key_log: false,
+            retry: Retry::default(),
+            resolver: System,
+        }
+    }
+}
+
+impl Default for ClientBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<R> ClientBuilder<R> {
+    /// The `user-agent` sent with the handshake and every request
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = user_agent.into();
+        self
+    }
+
+    /// Time allowed for each request, from connecting to reading the last body byte;
+    /// `None` waits indefinitely once connected
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// A header sent with every request unless the request sets it itself
+    ///
+    /// Names are lowercased, as GURT requires.
+    pub fn default_header(mut self, name: &str, value: impl Into<String>) -> Self {
+        self.headers.push((name.to_ascii_lowercase(), value.into()));
+        self
+    }
+
+    /// When to send failed requests again, [`Retry::default`] unless set
+    pub fn retry(mut self, retry: Retry) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Keep cookies in `store` instead of in memory
+    pub fn cookie_store(mut self, store: impl CookieStore + Send + 'static) -> Self {
+        self.cookies = Some(Box::new(store));
+        self
+    }
+
+    /// Neither send nor keep cookies
+    pub fn no_cookies(mut self) -> Self {
+        self.cookies = None;
+        self
+    }
+
+    /// Cache responses in `store`, such as a [`MemoryCache`](crate::cache::MemoryCache) or
+    /// [`DiskCache`](crate::cache::DiskCache); there is no cache unless set
+    pub fn cache(mut self, store: impl CacheStore + Send + 'static) -> Self {
+        self.cache = Some(Box::new(store));
+        self
+    }
+
+    /// Also trust certificates issued by `root`, such as a `gurt-ca` development CA
+    pub fn add_root_certificate(mut self, root: CertificateDer<'static>) -> Self {
+        self.roots.push(root);
+        self
+    }
+
+    /// Whether to trust the WebPKI roots (the default); without them, only roots added
+    /// with [`add_root_certificate`](Self::add_root_certificate) are trusted
+    pub fn webpki_roots(mut self, enabled: bool) -> Self {
+        self.webpki_roots = enabled;
+        self
+    }
+
+    /// Accept any server certificate
+    ///
+    /// Gurted sites often serve self-issued certificates. Connections stay encrypted, but
+    /// anyone on the path can impersonate the server.
+    pub fn danger_accept_invalid_certs(mut self, accept: bool) -> Self {
+        self.insecure = accept;
+        self
+    }
+
+    /// Present `cert_chain` and `key` to servers that ask for a client certificate
+    pub fn identity(
+        mut self,
+        cert_chain: Vec<CertificateDer<'static>>,
+        key: PrivateKeyDer<'static>,
+    ) -> Self {
+        self.identity = Some((cert_chain, key));
+        self
+    }
+
+    /// Append TLS session secrets to the file named by the `SSLKEYLOGFILE` environment
+    /// variable, so packet captures of the client's connections can be decrypted locally,
+    /// for example in Wireshark
+    ///
+    /// Nothing is written while the variable is unset. Anyone with the file can read the
+    /// traffic; keep it off outside debugging.
+    pub fn key_log(mut self, enabled: bool) -> Self {
+        self.key_log = enabled;
+        self
+    }
+
+    /// Look hosts up through `resolver`, for example a hosts file in front of the Gurted
+    /// DNS service, instead of the operating system
+    pub fn resolver<S: Resolver>(self, resolver: S) -> ClientBuilder<S> {
+        ClientBuilder {
+            user_agent: self.user_agent,
+            timeout: self.timeout,
+            headers: self.headers,
+            cookies: self.cookies,
+            cache: self.cache,
+            roots: self.roots,
+            webpki_roots: self.webpki_roots,
+            insecure: self.insecure,
+            identity: self.identity,
+            key_log: self.key_log,
+            retry: self.retry,
+            resolver,
+        }
+    }
+
+    /// A client connecting with [`Connector`]
+    ///
+    /// Fails if a root certificate cannot be parsed or the identity's key does not match
+    /// its certificate.
+    pub fn build(self) -> Result<Client<Connector<R>>, rustls::Error>
+    where
+        R: Resolver,
+    {
+        let mut roots = RootCertStore::empty();
+        if self.webpki_roots {
+            roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        }
+        for root in self.roots {
+            roots.add(root)?;
+        }
+        let mut config = tls::client_config(roots, self.insecure, self.identity)?;
+        if self.key_log {
+            config.key_log = Arc::new(rustls::KeyLogFile::new());
+        }
+        let connector = Connector::new(config, self.resolver);
+        Ok(Client::from_parts(
+            connector,
+            self.user_agent,
+            self.timeout,
+            self.retry,
+            self.headers,
+            self.cookies,
+            self.cache,
+        ))
+    }
+
+    /// A client connecting with `connector`
+    pub fn build_with<C: Connect>(self, connector: C) -> Client<C> {
+        Client::from_parts(
+            connector,
+            self.user_agent,
+            self.timeout,
+            self.retry,
+            self.headers,
+            self.cookies,
+            self.cache,
+        )
+    }
+}
+
+/// Idle connection in the pool
+struct Idle<T> {
+    connection: Connection<T>,
+    since: Instant,
+}
+
+struct Inner<C: Connect> {
+    connector: C,
+    user_agent: String,
+    timeout: Option<Duration>,
+    retry: Retry,
+    headers: Vec<(String, String)>,
+    cookies: Option<Mutex<CookieJar<Box<dyn CookieStore + Send>>>>,
+    cache: Option<Mutex<Box<dyn CacheStore + Send>>>,
+    idle: Mutex<Pool<C::Transport>>,
+}
+
+/// A pooled, handshaken GURT client
+///
+/// Cloning is cheap and shares the pool and cookies.
+pub struct Client<C: Connect = Connector> {
+    inner: Arc<Inner<C>>,
+}
+
+impl<C: Connect> Clone for Client<C> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl Client {
+    /// A client with default settings; see [`ClientBuilder::new`]
+    pub fn new() -> Self {
+        ClientBuilder::new()
+            .build()
+            .expect("default TLS settings are valid")
+    }
+
+    /// Settings for a new client
+    pub fn builder() -> ClientBuilder {
+        ClientBuilder::new()
+    }
+}
+
+impl Default for Client {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C: Connect> Client<C> {
+    fn from_parts(
+        connector: C,
+        user_agent: String,
+        timeout: Option<Duration>,
+        retry: Retry,
+        headers: Vec<(String, String)>,
+        cookies: Option<Box<dyn CookieStore + Send>>,
+        cache: Option<Box<dyn CacheStore + Send>>,
+    ) -> Self {
+        #[cfg(feature = "metrics")]
+        ::metrics::gauge!(metrics::CLIENT_POOL_CAPACITY).set(MAX_CONNECTION_POOL_SIZE as f64);
+        Self {
+            inner: Arc::new(Inner {
+                connector,
+                user_agent,
+                timeout,
+                retry,
+                headers,
+                cookies: cookies.map(|store| Mutex::new(CookieJar::new(store))),
+                cache: cache.map(Mutex::new),
+                idle: Mutex::new(HashMap::new()),
+            }),
+        }
+    }
+
+    /// Start a request with `method` for `url`
+    ///
+    /// An invalid URL is reported by [`RequestBuilder::send`].
+    pub fn request(&self, method: Method, url: &str) -> RequestBuilder<C> {
+        RequestBuilder {
+            client: self.clone(),
+            method,
+            url: Url::parse(url),
+            headers: Vec::new(),
+            body: None,
+            timeout: self.inner.timeout,
+            retry: self.inner.retry.clone(),
+            error: None,
+        }
+    }
+
+    /// Start a `GET` request
+    pub fn get(&self, url: &str) -> RequestBuilder<C> {
+        self.request(Method::Get, url)
+    }
+
+    /// Start a `POST` request
+    pub fn post(&self, url: &str) -> RequestBuilder<C> {
+        self.request(Method::Post, url)
+    }
+
+    /// Start a `PUT` request
+    pub fn put(&self, url: &str) -> RequestBuilder<C> {
+        self.request(Method::Put, url)
+    }
+
+    /// Start a `PATCH` request
+    pub fn patch(&self, url: &str) -> RequestBuilder<C> {
+        self.request(Method::Patch, url)
+    }
+
+    /// Start a `DELETE` request
+    pub fn delete(&self, url: &str) -> RequestBuilder<C> {
+        self.request(Method::Delete, url)
+    }
+
+    /// Start a `HEAD` request
+    pub fn head(&self, url: &str) -> RequestBuilder<C> {
+        self.request(Method::Head, url)
+    }
+
+    /// The `cookie` header the client would send to `url` now, if any
+    pub fn cookies_for(&self, url: &Url) -> Option<String> {
+        let jar = self.inner.cookies.as_ref()?.lock().unwrap();
+        jar.cookie_string(url.host(), url.path(), unix_time())
+    }
+
+    /// Number of idle pooled connections to `url`'s host and port
+    pub fn idle_connections(&self, url: &Url) -> usize {
+        let idle = self.inner.idle.lock().unwrap();
+        idle.get(&(url.host().to_string(), url.port()))
+            .map_or(0, Vec::len)
+    }
+
+    /// Take an idle connection to `url`'s host and port
+    fn take_idle(&self, url: &Url) -> Option<Connection<C::Transport>> {
+        let timeout = Duration::from_secs(POOL_IDLE_TIMEOUT_SECS.into());
+        let mut idle = self.inner.idle.lock().unwrap();
+        let entries = idle.get_mut(&(url.host().to_string(), url.port()))?;
+        #[cfg(feature = "metrics")]
+        let pooled = entries.len();
+        entries.retain(|entry| entry.since.elapsed() < timeout);
+        let connection = entries.pop().map(|entry| entry.connection);
+        #[cfg(feature = "metrics")]
+        ::metrics::gauge!(metrics::CLIENT_POOL_IDLE, "host" => pool_label(url.host(), url.port()))
+            .decrement((pooled - entries.len()) as f64);
+        connection
+    }
+
+    /// Return a connection whose last response was read to the end
+    fn checkin(&self, url: &Url, connection: Connection<C::Transport>) {
+        let mut idle = self.inner.idle.lock().unwrap();
+        let entries = idle
+            .entry((url.host().to_string(), url.port()))
+            .or_default();
+        if entries.len() < MAX_CONNECTION_POOL_SIZE {
+            entries.push(Idle {
+                connection,
+                since: Instant::now(),
+            });
+            #[cfg(feature = "metrics")]
+            ::metrics::gauge!(metrics::CLIENT_POOL_IDLE, "host" => pool_label(url.host(), url.port()))
+                .increment(1);
+        }
+    }
+
+    /// Open and handshake a new connection to `url`'s host and port
+    async fn connect(&self, url: &Url) -> Result<Connection<C::Transport>, GurtError<io::Error>> {
+        let started = Instant::now();
+        let connection = self.handshake(url).await;
+        #[cfg(feature = "metrics")]
+        match &connection {
+            Ok(_) => {
+                ::metrics::histogram!(metrics::CLIENT_HANDSHAKE_DURATION).record(started.elapsed())
+            }
+            Err(_) => ::metrics::counter!(metrics::CLIENT_HANDSHAKE_FAILURES).increment(1),
+        }
+        if connection.is_ok() {
+            debug!(
+                "connected to {} in {} ms",
+                url.host(),
+                started.elapsed().as_millis()
+            );
+        }
+        connection
+    }
+
+    /// The body of [`connect`](Self::connect)
+    async fn handshake(&self, url: &Url) -> Result<Connection<C::Transport>, GurtError<io::Error>> {
+        let transport = tokio::time::timeout(
+            Duration::from_secs(DEFAULT_CONNECTION_TIMEOUT_SECS.into()),
+            self.inner.connector.connect(url.host(), url.port()),
+        )
+        .await
+        .map_err(|_| GurtError::Timeout)?
+        .map_err(GurtError::Io)?;
+        let mut connection = GurtClient::new(FromTokio(BufReader::new(transport)));
+        let handshake = async {
+            connection
+                .handshake(url.host(), &self.inner.user_agent)
+                .await?;
+            connection
+                .read_handshake_response(&mut std::vec![0; HEADER_BUFFER_SIZE])
+                .await
+        };
+        tokio::time::timeout(
+            Duration::from_secs(DEFAULT_HANDSHAKE_TIMEOUT_SECS.into()),
+            handshake,
+        )
+        .await
+        .map_err(|_| GurtError::Timeout)??;
+        Ok(connection)
+    }
+
+    /// The request headers: the request's own, then defaults it does not override
+    fn request_headers(&self, url: &Url, headers: &[(String, String)]) -> Vec<(String, String)> {
+        let mut all = headers.to_vec();
+        let mut add = |name: &str, value: String| {
+            if !all.iter().any(|(n, _)| n == name) {
+                all.push((name.to_string(), value));
+            }
+        };
+        for (name, value) in &self.inner.headers {
+            add(name, value.clone());
+        }
+        add("accept-encoding", ACCEPT_ENCODING.to_string());
+        if let Some(cookies) = self.cookies_for(url) {
+            add("cookie", cookies);
+        }
+        all
+    }
+
+    /// Send a request on `connection` and read the response head
+    async fn exchange(
+        &self,
+        connection: &mut Connection<C::Transport>,
+        method: Method,
+        url: &Url,
+        headers: &[(&str, &str)],
+        body: Option<&[u8]>,
+    ) -> Result<Head, GurtError<io::Error>> {
+        #[cfg(feature = "metrics")]
+        let started = Instant::now();
+        let head = self
+            .send_and_read_head(connection, method, url, headers, body)
+            .await;
+        #[cfg(feature = "metrics")]
+        {
+            let method = method.as_str();
+            let status = match &head {
+                Ok(head) => {
+                    ::metrics::histogram!(metrics::CLIENT_REQUEST_DURATION, "method" => method)
+                        .record(started.elapsed());
+                    ::metrics::counter!(metrics::CLIENT_SENT_BYTES)
+                        .increment(body.map_or(0, <[u8]>::len) as u64);
+                    metrics::status_class(head.status)
+                }
+                Err(_) => "error",
+            };
+            ::metrics::counter!(metrics::CLIENT_REQUESTS, "method" => method, "status" => status)
+                .increment(1);
+        }
+        head
+    }
+
+    /// The body of [`exchange`](Self::exchange)
+    async fn send_and_read_head(
+        &self,
+        connection: &mut Connection<C::Transport>,
+        method: Method,
+        url: &Url,
+        headers: &[(&str, &str)],
+        body: Option<&[u8]>,
+    ) -> Result<Head, GurtError<io::Error>> {
+        let mut writer = connection
+            .request(
+                method,
+                url.path(),
+                url.host(),
+                Some(&self.inner.user_agent),
+                headers,
+                body.map(<[u8]>::len),
+            )
+            .await?;
+        if let Some(body) = body {
+            writer.write(body).await?;
+        }
+        writer.finish().await?;
+
+        let mut buf = std::vec![0; HEADER_BUFFER_SIZE];
+        let mut reader = connection.response_reader();
+        let status = reader.read_status_line(&mut buf).await?.status;
+        let mut headers = Vec::new();
+        while let Some(header) = reader.read_header(&mut buf).await? {
+            let name = String::from_utf8_lossy(header.name(&buf)).to_ascii_lowercase();
+            let value = String::from_utf8_lossy(header.value(&buf)).into_owned();
+            if name == "set-cookie"
+                && let Some(jar) = &self.inner.cookies
+            {
+                let now = unix_time();
+                jar.lock()
+                    .unwrap()
+                    .set_cookie(url.host(), url.path(), &value, now);
+            }
+            headers.push((name, value));
+        }
+        // From spec: HEAD "Get headers only (no body allowed)"
+        let length = match method {
+            Method::Head => 0,
+            _ => reader.content_length().unwrap_or(0),
+        };
+        Ok(Head {
+            status,
+            headers,
+            length,
+            encoding: reader.content_encoding(),
+        })
+    }
+
+    /// Send a request on a pooled or new connection, retrying once on a new connection
+    /// if a pooled one turns out to be closed and `retry` lets the method be repeated
+    ///
+    /// The server may have read the whole request before the connection failed, so a
+    /// `POST` is not sent again unless the policy says so.
+    async fn execute(
+        &self,
+        method: Method,
+        url: &Url,
+        headers: &[(String, String)],
+        body: Option<&[u8]>,
+        retry: &Retry,
+    ) -> Result<(Connection<C::Transport>, Head), GurtError<io::Error>> {
+        let headers = self.request_headers(url, headers);
+        let headers: Vec<(&str, &str)> = headers
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect();
+        // Refuse bad input before taking a connection, which would be lost with the error
+        RequestHead::new(method, url.path(), url.host())
+            .headers(&headers)
+            .validate()?;
+        let (mut connection, reused) = match self.take_idle(url) {
+            Some(connection) => (connection, true),
+            None => (self.connect(url).await?, false),
+        };
+        let head = match self
+            .exchange(&mut connection, method, url, &headers, body)
+            .await
+        {
+            Err(GurtError::Io(_) | GurtError::UnexpectedEof) if reused && retry.repeats(method) => {
+                debug!("pooled connection was closed, reconnecting");
+                connection = self.connect(url).await?;
+                self.exchange(&mut connection, method, url, &headers, body)
+                    .await?
+            }
+            result => result?,
+        };
+        Ok((connection, head))
+    }
+    /// Send a request, retrying as `retry` allows, and read the response head
+    async fn transmit(
+        &self,
+        method: Method,
+        url: &Url,
+        headers: &[(String, String)],
+        body: Option<&[u8]>,
+        retry: &Retry,
+        deadline: Option<Instant>,
+    ) -> Result<(Connection<C::Transport>, Head), Error> {
+        let request = self.attempt(method, url, headers, body, retry, deadline);
+        #[cfg(feature = "tracing")]
+        let request = tracing::Instrument::instrument(
+            request,
+            tracing::debug_span!("gurt.request", method = ?method, %url),
+        );
+        request.await
+    }
+
+    /// The body of [`transmit`](Self::transmit), outside its span
+    async fn attempt(
+        &self,
+        method: Method,
+        url: &Url,
+        headers: &[(String, String)],
+        body: Option<&[u8]>,
+        retry: &Retry,
+        deadline: Option<Instant>,
+    ) -> Result<(Connection<C::Transport>, Head), Error> {
+        let attempts = retry.attempts(method);
+        let mut attempt = 1;
+        loop {
+            let result = within(deadline, async {
+                Ok(self.execute(method, url, headers, body, retry).await?)
+            })
+            .await;
+            let delay = match &result {
+                _ if attempt >= attempts => None,
+                Ok((_, head)) if retry.retries_status(head.status) => {
+                    let retry_after = head
+                        .headers
+                        .iter()
+                        .find(|(name, _)| name == "retry-after")
+                        .map(|(_, value)| value.as_str());
+                    retry.delay(attempt, retry_after)
+                }
+                Err(Error::Gurt(e)) if Retry::retries_error(e) => retry.delay(attempt, None),
+                _ => None,
+            };
+            match delay {
+                Some(delay)
+                    if deadline.is_none_or(|deadline| Instant::now() + delay < deadline) =>
+                {
+                    debug!(
+                        "attempt {} failed, retrying in {} ms",
+                        attempt,
+                        delay.as_millis()
+                    );
+                    // A retried response's connection is closed rather than drained
+                    drop(result);
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                _ => return result,
+            }
+        }
+    }
+}
+
+/// The `host` label of the pool gauge
+#[cfg(feature = "metrics")]
+fn pool_label(host: &str, port: u16) -> String {
+    std::format!("{host}:{port}")
+}
+
+#[cfg(feature = "metrics")]
+impl<C: Connect> Drop for Inner<C> {
+    /// Idle connections close with the client, leaving the pool gauge
+    fn drop(&mut self) {
+        let idle = self.idle.get_mut().unwrap_or_else(|e| e.into_inner());
+        for ((host, port), entries) in idle.iter() {
+            ::metrics::gauge!(metrics::CLIENT_POOL_IDLE, "host" => pool_label(host, *port))
+                .decrement(entries.len() as f64);
+        }
+    }
+}
+
+/// Status, headers and body framing of a response
+struct Head {
+    status: StatusCode,
+    headers: Vec<(String, String)>,
+    length: usize,
+    encoding: Option<ContentEncoding>,
+}
+
+/// A request being prepared; see [`Client::request`]
+pub struct RequestBuilder<C: Connect = Connector> {
+    client: Client<C>,
+    method: Method,
+    url: Result<Url, Error>,
+    headers: Vec<(String, String)>,
+    body: Option<Vec<u8>>,
+    timeout: Option<Duration>,
+    retry: Retry,
+    /// Deferred failure from building the request, reported by `send`
+    error: Option<Error>,
+}
+
+impl<C: Connect> RequestBuilder<C> {
+    /// Add a header; the name is lowercased, as GURT requires
+    ///
+    /// Replaces any client default of the same name. Invalid names and values are
+    /// reported by [`send`](Self::send).
+    pub fn header(mut self, name: &str, value: impl Into<String>) -> Self {
+        self.headers.push((name.to_ascii_lowercase(), value.into()));
+        self
+    }
+
+    /// Send `body`
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = Some(body.into());
+        self
+    }
+
+    /// Send `fields` as a URL-encoded form
+    pub fn form(mut self, fields: &[(&str, &str)]) -> Self {
+        self.body = Some(UrlEncoded::new(fields).to_vec());
+        self.content_type(URLENCODED)
+    }
+
+    /// Send `value` as a JSON body
+    #[cfg(feature = "json")]
+    pub fn json<T: serde::Serialize + ?Sized>(mut self, value: &T) -> Self {
+        match serde_json::to_vec(value) {
+            Ok(body) => self.body = Some(body),
+            Err(e) => self.error = Some(Error::Json(e)),
+        }
+        self.content_type("application/json")
+    }
+
+    /// Time allowed for this request, replacing the client's; `None` waits indefinitely
+    /// once connected
+    pub fn timeout(mut self, timeout: impl Into<Option<Duration>>) -> Self {
+        self.timeout = timeout.into();
+        self
+    }
+
+    /// Ask for `range` of the resource only
+    ///
+    /// A server supporting it answers `206 PARTIAL_CONTENT` with the range described by
+    /// [`Response::content_range`]; one that does not sends the whole resource.
+    pub fn range(self, range: ByteRange) -> Self {
+        self.header("range", range.to_string())
+    }
+
+    /// When to send this request again, replacing the client's policy
+    pub fn retry(mut self, retry: Retry) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Set `content-type` unless a header already did
+    fn content_type(mut self, content_type: &str) -> Self {
+        if !self.headers.iter().any(|(name, _)| name == "content-type") {
+            self.headers
+                .push(("content-type".to_string(), content_type.to_string()));
+        }
+        self
+    }
+
+    /// Send the request and read the response status and headers
+    ///
+    /// The body is read by [`Response::bytes`] and friends, within the same timeout.
+    /// Failures and retryable statuses are retried as the [`Retry`] policy allows; once
+    /// attempts run out, the last error or response is returned.
+    pub async fn send(self) -> Result<Response<C>, Error> {
+        let Self {
+            client,
+            method,
+            url,
+            mut headers,
+            body,
+            timeout,
+            retry,
+            error,
+        } = self;
+        if let Some(error) = error {
+            return Err(error);
+        }
+        let url = url?;
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let Some(cache) = &client.inner.cache else {
+            let (connection, head) = client
+                .transmit(method, &url, &headers, body.as_deref(), &retry, deadline)
+                .await?;
+            return Ok(Response::new(client, url, connection, head, deadline));
+        };
+
+        let key = url.to_string();
+        let mut request = CacheControl::default();
+        for (_, value) in headers.iter().filter(|(name, _)| name == "cache-control") {
+            request.add(value);
+        }
+        // Conditional and range requests get the server's answer as it is
+        let conditional = headers.iter().any(|(name, _)| {
+            matches!(
+                name.as_str(),
+                "if-none-match" | "if-modified-since" | "range" | "if-range"
+            )
+        });
+        let cacheable = method == Method::Get && !conditional && !request.no_store;
+        let stored = match cacheable {
+            true => cache.lock().unwrap().get(&key),
+            false => None,
+        };
+        let now = unix_time();
+        if let Some(stored) = &stored {
+            // Ages are whole seconds, so `max-age=0` revalidates even a response from this
+            // very second
+            let acceptable = request.max_age.is_none_or(|max| stored.age(now) < max);
+            if stored.is_fresh(now) && acceptable && !request.no_cache {
+                debug!("{} served from cache", key.as_str());
+                return Ok(Response::from_cache(client, url, stored.clone()));
+            }
+            headers.extend(stored.validators());
+        }
+
+        let (connection, head) = client
+            .transmit(method, &url, &headers, body.as_deref(), &retry, deadline)
+            .await?;
+        let now = unix_time();
+        if !method.is_safe() && head.status.as_u16() < 400 {
+            // From RFC 9111 section 4.4: unsafe requests invalidate the stored response
+            cache.lock().unwrap().remove(&key);
+        }
+        if let Some(mut stored) = stored
+            && head.status == StatusCode::NotModified
+        {
+            debug!("{} revalidated in cache", key.as_str());
+            // A 304 has no body, but one that declares one anyway would leave its bytes in
+            // front of the next response; such a connection is closed instead of pooled
+            if head.length == 0 {
+                client.checkin(&url, connection);
+            }
+            stored.update(&head.headers, now);
+            cache.lock().unwrap().put(&key, stored.clone());
+            return Ok(Response::from_cache(client, url, stored));
+        }
+        let mut entry = CachedResponse::new(head.status, &head.headers, Vec::new(), now);
+        let response = Response::new(client.clone(), url.clone(), connection, head, deadline);
+        if !cacheable || !entry.is_storable(&request) {
+            return Ok(response);
+        }
+        entry.body = response.bytes().await?;
+        cache.lock().unwrap().put(&key, entry.clone());
+        Ok(Response::from_cache(client, url, entry))
+    }
+}
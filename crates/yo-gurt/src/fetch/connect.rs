@@ -0,0 +1,73 @@
+//! Opening TLS connections for a [`Client`](super::Client)
+
+use core::future::Future;
+use std::io;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use rustls::ClientConfig;
+use rustls::pki_types::ServerName;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+use tokio_rustls::client::TlsStream;
+
+use crate::ALPN_IDENTIFIER;
+use crate::resolve::{Resolver, System};
+
+/// Opens transports to GURT servers
+///
+/// [`Connector`] opens TLS connections; other implementations can route connections
+/// elsewhere, such as through a tunnel or to an in-process server in tests. The
+/// [`Client`](super::Client) performs the GURT handshake over the returned transport.
+pub trait Connect {
+    /// An established, encrypted connection
+    type Transport: AsyncRead + AsyncWrite + Unpin;
+
+    /// Connect to `port` on `host`, a DNS name or an IP address without brackets
+    fn connect(&self, host: &str, port: u16) -> impl Future<Output = io::Result<Self::Transport>>;
+}
+
+/// Connects over TCP and TLS 1.3 with ALPN `GURT/1.0`, looking hosts up through `R`
+///
+/// From spec: "All connections must use TLS 1.3 encryption"
+pub struct Connector<R = System> {
+    tls: TlsConnector,
+    resolver: R,
+}
+
+impl<R> Connector<R> {
+    /// A connector using `config`, with `GURT/1.0` as its only ALPN protocol
+    ///
+    /// `config` should allow TLS 1.3 only, as [`tls::client_config`](crate::tls::client_config)
+    /// does.
+    pub fn new(mut config: ClientConfig, resolver: R) -> Self {
+        config.alpn_protocols = std::vec![ALPN_IDENTIFIER.as_bytes().to_vec()];
+        Self {
+            tls: TlsConnector::from(Arc::new(config)),
+            resolver,
+        }
+    }
+}
+
+impl<R: Resolver> Connect for Connector<R> {
+    type Transport = TlsStream<TcpStream>;
+
+    async fn connect(&self, host: &str, port: u16) -> io::Result<Self::Transport> {
+        let server_name = ServerName::try_from(host)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?
+            .to_owned();
+        let address = match host.parse::<IpAddr>() {
+            Ok(address) => address,
+            Err(_) => self.resolver.resolve(host).await.map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    std::format!("cannot resolve {host}: {e:?}"),
+                )
+            })?,
+        };
+        let tcp = TcpStream::connect((address, port)).await?;
+        tcp.set_nodelay(true)?;
+        self.tls.connect(server_name, tcp).await
+    }
+}
@@ -0,0 +1,177 @@
+//! Resumable downloads to a file, a range at a time
+
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::string::{String, ToString};
+use std::time::Instant;
+use std::vec::Vec;
+
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+
+use super::{Connect, Error, RequestBuilder, Response};
+use crate::range::{ByteRange, ContentRange};
+use crate::{GurtError, ProtocolError, StatusCode};
+
+/// Bytes asked for by each request of [`RequestBuilder::download`]
+pub const DOWNLOAD_CHUNK_SIZE: u64 = 4 * 1024 * 1024;
+
+/// One answered range request
+struct Chunk {
+    status: StatusCode,
+    content_range: Option<ContentRange>,
+    /// A strong `etag`, or else `last-modified`, for `if-range`
+    validator: Option<String>,
+    body: Vec<u8>,
+}
+
+impl<C: Connect> RequestBuilder<C> {
+    /// Save the resource to the file at `path`, returning its length
+    ///
+    /// The resource is requested [`DOWNLOAD_CHUNK_SIZE`] bytes at a time, starting after
+    /// the bytes already in the file, so calling this again after a failure continues where
+    /// the last call stopped; resources larger than
+    /// [`MAX_MESSAGE_SIZE`](crate::MAX_MESSAGE_SIZE) can only be fetched this way. Later
+    /// ranges carry `if-range` with the first one's strong `etag` or `last-modified`, so that
+    /// a resource changing mid-download is sent whole instead. Until the download completes
+    /// that validator is kept next to the file, in `<path>.validator`, for a later call to
+    /// continue under; a file without one is taken to be a prefix of the resource. A server
+    /// that ignores ranges, or sees the resource changed, answers `200 OK`, which replaces
+    /// the file's contents.
+    ///
+    /// The timeout applies to each range. Each range is retried like [`send`](Self::send),
+    /// as the [`Retry`](super::Retry) policy allows for the method; a failure past that, or
+    /// while reading a body, ends the download with the ranges written so far left in the
+    /// file. Fails with [`Error::Status`] for any other status than `200 OK` and `206
+    /// PARTIAL_CONTENT`, including `416 RANGE_NOT_SATISFIABLE` when the file is longer than
+    /// the resource, and [`ProtocolError::InvalidContentRange`] when a partial response does
+    /// not start where asked.
+    pub async fn download(self, path: impl AsRef<Path>) -> Result<u64, Error> {
+        let Self {
+            client,
+            method,
+            url,
+            mut headers,
+            body,
+            timeout,
+            retry,
+            error,
+        } = self;
+        if let Some(error) = error {
+            return Err(error);
+        }
+        let url = url?;
+        let io = |e| Error::Gurt(GurtError::Io(e));
+        let path = path.as_ref();
+        let saved = validator_path(path);
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path)
+            .await
+            .map_err(io)?;
+        let mut offset = file.metadata().await.map_err(io)?.len();
+        file.seek(SeekFrom::Start(offset)).await.map_err(io)?;
+        // The validator of the download that left the file, if one did
+        let mut validator = match offset {
+            0 => None,
+            _ => tokio::fs::read_to_string(&saved).await.ok(),
+        };
+        // Ranges of an encoded body would be ranges of the encoding
+        headers.retain(|(name, _)| name != "accept-encoding" && name != "range");
+        headers.push(("accept-encoding".to_string(), "identity".to_string()));
+
+        loop {
+            let range = ByteRange::FromTo(offset, offset + DOWNLOAD_CHUNK_SIZE - 1);
+            let mut request = headers.clone();
+            request.push(("range".to_string(), range.to_string()));
+            if let Some(validator) = &validator {
+                request.push(("if-range".to_string(), validator.clone()));
+            }
+            let deadline = timeout.map(|timeout| Instant::now() + timeout);
+            let chunk = async {
+                let (connection, head) = client
+                    .transmit(method, &url, &request, body.as_deref(), &retry, deadline)
+                    .await?;
+                let response =
+                    Response::new(client.clone(), url.clone(), connection, head, deadline);
+                let status = response.status();
+                if !matches!(
+                    status,
+                    StatusCode::Ok | StatusCode::PartialContent | StatusCode::RangeNotSatisfiable
+                ) {
+                    return Err(Error::Status(status));
+                }
+                Ok(Chunk {
+                    status,
+                    content_range: response.content_range(),
+                    // Weak tags cannot validate a range
+                    validator: response
+                        .header("etag")
+                        .filter(|etag| !etag.starts_with("W/"))
+                        .or(response.header("last-modified"))
+                        .map(str::to_string),
+                    body: response.bytes().await?,
+                })
+            };
+            let chunk = chunk.await?;
+
+            match chunk.status {
+                StatusCode::PartialContent => {
+                    let content_range = chunk
+                        .content_range
+                        .filter(|content_range| {
+                            content_range
+                                .range
+                                .is_some_and(|(first, _)| first == offset)
+                                && content_range.len() == chunk.body.len() as u64
+                        })
+                        .ok_or(GurtError::Protocol(ProtocolError::InvalidContentRange))?;
+                    file.write_all(&chunk.body).await.map_err(io)?;
+                    offset += chunk.body.len() as u64;
+                    if validator.is_none()
+                        && let Some(first) = chunk.validator
+                    {
+                        tokio::fs::write(&saved, &first).await.map_err(io)?;
+                        validator = Some(first);
+                    }
+                    let done = match content_range.complete_length {
+                        Some(length) => offset >= length,
+                        None => (chunk.body.len() as u64) < DOWNLOAD_CHUNK_SIZE,
+                    };
+                    if done {
+                        break;
+                    }
+                }
+                StatusCode::RangeNotSatisfiable => {
+                    // Nothing left past the end of a complete file
+                    match chunk.content_range.and_then(|range| range.complete_length) {
+                        Some(length) if length == offset => break,
+                        _ => return Err(Error::Status(StatusCode::RangeNotSatisfiable)),
+                    }
+                }
+                _ => {
+                    // `200 OK`: the whole resource, because ranges are not supported or it
+                    // changed
+                    file.set_len(0).await.map_err(io)?;
+                    file.seek(SeekFrom::Start(0)).await.map_err(io)?;
+                    file.write_all(&chunk.body).await.map_err(io)?;
+                    offset = chunk.body.len() as u64;
+                    break;
+                }
+            }
+        }
+        file.flush().await.map_err(io)?;
+        match tokio::fs::remove_file(&saved).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(io(e)),
+            _ => Ok(offset),
+        }
+    }
+}
+
+/// The file kept next to an unfinished download at `path`, holding its validator
+fn validator_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".validator");
+    PathBuf::from(name)
+}
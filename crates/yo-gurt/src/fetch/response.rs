@@ -0,0 +1,181 @@
+//! Responses from a [`Client`]
+
+use std::string::String;
+use std::time::Instant;
+use std::vec::Vec;
+
+use super::{CachedResponse, Client, Connect, Connection, Connector, Error, Head, Url, within};
+use crate::encoding::{ContentEncoding, DecodedBody, Decoder};
+use crate::progress::{NoProgress, Progress};
+use crate::range::ContentRange;
+use crate::{BodyReader, ProtocolError, StatusCode};
+
+/// The status and headers of a response, with its body still to be read
+///
+/// Reading the body to the end returns the connection to the client's pool; dropping the
+/// response before then closes it. Responses from the client's cache hold their body
+/// already.
+pub struct Response<C: Connect = Connector> {
+    client: Client<C>,
+    url: Url,
+    status: StatusCode,
+    headers: Vec<(String, String)>,
+    /// The connection, while body bytes remain on it
+    connection: Option<Connection<C::Transport>>,
+    /// The decoded body of a cached response
+    cached: Option<Vec<u8>>,
+    length: usize,
+    encoding: Option<ContentEncoding>,
+    deadline: Option<Instant>,
+}
+
+impl<C: Connect> Response<C> {
+    pub(super) fn new(
+        client: Client<C>,
+        url: Url,
+        connection: Connection<C::Transport>,
+        head: Head,
+        deadline: Option<Instant>,
+    ) -> Self {
+        let connection = match head.length {
+            0 => {
+                client.checkin(&url, connection);
+                None
+            }
+            _ => Some(connection),
+        };
+        Self {
+            client,
+            url,
+            status: head.status,
+            headers: head.headers,
+            connection,
+            cached: None,
+            length: head.length,
+            encoding: head.encoding,
+            deadline,
+        }
+    }
+
+    /// A response served from the client's cache
+    pub(super) fn from_cache(client: Client<C>, url: Url, response: CachedResponse) -> Self {
+        Self {
+            client,
+            url,
+            status: response.status,
+            headers: response.headers,
+            connection: None,
+            length: response.body.len(),
+            cached: Some(response.body),
+            encoding: None,
+            deadline: None,
+        }
+    }
+
+    /// The response status
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// All headers in the order received, with lowercase names
+    pub fn headers(&self) -> &[(String, String)] {
+        &self.headers
+    }
+
+    /// The first value of the header `name` (lowercase)
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The `content-range` of a `206 PARTIAL_CONTENT` or `416 RANGE_NOT_SATISFIABLE`
+    /// response, if valid
+    pub fn content_range(&self) -> Option<ContentRange> {
+        self.header("content-range").and_then(ContentRange::parse)
+    }
+
+    /// The URL the request was sent to
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    /// Length of the body on the wire, before any `content-encoding` is decoded
+    ///
+    /// Cached responses are stored decoded, so for them this is the decoded length.
+    pub fn content_length(&self) -> usize {
+        self.length
+    }
+
+    /// This response, or [`Error::Status`] for a 4xx or 5xx status
+    pub fn error_for_status(self) -> Result<Self, Error> {
+        match self.status.as_u16() {
+            400..=599 => Err(Error::Status(self.status)),
+            _ => Ok(self),
+        }
+    }
+
+    /// Read the whole body, decoded according to its `content-encoding`
+    ///
+    /// Fails with [`ProtocolError::UnsupportedContentEncoding`] for an encoding this build
+    /// cannot decode, and [`Limit::MessageTooLarge`](crate::Limit::MessageTooLarge) for a
+    /// body that decodes to more than [`MAX_MESSAGE_SIZE`](crate::MAX_MESSAGE_SIZE).
+    pub async fn bytes(self) -> Result<Vec<u8>, Error> {
+        self.bytes_with_progress(NoProgress).await
+    }
+
+    /// Read the whole body like [`bytes`](Self::bytes), telling `progress` how much of
+    /// it has been received
+    ///
+    /// Progress counts the bytes on the wire against [`content_length`](Self::content_length);
+    /// a cached body is reported once, as complete.
+    pub async fn bytes_with_progress(
+        mut self,
+        mut progress: impl Progress,
+    ) -> Result<Vec<u8>, Error> {
+        if let Some(body) = self.cached.take() {
+            progress.progress(body.len(), body.len());
+            return Ok(body);
+        }
+        let Some(mut connection) = self.connection.take() else {
+            return Ok(Vec::new());
+        };
+        let encoding = self
+            .encoding
+            .ok_or(ProtocolError::UnsupportedContentEncoding)
+            .map_err(crate::GurtError::from)?;
+        let decoder = Decoder::new(encoding)
+            .ok_or(ProtocolError::UnsupportedContentEncoding)
+            .map_err(crate::GurtError::from)?;
+        let length = self.length;
+        let body = within(self.deadline, async {
+            let body = BodyReader::new(&mut connection.transport, length).with_progress(progress);
+            let mut body = DecodedBody::new(body, decoder);
+            let mut out = Vec::new();
+            body.read_to_end(&mut out).await?;
+            Ok(out)
+        })
+        .await?;
+        #[cfg(feature = "metrics")]
+        ::metrics::counter!(crate::metrics::CLIENT_RECEIVED_BYTES).increment(length as u64);
+        self.client.checkin(&self.url, connection);
+        Ok(body)
+    }
+
+    /// Read the whole body as text, replacing invalid UTF-8
+    pub async fn text(self) -> Result<String, Error> {
+        let bytes = self.bytes().await?;
+        Ok(match String::from_utf8(bytes) {
+            Ok(text) => text,
+            Err(e) => String::from_utf8_lossy(e.as_bytes()).into_owned(),
+        })
+    }
+
+    /// Read the whole body and deserialize it from JSON
+    #[cfg(feature = "json")]
+    pub async fn json<T: serde::de::DeserializeOwned>(self) -> Result<T, Error> {
+        let bytes = self.bytes().await?;
+        serde_json::from_slice(&bytes).map_err(Error::Json)
+    }
+}
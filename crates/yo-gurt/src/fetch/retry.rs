@@ -0,0 +1,155 @@
+//! Sending failed requests again
+
+use std::hash::BuildHasher;
+use std::time::Duration;
+use std::vec::Vec;
+
+use crate::cookie::date;
+use crate::{GurtError, Method, StatusCode};
+
+/// When and how often a [`Client`](super::Client) sends a request again
+///
+/// A request is retried when the connection fails (reset, closed or timed out before the
+/// request's own deadline) or the response has a retryable status, `503
+/// SERVICE_UNAVAILABLE` and `504 GATEWAY_TIMEOUT` by default. Only idempotent methods
+/// (see [`Method::is_idempotent`]) are retried unless
+/// [`non_idempotent`](Self::non_idempotent) is set. Request bodies are buffered, so every
+/// attempt sends the same bytes.
+///
+/// Attempt `n` waits `base_delay * 2^(n - 1)`, capped at `max_delay`; with jitter, a random
+/// duration between half of that and all of it. A `retry-after` header, in seconds or as
+/// a date, replaces the backoff; when it asks for more than
+/// [`max_retry_after`](Self::max_retry_after) the response is returned as it is. No wait
+/// may run past the request's timeout.
+#[derive(Debug, Clone)]
+pub struct Retry {
+    max_attempts: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+    jitter: bool,
+    max_retry_after: Duration,
+    non_idempotent: bool,
+    statuses: Vec<StatusCode>,
+}
+
+impl Retry {
+    /// Up to `max_attempts` attempts in all, waiting 100 ms, 200 ms, 400 ms, ... up to
+    /// 10 s between them, with jitter, and at most 60 s for a `retry-after`
+    pub fn new(max_attempts: u32) -> Self {
+        Self {
+            max_attempts: max_attempts.max(1),
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(10),
+            jitter: true,
+            max_retry_after: Duration::from_secs(60),
+            non_idempotent: false,
+            statuses: std::vec![StatusCode::ServiceUnavailable, StatusCode::GatewayTimeout],
+        }
+    }
+
+    /// Send every request once
+    pub fn never() -> Self {
+        Self::new(1)
+    }
+
+    /// Wait `base_delay` before the first retry, doubling up to `max_delay`
+    pub fn backoff(mut self, base_delay: Duration, max_delay: Duration) -> Self {
+        self.base_delay = base_delay;
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// Whether to randomize waits, so that clients failing together do not retry together
+    pub fn jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Longest `retry-after` to honor; a longer one ends the retries
+    pub fn max_retry_after(mut self, max: Duration) -> Self {
+        self.max_retry_after = max;
+        self
+    }
+
+    /// Also retry `POST`, `PATCH` and other methods that may not be safe to repeat
+    pub fn non_idempotent(mut self, retry: bool) -> Self {
+        self.non_idempotent = retry;
+        self
+    }
+
+    /// Also retry responses with `status`
+    pub fn status(mut self, status: StatusCode) -> Self {
+        if !self.statuses.contains(&status) {
+            self.statuses.push(status);
+        }
+        self
+    }
+
+    /// Number of attempts, the first included
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// Number of attempts allowed for `method`
+    pub(super) fn attempts(&self, method: Method) -> u32 {
+        if self.repeats(method) {
+            self.max_attempts
+        } else {
+            1
+        }
+    }
+
+    /// Whether a request with `method` may reach the server more than once
+    pub(super) fn repeats(&self, method: Method) -> bool {
+        method.is_idempotent() || self.non_idempotent
+    }
+
+    /// Whether a response with `status` is worth another attempt
+    pub(super) fn retries_status(&self, status: StatusCode) -> bool {
+        self.statuses.contains(&status)
+    }
+
+    /// Whether a failure is worth another attempt
+    pub(super) fn retries_error(error: &GurtError<std::io::Error>) -> bool {
+        matches!(
+            error,
+            GurtError::Io(_) | GurtError::UnexpectedEof | GurtError::Timeout
+        )
+    }
+
+    /// Wait before attempt `attempt + 1`, or `None` to stop because `retry_after` asks for
+    /// too long
+    pub(super) fn delay(&self, attempt: u32, retry_after: Option<&str>) -> Option<Duration> {
+        if let Some(value) = retry_after
+            && let Some(delay) = parse_retry_after(value, crate::cookie::unix_time())
+        {
+            return (delay <= self.max_retry_after).then_some(delay);
+        }
+        let delay = self
+            .base_delay
+            .saturating_mul(1 << (attempt - 1).min(31))
+            .min(self.max_delay);
+        if !self.jitter {
+            return Some(delay);
+        }
+        let random = std::hash::RandomState::new().hash_one(attempt);
+        Some(delay / 2 + (delay / 2).mul_f64(random as f64 / u64::MAX as f64))
+    }
+}
+
+impl Default for Retry {
+    /// Three attempts; see [`Retry::new`]
+    fn default() -> Self {
+        Self::new(3)
+    }
+}
+
+/// A `retry-after` of delay seconds or an HTTP date, as a wait from `now` (Unix seconds)
+fn parse_retry_after(value: &str, now: u64) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let at = date::parse(value)?;
+    Some(Duration::from_secs(at.saturating_sub(now)))
+}
@@ -0,0 +1,123 @@
+//! `gurt://` URLs
+
+use core::fmt;
+use core::str::FromStr;
+use std::string::{String, ToString};
+
+use super::Error;
+use crate::DEFAULT_PORT;
+
+/// A `gurt://host[:port][/path][?query]` URL
+///
+/// The host is lowercased and IPv6 addresses lose their brackets; the port defaults to
+/// [`DEFAULT_PORT`] and the path to `/`. A fragment is dropped, since it is never sent.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Url {
+    host: String,
+    port: u16,
+    path: String,
+}
+
+impl Url {
+    /// Parse a URL, failing with [`Error::InvalidUrl`] for anything but `gurt://` URLs
+    /// without user information
+    pub fn parse(url: &str) -> Result<Self, Error> {
+        let rest = url
+            .get(..7)
+            .filter(|scheme| scheme.eq_ignore_ascii_case("gurt://"))
+            .map(|_| &url[7..])
+            .ok_or(Error::InvalidUrl)?;
+        let rest = rest.split('#').next().unwrap_or_default();
+        let (authority, path) = match rest.find(['/', '?']) {
+            Some(at) => rest.split_at(at),
+            None => (rest, ""),
+        };
+        if path.bytes().any(|b| b <= b' ' || b == 0x7f) || authority.contains('@') {
+            return Err(Error::InvalidUrl);
+        }
+        let (host, port) = if let Some(rest) = authority.strip_prefix('[') {
+            let (host, rest) = rest.split_once(']').ok_or(Error::InvalidUrl)?;
+            host.parse::<core::net::Ipv6Addr>()
+                .map_err(|_| Error::InvalidUrl)?;
+            (host, rest)
+        } else {
+            match authority.find(':') {
+                Some(at) => authority.split_at(at),
+                None => (authority, ""),
+            }
+        };
+        let port = match port {
+            "" => DEFAULT_PORT,
+            port => port
+                .strip_prefix(':')
+                .and_then(|port| port.parse().ok())
+                .ok_or(Error::InvalidUrl)?,
+        };
+        if host.is_empty() || host.bytes().any(|b| b <= b' ' || b == 0x7f) {
+            return Err(Error::InvalidUrl);
+        }
+        let path = match path {
+            "" => "/".to_string(),
+            path if path.starts_with('?') => alloc::format!("/{path}"),
+            path => path.to_string(),
+        };
+        Ok(Self {
+            host: host.to_ascii_lowercase(),
+            port,
+            path,
+        })
+    }
+
+    /// Host name or address, without brackets
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    /// Port, [`DEFAULT_PORT`] unless the URL names one
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Path and query, as sent in the request line
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// This URL with another path and query
+    ///
+    /// `path` is taken as it is; one not starting with `/` is made absolute.
+    pub fn with_path(&self, path: &str) -> Self {
+        let path = if path.starts_with('/') {
+            path.to_string()
+        } else {
+            alloc::format!("/{path}")
+        };
+        Self {
+            path,
+            ..self.clone()
+        }
+    }
+}
+
+impl fmt::Display for Url {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("gurt://")?;
+        if self.host.contains(':') {
+            write!(f, "[{}]", self.host)?;
+        } else {
+            f.write_str(&self.host)?;
+        }
+        if self.port != DEFAULT_PORT {
+            write!(f, ":{}", self.port)?;
+        }
+        f.write_str(&self.path)
+    }
+}
+
+impl FromStr for Url {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        Self::parse(s)
+    }
+}
@@ -0,0 +1,169 @@
+//! Form bodies: `application/x-www-form-urlencoded` and `multipart/form-data`
+//!
+//! Both encoders know their exact length before anything is written, so a form is sent
+//! with an ordinary `content-length`:
+//!
+//! ```rust,ignore
+//! use portal_solutions_yo_gurt::form::{Boundary, MultipartWriter, Part, URLENCODED, UrlEncoded};
+//!
+//! let form = UrlEncoded::new(&[("q", "gurt protocol"), ("page", "2")]);
+//! let mut body = client
+//!     .request(Method::Post, "/search", host, None, &[("content-type", URLENCODED)], Some(form.len()))
+//!     .await?;
+//! form.write_to(&mut body).await?;
+//! body.finish().await?;
+//!
+//! let boundary = Boundary::random();
+//! let parts = [
+//!     Part::new("title", title.len()),
+//!     Part::new("upload", file_size).filename("photo.png").content_type("image/png"),
+//! ];
+//! let headers = [("content-type", boundary.content_type())];
+//! let mut body = client
+//!     .request(Method::Post, "/upload", host, None, &headers, Some(boundary.content_length(&parts)))
+//!     .await?;
+//! let mut multipart = MultipartWriter::new(&mut body, &boundary);
+//! multipart.part(&parts[0]).await?;
+//! multipart.write(title.as_bytes()).await?;
+//! multipart.part(&parts[1]).await?;
+//! while let Some(chunk) = file.next_chunk().await {
+//!     multipart.write(chunk).await?;
+//! }
+//! multipart.finish().await?;
+//! body.finish().await?;
+//! ```
+//!
+//! GURT defines no chunked framing, so a body always needs its length up front. Parts
+//! whose size is not known in advance can be collected with [`MultipartBody`] (`alloc`
+//! feature) instead, which buffers the whole form.
+//!
+//! On the server side, [`UrlEncodedReader`] and [`MultipartReader`] parse a body as it
+//! arrives, in a buffer supplied by the caller: form fields are returned one at a time
+//! and multipart part contents are streamed, so no more than one field or one part
+//! header block has to fit in memory.
+
+mod multipart;
+mod urlencoded;
+
+#[cfg(feature = "alloc")]
+pub use multipart::MultipartBody;
+pub use multipart::{
+    Boundary, MAX_BOUNDARY_LEN, MultipartReader, MultipartWriter, Part, PartHead,
+    multipart_boundary,
+};
+#[cfg(feature = "alloc")]
+pub use urlencoded::decode_urlencoded;
+pub use urlencoded::{UrlEncoded, UrlEncodedReader};
+
+use embedded_io_async::{Read, Write};
+
+use crate::{GurtError, Limit, RequestBodyWriter};
+
+/// `content-type` of URL-encoded form bodies
+pub const URLENCODED: &str = "application/x-www-form-urlencoded";
+
+/// Media type of multipart form bodies; the full `content-type` carries a boundary, see
+/// [`Boundary::content_type`]
+pub const MULTIPART_FORM_DATA: &str = "multipart/form-data";
+
+/// Stack buffer the encoders render into before writing to the body
+const WRITE_BUFFER_SIZE: usize = 256;
+
+/// Collects encoded bytes and writes them to a body in [`WRITE_BUFFER_SIZE`] pieces
+struct BufferedWrite<'w, 'a, T> {
+    body: &'w mut RequestBodyWriter<'a, T>,
+    buf: [u8; WRITE_BUFFER_SIZE],
+    len: usize,
+}
+
+impl<'w, 'a, T: Write> BufferedWrite<'w, 'a, T> {
+    fn new(body: &'w mut RequestBodyWriter<'a, T>) -> Self {
+        Self {
+            body,
+            buf: [0; WRITE_BUFFER_SIZE],
+            len: 0,
+        }
+    }
+
+    async fn put(
+        &mut self,
+        bytes: impl IntoIterator<Item = u8>,
+    ) -> Result<(), GurtError<T::Error>> {
+        for b in bytes {
+            if self.len == self.buf.len() {
+                self.flush().await?;
+            }
+            self.buf[self.len] = b;
+            self.len += 1;
+        }
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<(), GurtError<T::Error>> {
+        self.body.write(&self.buf[..self.len]).await?;
+        self.len = 0;
+        Ok(())
+    }
+}
+
+/// The unread part of a body, held in a caller-supplied buffer
+struct Window<'b, R> {
+    source: R,
+    buf: &'b mut [u8],
+    start: usize,
+    end: usize,
+    /// The source has no more data
+    eof: bool,
+}
+
+impl<'b, R: Read> Window<'b, R>
+where
+    R::Error: From<Limit>,
+{
+    fn new(source: R, buf: &'b mut [u8]) -> Self {
+        Self {
+            source,
+            buf,
+            start: 0,
+            end: 0,
+            eof: false,
+        }
+    }
+
+    fn data(&self) -> &[u8] {
+        &self.buf[self.start..self.end]
+    }
+
+    fn consume(&mut self, n: usize) {
+        self.start += n;
+    }
+
+    /// Read more data after what is held, moving it to the start of the buffer first
+    ///
+    /// Fails with [`Limit::BufferTooSmall`] if the buffer is already full. Sets `eof`
+    /// instead of adding data once the source is exhausted.
+    async fn fill(&mut self) -> Result<(), R::Error> {
+        if self.start > 0 {
+            self.buf.copy_within(self.start..self.end, 0);
+            self.end -= self.start;
+            self.start = 0;
+        }
+        if self.end == self.buf.len() {
+            return Err(Limit::BufferTooSmall.into());
+        }
+        let n = self.source.read(&mut self.buf[self.end..]).await?;
+        self.eof = n == 0;
+        self.end += n;
+        Ok(())
+    }
+
+    /// Discard everything up to the end of the source
+    async fn drain(&mut self) -> Result<(), R::Error> {
+        while !self.eof {
+            self.start = self.end;
+            self.fill().await?;
+        }
+        self.start = self.end;
+        Ok(())
+    }
+}
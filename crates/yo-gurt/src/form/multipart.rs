@@ -0,0 +1,634 @@
+//! `multipart/form-data` (RFC 7578)
+
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+use embedded_io_async::{ErrorType, Read, Write};
+
+use super::{BufferedWrite, MULTIPART_FORM_DATA, Window};
+use crate::head::validate_header_value;
+use crate::{GurtError, Limit, ProtocolError, RequestBodyWriter, RequestError};
+
+/// Longest boundary allowed (RFC 2046 section 5.1.1)
+pub const MAX_BOUNDARY_LEN: usize = 70;
+
+const CONTENT_TYPE_PREFIX: &str = "multipart/form-data; boundary=";
+
+/// A multipart boundary, and the `content-type` that announces it
+#[derive(Debug, Clone)]
+pub struct Boundary {
+    /// `content-type` value, ending with the boundary
+    value: [u8; CONTENT_TYPE_PREFIX.len() + MAX_BOUNDARY_LEN],
+    len: usize,
+}
+
+impl Boundary {
+    /// Use `boundary` as it is
+    ///
+    /// Returns `None` unless it is 1 to [`MAX_BOUNDARY_LEN`] ASCII letters, digits and
+    /// `'+-._`, which need no quoting in `content-type`. The boundary must not occur in
+    /// any part's contents.
+    pub fn new(boundary: &str) -> Option<Self> {
+        let valid = (1..=MAX_BOUNDARY_LEN).contains(&boundary.len())
+            && boundary
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b"'+-._".contains(&b));
+        if !valid {
+            return None;
+        }
+        let mut value = [0; CONTENT_TYPE_PREFIX.len() + MAX_BOUNDARY_LEN];
+        let len = CONTENT_TYPE_PREFIX.len() + boundary.len();
+        value[..CONTENT_TYPE_PREFIX.len()].copy_from_slice(CONTENT_TYPE_PREFIX.as_bytes());
+        value[CONTENT_TYPE_PREFIX.len()..len].copy_from_slice(boundary.as_bytes());
+        Some(Self { value, len })
+    }
+
+    /// A boundary derived from `seed`, which should come from a random source so that
+    /// it cannot be guessed by whoever supplies the part contents
+    pub fn from_seed(seed: u64) -> Self {
+        const HEX: &[u8; 16] = b"0123456789abcdef";
+        // splitmix64 finaliser, so nearby seeds give unrelated boundaries
+        let mut x = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        x ^= x >> 31;
+        let mut boundary = *b"yo-gurt-0000000000000000";
+        for (i, b) in boundary[8..].iter_mut().enumerate() {
+            *b = HEX[((x >> (60 - 4 * i)) & 0xf) as usize];
+        }
+        Self::new(core::str::from_utf8(&boundary).unwrap()).unwrap()
+    }
+
+    /// A boundary from the standard library's per-process random hash keys
+    #[cfg(feature = "std")]
+    pub fn random() -> Self {
+        use core::hash::BuildHasher;
+        Self::from_seed(std::hash::RandomState::new().hash_one(0u8))
+    }
+
+    /// The boundary itself
+    pub fn as_str(&self) -> &str {
+        &self.content_type()[CONTENT_TYPE_PREFIX.len()..]
+    }
+
+    /// `content-type` value for a form with this boundary, e.g.
+    /// `multipart/form-data; boundary=yo-gurt-5f0c...`
+    pub fn content_type(&self) -> &str {
+        core::str::from_utf8(&self.value[..self.len]).unwrap()
+    }
+
+    /// Length of the encoded form with `parts`, for its `content-length`
+    pub fn content_length(&self, parts: &[Part<'_>]) -> usize {
+        let parts: usize = parts
+            .iter()
+            .map(|part| part.head(self.as_str()).count() + part.len + 2)
+            .sum();
+        parts + self.close().count()
+    }
+
+    /// Delimiter ending the form
+    fn close(&self) -> impl Iterator<Item = u8> + '_ {
+        b"--"
+            .iter()
+            .chain(self.as_str().as_bytes())
+            .chain(b"--\r\n")
+            .copied()
+    }
+}
+
+/// Description of one part of a form: its field name, optional file name and content
+/// type, and the exact length of its contents
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Part<'a> {
+    name: &'a str,
+    filename: Option<&'a str>,
+    content_type: Option<&'a str>,
+    len: usize,
+}
+
+impl<'a> Part<'a> {
+    /// A part for field `name` with `len` bytes of contents
+    pub const fn new(name: &'a str, len: usize) -> Self {
+        Self {
+            name,
+            filename: None,
+            content_type: None,
+            len,
+        }
+    }
+
+    /// Send the part as a file named `filename`
+    pub const fn filename(mut self, filename: &'a str) -> Self {
+        self.filename = Some(filename);
+        self
+    }
+
+    /// Set the part's `content-type`
+    pub const fn content_type(mut self, content_type: &'a str) -> Self {
+        self.content_type = Some(content_type);
+        self
+    }
+
+    /// Delimiter and headers starting this part
+    ///
+    /// Quotes, CR and LF in names are percent-encoded, as browsers do.
+    fn head<'s>(&'s self, boundary: &'s str) -> impl Iterator<Item = u8> + 's {
+        let filename = self.filename.into_iter().flat_map(|filename| {
+            b"; filename=\""
+                .iter()
+                .copied()
+                .chain(escape(filename))
+                .chain([b'"'])
+        });
+        let content_type = self.content_type.into_iter().flat_map(|content_type| {
+            b"content-type: "
+                .iter()
+                .chain(content_type.as_bytes())
+                .chain(b"\r\n")
+                .copied()
+        });
+        b"--"
+            .iter()
+            .chain(boundary.as_bytes())
+            .chain(b"\r\ncontent-disposition: form-data; name=\"")
+            .copied()
+            .chain(escape(self.name))
+            .chain([b'"'])
+            .chain(filename)
+            .chain(*b"\r\n")
+            .chain(content_type)
+            .chain(*b"\r\n")
+    }
+
+    fn validate(&self) -> Result<(), RequestError> {
+        match self.content_type {
+            Some(content_type) => validate_header_value(content_type),
+            None => Ok(()),
+        }
+    }
+}
+
+fn escape(s: &str) -> impl Iterator<Item = u8> + '_ {
+    s.bytes().flat_map(|b| {
+        let (bytes, len) = match b {
+            b'"' => (*b"%22", 3),
+            b'\r' => (*b"%0D", 3),
+            b'\n' => (*b"%0A", 3),
+            b => ([b, 0, 0], 1),
+        };
+        bytes.into_iter().take(len)
+    })
+}
+
+/// Streaming encoder for a multipart form with a known length
+///
+/// Parts are started with [`part`](Self::part) and their contents written with
+/// [`write`](Self::write); each part must receive exactly its declared length. The
+/// body's `content-length` is [`Boundary::content_length`] of the same parts.
+pub struct MultipartWriter<'w, 'a, T> {
+    body: &'w mut RequestBodyWriter<'a, T>,
+    boundary: &'w Boundary,
+    /// Contents still owed to the current part
+    remaining: Option<usize>,
+}
+
+impl<'w, 'a, T: Write> MultipartWriter<'w, 'a, T> {
+    /// Write a form with `boundary` to a request or response body
+    pub fn new(body: &'w mut RequestBodyWriter<'a, T>, boundary: &'w Boundary) -> Self {
+        Self {
+            body,
+            boundary,
+            remaining: None,
+        }
+    }
+
+    /// End the current part, if any, and start `part`
+    ///
+    /// Fails with [`RequestError::BodyIncomplete`] if the current part is short, and
+    /// [`RequestError::InvalidHeaderValue`] for a content type with control characters,
+    /// reported as errors of the request or response the body belongs to.
+    pub async fn part(&mut self, part: &Part<'_>) -> Result<(), GurtError<T::Error>> {
+        part.validate().map_err(|e| self.body.invalid(e))?;
+        self.end_part().await?;
+        let mut out = BufferedWrite::new(self.body);
+        out.put(part.head(self.boundary.as_str())).await?;
+        out.flush().await?;
+        self.remaining = Some(part.len);
+        Ok(())
+    }
+
+    /// Write contents of the current part
+    ///
+    /// Fails with [`RequestError::BodyTooLong`] past the part's declared length or
+    /// before any part was started.
+    pub async fn write(&mut self, data: &[u8]) -> Result<(), GurtError<T::Error>> {
+        match &mut self.remaining {
+            Some(remaining) if data.len() <= *remaining => *remaining -= data.len(),
+            _ => return Err(self.body.invalid(RequestError::BodyTooLong)),
+        }
+        self.body.write(data).await
+    }
+
+    /// Write a whole part for field `name` with contents `value`
+    pub async fn field(&mut self, name: &str, value: &[u8]) -> Result<(), GurtError<T::Error>> {
+        self.part(&Part::new(name, value.len())).await?;
+        self.write(value).await
+    }
+
+    /// End the last part and the form
+    pub async fn finish(mut self) -> Result<(), GurtError<T::Error>> {
+        self.end_part().await?;
+        let mut out = BufferedWrite::new(self.body);
+        out.put(self.boundary.close()).await?;
+        out.flush().await
+    }
+
+    async fn end_part(&mut self) -> Result<(), GurtError<T::Error>> {
+        match self.remaining.take() {
+            Some(0) => self.body.write(b"\r\n").await,
+            Some(_) => Err(self.body.invalid(RequestError::BodyIncomplete)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A multipart form collected in memory, for parts whose size is not known up front
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone)]
+pub struct MultipartBody {
+    boundary: Boundary,
+    body: Vec<u8>,
+}
+
+#[cfg(feature = "alloc")]
+impl MultipartBody {
+    /// An empty form with `boundary`
+    pub fn new(boundary: Boundary) -> Self {
+        Self {
+            boundary,
+            body: Vec::new(),
+        }
+    }
+
+    /// `content-type` value for the form
+    pub fn content_type(&self) -> &str {
+        self.boundary.content_type()
+    }
+
+    /// Add a field `name` with contents `value`
+    pub fn field(&mut self, name: &str, value: &[u8]) -> &mut Self {
+        self.push(Part::new(name, value.len()), value);
+        self
+    }
+
+    /// Add a file
+    ///
+    /// Fails with [`RequestError::InvalidHeaderValue`] for a content type with control
+    /// characters.
+    pub fn file(
+        &mut self,
+        name: &str,
+        filename: &str,
+        content_type: &str,
+        data: &[u8],
+    ) -> Result<&mut Self, RequestError> {
+        let part = Part::new(name, data.len())
+            .filename(filename)
+            .content_type(content_type);
+        part.validate()?;
+        self.push(part, data);
+        Ok(self)
+    }
+
+    /// The encoded form
+    pub fn finish(mut self) -> Vec<u8> {
+        self.body.extend(self.boundary.close());
+        self.body
+    }
+
+    fn push(&mut self, part: Part<'_>, data: &[u8]) {
+        self.body.extend(part.head(self.boundary.as_str()));
+        self.body.extend_from_slice(data);
+        self.body.extend_from_slice(b"\r\n");
+    }
+}
+
+/// The boundary parameter of a `multipart/form-data` content type
+///
+/// Returns `None` for other media types and missing or over-long boundaries.
+pub fn multipart_boundary(content_type: &str) -> Option<&str> {
+    let mut params = content_type.split(';');
+    if !params
+        .next()?
+        .trim()
+        .eq_ignore_ascii_case(MULTIPART_FORM_DATA)
+    {
+        return None;
+    }
+    params.find_map(|param| {
+        let (name, value) = param.split_once('=')?;
+        if !name.trim().eq_ignore_ascii_case("boundary") {
+            return None;
+        }
+        let value = value.trim();
+        let value = value
+            .strip_prefix('"')
+            .and_then(|value| value.strip_suffix('"'))
+            .unwrap_or(value);
+        (1..=MAX_BOUNDARY_LEN)
+            .contains(&value.len())
+            .then_some(value)
+    })
+}
+
+/// Headers of a part read by [`MultipartReader::next_part`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PartHead<'r> {
+    name: &'r str,
+    filename: Option<&'r str>,
+    content_type: Option<&'r str>,
+}
+
+impl<'r> PartHead<'r> {
+    /// The form field name, as sent (browsers percent-encode quotes, CR and LF)
+    pub fn name(&self) -> &'r str {
+        self.name
+    }
+
+    /// The file name, for file uploads
+    pub fn filename(&self) -> Option<&'r str> {
+        self.filename
+    }
+
+    /// The part's `content-type`, if given
+    pub fn content_type(&self) -> Option<&'r str> {
+        self.content_type
+    }
+}
+
+/// Where a [`MultipartReader`] is within the body
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Nothing read yet
+    Start,
+    /// Before the first delimiter
+    Preamble,
+    /// Within a part's contents
+    Body,
+    /// Just after a delimiter
+    Delimiter,
+    /// At the start of a part's headers
+    Headers,
+    /// After the closing delimiter; the rest of the body has been discarded
+    Done,
+}
+
+/// Incremental parser for a `multipart/form-data` body
+///
+/// Reads the body from `source` (such as a [`BodyReader`](crate::BodyReader)) into the
+/// buffer passed to [`new`](Self::new), which must hold a part's whole header block.
+/// [`next_part`](Self::next_part) moves to the next part and returns its headers; the
+/// part's contents are then read through the [`Read`] impl, which returns `Ok(0)` at the
+/// end of the part. Contents not read before the next call to `next_part` are skipped.
+///
+/// Malformed bodies fail with [`ProtocolError::InvalidFormData`]. Anything after the
+/// closing delimiter is read and discarded, so the transport is left at the end of the
+/// body.
+pub struct MultipartReader<'b, R> {
+    window: Window<'b, R>,
+    boundary: &'b [u8],
+    state: State,
+}
+
+impl<'b, R: Read> MultipartReader<'b, R>
+where
+    R::Error: From<ProtocolError> + From<Limit>,
+{
+    /// A parser for a form with `boundary` (see [`multipart_boundary`]) read from
+    /// `source`, using `buf` for part headers and scanning for delimiters
+    pub fn new(source: R, boundary: &'b str, buf: &'b mut [u8]) -> Self {
+        Self {
+            window: Window::new(source, buf),
+            boundary: boundary.as_bytes(),
+            state: State::Start,
+        }
+    }
+
+    /// Move to the next part and return its headers, or `None` after the last part
+    ///
+    /// Fails with [`Limit::BufferTooSmall`] for headers that do not fit in the buffer.
+    pub async fn next_part(&mut self) -> Result<Option<PartHead<'_>>, R::Error> {
+        let len = loop {
+            match self.state {
+                State::Start => self.start()?,
+                State::Preamble | State::Body => {
+                    let (len, delimiter) = self.scan().await?;
+                    self.window.consume(len);
+                    if delimiter {
+                        self.window.consume(self.delimiter_len());
+                        self.state = State::Delimiter;
+                    }
+                }
+                State::Delimiter => self.after_delimiter().await?,
+                State::Headers => break self.header_block().await?,
+                State::Done => return Ok(None),
+            }
+        };
+        let start = self.window.start;
+        // The block's lines and the empty line ending it
+        self.window.consume(len + 2);
+        self.state = State::Body;
+        Ok(Some(parse_head(&self.window.buf[start..start + len])?))
+    }
+
+    fn delimiter_len(&self) -> usize {
+        4 + self.boundary.len()
+    }
+
+    /// Position of the next `CRLF--boundary` in `data`
+    fn find_delimiter(&self, data: &[u8]) -> Option<usize> {
+        (0..data.len()).find(|&at| {
+            let rest = &data[at..];
+            rest.starts_with(b"\r\n--") && rest[4..].starts_with(self.boundary)
+        })
+    }
+
+    /// Set up the window so the first delimiter needs no leading CRLF
+    fn start(&mut self) -> Result<(), R::Error> {
+        if self.window.buf.len() < self.delimiter_len() {
+            return Err(Limit::BufferTooSmall.into());
+        }
+        self.window.buf[..2].copy_from_slice(b"\r\n");
+        self.window.end = 2;
+        self.state = State::Preamble;
+        Ok(())
+    }
+
+    /// Length of part contents at the start of the window, and whether a delimiter
+    /// follows them
+    async fn scan(&mut self) -> Result<(usize, bool), R::Error> {
+        loop {
+            let data = self.window.data();
+            if let Some(at) = self.find_delimiter(data) {
+                return Ok((at, true));
+            }
+            // Hold back what could be the start of a delimiter
+            let safe = data.len().saturating_sub(self.delimiter_len() - 1);
+            if safe > 0 {
+                return Ok((safe, false));
+            }
+            if self.window.eof {
+                return Err(ProtocolError::InvalidFormData.into());
+            }
+            self.window.fill().await?;
+        }
+    }
+
+    /// Handle what follows a delimiter: `--` ends the form, optional whitespace and CRLF
+    /// start a part
+    async fn after_delimiter(&mut self) -> Result<(), R::Error> {
+        while self.window.data().len() < 2 {
+            if self.window.eof {
+                return Err(ProtocolError::InvalidFormData.into());
+            }
+            self.window.fill().await?;
+        }
+        match self.window.data() {
+            [b'-', b'-', ..] => {
+                self.window.consume(2);
+                self.window.drain().await?;
+                self.state = State::Done;
+            }
+            [b'\r', b'\n', ..] => {
+                self.window.consume(2);
+                self.state = State::Headers;
+            }
+            [b' ' | b'\t', ..] => self.window.consume(1),
+            _ => return Err(ProtocolError::InvalidFormData.into()),
+        }
+        Ok(())
+    }
+
+    /// Length of the header lines at the start of the window, once all are there
+    async fn header_block(&mut self) -> Result<usize, R::Error> {
+        loop {
+            let data = self.window.data();
+            if data.starts_with(b"\r\n") {
+                return Ok(0);
+            }
+            if let Some(at) = data.windows(4).position(|w| w == b"\r\n\r\n") {
+                return Ok(at + 2);
+            }
+            if self.window.eof {
+                return Err(ProtocolError::InvalidFormData.into());
+            }
+            self.window.fill().await?;
+        }
+    }
+}
+
+/// Parse a part's header lines, each ending in CRLF
+fn parse_head(block: &[u8]) -> Result<PartHead<'_>, ProtocolError> {
+    let text = |bytes| core::str::from_utf8(bytes).map_err(|_| ProtocolError::InvalidFormData);
+    let mut disposition = None;
+    let mut content_type = None;
+    for line in block.split(|&b| b == b'\n') {
+        let Some(line) = line.strip_suffix(b"\r") else {
+            continue;
+        };
+        let colon = line
+            .iter()
+            .position(|&b| b == b':')
+            .ok_or(ProtocolError::InvalidFormData)?;
+        let (name, value) = (&line[..colon], line[colon + 1..].trim_ascii());
+        if name.eq_ignore_ascii_case(b"content-disposition") {
+            disposition = Some(parse_disposition(value)?);
+        } else if name.eq_ignore_ascii_case(b"content-type") {
+            content_type = Some(text(value)?);
+        }
+    }
+    let (name, filename) = disposition.ok_or(ProtocolError::InvalidFormData)?;
+    Ok(PartHead {
+        name: text(name.ok_or(ProtocolError::InvalidFormData)?)?,
+        filename: filename.map(text).transpose()?,
+        content_type,
+    })
+}
+
+/// The `name` and `filename` parameters of a `form-data` content disposition
+type Disposition<'r> = (Option<&'r [u8]>, Option<&'r [u8]>);
+
+fn parse_disposition(value: &[u8]) -> Result<Disposition<'_>, ProtocolError> {
+    let end = value.iter().position(|&b| b == b';').unwrap_or(value.len());
+    if !value[..end].trim_ascii().eq_ignore_ascii_case(b"form-data") {
+        return Err(ProtocolError::InvalidFormData);
+    }
+    let (mut name, mut filename) = (None, None);
+    let mut rest = &value[end..];
+    while let Some(param) = rest.strip_prefix(b";") {
+        let param = param.trim_ascii_start();
+        let eq = param
+            .iter()
+            .position(|&b| b == b'=')
+            .ok_or(ProtocolError::InvalidFormData)?;
+        let key = param[..eq].trim_ascii();
+        let param = param[eq + 1..].trim_ascii_start();
+        let (value, after) = match param.strip_prefix(b"\"") {
+            Some(quoted) => {
+                let close = closing_quote(quoted).ok_or(ProtocolError::InvalidFormData)?;
+                (&quoted[..close], &quoted[close + 1..])
+            }
+            None => {
+                let end = param.iter().position(|&b| b == b';').unwrap_or(param.len());
+                (param[..end].trim_ascii(), &param[end..])
+            }
+        };
+        if key.eq_ignore_ascii_case(b"name") {
+            name = Some(value);
+        } else if key.eq_ignore_ascii_case(b"filename") {
+            filename = Some(value);
+        }
+        rest = after.trim_ascii_start();
+    }
+    if !rest.is_empty() {
+        return Err(ProtocolError::InvalidFormData);
+    }
+    Ok((name, filename))
+}
+
+/// Position of the quote ending a quoted string, skipping backslash escapes
+fn closing_quote(quoted: &[u8]) -> Option<usize> {
+    let mut escaped = false;
+    quoted.iter().position(|&b| {
+        let close = b == b'"' && !escaped;
+        escaped = b == b'\\' && !escaped;
+        close
+    })
+}
+
+impl<'b, R: ErrorType> ErrorType for MultipartReader<'b, R> {
+    type Error = R::Error;
+}
+
+impl<'b, R: Read> Read for MultipartReader<'b, R>
+where
+    R::Error: From<ProtocolError> + From<Limit>,
+{
+    /// Read contents of the current part
+    ///
+    /// Returns `Ok(0)` at the end of the part, and before the first part.
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        if self.state != State::Body || buf.is_empty() {
+            return Ok(0);
+        }
+        let (len, delimiter) = self.scan().await?;
+        let n = len.min(buf.len());
+        buf[..n].copy_from_slice(&self.window.data()[..n]);
+        self.window.consume(n);
+        if delimiter && n == len {
+            self.window.consume(self.delimiter_len());
+            self.state = State::Delimiter;
+        }
+        Ok(n)
+    }
+}
@@ -0,0 +1,194 @@
+//! `application/x-www-form-urlencoded`, as HTML forms submit it
+
+#[cfg(feature = "alloc")]
+use alloc::{string::String, vec::Vec};
+
+use embedded_io_async::{Read, Write};
+
+use super::{BufferedWrite, Window};
+use crate::{GurtError, Limit, ProtocolError, RequestBodyWriter};
+
+/// Encoder for a URL-encoded form
+///
+/// Bytes other than ASCII letters, digits and `*-._` are percent-encoded, and spaces
+/// become `+`, matching what browsers submit.
+#[derive(Debug, Clone, Copy)]
+pub struct UrlEncoded<'a> {
+    fields: &'a [(&'a str, &'a str)],
+}
+
+impl<'a> UrlEncoded<'a> {
+    /// A form with `fields` as name-value pairs, in order
+    pub const fn new(fields: &'a [(&'a str, &'a str)]) -> Self {
+        Self { fields }
+    }
+
+    /// Length of the encoded form, for its `content-length`
+    pub fn len(&self) -> usize {
+        self.bytes().count()
+    }
+
+    /// Whether the encoded form is empty
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Write the encoded form to a request or response body
+    pub async fn write_to<T: Write>(
+        &self,
+        body: &mut RequestBodyWriter<'_, T>,
+    ) -> Result<(), GurtError<T::Error>> {
+        let mut out = BufferedWrite::new(body);
+        out.put(self.bytes()).await?;
+        out.flush().await
+    }
+
+    /// The encoded form
+    #[cfg(feature = "alloc")]
+    pub fn to_vec(&self) -> Vec<u8> {
+        self.bytes().collect()
+    }
+
+    fn bytes(&self) -> impl Iterator<Item = u8> + '_ {
+        self.fields
+            .iter()
+            .enumerate()
+            .flat_map(|(i, (name, value))| {
+                (i > 0)
+                    .then_some(b'&')
+                    .into_iter()
+                    .chain(encode(name))
+                    .chain([b'='])
+                    .chain(encode(value))
+            })
+    }
+}
+
+fn encode(s: &str) -> impl Iterator<Item = u8> + '_ {
+    const HEX: &[u8; 16] = b"0123456789ABCDEF";
+    s.bytes().flat_map(|b| {
+        let (bytes, len) = match b {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'*' | b'-' | b'.' | b'_' => ([b, 0, 0], 1),
+            b' ' => ([b'+', 0, 0], 1),
+            _ => (
+                [b'%', HEX[usize::from(b >> 4)], HEX[usize::from(b & 0xf)]],
+                3,
+            ),
+        };
+        bytes.into_iter().take(len)
+    })
+}
+
+/// Decode `+` and percent-escapes in place, returning the decoded length
+///
+/// A `%` not followed by two hex digits is kept as it is.
+fn decode_in_place(data: &mut [u8]) -> usize {
+    let mut read = 0;
+    let mut write = 0;
+    while read < data.len() {
+        let b = match data[read] {
+            b'+' => b' ',
+            b'%' => match (
+                data.get(read + 1).and_then(|&h| hex(h)),
+                data.get(read + 2).and_then(|&l| hex(l)),
+            ) {
+                (Some(high), Some(low)) => {
+                    read += 2;
+                    high << 4 | low
+                }
+                _ => b'%',
+            },
+            b => b,
+        };
+        data[write] = b;
+        read += 1;
+        write += 1;
+    }
+    write
+}
+
+fn hex(b: u8) -> Option<u8> {
+    (b as char).to_digit(16).map(|d| d as u8)
+}
+
+/// Split a raw `name=value` field and decode both halves in place
+fn decode_field(field: &mut [u8]) -> Result<(&str, &str), ProtocolError> {
+    let split = field.iter().position(|&b| b == b'=');
+    let (name, value) = match split {
+        Some(at) => {
+            let (name, value) = field.split_at_mut(at);
+            (name, &mut value[1..])
+        }
+        None => (field, &mut [][..]),
+    };
+    let name_len = decode_in_place(name);
+    let value_len = decode_in_place(value);
+    let text = |bytes| core::str::from_utf8(bytes).map_err(|_| ProtocolError::InvalidFormData);
+    Ok((text(&name[..name_len])?, text(&value[..value_len])?))
+}
+
+/// Incremental parser for a URL-encoded form body
+///
+/// Reads the body from `source` (such as a [`BodyReader`](crate::BodyReader)) into the
+/// buffer passed to [`new`](Self::new), which must hold the longest field. Fields must
+/// decode to UTF-8; anything else fails with [`ProtocolError::InvalidFormData`].
+pub struct UrlEncodedReader<'b, R> {
+    window: Window<'b, R>,
+}
+
+impl<'b, R: Read> UrlEncodedReader<'b, R>
+where
+    R::Error: From<ProtocolError> + From<Limit>,
+{
+    /// A parser reading from `source`, holding one field at a time in `buf`
+    pub fn new(source: R, buf: &'b mut [u8]) -> Self {
+        Self {
+            window: Window::new(source, buf),
+        }
+    }
+
+    /// The next field as a decoded name and value, or `None` at the end of the body
+    ///
+    /// Fails with [`Limit::BufferTooSmall`] for a field that does not fit in the buffer.
+    pub async fn next_field(&mut self) -> Result<Option<(&str, &str)>, R::Error> {
+        let mut searched = 0;
+        let len = loop {
+            let data = self.window.data();
+            if let Some(at) = data[searched..].iter().position(|&b| b == b'&') {
+                let len = searched + at;
+                if len == 0 {
+                    // Empty fields between separators are skipped
+                    self.window.consume(1);
+                    continue;
+                }
+                break len;
+            }
+            if self.window.eof {
+                if data.is_empty() {
+                    return Ok(None);
+                }
+                break data.len();
+            }
+            searched = data.len();
+            self.window.fill().await?;
+        };
+        let start = self.window.start;
+        // The separator, if any, goes with the field
+        self.window.consume((len + 1).min(self.window.end - start));
+        Ok(Some(decode_field(
+            &mut self.window.buf[start..start + len],
+        )?))
+    }
+}
+
+/// Decode a whole URL-encoded form body into owned name-value pairs
+#[cfg(feature = "alloc")]
+pub fn decode_urlencoded(body: &[u8]) -> Result<Vec<(String, String)>, ProtocolError> {
+    let mut fields = Vec::new();
+    for field in body.split(|&b| b == b'&').filter(|field| !field.is_empty()) {
+        let mut field = field.to_vec();
+        let (name, value) = decode_field(&mut field)?;
+        fields.push((String::from(name), String::from(value)));
+    }
+    Ok(fields)
+}
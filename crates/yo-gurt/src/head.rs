@@ -0,0 +1,360 @@
+//! Request and response head rendering
+//!
+//! A [`RequestHead`] describes the method line and headers of a request and renders them
+//! as a sequence of byte slices, so a client can copy the whole head into one buffer and
+//! hand it to the transport with a single write. [`ResponseHead`] does the same for the
+//! status line and headers of a response.
+
+use crate::log::Header;
+use crate::{GURT_VERSION, GurtError, Limit, MAX_MESSAGE_SIZE, Method, RequestError, StatusCode};
+
+/// User agent sent when a request does not name one
+pub const DEFAULT_USER_AGENT: &str = "yo-gurt/0.1";
+
+/// The method line and headers of a request
+///
+/// From spec: "Request Structure:
+/// ```text
+/// METHOD /path GURT/1.0.0\r\n
+/// header-name: header-value\r\n
+/// content-length: 123\r\n
+/// user-agent: GURT-Client/1.0.0\r\n
+/// \r\n
+/// ```"
+///
+/// Headers are rendered in order after `host`, followed by `content-length` (when set)
+/// and `user-agent`.
+#[derive(Debug, Clone)]
+pub struct RequestHead<'a> {
+    method: Method,
+    path: &'a str,
+    host: &'a str,
+    user_agent: &'a str,
+    headers: &'a [(&'a str, &'a str)],
+    content_length: Option<usize>,
+    /// `content_length` as decimal digits, right-aligned
+    digits: [u8; 20],
+    digits_start: usize,
+}
+
+impl<'a> RequestHead<'a> {
+    /// A head with only `host` and the default `user-agent`
+    pub fn new(method: Method, path: &'a str, host: &'a str) -> Self {
+        Self {
+            method,
+            path,
+            host,
+            user_agent: DEFAULT_USER_AGENT,
+            headers: &[],
+            content_length: None,
+            digits: [0; 20],
+            digits_start: 20,
+        }
+    }
+
+    /// Set the `user-agent` value
+    pub fn user_agent(mut self, user_agent: &'a str) -> Self {
+        self.user_agent = user_agent;
+        self
+    }
+
+    /// Set additional headers, written in order after `host`
+    pub fn headers(mut self, headers: &'a [(&'a str, &'a str)]) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    /// Declare a body of `length` bytes
+    pub fn content_length(mut self, length: usize) -> Self {
+        self.content_length = Some(length);
+        self.digits_start = render_digits(length, &mut self.digits);
+        self
+    }
+
+    /// The declared body length, if any
+    pub fn body_length(&self) -> Option<usize> {
+        self.content_length
+    }
+
+    /// Log the request line and headers
+    pub(crate) fn log(&self) {
+        debug!("request {:?} {}", self.method, self.path);
+        trace!("{:?}", Header::new(b"host", self.host.as_bytes()));
+        for (name, value) in self.headers {
+            trace!("{:?}", Header::new(name.as_bytes(), value.as_bytes()));
+        }
+        if let Some(length) = self.content_length {
+            trace!("content-length: {}", length);
+        }
+        trace!(
+            "{:?}",
+            Header::new(b"user-agent", self.user_agent.as_bytes())
+        );
+    }
+
+    /// Check the head can be written without injecting extra lines
+    ///
+    /// Header names must be lowercase tokens, values must not contain CR, LF or other
+    /// control characters, and `host`, `user-agent`, `content-length` and
+    /// `transfer-encoding` cannot be passed as additional headers.
+    pub fn validate<E>(&self) -> Result<(), GurtError<E>> {
+        validate_path(self.path)?;
+        validate_host(self.host)?;
+        validate_header_value(self.user_agent)?;
+        for (name, value) in self.headers {
+            validate_header_name(name)?;
+            if matches!(
+                *name,
+                "host" | "user-agent" | "content-length" | "transfer-encoding"
+            ) {
+                return Err(RequestError::ReservedHeader.into());
+            }
+            validate_header_value(value)?;
+        }
+        if self
+            .content_length
+            .is_some_and(|length| length > MAX_MESSAGE_SIZE)
+        {
+            return Err(Limit::MessageTooLarge.into());
+        }
+        Ok(())
+    }
+
+    /// The rendered head as slices, in wire order
+    pub fn slices(&self) -> impl Iterator<Item = &[u8]> + '_ {
+        // From spec: "header-name: header-value\r\n"
+        fn header<'s>(name: &'s [u8], value: &'s [u8]) -> [&'s [u8]; 4] {
+            [name, b": ", value, b"\r\n"]
+        }
+
+        // From spec: "Method line: `METHOD /path GURT/1.0.0`"
+        let method_line: [&[u8]; 6] = [
+            self.method.as_str().as_bytes(),
+            b" ",
+            self.path.as_bytes(),
+            b" ",
+            GURT_VERSION.as_bytes(),
+            b"\r\n",
+        ];
+        let content_length = self
+            .content_length
+            .map(|_| header(b"content-length", &self.digits[self.digits_start..]));
+        method_line
+            .into_iter()
+            .chain(header(b"host", self.host.as_bytes()))
+            .chain(
+                self.headers
+                    .iter()
+                    .flat_map(|(name, value)| header(name.as_bytes(), value.as_bytes())),
+            )
+            .chain(content_length.into_iter().flatten())
+            .chain(header(b"user-agent", self.user_agent.as_bytes()))
+            // From spec: "Header terminator: `\r\n\r\n`"
+            .chain([&b"\r\n"[..]])
+    }
+
+    /// Length of the rendered head in bytes
+    pub fn encoded_len(&self) -> usize {
+        self.slices().map(<[u8]>::len).sum()
+    }
+
+    /// Render the head into `buf`, returning its length, or `None` if it does not fit
+    pub fn encode(&self, buf: &mut [u8]) -> Option<usize> {
+        let mut len = 0;
+        for slice in self.slices() {
+            buf.get_mut(len..len + slice.len())?.copy_from_slice(slice);
+            len += slice.len();
+        }
+        Some(len)
+    }
+}
+
+/// The status line and headers of a response
+///
+/// From spec: "Response Structure:
+/// ```text
+/// GURT/1.0.0 200 OK\r\n
+/// content-type: application/json\r\n
+/// content-length: 123\r\n
+/// server: GURT/1.0.0\r\n
+/// date: Wed, 01 Jan 2020 00:00:00 GMT\r\n
+/// \r\n
+/// [response body]
+/// ```"
+///
+/// Headers are rendered in order, followed by `content-length`. Every response carries a
+/// `content-length`, `0` unless set, so the client always knows where the body ends.
+#[derive(Debug, Clone)]
+pub struct ResponseHead<'a> {
+    status: StatusCode,
+    headers: &'a [(&'a str, &'a str)],
+    content_length: usize,
+    /// `status` as three digits
+    code: [u8; 3],
+    /// `content_length` as decimal digits, right-aligned
+    digits: [u8; 20],
+    digits_start: usize,
+}
+
+impl<'a> ResponseHead<'a> {
+    /// A head with no headers and an empty body
+    pub fn new(status: StatusCode) -> Self {
+        let code = status.as_u16();
+        Self {
+            status,
+            headers: &[],
+            content_length: 0,
+            code: [
+                b'0' + (code / 100) as u8,
+                b'0' + (code / 10 % 10) as u8,
+                b'0' + (code % 10) as u8,
+            ],
+            digits: [0; 20],
+            digits_start: 20,
+        }
+        .content_length(0)
+    }
+
+    /// Set additional headers, written in order after the status line
+    pub fn headers(mut self, headers: &'a [(&'a str, &'a str)]) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    /// Declare a body of `length` bytes
+    pub fn content_length(mut self, length: usize) -> Self {
+        self.content_length = length;
+        self.digits_start = render_digits(length, &mut self.digits);
+        self
+    }
+
+    /// The response status
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// The declared body length
+    pub fn body_length(&self) -> usize {
+        self.content_length
+    }
+
+    /// Log the status line and headers
+    pub(crate) fn log(&self) {
+        debug!("response {:?}", self.status);
+        for (name, value) in self.headers {
+            trace!("{:?}", Header::new(name.as_bytes(), value.as_bytes()));
+        }
+        trace!("content-length: {}", self.content_length);
+    }
+
+    /// Check the head can be written without injecting extra lines
+    ///
+    /// Header names must be lowercase tokens, values must not contain CR, LF or other
+    /// control characters, and `content-length` and `transfer-encoding` cannot be passed
+    /// as additional headers. Failures are reported as [`GurtError::InvalidResponse`].
+    pub fn validate<E>(&self) -> Result<(), GurtError<E>> {
+        for (name, value) in self.headers {
+            validate_header_name(name).map_err(GurtError::InvalidResponse)?;
+            if matches!(*name, "content-length" | "transfer-encoding") {
+                return Err(GurtError::InvalidResponse(RequestError::ReservedHeader));
+            }
+            validate_header_value(value).map_err(GurtError::InvalidResponse)?;
+        }
+        if self.content_length > MAX_MESSAGE_SIZE {
+            return Err(Limit::MessageTooLarge.into());
+        }
+        Ok(())
+    }
+
+    /// The rendered head as slices, in wire order
+    pub fn slices(&self) -> impl Iterator<Item = &[u8]> + '_ {
+        fn header<'s>(name: &'s [u8], value: &'s [u8]) -> [&'s [u8]; 4] {
+            [name, b": ", value, b"\r\n"]
+        }
+
+        // From spec: "Status line: `GURT/1.0.0 <code> <message>`"
+        let status_line: [&[u8]; 6] = [
+            GURT_VERSION.as_bytes(),
+            b" ",
+            &self.code,
+            b" ",
+            self.status.reason_phrase().as_bytes(),
+            b"\r\n",
+        ];
+        status_line
+            .into_iter()
+            .chain(
+                self.headers
+                    .iter()
+                    .flat_map(|(name, value)| header(name.as_bytes(), value.as_bytes())),
+            )
+            .chain(header(b"content-length", &self.digits[self.digits_start..]))
+            .chain([&b"\r\n"[..]])
+    }
+
+    /// Length of the rendered head in bytes
+    pub fn encoded_len(&self) -> usize {
+        self.slices().map(<[u8]>::len).sum()
+    }
+
+    /// Render the head into `buf`, returning its length, or `None` if it does not fit
+    pub fn encode(&self, buf: &mut [u8]) -> Option<usize> {
+        let mut len = 0;
+        for slice in self.slices() {
+            buf.get_mut(len..len + slice.len())?.copy_from_slice(slice);
+            len += slice.len();
+        }
+        Some(len)
+    }
+}
+
+/// Write `n` as decimal digits right-aligned in `digits`, returning the first digit's index
+fn render_digits(mut n: usize, digits: &mut [u8; 20]) -> usize {
+    let mut i = digits.len();
+    loop {
+        i -= 1;
+        digits[i] = b'0' + (n % 10) as u8;
+        n /= 10;
+        if n == 0 {
+            return i;
+        }
+    }
+}
+
+/// Check a request path before it is written on the method line
+fn validate_path(path: &str) -> Result<(), RequestError> {
+    if path.is_empty() || !path.bytes().all(|b| b.is_ascii_graphic() || b >= 0x80) {
+        return Err(RequestError::InvalidPath);
+    }
+    Ok(())
+}
+
+/// Check a `host` value, which must be a single non-empty word
+fn validate_host(host: &str) -> Result<(), RequestError> {
+    if host.is_empty() || !host.bytes().all(|b| b.is_ascii_graphic() || b >= 0x80) {
+        return Err(RequestError::InvalidHost);
+    }
+    Ok(())
+}
+
+/// Check an outgoing header name: a lowercase token
+///
+/// From spec: "Headers must be lowercase"
+pub(crate) fn validate_header_name(name: &str) -> Result<(), RequestError> {
+    if name.is_empty()
+        || !name
+            .bytes()
+            .all(|b| crate::is_token_char(b) && !b.is_ascii_uppercase())
+    {
+        return Err(RequestError::InvalidHeaderName);
+    }
+    Ok(())
+}
+
+/// Check an outgoing header value, rejecting CR, LF and other control characters
+pub(crate) fn validate_header_value(value: &str) -> Result<(), RequestError> {
+    if !value.bytes().all(crate::is_field_char) {
+        return Err(RequestError::InvalidHeaderValue);
+    }
+    Ok(())
+}
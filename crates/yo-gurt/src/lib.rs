@@ -52,7 +52,65 @@
 
 #![no_std]
 
-use embedded_io_async::{ErrorType, Read, ReadExactError, Write};
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+#[cfg(feature = "std")]
+extern crate std;
+
+#[macro_use]
+mod log;
+
+#[cfg(feature = "testing")]
+pub mod testing;
+
+#[cfg(feature = "embedded-tls")]
+pub mod embedded_tls;
+
+#[cfg(feature = "std")]
+pub mod adapters;
+
+pub mod cache;
+pub mod capture;
+#[cfg(feature = "testing")]
+pub mod cassette;
+pub mod cookie;
+pub mod encoding;
+#[cfg(feature = "fetch")]
+pub mod fetch;
+pub mod form;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod progress;
+pub mod range;
+pub mod resolve;
+pub mod server;
+#[cfg(feature = "rustls")]
+pub mod tls;
+#[cfg(feature = "tracing")]
+pub mod trace;
+
+mod error;
+mod head;
+
+use encoding::ContentEncoding;
+#[cfg(feature = "alloc")]
+use encoding::{DecodedBody, Decoder};
+pub use error::{GurtError, Limit, ProtocolError, RequestError};
+pub use head::{DEFAULT_USER_AGENT, RequestHead, ResponseHead};
+
+/// The error type response readers returned before [`GurtError`] replaced it
+///
+/// Only the name is kept: its parsing variants are now [`GurtError::Protocol`] cases, and
+/// `BufferTooSmall` is [`GurtError::LimitExceeded`] with [`Limit::BufferTooSmall`].
+#[deprecated(note = "use GurtError; parse errors are now GurtError::Protocol(ProtocolError)")]
+pub type ResponseError<E> = GurtError<E>;
+
+use head::{validate_header_name, validate_header_value};
+use progress::{NoProgress, Progress};
+use range::ContentRange;
+
+use embedded_io_async::{ErrorType, Read, Write};
 
 /// GURT Protocol version constant
 /// From spec: "GURT (version 1.0.0)"
@@ -94,10 +152,15 @@ pub const MAX_CONNECTION_POOL_SIZE: usize = 10;
 /// From spec: "Pool idle timeout: 300 seconds"
 pub const POOL_IDLE_TIMEOUT_SECS: u32 = 300;
 
+/// Stack buffer used by [`GurtClient::request`] and [`GurtClient::handshake`] to render a
+/// request head; larger heads are written piece by piece
+pub const HEAD_BUFFER_SIZE: usize = 512;
+
 /// HTTP Methods supported by GURT
 ///
 /// From spec: "GURT supports all standard HTTP methods"
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Method {
     /// Retrieve resource (no body allowed)
     Get,
@@ -133,6 +196,21 @@ impl Method {
         }
     }
 
+    /// Whether the request only reads, without changing anything on the server: `GET`,
+    /// `HEAD` and `OPTIONS`
+    pub const fn is_safe(&self) -> bool {
+        matches!(self, Method::Get | Method::Head | Method::Options)
+    }
+
+    /// Whether sending the request twice has the same effect as sending it once, so it can
+    /// be retried after a failure: `GET`, `HEAD`, `OPTIONS`, `PUT` and `DELETE`
+    pub const fn is_idempotent(&self) -> bool {
+        matches!(
+            self,
+            Method::Get | Method::Head | Method::Options | Method::Put | Method::Delete
+        )
+    }
+
     /// Parse method from bytes
     pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
         match bytes {
@@ -153,6 +231,7 @@ impl Method {
 ///
 /// From spec: "GURT uses HTTP-compatible status codes"
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum StatusCode {
     // Protocol (1xx)
     /// From spec: "101 SWITCHING_PROTOCOLS - Handshake successful"
@@ -167,6 +246,14 @@ pub enum StatusCode {
     Accepted = 202,
     /// From spec: "204 NO_CONTENT - Success with no response body"
     NoContent = 204,
+    /// 206 PARTIAL_CONTENT - The body is the byte range named by `content-range`; not in
+    /// the spec, but needed for range requests (RFC 9110 section 15.3.7)
+    PartialContent = 206,
+
+    // Redirection (3xx)
+    /// 304 NOT_MODIFIED - The cached response named by a conditional request is still
+    /// current; not in the spec, but needed for revalidating caches (RFC 9110 section 15.4.5)
+    NotModified = 304,
 
     // Client Error (4xx)
     /// From spec: "400 BAD_REQUEST - Invalid request format"
@@ -185,6 +272,9 @@ pub enum StatusCode {
     TooLarge = 413,
     /// From spec: "415 UNSUPPORTED_MEDIA_TYPE - Unsupported content type"
     UnsupportedMediaType = 415,
+    /// 416 RANGE_NOT_SATISFIABLE - The requested range lies outside the resource; not in
+    /// the spec, but needed for range requests (RFC 9110 section 15.5.17)
+    RangeNotSatisfiable = 416,
 
     // Server Error (5xx)
     /// From spec: "500 INTERNAL_SERVER_ERROR - Server error"
@@ -213,6 +303,8 @@ impl StatusCode {
             StatusCode::Created => "CREATED",
             StatusCode::Accepted => "ACCEPTED",
             StatusCode::NoContent => "NO_CONTENT",
+            StatusCode::PartialContent => "PARTIAL_CONTENT",
+            StatusCode::NotModified => "NOT_MODIFIED",
             StatusCode::BadRequest => "BAD_REQUEST",
             StatusCode::Unauthorized => "UNAUTHORIZED",
             StatusCode::Forbidden => "FORBIDDEN",
@@ -221,6 +313,7 @@ impl StatusCode {
             StatusCode::Timeout => "TIMEOUT",
             StatusCode::TooLarge => "TOO_LARGE",
             StatusCode::UnsupportedMediaType => "UNSUPPORTED_MEDIA_TYPE",
+            StatusCode::RangeNotSatisfiable => "RANGE_NOT_SATISFIABLE",
             StatusCode::InternalServerError => "INTERNAL_SERVER_ERROR",
             StatusCode::NotImplemented => "NOT_IMPLEMENTED",
             StatusCode::BadGateway => "BAD_GATEWAY",
@@ -237,6 +330,8 @@ impl StatusCode {
             201 => Some(StatusCode::Created),
             202 => Some(StatusCode::Accepted),
             204 => Some(StatusCode::NoContent),
+            206 => Some(StatusCode::PartialContent),
+            304 => Some(StatusCode::NotModified),
             400 => Some(StatusCode::BadRequest),
             401 => Some(StatusCode::Unauthorized),
             403 => Some(StatusCode::Forbidden),
@@ -245,6 +340,7 @@ impl StatusCode {
             408 => Some(StatusCode::Timeout),
             413 => Some(StatusCode::TooLarge),
             415 => Some(StatusCode::UnsupportedMediaType),
+            416 => Some(StatusCode::RangeNotSatisfiable),
             500 => Some(StatusCode::InternalServerError),
             501 => Some(StatusCode::NotImplemented),
             502 => Some(StatusCode::BadGateway),
@@ -268,6 +364,19 @@ pub trait HeaderWriter: ErrorType {
     ) -> impl core::future::Future<Output = Result<(), Self::Error>>;
 }
 
+/// Transports that can end a session cleanly
+///
+/// From spec: "All connections must use TLS 1.3 encryption"
+///
+/// For TLS transports this sends a `close_notify` alert, telling the server the session
+/// ended deliberately rather than being truncated. Used by [`GurtClient::close`].
+pub trait Close: Write {
+    /// Flush pending data, then close the write side of the transport
+    ///
+    /// The transport may still be read afterwards until the peer closes its side.
+    fn close(&mut self) -> impl core::future::Future<Output = Result<(), Self::Error>>;
+}
+
 /// GURT Client for making requests
 ///
 /// From spec: "GURT provides a familiar HTTP-like syntax while offering security through
@@ -291,36 +400,60 @@ impl<T: Read + Write> GurtClient<T> {
     /// user-agent: GURT-Client/1.0.0\r\n
     /// \r\n
     /// ```"
-    pub async fn handshake(&mut self, host: &str, user_agent: &str) -> Result<(), T::Error> {
-        // From spec: "METHOD /path GURT/1.0.0\r\n"
-        self.transport.write_all(b"HANDSHAKE / ").await?;
-        self.transport.write_all(GURT_VERSION.as_bytes()).await?;
-        self.transport.write_all(b"\r\n").await?;
-
-        // From spec: "host: example.com\r\n"
-        self.transport.write_all(b"host: ").await?;
-        self.transport.write_all(host.as_bytes()).await?;
-        self.transport.write_all(b"\r\n").await?;
-
-        // From spec: "user-agent: GURT-Client/1.0.0\r\n"
-        self.transport.write_all(b"user-agent: ").await?;
-        self.transport.write_all(user_agent.as_bytes()).await?;
-        self.transport.write_all(b"\r\n").await?;
-
-        // From spec: "Header terminator: `\r\n\r\n`"
-        self.transport.write_all(b"\r\n").await?;
+    pub async fn handshake(
+        &mut self,
+        host: &str,
+        user_agent: &str,
+    ) -> Result<(), GurtError<T::Error>> {
+        let head = RequestHead::new(Method::Handshake, "/", host).user_agent(user_agent);
+        self.send_head(&head, &mut [0; HEAD_BUFFER_SIZE]).await?;
+        Ok(())
+    }
 
+    /// Read the response to [`GurtClient::handshake`], skipping its headers
+    ///
+    /// From spec: "101 SWITCHING_PROTOCOLS - Handshake successful"
+    ///
+    /// Any other status is returned as [`GurtError::HandshakeRejected`].
+    pub async fn read_handshake_response(
+        &mut self,
+        buf: &mut [u8],
+    ) -> Result<(), GurtError<T::Error>> {
+        let mut response = self.response_reader();
+        let status = response.read_status_line(buf).await?.status;
+        while response.read_header(buf).await?.is_some() {}
+        if status != StatusCode::SwitchingProtocols {
+            return log::failed("handshake", Err(GurtError::HandshakeRejected(status)));
+        }
+        debug!("handshake complete");
         Ok(())
     }
 
+    /// End the session, closing the transport's write side
+    ///
+    /// On TLS transports this sends `close_notify`. Responses already in flight can still
+    /// be read afterwards.
+    pub async fn close(&mut self) -> Result<(), GurtError<T::Error>>
+    where
+        T: Close,
+    {
+        self.transport.close().await.map_err(GurtError::Io)
+    }
+
     /// Get a response reader for reading server responses
     pub fn response_reader(&mut self) -> ResponseReader<'_, T> {
         ResponseReader::new(&mut self.transport)
     }
 
+    /// Get a response reader using the given [`ParseMode`]
+    pub fn response_reader_with_mode(&mut self, mode: ParseMode) -> ResponseReader<'_, T> {
+        ResponseReader::with_mode(&mut self.transport, mode)
+    }
+
     /// Send a request without a body
     ///
-    /// Note: Custom headers cannot be passed yet. Only `host` and `user-agent` headers are written.
+    /// Only `host` and `user-agent` headers are written; use [`GurtClient::request`] to pass
+    /// additional headers.
     ///
     /// From spec: "Request Structure:
     /// ```text
@@ -337,36 +470,17 @@ impl<T: Read + Write> GurtClient<T> {
         path: &str,
         host: &str,
         user_agent: Option<&str>,
-    ) -> Result<(), T::Error> {
-        let user_agent = user_agent.unwrap_or("yo-gurt/0.1");
-
-        // From spec: "Method line: `METHOD /path GURT/1.0.0`"
-        self.transport.write_all(method.as_str().as_bytes()).await?;
-        self.transport.write_all(b" ").await?;
-        self.transport.write_all(path.as_bytes()).await?;
-        self.transport.write_all(b" ").await?;
-        self.transport.write_all(GURT_VERSION.as_bytes()).await?;
-        self.transport.write_all(b"\r\n").await?;
-
-        // From spec: "Headers: Lowercase names, colon-separated values"
-        self.transport.write_all(b"host: ").await?;
-        self.transport.write_all(host.as_bytes()).await?;
-        self.transport.write_all(b"\r\n").await?;
-
-        self.transport.write_all(b"user-agent: ").await?;
-        self.transport.write_all(user_agent.as_bytes()).await?;
-        self.transport.write_all(b"\r\n").await?;
-
-        // From spec: "Header terminator: `\r\n\r\n`"
-        self.transport.write_all(b"\r\n").await?;
-
-        Ok(())
+    ) -> Result<(), GurtError<T::Error>> {
+        self.request(method, path, host, user_agent, &[], None)
+            .await?
+            .finish()
+            .await
     }
 
     /// Start a request with a body
     ///
-    /// Note: Custom headers cannot be passed yet. Only `host`, `content-type`, `content-length`,
-    /// and `user-agent` headers are written.
+    /// Only `host`, `content-type`, `content-length`, and `user-agent` headers are written;
+    /// use [`GurtClient::request`] to pass additional headers.
     ///
     /// This writes the request line and headers, allowing the caller to write the body
     /// Returns a RequestBodyWriter that can be used to write the body and finalize the request
@@ -378,81 +492,284 @@ impl<T: Read + Write> GurtClient<T> {
         user_agent: Option<&str>,
         content_type: Option<&str>,
         content_length: usize,
-    ) -> Result<RequestBodyWriter<'a, T>, T::Error> {
-        let user_agent = user_agent.unwrap_or("yo-gurt/0.1");
-
-        // From spec: "Method line: `METHOD /path GURT/1.0.0`"
-        self.transport.write_all(method.as_str().as_bytes()).await?;
-        self.transport.write_all(b" ").await?;
-        self.transport.write_all(path.as_bytes()).await?;
-        self.transport.write_all(b" ").await?;
-        self.transport.write_all(GURT_VERSION.as_bytes()).await?;
-        self.transport.write_all(b"\r\n").await?;
-
-        // Write headers
-        self.transport.write_all(b"host: ").await?;
-        self.transport.write_all(host.as_bytes()).await?;
-        self.transport.write_all(b"\r\n").await?;
-
-        if let Some(content_type) = content_type {
-            self.transport.write_all(b"content-type: ").await?;
-            self.transport.write_all(content_type.as_bytes()).await?;
-            self.transport.write_all(b"\r\n").await?;
+    ) -> Result<RequestBodyWriter<'a, T>, GurtError<T::Error>> {
+        match content_type {
+            Some(content_type) => {
+                self.request(
+                    method,
+                    path,
+                    host,
+                    user_agent,
+                    &[("content-type", content_type)],
+                    Some(content_length),
+                )
+                .await
+            }
+            None => {
+                self.request(method, path, host, user_agent, &[], Some(content_length))
+                    .await
+            }
         }
+    }
 
-        // From spec: "content-length: 123\r\n"
-        self.transport.write_all(b"content-length: ").await?;
-        self.write_usize(content_length).await?;
-        self.transport.write_all(b"\r\n").await?;
+    /// Send a request head with additional headers
+    ///
+    /// Headers are written in order after `host`, followed by `content-length` (when given)
+    /// and `user-agent`.
+    ///
+    /// From spec: "Headers: Lowercase names, colon-separated values"
+    ///
+    /// All input is validated before anything is written: header names must be lowercase
+    /// tokens, values must not contain CR, LF or other control characters, and `host`,
+    /// `user-agent`, `content-length` and `transfer-encoding` cannot be passed in `headers`.
+    ///
+    /// Returns a RequestBodyWriter for the body; when `content_length` is `None` the request
+    /// has no body and the writer can be finished immediately. The request is flushed by
+    /// [`RequestBodyWriter::finish`].
+    pub async fn request<'a>(
+        &'a mut self,
+        method: Method,
+        path: &str,
+        host: &str,
+        user_agent: Option<&str>,
+        headers: &[(&str, &str)],
+        content_length: Option<usize>,
+    ) -> Result<RequestBodyWriter<'a, T>, GurtError<T::Error>> {
+        let mut head = RequestHead::new(method, path, host).headers(headers);
+        if let Some(user_agent) = user_agent {
+            head = head.user_agent(user_agent);
+        }
+        if let Some(content_length) = content_length {
+            head = head.content_length(content_length);
+        }
+        self.send_head(&head, &mut [0; HEAD_BUFFER_SIZE]).await
+    }
 
-        self.transport.write_all(b"user-agent: ").await?;
-        self.transport.write_all(user_agent.as_bytes()).await?;
-        self.transport.write_all(b"\r\n").await?;
+    /// Send a request head, rendering it into `buf` first
+    ///
+    /// When the rendered head fits in `buf` it is written with a single `write_all`, so a
+    /// TLS transport sends it in one record; otherwise it is written piece by piece. Heads
+    /// without a body are flushed straight away; otherwise the head is flushed along with
+    /// the body by [`RequestBodyWriter::finish`].
+    ///
+    /// The head is validated with [`RequestHead::validate`] before anything is written.
+    pub async fn send_head<'a>(
+        &'a mut self,
+        head: &RequestHead<'_>,
+        buf: &mut [u8],
+    ) -> Result<RequestBodyWriter<'a, T>, GurtError<T::Error>> {
+        log::failed("request", head.validate())?;
+        head.log();
+        let encoded = head.encode(buf).map(|len| &buf[..len]);
+        let length = head.body_length().unwrap_or(0);
+        write_head(
+            &mut self.transport,
+            Message::Request,
+            encoded,
+            head.slices(),
+            length,
+        )
+        .await
+    }
+}
 
-        // From spec: "Header terminator: `\r\n\r\n`"
-        self.transport.write_all(b"\r\n").await?;
+impl<T: Write> ErrorType for GurtClient<T>
+where
+    T::Error: 'static,
+{
+    type Error = GurtError<T::Error>;
+}
 
-        Ok(RequestBodyWriter {
-            transport: &mut self.transport,
-        })
+impl<T: Write> HeaderWriter for GurtClient<T>
+where
+    T::Error: 'static,
+{
+    /// Write a single `name: value` header line
+    /// From spec: "header-name: header-value\r\n"
+    async fn write_header(&mut self, name: &str, value: &str) -> Result<(), Self::Error> {
+        validate_header_name(name)?;
+        validate_header_value(value)?;
+        self.transport
+            .write_all(name.as_bytes())
+            .await
+            .map_err(GurtError::Io)?;
+        self.transport
+            .write_all(b": ")
+            .await
+            .map_err(GurtError::Io)?;
+        self.transport
+            .write_all(value.as_bytes())
+            .await
+            .map_err(GurtError::Io)?;
+        self.transport
+            .write_all(b"\r\n")
+            .await
+            .map_err(GurtError::Io)?;
+        Ok(())
     }
+}
 
-    /// Helper to write a usize as ASCII decimal
-    async fn write_usize(&mut self, mut n: usize) -> Result<(), T::Error> {
-        if n == 0 {
-            self.transport.write_all(b"0").await?;
-            return Ok(());
-        }
-
-        let mut buf = [0u8; 20]; // Enough for 64-bit usize
-        let mut i = buf.len();
+/// Which kind of message a head or body belongs to, for reporting invalid input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Message {
+    Request,
+    Response,
+}
 
-        while n > 0 {
-            i -= 1;
-            buf[i] = b'0' + (n % 10) as u8;
-            n /= 10;
+impl Message {
+    /// `e` reported as [`GurtError::InvalidRequest`] or [`GurtError::InvalidResponse`]
+    pub(crate) fn invalid<E>(self, e: RequestError) -> GurtError<E> {
+        match self {
+            Message::Request => GurtError::InvalidRequest(e),
+            Message::Response => GurtError::InvalidResponse(e),
         }
+    }
+}
 
-        self.transport.write_all(&buf[i..]).await?;
-        Ok(())
+/// Write a validated head, returning the writer for its body
+///
+/// The head goes out in one write when it was `encoded` into a buffer, and slice by slice
+/// otherwise. Heads without a body are flushed straight away; otherwise the head is
+/// flushed along with the body by [`RequestBodyWriter::finish`].
+pub(crate) async fn write_head<'a, 's, T: Write>(
+    transport: &'a mut T,
+    message: Message,
+    encoded: Option<&[u8]>,
+    slices: impl Iterator<Item = &'s [u8]>,
+    body_length: usize,
+) -> Result<RequestBodyWriter<'a, T>, GurtError<T::Error>> {
+    let what = match message {
+        Message::Request => "sending request",
+        Message::Response => "sending response",
+    };
+    let written = match encoded {
+        Some(head) => transport.write_all(head).await,
+        None => {
+            let mut result = Ok(());
+            for slice in slices {
+                result = transport.write_all(slice).await;
+                if result.is_err() {
+                    break;
+                }
+            }
+            result
+        }
+    };
+    log::failed(what, written.map_err(GurtError::Io))?;
+    if body_length == 0 {
+        log::failed(what, transport.flush().await.map_err(GurtError::Io))?;
     }
+    Ok(RequestBodyWriter {
+        transport,
+        message,
+        length: body_length,
+        remaining: body_length,
+        progress: NoProgress,
+    })
 }
 
-/// Helper for writing request bodies
-pub struct RequestBodyWriter<'a, T> {
+/// Writer for a request body, and on the server side for a response body
+/// ([`ResponseBodyWriter`](server::ResponseBodyWriter))
+///
+/// Writes are checked against the declared `content-length` so that extra bytes can never
+/// be read by the peer as the start of another message. Bodies that do not match their
+/// length fail with [`GurtError::InvalidRequest`] or, for responses,
+/// [`GurtError::InvalidResponse`]. An observer given with
+/// [`with_progress`](Self::with_progress) is told how much of the body has been written.
+pub struct RequestBodyWriter<'a, T, P = NoProgress> {
     transport: &'a mut T,
+    message: Message,
+    length: usize,
+    remaining: usize,
+    progress: P,
 }
 
-impl<'a, T: Write> RequestBodyWriter<'a, T> {
+impl<'a, T, P> RequestBodyWriter<'a, T, P> {
+    /// Report the body bytes written so far to `progress` after each write
+    pub fn with_progress<Q: Progress>(self, progress: Q) -> RequestBodyWriter<'a, T, Q> {
+        RequestBodyWriter {
+            transport: self.transport,
+            message: self.message,
+            length: self.length,
+            remaining: self.remaining,
+            progress,
+        }
+    }
+
+    /// `e` as an error of the message this body belongs to
+    pub(crate) fn invalid<E>(&self, e: RequestError) -> GurtError<E> {
+        self.message.invalid(e)
+    }
+}
+
+impl<'a, T: Write, P: Progress> RequestBodyWriter<'a, T, P> {
     /// Write body data
     /// From spec: "[message body]"
-    pub async fn write(&mut self, data: &[u8]) -> Result<(), T::Error> {
-        self.transport.write_all(data).await
+    pub async fn write(&mut self, data: &[u8]) -> Result<(), GurtError<T::Error>> {
+        if data.len() > self.remaining {
+            return log::failed("writing body", Err(self.invalid(RequestError::BodyTooLong)));
+        }
+        let written = self.transport.write_all(data).await.map_err(GurtError::Io);
+        log::failed("writing body", written)?;
+        self.remaining -= data.len();
+        if !data.is_empty() {
+            self.progress
+                .progress(self.length - self.remaining, self.length);
+        }
+        Ok(())
     }
 
-    /// Consume the writer (body is complete)
-    pub fn finish(self) {
-        // Body is written, nothing else to do
+    /// Write the rest of the body from `source`, a chunk of up to `buf.len()` bytes at a
+    /// time
+    ///
+    /// Exactly [`remaining`](Self::remaining) bytes are read from `source`. Fails with
+    /// [`RequestError::BodyIncomplete`] if it ends before then, and with
+    /// [`Limit::BufferTooSmall`] if `buf` is empty while bytes remain.
+    pub async fn write_from<R>(
+        &mut self,
+        source: &mut R,
+        buf: &mut [u8],
+    ) -> Result<(), GurtError<T::Error>>
+    where
+        R: Read<Error = T::Error>,
+    {
+        while self.remaining > 0 {
+            let len = buf.len().min(self.remaining);
+            if len == 0 {
+                return Err(Limit::BufferTooSmall.into());
+            }
+            let n = source.read(&mut buf[..len]).await.map_err(GurtError::Io)?;
+            if n == 0 {
+                return Err(self.invalid(RequestError::BodyIncomplete));
+            }
+            self.write(&buf[..n]).await?;
+        }
+        Ok(())
+    }
+
+    /// Number of declared body bytes not yet written
+    pub fn remaining(&self) -> usize {
+        self.remaining
+    }
+
+    /// Complete the request, flushing the transport
+    ///
+    /// Fails with [`RequestError::BodyIncomplete`] if fewer bytes were written than the
+    /// declared `content-length`; the server would otherwise wait for the rest.
+    pub async fn finish(self) -> Result<(), GurtError<T::Error>> {
+        if self.remaining != 0 {
+            return log::failed(
+                "writing body",
+                Err(self.invalid(RequestError::BodyIncomplete)),
+            );
+        }
+        if self.length != 0 {
+            debug!("body sent, {} bytes", self.length);
+        }
+        log::failed(
+            "writing body",
+            self.transport.flush().await.map_err(GurtError::Io),
+        )
     }
 }
 
@@ -460,6 +777,7 @@ impl<'a, T: Write> RequestBodyWriter<'a, T> {
 ///
 /// Contains the parsed status code and the total number of bytes read
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct StatusLineResult {
     /// The parsed status code
     pub status: StatusCode,
@@ -471,6 +789,7 @@ pub struct StatusLineResult {
 ///
 /// Contains the positions and lengths of the header name and value in the buffer
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct HeaderResult {
     /// Length of the header name in bytes
     pub name_len: usize,
@@ -482,6 +801,18 @@ pub struct HeaderResult {
     pub total_bytes: usize,
 }
 
+impl HeaderResult {
+    /// Header name as read into `buf`
+    pub fn name<'b>(&self, buf: &'b [u8]) -> &'b [u8] {
+        &buf[..self.name_len]
+    }
+
+    /// Header value as read into `buf`
+    pub fn value<'b>(&self, buf: &'b [u8]) -> &'b [u8] {
+        &buf[self.value_start..self.value_start + self.value_len]
+    }
+}
+
 /// Response reader for parsing GURT responses
 ///
 /// From spec: "Response Structure:
@@ -494,122 +825,281 @@ pub struct HeaderResult {
 /// \r\n
 /// [response body]
 /// ```"
+///
+/// The reader tracks `content-length` across header lines and rejects ambiguous message
+/// framing (see [`ParseMode`]), so the body boundary it reports can be trusted by proxies
+/// and other intermediaries.
 pub struct ResponseReader<'a, T> {
     transport: &'a mut T,
+    mode: ParseMode,
+    content_length: Option<usize>,
+    content_encoding: Option<ContentEncoding>,
+    content_range: Option<ContentRange>,
+}
+
+/// How strictly [`ResponseReader`] validates message framing
+///
+/// Both modes reject invalid `content-length` values, conflicting lengths, obsolete line
+/// folding and `transfer-encoding` (GURT has no transfer codings).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ParseMode {
+    /// Reject anything that could be read two ways: repeated `content-length` headers or
+    /// lists (even with equal values) and whitespace between a header name and the colon
+    #[default]
+    Strict,
+    /// Accept repeated `content-length` values if they all agree, and strip whitespace
+    /// between a header name and the colon
+    Lenient,
 }
 
 impl<'a, T: Read> ResponseReader<'a, T> {
-    /// Create a new response reader
+    /// Create a new response reader using [`ParseMode::Strict`]
     pub fn new(transport: &'a mut T) -> Self {
-        Self { transport }
+        Self::with_mode(transport, ParseMode::Strict)
+    }
+
+    /// Create a new response reader with the given parse mode
+    pub fn with_mode(transport: &'a mut T, mode: ParseMode) -> Self {
+        Self {
+            transport,
+            mode,
+            content_length: None,
+            content_encoding: Some(ContentEncoding::Identity),
+            content_range: None,
+        }
+    }
+
+    /// The validated `content-length` of the current response, once its header was read
+    pub fn content_length(&self) -> Option<usize> {
+        self.content_length
+    }
+
+    /// The `content-encoding` of the current response's body
+    ///
+    /// [`ContentEncoding::Identity`] until a `content-encoding` header is read, and `None`
+    /// if the header names an unknown encoding or several applied in turn.
+    pub fn content_encoding(&self) -> Option<ContentEncoding> {
+        self.content_encoding
+    }
+
+    /// The `content-range` of the current response, once its header was read
+    ///
+    /// `None` if there is no such header or it is not a valid byte range; see [`range`].
+    pub fn content_range(&self) -> Option<ContentRange> {
+        self.content_range
     }
 
     /// Read response status line
     /// From spec: "Status line: `GURT/1.0.0 <code> <message>`"
     ///
+    /// The code must be three digits naming a known [`StatusCode`]; the message may be
+    /// omitted but must not contain control characters.
+    ///
     /// Returns a StatusLineResult containing the parsed status code and bytes read
     pub async fn read_status_line(
         &mut self,
         buf: &mut [u8],
-    ) -> Result<StatusLineResult, ResponseError<T::Error>> {
-        let mut pos = 0;
+    ) -> Result<StatusLineResult, GurtError<T::Error>> {
+        let line = log::failed("reading status line", self.parse_status_line(buf).await)?;
+        debug!("response {:?}", line.status);
+        Ok(line)
+    }
 
-        // Read until we find \r\n
-        loop {
-            if pos >= buf.len() {
-                return Err(ResponseError::BufferTooSmall);
-            }
+    async fn parse_status_line(
+        &mut self,
+        buf: &mut [u8],
+    ) -> Result<StatusLineResult, GurtError<T::Error>> {
+        let len = self.read_line(buf).await?;
+        let line = &buf[..len];
+
+        // Parse: "GURT/1.0.0 200 OK"
+        let (version, rest) = match line.iter().position(|&b| b == b' ') {
+            Some(sp) => (&line[..sp], &line[sp + 1..]),
+            None => (line, &[][..]),
+        };
+
+        // Verify protocol version
+        if version != GURT_VERSION.as_bytes() {
+            return Err(ProtocolError::InvalidProtocol.into());
+        }
 
-            self.transport
-                .read_exact(&mut buf[pos..pos + 1])
-                .await
-                .map_err(|e| match e {
-                    ReadExactError::UnexpectedEof => ResponseError::UnexpectedEof,
-                    ReadExactError::Other(e) => ResponseError::Io(e),
-                })?;
+        // Parse status code: exactly three digits, then the end of line or a space
+        let (code_bytes, reason) = match rest.get(3) {
+            None => (rest, &[][..]),
+            Some(b' ') => (&rest[..3], &rest[4..]),
+            Some(_) => return Err(ProtocolError::InvalidStatusLine.into()),
+        };
+        if code_bytes.len() != 3 || !reason.iter().all(|&b| is_field_char(b)) {
+            return Err(ProtocolError::InvalidStatusLine.into());
+        }
+        let code = parse_u16(code_bytes).ok_or(ProtocolError::InvalidStatusLine)?;
+        let status_code = StatusCode::from_u16(code).ok_or(ProtocolError::InvalidStatusLine)?;
+
+        self.content_length = None;
+        self.content_encoding = Some(ContentEncoding::Identity);
+        self.content_range = None;
+        Ok(StatusLineResult {
+            status: status_code,
+            bytes_read: len + 2,
+        })
+    }
+
+    /// Read a single header line
+    /// From spec: "header-name: header-value\r\n"
+    ///
+    /// The name must be a non-empty token; whitespace around the value is not part of it.
+    ///
+    /// Returns Some(HeaderResult) with header information, or None if end of headers
+    pub async fn read_header(
+        &mut self,
+        buf: &mut [u8],
+    ) -> Result<Option<HeaderResult>, GurtError<T::Error>> {
+        let header = log::failed("reading header", self.parse_header(buf).await)?;
+        if let Some(header) = &header {
+            trace!(
+                "{:?}",
+                log::Header::new(header.name(buf), header.value(buf))
+            );
+        }
+        Ok(header)
+    }
+
+    async fn parse_header(
+        &mut self,
+        buf: &mut [u8],
+    ) -> Result<Option<HeaderResult>, GurtError<T::Error>> {
+        let len = self.read_line(buf).await?;
+        if len == 0 {
+            // Empty line (just \r\n) means end of headers
+            return Ok(None);
+        }
 
-            if pos > 0 && buf[pos - 1] == b'\r' && buf[pos] == b'\n' {
-                // Found end of line
-                let line = &buf[..pos - 1]; // Exclude \r\n
+        let line = &buf[..len];
 
-                // Parse: "GURT/1.0.0 200 OK"
-                let mut parts = line.split(|&b| b == b' ');
+        // A line starting with whitespace continues the previous header (RFC 9112
+        // section 5.2); it would be read as part of the value by some parsers only
+        if is_whitespace(line[0]) {
+            return Err(ProtocolError::ObsoleteLineFolding.into());
+        }
 
-                // Verify protocol version
-                if parts.next() != Some(GURT_VERSION.as_bytes()) {
-                    return Err(ResponseError::InvalidProtocol);
-                }
+        // Parse "name: value"
+        let colon_pos = line
+            .iter()
+            .position(|&b| b == b':')
+            .ok_or(ProtocolError::InvalidHeader)?;
+        let mut name_len = colon_pos;
+        while name_len > 0 && is_whitespace(line[name_len - 1]) {
+            name_len -= 1;
+        }
+        if name_len != colon_pos && self.mode == ParseMode::Strict {
+            return Err(ProtocolError::WhitespaceBeforeColon.into());
+        }
+        let name = &line[..name_len];
+        if name.is_empty() {
+            return Err(ProtocolError::EmptyHeaderName.into());
+        }
+        if !name.iter().all(|&b| is_token_char(b)) {
+            return Err(ProtocolError::InvalidHeaderName.into());
+        }
+
+        // Skip whitespace around the value
+        let mut value_start = colon_pos + 1;
+        while value_start < len && is_whitespace(line[value_start]) {
+            value_start += 1;
+        }
+        let mut value_end = len;
+        while value_end > value_start && is_whitespace(line[value_end - 1]) {
+            value_end -= 1;
+        }
+        if !line[value_start..value_end]
+            .iter()
+            .all(|&b| is_field_char(b))
+        {
+            return Err(ProtocolError::InvalidHeaderValue.into());
+        }
+
+        // From spec: "content-length: 123\r\n"
+        let value = &line[value_start..value_end];
+        if name.eq_ignore_ascii_case(b"content-length") {
+            self.record_content_length(value)?;
+        } else if name.eq_ignore_ascii_case(b"transfer-encoding") {
+            return Err(ProtocolError::TransferEncodingNotAllowed.into());
+        } else if name.eq_ignore_ascii_case(b"content-encoding") {
+            // Repeated headers list encodings applied in turn; only one is supported
+            self.content_encoding =
+                match (self.content_encoding, ContentEncoding::from_bytes(value)) {
+                    (Some(ContentEncoding::Identity), encoding) => encoding,
+                    (encoding, Some(ContentEncoding::Identity)) => encoding,
+                    _ => None,
+                };
+        } else if name.eq_ignore_ascii_case(b"content-range") {
+            self.content_range = core::str::from_utf8(value)
+                .ok()
+                .and_then(ContentRange::parse);
+        }
 
-                // Parse status code
-                let code_bytes = parts.next().ok_or(ResponseError::InvalidStatusLine)?;
-                let code = parse_u16(code_bytes).ok_or(ResponseError::InvalidStatusLine)?;
-                let status_code =
-                    StatusCode::from_u16(code).ok_or(ResponseError::InvalidStatusLine)?;
+        Ok(Some(HeaderResult {
+            name_len,
+            value_start,
+            value_len: value_end - value_start,
+            total_bytes: len + 2,
+        }))
+    }
 
-                return Ok(StatusLineResult {
-                    status: status_code,
-                    bytes_read: pos + 1,
-                });
+    /// Validate a `content-length` value against any seen earlier in the response
+    fn record_content_length(&mut self, value: &[u8]) -> Result<(), GurtError<T::Error>> {
+        let mut length = None;
+        for item in value.split(|&b| b == b',') {
+            let item = parse_content_length(item.trim_ascii())
+                .ok_or(ProtocolError::InvalidContentLength)?;
+            if length.is_some() && self.mode == ParseMode::Strict {
+                return Err(ProtocolError::DuplicateContentLength.into());
+            }
+            if length.is_some_and(|length| length != item) {
+                return Err(ProtocolError::ConflictingContentLength.into());
             }
+            length = Some(item);
+        }
+        let length = length.ok_or(ProtocolError::InvalidContentLength)?;
+        if length > MAX_MESSAGE_SIZE {
+            return Err(Limit::MessageTooLarge.into());
+        }
 
-            pos += 1;
+        match self.content_length {
+            None => self.content_length = Some(length),
+            Some(_) if self.mode == ParseMode::Strict => {
+                return Err(ProtocolError::DuplicateContentLength.into());
+            }
+            Some(previous) if previous != length => {
+                return Err(ProtocolError::ConflictingContentLength.into());
+            }
+            Some(_) => {}
         }
+        Ok(())
     }
 
-    /// Read a single header line
-    /// From spec: "header-name: header-value\r\n"
+    /// Read one CRLF-terminated line into `buf`, returning its length without the CRLF
     ///
-    /// Returns Some(HeaderResult) with header information, or None if end of headers
-    pub async fn read_header(
-        &mut self,
-        buf: &mut [u8],
-    ) -> Result<Option<HeaderResult>, ResponseError<T::Error>> {
+    /// From spec: "Messages use CRLF (`\r\n`) line endings"
+    async fn read_line(&mut self, buf: &mut [u8]) -> Result<usize, GurtError<T::Error>> {
         let mut pos = 0;
 
         // Read until we find \r\n
         loop {
             if pos >= buf.len() {
-                return Err(ResponseError::BufferTooSmall);
+                return Err(Limit::BufferTooSmall.into());
             }
 
-            self.transport
-                .read_exact(&mut buf[pos..pos + 1])
-                .await
-                .map_err(|e| match e {
-                    ReadExactError::UnexpectedEof => ResponseError::UnexpectedEof,
-                    ReadExactError::Other(e) => ResponseError::Io(e),
-                })?;
-
-            if pos > 0 && buf[pos - 1] == b'\r' && buf[pos] == b'\n' {
-                // Found end of line
-                if pos == 1 {
-                    // Empty line (just \r\n) means end of headers
-                    return Ok(None);
-                }
+            self.transport.read_exact(&mut buf[pos..pos + 1]).await?;
 
-                let line = &buf[..pos - 1]; // Exclude \r\n
-
-                // Parse "name: value"
-                if let Some(colon_pos) = line.iter().position(|&b| b == b':') {
-                    let name_len = colon_pos;
-                    let value_start = colon_pos + 1;
-                    // Skip leading space after colon
-                    let value_start = if value_start < line.len() && line[value_start] == b' ' {
-                        value_start + 1
-                    } else {
-                        value_start
-                    };
-                    let value_len = line.len() - value_start;
-
-                    return Ok(Some(HeaderResult {
-                        name_len,
-                        value_start,
-                        value_len,
-                        total_bytes: pos + 1,
-                    }));
-                } else {
-                    return Err(ResponseError::InvalidHeader);
-                }
+            let after_cr = pos > 0 && buf[pos - 1] == b'\r';
+            match buf[pos] {
+                b'\n' if after_cr => return Ok(pos - 1),
+                // A bare LF, or a CR not followed by LF
+                b'\n' => return Err(ProtocolError::InvalidLineEnding.into()),
+                _ if after_cr => return Err(ProtocolError::InvalidLineEnding.into()),
+                _ => {}
             }
 
             pos += 1;
@@ -618,34 +1108,165 @@ impl<'a, T: Read> ResponseReader<'a, T> {
 
     /// Read response body
     /// From spec: "[response body]"
-    pub async fn read_body(&mut self, buf: &mut [u8]) -> Result<usize, T::Error> {
-        self.transport.read(buf).await
+    pub async fn read_body(&mut self, buf: &mut [u8]) -> Result<usize, GurtError<T::Error>> {
+        self.transport.read(buf).await.map_err(GurtError::Io)
     }
 
     /// Read exact amount of body data
-    pub async fn read_body_exact(
-        &mut self,
-        buf: &mut [u8],
-    ) -> Result<(), ReadExactError<T::Error>> {
-        self.transport.read_exact(buf).await
-    }
-}
-
-/// Response parsing errors
-#[derive(Debug)]
-pub enum ResponseError<E> {
-    /// IO error from transport
-    Io(E),
-    /// Unexpected end of file
-    UnexpectedEof,
-    /// Buffer too small for response
-    BufferTooSmall,
-    /// Invalid protocol version
-    InvalidProtocol,
-    /// Invalid status line
-    InvalidStatusLine,
-    /// Invalid header format
-    InvalidHeader,
+    pub async fn read_body_exact(&mut self, buf: &mut [u8]) -> Result<(), GurtError<T::Error>> {
+        Ok(self.transport.read_exact(buf).await?)
+    }
+
+    /// Read the remaining response as a body of `content_length` bytes
+    /// From spec: "content-length: 123\r\n"
+    pub fn body(self, content_length: usize) -> BodyReader<'a, T> {
+        BodyReader::new(self.transport, content_length)
+    }
+
+    /// Read the remaining response as a body of `content_length` bytes, decoded according
+    /// to its `content-encoding`
+    ///
+    /// Fails with [`ProtocolError::UnsupportedContentEncoding`] if the encoding is unknown
+    /// or its feature is not enabled. Reading fails with [`Limit::MessageTooLarge`] once
+    /// the decoded body exceeds [`MAX_MESSAGE_SIZE`]. An empty body is always accepted.
+    #[cfg(feature = "alloc")]
+    pub fn decoded_body(
+        self,
+        content_length: usize,
+    ) -> Result<DecodedBody<'a, T>, GurtError<T::Error>> {
+        let encoding = match content_length {
+            0 => ContentEncoding::Identity,
+            _ => self
+                .content_encoding
+                .ok_or(ProtocolError::UnsupportedContentEncoding)?,
+        };
+        let decoder = Decoder::new(encoding).ok_or(ProtocolError::UnsupportedContentEncoding)?;
+        Ok(DecodedBody::new(self.body(content_length), decoder))
+    }
+}
+
+/// Reader for a response body delimited by `content-length`
+///
+/// Reads never go past the end of the body, so the transport is left positioned at the
+/// next response once the body has been fully consumed. An observer given with
+/// [`with_progress`](Self::with_progress) is told how much of the body has been read.
+pub struct BodyReader<'a, T, P = NoProgress> {
+    transport: &'a mut T,
+    length: usize,
+    remaining: usize,
+    progress: P,
+}
+
+impl<'a, T> BodyReader<'a, T> {
+    pub(crate) fn new(transport: &'a mut T, length: usize) -> Self {
+        Self {
+            transport,
+            length,
+            remaining: length,
+            progress: NoProgress,
+        }
+    }
+}
+
+impl<'a, T, P> BodyReader<'a, T, P> {
+    /// Report the body bytes read so far to `progress` after each read
+    pub fn with_progress<Q: Progress>(self, progress: Q) -> BodyReader<'a, T, Q> {
+        BodyReader {
+            transport: self.transport,
+            length: self.length,
+            remaining: self.remaining,
+            progress,
+        }
+    }
+
+    /// Number of body bytes not yet read
+    pub fn remaining(&self) -> usize {
+        self.remaining
+    }
+
+    /// Whether the whole body has been read
+    pub fn is_done(&self) -> bool {
+        self.remaining == 0
+    }
+}
+
+impl<'a, T, P: Progress> BodyReader<'a, T, P> {
+    /// Count `n` bytes as read from the transport
+    pub(crate) fn advance(&mut self, n: usize) {
+        self.remaining -= n;
+        if self.remaining == 0 {
+            debug!("body received, {} bytes", self.length);
+        }
+        self.progress
+            .progress(self.length - self.remaining, self.length);
+    }
+}
+
+impl<'a, T: ErrorType, P> ErrorType for BodyReader<'a, T, P>
+where
+    T::Error: 'static,
+{
+    type Error = GurtError<T::Error>;
+}
+
+impl<'a, T: Read, P: Progress> Read for BodyReader<'a, T, P>
+where
+    T::Error: 'static,
+{
+    /// Read body data
+    ///
+    /// Returns `Ok(0)` at the end of the body, and [`GurtError::UnexpectedEof`] if the
+    /// transport reaches end of file first.
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let len = buf.len().min(self.remaining);
+        if len == 0 {
+            return Ok(0);
+        }
+        let n = self.transport.read(&mut buf[..len]).await;
+        let n = log::failed("reading body", n.map_err(GurtError::Io))?;
+        if n == 0 {
+            return log::failed("reading body", Err(GurtError::UnexpectedEof));
+        }
+        self.advance(n);
+        Ok(n)
+    }
+}
+
+/// Parse a `content-length` header value
+///
+/// Only ASCII digits are accepted: no sign, whitespace or list syntax.
+pub fn parse_content_length(value: &[u8]) -> Option<usize> {
+    if value.is_empty() {
+        return None;
+    }
+    let mut result = 0usize;
+    for &b in value {
+        if !b.is_ascii_digit() {
+            return None;
+        }
+        result = result.checked_mul(10)?;
+        result = result.checked_add((b - b'0') as usize)?;
+    }
+    Some(result)
+}
+
+/// Token characters allowed in header names (RFC 9110 `tchar`)
+pub(crate) const fn is_token_char(b: u8) -> bool {
+    matches!(b,
+        b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9'
+        | b'!' | b'#' | b'$' | b'%' | b'&' | b'\'' | b'*'
+        | b'+' | b'-' | b'.' | b'^' | b'_' | b'`' | b'|' | b'~')
+}
+
+/// Characters allowed in header values and reason phrases: visible ASCII, space, tab
+/// and non-ASCII bytes
+pub(crate) const fn is_field_char(b: u8) -> bool {
+    matches!(b, b'\t' | b' '..=b'~' | 0x80..=0xff)
+}
+
+/// Optional whitespace around header values
+const fn is_whitespace(b: u8) -> bool {
+    matches!(b, b' ' | b'\t')
 }
 
 /// Parse a u16 from ASCII bytes
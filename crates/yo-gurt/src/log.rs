@@ -0,0 +1,141 @@
+//! Log points, forwarded to `tracing` and `defmt` when those features are enabled
+//!
+//! The macros take a format string and arguments understood by both: `{}` for strings
+//! and integers, `{:?}` for everything else, which must implement both `Debug` and
+//! `defmt::Format`. Without either feature they compile to nothing.
+
+use core::fmt;
+
+use crate::GurtError;
+
+macro_rules! log {
+    ($level:ident, $s:literal $(, $x:expr)* $(,)?) => {{
+        #[cfg(feature = "tracing")]
+        ::tracing::$level!($s $(, $x)*);
+        #[cfg(feature = "defmt")]
+        ::defmt::$level!($s $(, $x)*);
+        #[cfg(not(any(feature = "tracing", feature = "defmt")))]
+        let _ = ($(&$x,)*);
+    }};
+}
+
+macro_rules! trace {
+    ($($arg:tt)*) => { log!(trace, $($arg)*) };
+}
+
+macro_rules! debug {
+    ($($arg:tt)*) => { log!(debug, $($arg)*) };
+}
+
+macro_rules! warn {
+    ($($arg:tt)*) => { log!(warn, $($arg)*) };
+}
+
+/// Headers whose values are not logged unless configured otherwise
+pub const DEFAULT_REDACTED_HEADERS: &[&str] = &[
+    "authorization",
+    "cookie",
+    "proxy-authorization",
+    "set-cookie",
+];
+
+#[cfg(feature = "tracing")]
+pub(crate) use crate::trace::is_redacted;
+
+/// Whether the value of header `name` is left out of logs
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn is_redacted(name: &[u8]) -> bool {
+    DEFAULT_REDACTED_HEADERS
+        .iter()
+        .any(|redacted| name.eq_ignore_ascii_case(redacted.as_bytes()))
+}
+
+/// Bytes of a message as logged, as text where they are UTF-8
+pub(crate) struct Bytes<'a>(pub &'a [u8]);
+
+impl fmt::Debug for Bytes<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match core::str::from_utf8(self.0) {
+            Ok(text) => f.write_str(text),
+            Err(_) => write!(f, "{:?}", self.0),
+        }
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for Bytes<'_> {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        defmt::write!(f, "{=[u8]:a}", self.0)
+    }
+}
+
+/// A header line as logged, with its value hidden if [`is_redacted`]
+pub(crate) struct Header<'a> {
+    name: &'a [u8],
+    value: &'a [u8],
+}
+
+impl<'a> Header<'a> {
+    pub(crate) fn new(name: &'a [u8], value: &'a [u8]) -> Self {
+        Self { name, value }
+    }
+}
+
+impl fmt::Debug for Header<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match is_redacted(self.name) {
+            true => write!(f, "{:?}: <redacted>", Bytes(self.name)),
+            false => write!(f, "{:?}: {:?}", Bytes(self.name), Bytes(self.value)),
+        }
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for Header<'_> {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        match is_redacted(self.name) {
+            true => defmt::write!(f, "{=[u8]:a}: <redacted>", self.name),
+            false => defmt::write!(f, "{=[u8]:a}: {=[u8]:a}", self.name, self.value),
+        }
+    }
+}
+
+/// A [`GurtError`] as logged; `defmt` shows the transport error's
+/// [`ErrorKind`](embedded_io_async::ErrorKind), since the error itself may not be
+/// formattable
+pub(crate) struct Error<'a, E>(pub &'a GurtError<E>);
+
+impl<E: fmt::Debug> fmt::Debug for Error<'_, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl<E: embedded_io_async::Error> defmt::Format for Error<'_, E> {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        let error = match self.0 {
+            GurtError::Io(e) => GurtError::Io(e.kind()),
+            GurtError::UnexpectedEof => GurtError::UnexpectedEof,
+            GurtError::Protocol(e) => GurtError::Protocol(*e),
+            GurtError::LimitExceeded(e) => GurtError::LimitExceeded(*e),
+            GurtError::Timeout => GurtError::Timeout,
+            GurtError::InvalidRequest(e) => GurtError::InvalidRequest(*e),
+            GurtError::InvalidResponse(e) => GurtError::InvalidResponse(*e),
+            GurtError::HandshakeRejected(status) => GurtError::HandshakeRejected(*status),
+            GurtError::PinMismatch(pin) => GurtError::PinMismatch(*pin),
+        };
+        defmt::write!(f, "{}", error)
+    }
+}
+
+/// Log `result` if it is an error from `what`, and pass it on
+pub(crate) fn failed<T, E: embedded_io_async::Error>(
+    what: &str,
+    result: Result<T, GurtError<E>>,
+) -> Result<T, GurtError<E>> {
+    if let Err(e) = &result {
+        warn!("{} failed: {:?}", what, Error(e));
+    }
+    result
+}
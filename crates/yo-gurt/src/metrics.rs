@@ -0,0 +1,131 @@
+//! Metrics recorded through the [`metrics`](https://docs.rs/metrics) facade
+//!
+//! With the `metrics` feature, the fetch [`Client`](crate::fetch::Client) records the
+//! `gurt_client_*` metrics named here, and `yo-gurt-server` with its own `metrics` feature
+//! records the `gurt_server_*` ones. Nothing is kept until the application installs a
+//! recorder, such as the Prometheus exporter behind `yo-gurt-server`'s `/metrics`
+//! endpoint; [`describe`] adds units and help texts to it.
+//!
+//! Requests are counted with a `method` label (`GET`, `POST`, ...) and a `status` label
+//! holding the status class, `2xx` to `5xx` (`1xx` for `HANDSHAKE`), or `error` when no
+//! response was received or sent. Durations are histograms in seconds, kept apart for
+//! the two phases of a connection:
+//!
+//! - handshakes, from opening the connection through TLS and the GURT `HANDSHAKE`
+//! - requests, on the client from sending the request to reading the response head, and
+//!   on the server from reading the request line to sending the whole response
+//!
+//! Byte counters count message bodies as sent on the wire, before any
+//! `content-encoding` is decoded. The client's idle pool is reported per `host` (as
+//! `host:port`), next to [`MAX_CONNECTION_POOL_SIZE`], the most one host can hold.
+
+use crate::{MAX_CONNECTION_POOL_SIZE, StatusCode};
+
+/// Counter of requests sent, by `method` and `status`
+pub const CLIENT_REQUESTS: &str = "gurt_client_requests_total";
+/// Histogram of request durations, by `method`
+pub const CLIENT_REQUEST_DURATION: &str = "gurt_client_request_duration_seconds";
+/// Histogram of handshake durations for new connections
+pub const CLIENT_HANDSHAKE_DURATION: &str = "gurt_client_handshake_duration_seconds";
+/// Counter of connections that failed before completing the handshake
+pub const CLIENT_HANDSHAKE_FAILURES: &str = "gurt_client_handshake_failures_total";
+/// Counter of request body bytes sent
+pub const CLIENT_SENT_BYTES: &str = "gurt_client_sent_bytes_total";
+/// Counter of response body bytes received
+pub const CLIENT_RECEIVED_BYTES: &str = "gurt_client_received_bytes_total";
+/// Gauge of idle pooled connections, by `host`
+pub const CLIENT_POOL_IDLE: &str = "gurt_client_pool_idle_connections";
+/// Gauge of the idle connections the pool keeps per host, [`MAX_CONNECTION_POOL_SIZE`]
+pub const CLIENT_POOL_CAPACITY: &str = "gurt_client_pool_max_idle_connections";
+
+/// Counter of requests answered, by `method` and `status`
+pub const SERVER_REQUESTS: &str = "gurt_server_requests_total";
+/// Histogram of request durations, by `method`
+pub const SERVER_REQUEST_DURATION: &str = "gurt_server_request_duration_seconds";
+/// Histogram of handshake durations for accepted connections
+pub const SERVER_HANDSHAKE_DURATION: &str = "gurt_server_handshake_duration_seconds";
+/// Counter of connections that failed before completing the handshake
+pub const SERVER_HANDSHAKE_FAILURES: &str = "gurt_server_handshake_failures_total";
+/// Counter of request body bytes received
+pub const SERVER_RECEIVED_BYTES: &str = "gurt_server_received_bytes_total";
+/// Counter of response body bytes sent
+pub const SERVER_SENT_BYTES: &str = "gurt_server_sent_bytes_total";
+/// Gauge of open connections
+pub const SERVER_CONNECTIONS: &str = "gurt_server_open_connections";
+
+/// The `status` label for `status`: its class, such as `2xx`
+pub fn status_class(status: StatusCode) -> &'static str {
+    match status.as_u16() {
+        100..=199 => "1xx",
+        200..=299 => "2xx",
+        300..=399 => "3xx",
+        400..=499 => "4xx",
+        _ => "5xx",
+    }
+}
+
+/// Register units and descriptions for every metric with the installed recorder
+///
+/// Also sets [`CLIENT_POOL_CAPACITY`], which never changes.
+pub fn describe() {
+    use ::metrics::{Unit, describe_counter, describe_gauge, describe_histogram, gauge};
+
+    describe_counter!(CLIENT_REQUESTS, "GURT requests sent");
+    describe_histogram!(
+        CLIENT_REQUEST_DURATION,
+        Unit::Seconds,
+        "Time from sending a GURT request to reading the response head"
+    );
+    describe_histogram!(
+        CLIENT_HANDSHAKE_DURATION,
+        Unit::Seconds,
+        "Time to connect, including TLS and the GURT handshake"
+    );
+    describe_counter!(
+        CLIENT_HANDSHAKE_FAILURES,
+        "GURT connections that failed to handshake"
+    );
+    describe_counter!(
+        CLIENT_SENT_BYTES,
+        Unit::Bytes,
+        "GURT request body bytes sent"
+    );
+    describe_counter!(
+        CLIENT_RECEIVED_BYTES,
+        Unit::Bytes,
+        "GURT response body bytes received"
+    );
+    describe_gauge!(CLIENT_POOL_IDLE, "Idle pooled GURT connections");
+    describe_gauge!(
+        CLIENT_POOL_CAPACITY,
+        "Idle GURT connections pooled per host at most"
+    );
+    gauge!(CLIENT_POOL_CAPACITY).set(MAX_CONNECTION_POOL_SIZE as f64);
+
+    describe_counter!(SERVER_REQUESTS, "GURT requests answered");
+    describe_histogram!(
+        SERVER_REQUEST_DURATION,
+        Unit::Seconds,
+        "Time from reading a GURT request line to sending the whole response"
+    );
+    describe_histogram!(
+        SERVER_HANDSHAKE_DURATION,
+        Unit::Seconds,
+        "Time to accept a connection, including TLS and the GURT handshake"
+    );
+    describe_counter!(
+        SERVER_HANDSHAKE_FAILURES,
+        "GURT connections that failed to handshake"
+    );
+    describe_counter!(
+        SERVER_RECEIVED_BYTES,
+        Unit::Bytes,
+        "GURT request body bytes received"
+    );
+    describe_counter!(
+        SERVER_SENT_BYTES,
+        Unit::Bytes,
+        "GURT response body bytes sent"
+    );
+    describe_gauge!(SERVER_CONNECTIONS, "Open GURT server connections");
+}
@@ -0,0 +1,32 @@
+//! Progress of body transfers, for progress bars on large uploads and downloads
+//!
+//! [`RequestBodyWriter`](crate::RequestBodyWriter) and [`BodyReader`](crate::BodyReader)
+//! take an optional observer with `with_progress`, which is told the number of body bytes
+//! transferred so far and the declared `content-length` after every write or read. Any
+//! `FnMut(usize, usize)` closure is an observer. Observers are held by value in the
+//! writer or reader, so nothing is allocated or boxed; without one, the default
+//! [`NoProgress`] compiles away.
+//!
+//! Counts are bytes on the wire: a [`DecodedBody`](crate::encoding::DecodedBody) reports
+//! the encoded bytes read against the encoded length, not the decoded output.
+
+/// Observer of a body transfer
+pub trait Progress {
+    /// Called after each write or read with the body bytes `transferred` so far, out of
+    /// `total`, the declared `content-length`
+    fn progress(&mut self, transferred: usize, total: usize);
+}
+
+impl<F: FnMut(usize, usize)> Progress for F {
+    fn progress(&mut self, transferred: usize, total: usize) {
+        self(transferred, total)
+    }
+}
+
+/// The observer that ignores progress, used when none is given
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NoProgress;
+
+impl Progress for NoProgress {
+    fn progress(&mut self, _transferred: usize, _total: usize) {}
+}
@@ -0,0 +1,150 @@
+//! Byte ranges: `range` requests and `content-range` responses
+//!
+//! A client asks for part of a resource with a `range: bytes=...` header, written by
+//! [`ByteRange`]'s `Display`. A server able to serve it answers `206 PARTIAL_CONTENT`
+//! with a `content-range` naming the bytes sent and the full length, which
+//! [`ResponseReader::content_range`](crate::ResponseReader::content_range) parses; a
+//! range past the end is answered with `416 RANGE_NOT_SATISFIABLE` and
+//! `content-range: bytes */<length>`. A server may also ignore the range and send the
+//! whole resource with `200 OK`, so clients must check the status.
+//!
+//! Ranges are how resources larger than [`MAX_MESSAGE_SIZE`](crate::MAX_MESSAGE_SIZE)
+//! are transferred at all: one message carries at most that much, so large files are
+//! fetched a range at a time. Only single ranges are supported; a `range` header listing
+//! several is treated as absent.
+
+use core::fmt;
+
+/// One range of bytes, as in a `range: bytes=...` header (RFC 9110 section 14.1.2)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ByteRange {
+    /// `bytes=first-last`: from `first` to `last`, inclusive
+    FromTo(u64, u64),
+    /// `bytes=first-`: from `first` to the end
+    From(u64),
+    /// `bytes=-n`: the last `n` bytes
+    Last(u64),
+}
+
+impl ByteRange {
+    /// Parse a `range` header value
+    ///
+    /// Returns `None` for anything but a single valid byte range.
+    pub fn parse(value: &str) -> Option<Self> {
+        let spec = value.trim().strip_prefix("bytes=")?.trim();
+        let (first, last) = spec.split_once('-')?;
+        let (first, last) = (first.trim(), last.trim());
+        match (first, last) {
+            ("", "") => None,
+            ("", n) => Some(ByteRange::Last(parse_u64(n)?)),
+            (first, "") => Some(ByteRange::From(parse_u64(first)?)),
+            (first, last) => {
+                let (first, last) = (parse_u64(first)?, parse_u64(last)?);
+                (first <= last).then_some(ByteRange::FromTo(first, last))
+            }
+        }
+    }
+
+    /// The first and last byte, inclusive, of this range of a resource of `length` bytes
+    ///
+    /// A range reaching past the end is cut short; `None` means the range is not
+    /// satisfiable, because it starts at or after the end or asks for the last 0 bytes.
+    pub fn resolve(&self, length: u64) -> Option<(u64, u64)> {
+        let (first, last) = match *self {
+            ByteRange::FromTo(first, last) => (first, last.min(length.checked_sub(1)?)),
+            ByteRange::From(first) => (first, length.checked_sub(1)?),
+            ByteRange::Last(0) => return None,
+            ByteRange::Last(n) => (length.saturating_sub(n), length.checked_sub(1)?),
+        };
+        (first <= last).then_some((first, last))
+    }
+}
+
+impl fmt::Display for ByteRange {
+    /// The `range` header value, such as `bytes=0-1023`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ByteRange::FromTo(first, last) => write!(f, "bytes={first}-{last}"),
+            ByteRange::From(first) => write!(f, "bytes={first}-"),
+            ByteRange::Last(n) => write!(f, "bytes=-{n}"),
+        }
+    }
+}
+
+/// A `content-range` header (RFC 9110 section 14.4)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ContentRange {
+    /// First and last byte sent, inclusive; `None` for `bytes */<length>`, as sent with
+    /// `416 RANGE_NOT_SATISFIABLE`
+    pub range: Option<(u64, u64)>,
+    /// Length of the whole resource, if the server knows it
+    pub complete_length: Option<u64>,
+}
+
+impl ContentRange {
+    /// Parse a `content-range` header value
+    ///
+    /// Returns `None` unless it is a valid `bytes` range lying within the complete
+    /// length, or `bytes */<length>`.
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim().strip_prefix("bytes ")?.trim_start();
+        let (range, complete_length) = value.split_once('/')?;
+        let complete_length = match complete_length {
+            "*" => None,
+            length => Some(parse_u64(length)?),
+        };
+        let range = match range {
+            "*" => {
+                complete_length?;
+                None
+            }
+            range => {
+                let (first, last) = range.split_once('-')?;
+                let (first, last) = (parse_u64(first)?, parse_u64(last)?);
+                if first > last || complete_length.is_some_and(|length| last >= length) {
+                    return None;
+                }
+                Some((first, last))
+            }
+        };
+        Some(Self {
+            range,
+            complete_length,
+        })
+    }
+
+    /// Number of bytes the range covers
+    pub fn len(&self) -> u64 {
+        self.range.map_or(0, |(first, last)| last - first + 1)
+    }
+
+    /// Whether no bytes are covered, as with `bytes */<length>`
+    pub fn is_empty(&self) -> bool {
+        self.range.is_none()
+    }
+}
+
+impl fmt::Display for ContentRange {
+    /// The `content-range` header value, such as `bytes 0-1023/4096`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("bytes ")?;
+        match self.range {
+            Some((first, last)) => write!(f, "{first}-{last}/")?,
+            None => f.write_str("*/")?,
+        }
+        match self.complete_length {
+            Some(length) => write!(f, "{length}"),
+            None => f.write_str("*"),
+        }
+    }
+}
+
+/// A non-empty run of ASCII digits
+fn parse_u64(digits: &str) -> Option<u64> {
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
@@ -0,0 +1,165 @@
+//! Host name resolution for connectors
+//!
+//! Gurted names such as `site.web` use their own top-level domains, which system DNS
+//! does not know about. Connectors therefore look hosts up through a [`Resolver`], so
+//! the Gurted DNS service, a hosts file or a test stand-in can be plugged in. [`Hosts`]
+//! resolves from hosts-file text without allocating, and [`Fallback`] chains resolvers.
+//! With the `embedded-tls` feature, [`NalDns`] adapts an `embedded_nal_async::Dns`, and
+//! with the `fetch` feature, [`System`] asks the operating system through tokio.
+//!
+//! ```rust,ignore
+//! use portal_solutions_yo_gurt::resolve::{Fallback, Hosts, NalDns};
+//!
+//! let resolver = Fallback(Hosts::new("127.0.0.1 site.web api.site.web"), NalDns(&stack));
+//! ```
+
+use core::fmt;
+use core::future::Future;
+use core::net::IpAddr;
+
+/// Resolves host names to addresses
+pub trait Resolver {
+    /// Why a name could not be resolved
+    type Error: fmt::Debug;
+
+    /// Find an address for `host`, a DNS name without port or brackets
+    fn resolve(&self, host: &str) -> impl Future<Output = Result<IpAddr, Self::Error>>;
+}
+
+impl<R: Resolver + ?Sized> Resolver for &R {
+    type Error = R::Error;
+
+    fn resolve(&self, host: &str) -> impl Future<Output = Result<IpAddr, Self::Error>> {
+        R::resolve(self, host)
+    }
+}
+
+/// `None` resolves nothing, failing with `None`; handy for optional resolvers in a
+/// [`Fallback`] chain
+impl<R: Resolver> Resolver for Option<R> {
+    type Error = Option<R::Error>;
+
+    async fn resolve(&self, host: &str) -> Result<IpAddr, Self::Error> {
+        match self {
+            Some(resolver) => resolver.resolve(host).await.map_err(Some),
+            None => Err(None),
+        }
+    }
+}
+
+/// Resolves through an `embedded-nal-async` DNS implementation, e.g. a network stack's
+#[cfg(feature = "embedded-tls")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NalDns<D>(pub D);
+
+#[cfg(feature = "embedded-tls")]
+impl<D: embedded_nal_async::Dns> Resolver for NalDns<D> {
+    type Error = D::Error;
+
+    async fn resolve(&self, host: &str) -> Result<IpAddr, D::Error> {
+        self.0
+            .get_host_by_name(host, embedded_nal_async::AddrType::Either)
+            .await
+    }
+}
+
+/// Resolves through the operating system
+#[cfg(feature = "fetch")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct System;
+
+#[cfg(feature = "fetch")]
+impl Resolver for System {
+    type Error = std::io::Error;
+
+    async fn resolve(&self, host: &str) -> std::io::Result<IpAddr> {
+        tokio::net::lookup_host((host, 0))
+            .await?
+            .next()
+            .map(|address| address.ip())
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "host not found"))
+    }
+}
+
+/// The name is not listed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NotFound;
+
+impl fmt::Display for NotFound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("host not found")
+    }
+}
+
+impl core::error::Error for NotFound {}
+
+/// Static resolver over hosts-file text
+///
+/// Each line is an address followed by one or more names, as in `/etc/hosts`:
+///
+/// ```text
+/// # local stand-ins for Gurted sites
+/// 127.0.0.1   site.web www.site.web
+/// ::1         api.site.web
+/// ```
+///
+/// Text after `#` is a comment. Names compare case-insensitively and the first matching
+/// line wins. The text is scanned on every lookup, which suits the handful of entries
+/// used in tests and on devices.
+#[derive(Debug, Clone, Default)]
+pub struct Hosts<T = &'static str> {
+    text: T,
+}
+
+impl<T: AsRef<str>> Hosts<T> {
+    /// Resolve from `text` in hosts-file format
+    pub const fn new(text: T) -> Self {
+        Self { text }
+    }
+
+    /// The address listed for `host`, if any
+    pub fn lookup(&self, host: &str) -> Option<IpAddr> {
+        self.text.as_ref().lines().find_map(|line| {
+            let line = line.split('#').next().unwrap_or_default();
+            let mut fields = line.split_whitespace();
+            let address = fields.next()?;
+            if !fields.any(|name| name.eq_ignore_ascii_case(host)) {
+                return None;
+            }
+            address.parse().ok()
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+impl Hosts<alloc::string::String> {
+    /// Read a hosts file
+    pub fn load(path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
+        Ok(Self::new(std::fs::read_to_string(path)?))
+    }
+}
+
+impl<T: AsRef<str>> Resolver for Hosts<T> {
+    type Error = NotFound;
+
+    async fn resolve(&self, host: &str) -> Result<IpAddr, NotFound> {
+        self.lookup(host).ok_or(NotFound)
+    }
+}
+
+/// Try the first resolver, then the second if the first fails
+///
+/// Errors from the first resolver are discarded; a failure reports the second's error.
+#[derive(Debug, Clone, Default)]
+pub struct Fallback<A, B>(pub A, pub B);
+
+impl<A: Resolver, B: Resolver> Resolver for Fallback<A, B> {
+    type Error = B::Error;
+
+    async fn resolve(&self, host: &str) -> Result<IpAddr, B::Error> {
+        match self.0.resolve(host).await {
+            Ok(address) => Ok(address),
+            Err(_) => self.1.resolve(host).await,
+        }
+    }
+}
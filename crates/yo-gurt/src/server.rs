@@ -0,0 +1,284 @@
+//! Server side of a GURT session: reading requests and writing responses
+//!
+//! [`GurtServer`] wraps an accepted transport the same way [`GurtClient`](crate::GurtClient)
+//! wraps a connected one. It answers the opening `HANDSHAKE`, then reads each request with
+//! a [`RequestReader`] and sends the response head with [`GurtServer::respond`].
+//!
+//! ```rust,ignore
+//! use portal_solutions_yo_gurt::server::GurtServer;
+//!
+//! let mut server = GurtServer::new(tls_transport);
+//! let mut buf = [0u8; 512];
+//! server.accept_handshake(&mut buf).await?;
+//!
+//! let mut request = server.request_reader();
+//! let line = request.read_request_line(&mut buf).await?;
+//! // copy the path out of `buf` before reading headers into it
+//! while request.read_header(&mut buf).await?.is_some() {}
+//! let length = request.content_length().unwrap_or(0);
+//! // ... read the body with request.body(length)
+//!
+//! let mut body = server.respond(StatusCode::Ok, &[("content-type", "text/plain")], 5).await?;
+//! body.write(b"hello").await?;
+//! body.finish().await?;
+//! ```
+//!
+//! Transport security is up to the caller, as on the client side; the TLS acceptor decides
+//! which client certificates are accepted.
+
+use embedded_io_async::{Read, Write};
+
+use crate::encoding::ContentEncoding;
+#[cfg(feature = "alloc")]
+use crate::encoding::DecodedBody;
+use crate::log;
+use crate::progress::NoProgress;
+
+use crate::{
+    ALPN_IDENTIFIER, BodyReader, Close, GURT_VERSION, GurtError, HEAD_BUFFER_SIZE, HeaderResult,
+    Message, Method, ParseMode, ProtocolError, RequestBodyWriter, ResponseHead, ResponseReader,
+    StatusCode, write_head,
+};
+
+/// Writer for a response body, checked against its declared `content-length`
+///
+/// Bodies that do not match their length fail with [`GurtError::InvalidResponse`].
+pub type ResponseBodyWriter<'a, T, P = NoProgress> = RequestBodyWriter<'a, T, P>;
+
+/// GURT server side of one connection
+///
+/// From spec: "Every GURT session must begin with a `HANDSHAKE` request"
+pub struct GurtServer<T> {
+    pub transport: T,
+}
+
+impl<T: Read + Write> GurtServer<T> {
+    /// Wrap an accepted transport
+    pub fn new(transport: T) -> Self {
+        Self { transport }
+    }
+
+    /// Read the opening `HANDSHAKE` and answer `101 SWITCHING_PROTOCOLS`
+    ///
+    /// From spec: "101 SWITCHING_PROTOCOLS - Handshake successful"
+    ///
+    /// Fails with [`ProtocolError::HandshakeRequired`] if the first request is anything
+    /// else; nothing is written in that case, so the caller can answer `400 BAD_REQUEST`.
+    pub async fn accept_handshake(&mut self, buf: &mut [u8]) -> Result<(), GurtError<T::Error>> {
+        let mut request = self.request_reader();
+        let line = request.read_request_line(buf).await?;
+        while request.read_header(buf).await?.is_some() {}
+        if line.method != Method::Handshake || request.content_length().is_some_and(|n| n != 0) {
+            return log::failed("handshake", Err(ProtocolError::HandshakeRequired.into()));
+        }
+        let headers = [
+            ("gurt-version", "1.0.0"),
+            ("encryption", "TLS/1.3"),
+            ("alpn", ALPN_IDENTIFIER),
+        ];
+        self.respond(StatusCode::SwitchingProtocols, &headers, 0)
+            .await?;
+        debug!("handshake complete");
+        Ok(())
+    }
+
+    /// End the session, closing the transport's write side
+    ///
+    /// On TLS transports this sends `close_notify`.
+    pub async fn close(&mut self) -> Result<(), GurtError<T::Error>>
+    where
+        T: Close,
+    {
+        self.transport.close().await.map_err(GurtError::Io)
+    }
+
+    /// Get a reader for the next request
+    pub fn request_reader(&mut self) -> RequestReader<'_, T> {
+        RequestReader::new(&mut self.transport)
+    }
+
+    /// Get a reader for the next request using the given [`ParseMode`]
+    pub fn request_reader_with_mode(&mut self, mode: ParseMode) -> RequestReader<'_, T> {
+        RequestReader::with_mode(&mut self.transport, mode)
+    }
+
+    /// Send a response head with `headers` and a body of `content_length` bytes
+    ///
+    /// Headers are validated as for requests: lowercase token names, no control characters
+    /// in values, and no `content-length` or `transfer-encoding`.
+    pub async fn respond<'a>(
+        &'a mut self,
+        status: StatusCode,
+        headers: &[(&str, &str)],
+        content_length: usize,
+    ) -> Result<ResponseBodyWriter<'a, T>, GurtError<T::Error>> {
+        let head = ResponseHead::new(status)
+            .headers(headers)
+            .content_length(content_length);
+        self.send_head(&head, &mut [0; HEAD_BUFFER_SIZE]).await
+    }
+
+    /// Send a response head, rendering it into `buf` first
+    ///
+    /// Like [`GurtClient::send_head`](crate::GurtClient::send_head), the head goes out in
+    /// one write when it fits in `buf`, and is flushed straight away when there is no body.
+    pub async fn send_head<'a>(
+        &'a mut self,
+        head: &ResponseHead<'_>,
+        buf: &mut [u8],
+    ) -> Result<ResponseBodyWriter<'a, T>, GurtError<T::Error>> {
+        log::failed("response", head.validate())?;
+        head.log();
+        let encoded = head.encode(buf).map(|len| &buf[..len]);
+        write_head(
+            &mut self.transport,
+            Message::Response,
+            encoded,
+            head.slices(),
+            head.body_length(),
+        )
+        .await
+    }
+}
+
+/// Result from reading a request line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RequestLineResult {
+    /// The parsed method
+    pub method: Method,
+    /// Starting position of the path in the buffer
+    pub path_start: usize,
+    /// Length of the path in bytes
+    pub path_len: usize,
+    /// Total bytes read including the request line and CRLF
+    pub bytes_read: usize,
+}
+
+impl RequestLineResult {
+    /// Request path as read into `buf`
+    pub fn path<'b>(&self, buf: &'b [u8]) -> &'b [u8] {
+        &buf[self.path_start..self.path_start + self.path_len]
+    }
+}
+
+/// Request reader for parsing GURT requests
+///
+/// From spec: "Request Structure:
+/// ```text
+/// METHOD /path GURT/1.0.0\r\n
+/// header-name: header-value\r\n
+/// content-length: 123\r\n
+/// user-agent: GURT-Client/1.0.0\r\n
+/// \r\n
+/// [message body]
+/// ```"
+///
+/// Headers and framing are validated exactly as by [`ResponseReader`].
+pub struct RequestReader<'a, T> {
+    inner: ResponseReader<'a, T>,
+}
+
+impl<'a, T: Read> RequestReader<'a, T> {
+    /// Create a new request reader using [`ParseMode::Strict`]
+    pub fn new(transport: &'a mut T) -> Self {
+        Self::with_mode(transport, ParseMode::Strict)
+    }
+
+    /// Create a new request reader with the given parse mode
+    pub fn with_mode(transport: &'a mut T, mode: ParseMode) -> Self {
+        Self {
+            inner: ResponseReader::with_mode(transport, mode),
+        }
+    }
+
+    /// The validated `content-length` of the current request, once its header was read
+    pub fn content_length(&self) -> Option<usize> {
+        self.inner.content_length()
+    }
+
+    /// The `content-encoding` of the current request's body; see
+    /// [`ResponseReader::content_encoding`]
+    pub fn content_encoding(&self) -> Option<ContentEncoding> {
+        self.inner.content_encoding()
+    }
+
+    /// Read the request line
+    /// From spec: "Method line: `METHOD /path GURT/1.0.0`"
+    ///
+    /// The method must be one GURT defines, and the path must start with `/` and contain
+    /// no whitespace or control characters.
+    pub async fn read_request_line(
+        &mut self,
+        buf: &mut [u8],
+    ) -> Result<RequestLineResult, GurtError<T::Error>> {
+        let line = match self.parse_request_line(buf).await {
+            // The client closing its connection between requests
+            Err(GurtError::UnexpectedEof) => {
+                debug!("connection closed");
+                return Err(GurtError::UnexpectedEof);
+            }
+            line => log::failed("reading request line", line)?,
+        };
+        debug!("request {:?} {:?}", line.method, log::Bytes(line.path(buf)));
+        Ok(line)
+    }
+
+    async fn parse_request_line(
+        &mut self,
+        buf: &mut [u8],
+    ) -> Result<RequestLineResult, GurtError<T::Error>> {
+        let len = self.inner.read_line(buf).await?;
+        let line = &buf[..len];
+
+        let mut parts = line.split(|&b| b == b' ');
+        let (Some(method), Some(path), Some(version), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(ProtocolError::InvalidRequestLine.into());
+        };
+        if version != GURT_VERSION.as_bytes() {
+            return Err(ProtocolError::InvalidProtocol.into());
+        }
+        let method = Method::from_bytes(method).ok_or(ProtocolError::InvalidMethod)?;
+        if path.first() != Some(&b'/') || !path.iter().all(|&b| b.is_ascii_graphic() || b >= 0x80) {
+            return Err(ProtocolError::InvalidRequestLine.into());
+        }
+
+        self.inner.content_length = None;
+        self.inner.content_encoding = Some(ContentEncoding::Identity);
+        Ok(RequestLineResult {
+            method,
+            path_start: method.as_str().len() + 1,
+            path_len: path.len(),
+            bytes_read: len + 2,
+        })
+    }
+
+    /// Read a single header line
+    /// From spec: "header-name: header-value\r\n"
+    ///
+    /// Returns Some(HeaderResult) with header information, or None if end of headers
+    pub async fn read_header(
+        &mut self,
+        buf: &mut [u8],
+    ) -> Result<Option<HeaderResult>, GurtError<T::Error>> {
+        self.inner.read_header(buf).await
+    }
+
+    /// Read the remaining request as a body of `content_length` bytes
+    /// From spec: "content-length: 123\r\n"
+    pub fn body(self, content_length: usize) -> BodyReader<'a, T> {
+        self.inner.body(content_length)
+    }
+
+    /// Read the remaining request as a body of `content_length` bytes, decoded according
+    /// to its `content-encoding`; see [`ResponseReader::decoded_body`]
+    #[cfg(feature = "alloc")]
+    pub fn decoded_body(
+        self,
+        content_length: usize,
+    ) -> Result<DecodedBody<'a, T>, GurtError<T::Error>> {
+        self.inner.decoded_body(content_length)
+    }
+}
@@ -0,0 +1,562 @@
+//! Test utilities: in-memory transports and a scripted GURT peer
+//!
+//! Enabled by the `testing` feature. [`duplex`] creates a connected pair of in-memory
+//! streams implementing `embedded_io_async::Read + Write`; one end is handed to the code
+//! under test (for example a [`GurtClient`](crate::GurtClient)) and the other to a
+//! [`MockPeer`] that plays a scripted conversation, asserting on exactly what was written.
+//!
+//! ```rust,ignore
+//! use portal_solutions_yo_gurt::testing::{duplex, MockPeer, Reply};
+//!
+//! let (client_io, server_io) = duplex(1024);
+//! let server = MockPeer::new()
+//!     .expect_handshake("example.com", "yo-gurt/0.1")
+//!     .reply(Reply::new(StatusCode::SwitchingProtocols))
+//!     .run(server_io);
+//! let client = async {
+//!     let mut client = GurtClient::new(client_io);
+//!     client.handshake("example.com", "yo-gurt/0.1").await.unwrap();
+//!     // ...
+//! };
+//! join(client, server).await;
+//! ```
+//!
+//! The streams are single-threaded (`!Send`); drive both ends from one task with a
+//! `join` combinator. With the `tokio` feature they also implement tokio's `AsyncRead`
+//! and `AsyncWrite`, so a `fetch::Connect` implementation can hand one end to a pooled
+//! client while a [`MockPeer`] plays the server.
+//!
+//! Where no peer needs to react, [`Scripted`] replays fixed input and records what was
+//! written.
+
+use alloc::collections::VecDeque;
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::future::poll_fn;
+#[cfg(feature = "tokio")]
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+
+use embedded_io_async::{ErrorKind, ErrorType, Read, Write};
+
+use crate::{Close, GURT_VERSION, Method, StatusCode};
+
+/// One direction of a [`duplex`] pair
+struct Pipe {
+    buf: VecDeque<u8>,
+    capacity: usize,
+    /// Set once either end of the pair is dropped
+    closed: bool,
+    reader: Option<Waker>,
+    writer: Option<Waker>,
+}
+
+impl Pipe {
+    fn new(capacity: usize) -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(Self {
+            buf: VecDeque::new(),
+            capacity,
+            closed: false,
+            reader: None,
+            writer: None,
+        }))
+    }
+
+    fn close(&mut self) {
+        self.closed = true;
+        if let Some(waker) = self.reader.take() {
+            waker.wake();
+        }
+        if let Some(waker) = self.writer.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// One end of an in-memory duplex stream created by [`duplex`]
+///
+/// Bytes written to one end are read from the other. Dropping an end signals end of file
+/// to the peer's reads and makes the peer's writes fail with [`DuplexError::BrokenPipe`].
+pub struct DuplexStream {
+    read: Rc<RefCell<Pipe>>,
+    write: Rc<RefCell<Pipe>>,
+}
+
+/// Create a connected pair of in-memory streams
+///
+/// Each direction buffers up to `capacity` bytes; writers wait for the reader to make room.
+pub fn duplex(capacity: usize) -> (DuplexStream, DuplexStream) {
+    assert!(capacity > 0, "duplex capacity must be non-zero");
+    let a = Pipe::new(capacity);
+    let b = Pipe::new(capacity);
+    (
+        DuplexStream {
+            read: a.clone(),
+            write: b.clone(),
+        },
+        DuplexStream { read: b, write: a },
+    )
+}
+
+impl Drop for DuplexStream {
+    fn drop(&mut self) {
+        self.read.borrow_mut().close();
+        self.write.borrow_mut().close();
+    }
+}
+
+/// Errors from a [`DuplexStream`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuplexError {
+    /// The other end was dropped
+    BrokenPipe,
+}
+
+impl core::fmt::Display for DuplexError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            DuplexError::BrokenPipe => f.write_str("other end of duplex stream was dropped"),
+        }
+    }
+}
+
+impl core::error::Error for DuplexError {}
+
+impl embedded_io_async::Error for DuplexError {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            DuplexError::BrokenPipe => ErrorKind::BrokenPipe,
+        }
+    }
+}
+
+impl ErrorType for DuplexStream {
+    type Error = DuplexError;
+}
+
+impl DuplexStream {
+    /// Move buffered bytes into `buf`; 0 at end of file
+    fn poll_read_into(&self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<usize> {
+        let mut pipe = self.read.borrow_mut();
+        if buf.is_empty() {
+            return Poll::Ready(0);
+        }
+        if pipe.buf.is_empty() {
+            if pipe.closed {
+                return Poll::Ready(0);
+            }
+            pipe.reader = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+        let n = buf.len().min(pipe.buf.len());
+        for (dst, src) in buf.iter_mut().zip(pipe.buf.drain(..n)) {
+            *dst = src;
+        }
+        if let Some(waker) = pipe.writer.take() {
+            waker.wake();
+        }
+        Poll::Ready(n)
+    }
+
+    /// Buffer as much of `buf` as there is room for
+    fn poll_write_from(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, DuplexError>> {
+        let mut pipe = self.write.borrow_mut();
+        if pipe.closed {
+            return Poll::Ready(Err(DuplexError::BrokenPipe));
+        }
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        let room = pipe.capacity - pipe.buf.len();
+        if room == 0 {
+            pipe.writer = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+        let n = buf.len().min(room);
+        pipe.buf.extend(&buf[..n]);
+        if let Some(waker) = pipe.reader.take() {
+            waker.wake();
+        }
+        Poll::Ready(Ok(n))
+    }
+}
+
+impl Read for DuplexStream {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, DuplexError> {
+        poll_fn(|cx| self.poll_read_into(cx, buf).map(Ok)).await
+    }
+}
+
+impl Write for DuplexStream {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, DuplexError> {
+        poll_fn(|cx| self.poll_write_from(cx, buf)).await
+    }
+
+    /// Written bytes are visible to the peer immediately, so there is nothing to flush
+    async fn flush(&mut self) -> Result<(), DuplexError> {
+        Ok(())
+    }
+}
+
+impl Close for DuplexStream {
+    /// Close this end's write half; the peer reads end of file once the buffer drains
+    async fn close(&mut self) -> Result<(), DuplexError> {
+        self.write.borrow_mut().close();
+        Ok(())
+    }
+}
+
+/// With the `tokio` feature either end is also a tokio stream, such as the transport of a
+/// `fetch::Connect` implementation
+#[cfg(feature = "tokio")]
+impl tokio::io::AsyncRead for DuplexStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let n = core::task::ready!(self.poll_read_into(cx, buf.initialize_unfilled()));
+        buf.advance(n);
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl tokio::io::AsyncWrite for DuplexStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        self.poll_write_from(cx, buf)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::BrokenPipe, e))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        self.write.borrow_mut().close();
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// A transport replaying fixed input and recording what is written to it
+///
+/// For tests that only need canned bytes to read, or want to inspect the bytes written
+/// (including how they were split into write calls and flushes), without a peer running
+/// alongside. Reads hand out the input, at most [`step`](Self::step) bytes at a time, then
+/// end of file.
+///
+/// ```rust,ignore
+/// let mut client = GurtClient::new(Scripted::new(b"GURT/1.0.0 200 OK\r\n\r\n"));
+/// client.request_no_body(Method::Get, "/", "example.com", None).await?;
+/// assert!(client.transport.written().starts_with(b"GET / GURT/1.0.0\r\n"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Scripted<'a> {
+    input: &'a [u8],
+    step: usize,
+    writes: Vec<Vec<u8>>,
+    flushes: usize,
+}
+
+impl<'a> Scripted<'a> {
+    /// A transport reading `input`
+    pub fn new(input: &'a [u8]) -> Self {
+        Self {
+            input,
+            step: usize::MAX,
+            writes: Vec::new(),
+            flushes: 0,
+        }
+    }
+
+    /// Hand out at most `step` bytes per read, to exercise parsers across short reads
+    pub fn step(mut self, step: usize) -> Self {
+        assert!(step > 0, "a step of 0 bytes reads as end of file");
+        self.step = step;
+        self
+    }
+
+    /// The input not read yet
+    pub fn remaining(&self) -> &'a [u8] {
+        self.input
+    }
+
+    /// Everything written, in order
+    pub fn written(&self) -> Vec<u8> {
+        self.writes.concat()
+    }
+
+    /// The bytes of each write call, in order
+    pub fn writes(&self) -> &[Vec<u8>] {
+        &self.writes
+    }
+
+    /// How many times the transport was flushed
+    pub fn flushes(&self) -> usize {
+        self.flushes
+    }
+}
+
+impl Default for Scripted<'_> {
+    /// A transport with nothing to read, for recording writes
+    fn default() -> Self {
+        Self::new(&[])
+    }
+}
+
+impl ErrorType for Scripted<'_> {
+    type Error = core::convert::Infallible;
+}
+
+impl Read for Scripted<'_> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let n = buf.len().min(self.step).min(self.input.len());
+        buf[..n].copy_from_slice(&self.input[..n]);
+        self.input = &self.input[n..];
+        Ok(n)
+    }
+}
+
+impl Write for Scripted<'_> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.writes.push(buf.to_vec());
+        Ok(buf.len())
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.flushes += 1;
+        Ok(())
+    }
+}
+
+/// A scripted response for [`MockPeer::reply`]
+///
+/// From spec: "Status line: `GURT/1.0.0 <code> <message>`"
+pub struct Reply {
+    status: StatusCode,
+    headers: Vec<(Vec<u8>, Vec<u8>)>,
+    body: Option<Vec<u8>>,
+}
+
+impl Reply {
+    /// Reply with `status` and no headers or body
+    pub fn new(status: StatusCode) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: None,
+        }
+    }
+
+    /// Add a header line, written in the order added
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers
+            .push((name.as_bytes().to_vec(), value.as_bytes().to_vec()));
+        self
+    }
+
+    /// Set the body; a `content-length` header is added unless one was set explicitly
+    pub fn body(mut self, body: &[u8]) -> Self {
+        self.body = Some(body.to_vec());
+        self
+    }
+
+    /// Render the reply as it appears on the wire
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(GURT_VERSION.as_bytes());
+        out.push(b' ');
+        push_decimal(&mut out, self.status.as_u16().into());
+        out.push(b' ');
+        out.extend_from_slice(self.status.reason_phrase().as_bytes());
+        out.extend_from_slice(b"\r\n");
+        for (name, value) in &self.headers {
+            push_header(&mut out, name, value);
+        }
+        let has_length = self
+            .headers
+            .iter()
+            .any(|(name, _)| name == b"content-length");
+        if let Some(body) = &self.body
+            && !has_length
+        {
+            let mut length = Vec::new();
+            push_decimal(&mut length, body.len());
+            push_header(&mut out, b"content-length", &length);
+        }
+        out.extend_from_slice(b"\r\n");
+        if let Some(body) = &self.body {
+            out.extend_from_slice(body);
+        }
+        out
+    }
+}
+
+enum Step {
+    Expect(Vec<u8>),
+    Send(Vec<u8>),
+    ExpectEof,
+}
+
+/// A scripted GURT peer
+///
+/// Steps run in the order they were added. Expectations read exactly the expected number
+/// of bytes and panic with both byte strings if they differ, so a test fails on the first
+/// unexpected byte the code under test writes.
+#[derive(Default)]
+pub struct MockPeer {
+    steps: Vec<Step>,
+}
+
+impl MockPeer {
+    /// Create an empty script
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Expect exactly `bytes` to be written by the other side
+    pub fn expect(mut self, bytes: &[u8]) -> Self {
+        self.steps.push(Step::Expect(bytes.to_vec()));
+        self
+    }
+
+    /// Expect a HANDSHAKE request as written by [`GurtClient::handshake`](crate::GurtClient::handshake)
+    ///
+    /// From spec: "Every GURT session must begin with a `HANDSHAKE` request"
+    pub fn expect_handshake(self, host: &str, user_agent: &str) -> Self {
+        self.expect_request(
+            Method::Handshake,
+            "/",
+            &[("host", host), ("user-agent", user_agent)],
+            b"",
+        )
+    }
+
+    /// Expect a request with exactly these headers, in order, followed by `body`
+    ///
+    /// From spec: "Method line: `METHOD /path GURT/1.0.0`"
+    pub fn expect_request(
+        self,
+        method: Method,
+        path: &str,
+        headers: &[(&str, &str)],
+        body: &[u8],
+    ) -> Self {
+        let mut out = Vec::new();
+        out.extend_from_slice(method.as_str().as_bytes());
+        out.push(b' ');
+        out.extend_from_slice(path.as_bytes());
+        out.push(b' ');
+        out.extend_from_slice(GURT_VERSION.as_bytes());
+        out.extend_from_slice(b"\r\n");
+        for (name, value) in headers {
+            push_header(&mut out, name.as_bytes(), value.as_bytes());
+        }
+        out.extend_from_slice(b"\r\n");
+        out.extend_from_slice(body);
+        self.expect(&out)
+    }
+
+    /// Send a scripted response
+    pub fn reply(self, reply: Reply) -> Self {
+        self.send(&reply.to_bytes())
+    }
+
+    /// Send raw bytes, for malformed or partial responses
+    pub fn send(mut self, bytes: &[u8]) -> Self {
+        self.steps.push(Step::Send(bytes.to_vec()));
+        self
+    }
+
+    /// Expect the other side to close its write half without writing anything more
+    pub fn expect_eof(mut self) -> Self {
+        self.steps.push(Step::ExpectEof);
+        self
+    }
+
+    /// Play the script over `stream`, dropping it (closing the connection) at the end
+    ///
+    /// # Panics
+    ///
+    /// Panics if the other side writes anything other than what was expected, or if the
+    /// transport fails.
+    pub async fn run<S: Read + Write>(self, mut stream: S) {
+        for step in self.steps {
+            match step {
+                Step::Expect(expected) => {
+                    let mut actual = Vec::new();
+                    let mut buf = [0u8; 256];
+                    while actual.len() < expected.len() {
+                        let want = buf.len().min(expected.len() - actual.len());
+                        let n = match stream.read(&mut buf[..want]).await {
+                            Ok(n) => n,
+                            Err(e) => panic!("mock peer read failed: {e:?}"),
+                        };
+                        if n == 0 {
+                            break;
+                        }
+                        actual.extend_from_slice(&buf[..n]);
+                        if !expected.starts_with(&actual) {
+                            break;
+                        }
+                    }
+                    assert!(
+                        actual == expected,
+                        "mock peer received unexpected bytes\n  expected: \"{}\"\n  received: \"{}\"",
+                        expected.escape_ascii(),
+                        actual.escape_ascii(),
+                    );
+                }
+                Step::Send(bytes) => {
+                    if let Err(e) = stream.write_all(&bytes).await {
+                        panic!("mock peer write failed: {e:?}");
+                    }
+                    if let Err(e) = stream.flush().await {
+                        panic!("mock peer flush failed: {e:?}");
+                    }
+                }
+                Step::ExpectEof => {
+                    let mut buf = [0u8; 256];
+                    match stream.read(&mut buf).await {
+                        Ok(0) => {}
+                        Ok(n) => panic!(
+                            "mock peer expected end of stream, received: \"{}\"",
+                            buf[..n].escape_ascii()
+                        ),
+                        Err(e) => panic!("mock peer read failed: {e:?}"),
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn push_header(out: &mut Vec<u8>, name: &[u8], value: &[u8]) {
+    out.extend_from_slice(name);
+    out.extend_from_slice(b": ");
+    out.extend_from_slice(value);
+    out.extend_from_slice(b"\r\n");
+}
+
+fn push_decimal(out: &mut Vec<u8>, mut n: usize) {
+    let mut buf = [0u8; 20];
+    let mut i = buf.len();
+    loop {
+        i -= 1;
+        buf[i] = b'0' + (n % 10) as u8;
+        n /= 10;
+        if n == 0 {
+            break;
+        }
+    }
+    out.extend_from_slice(&buf[i..]);
+}
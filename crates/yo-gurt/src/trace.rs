@@ -0,0 +1,54 @@
+//! `tracing` support: which header values are kept out of logs
+//!
+//! With the `tracing` feature, clients and servers emit events for the handshake, each
+//! request and response line, every header, body completion and failures; the fetch
+//! client also wraps each request in a `gurt.request` span carrying the method and URL.
+//! Headers are logged at `TRACE` level, the rest at `DEBUG`, and failures at `WARN`.
+//!
+//! Header values can carry credentials, so the values of the headers named in
+//! [`DEFAULT_REDACTED_HEADERS`] are logged as `<redacted>`. [`set_redacted_headers`]
+//! replaces the list for the whole process, for instance to hide an API key header too.
+
+use std::string::{String, ToString};
+use std::sync::RwLock;
+use std::vec::Vec;
+
+pub use crate::log::DEFAULT_REDACTED_HEADERS;
+
+/// Configured list, or `None` for [`DEFAULT_REDACTED_HEADERS`]
+static REDACTED: RwLock<Option<Vec<String>>> = RwLock::new(None);
+
+/// Log the values of the headers in `names` as `<redacted>`, in place of the current list
+///
+/// Names are compared ignoring ASCII case. An empty list logs every value.
+pub fn set_redacted_headers<I>(names: I)
+where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+{
+    let names = names
+        .into_iter()
+        .map(|name| name.as_ref().to_ascii_lowercase())
+        .collect();
+    *REDACTED.write().unwrap_or_else(|e| e.into_inner()) = Some(names);
+}
+
+/// The header names whose values are currently redacted
+pub fn redacted_headers() -> Vec<String> {
+    match &*REDACTED.read().unwrap_or_else(|e| e.into_inner()) {
+        Some(names) => names.clone(),
+        None => DEFAULT_REDACTED_HEADERS
+            .iter()
+            .map(|name| name.to_string())
+            .collect(),
+    }
+}
+
+/// Whether the value of header `name` is left out of logs
+pub(crate) fn is_redacted(name: &[u8]) -> bool {
+    let matches = |redacted: &str| name.eq_ignore_ascii_case(redacted.as_bytes());
+    match &*REDACTED.read().unwrap_or_else(|e| e.into_inner()) {
+        Some(names) => names.iter().any(|redacted| matches(redacted)),
+        None => DEFAULT_REDACTED_HEADERS.iter().copied().any(matches),
+    }
+}
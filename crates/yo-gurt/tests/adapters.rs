@@ -0,0 +1,139 @@
+//! GurtClient over the std, tokio and futures-io adapters
+
+use std::io::{ErrorKind, Read as _, Write as _};
+use std::os::unix::net::UnixStream;
+use std::thread;
+
+use embassy_futures::block_on;
+use portal_solutions_yo_gurt::adapters::{FromFutures, FromStd, FromTokio};
+use portal_solutions_yo_gurt::{GurtClient, GurtError, Method, ResponseReader, StatusCode};
+
+const REQUEST: &[u8] =
+    b"GET /hello GURT/1.0.0\r\nhost: example.com\r\nuser-agent: yo-gurt/0.1\r\n\r\n";
+
+/// Read a status line and headers, returning the content length
+async fn read_head<T: embedded_io_async::Read>(response: &mut ResponseReader<'_, T>) -> usize {
+    let mut buf = [0u8; 128];
+    let status = response.read_status_line(&mut buf).await.unwrap();
+    assert_eq!(status.status, StatusCode::Ok);
+    while response.read_header(&mut buf).await.unwrap().is_some() {}
+    response.content_length().unwrap()
+}
+
+#[tokio::test]
+async fn tokio_body_is_async_read() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let (client_io, mut server_io) = tokio::io::duplex(64);
+    let server = tokio::spawn(async move {
+        let mut head = vec![0u8; REQUEST.len()];
+        server_io.read_exact(&mut head).await.unwrap();
+        assert_eq!(head, REQUEST);
+        server_io
+            .write_all(b"GURT/1.0.0 200 OK\r\ncontent-length: 5\r\n\r\nhelloGURT/1.0.0")
+            .await
+            .unwrap();
+        // The client's close shows up as end of file
+        let mut rest = Vec::new();
+        server_io.read_to_end(&mut rest).await.unwrap();
+        assert!(rest.is_empty());
+    });
+
+    let mut client = GurtClient::new(FromTokio(client_io));
+    client
+        .request_no_body(Method::Get, "/hello", "example.com", None)
+        .await
+        .unwrap();
+    let mut response = client.response_reader();
+    let length = read_head(&mut response).await;
+    let mut body = Vec::new();
+    response.body(length).read_to_end(&mut body).await.unwrap();
+    // Reading stops at the end of the body, leaving the next response on the stream
+    assert_eq!(body, b"hello");
+    client.close().await.unwrap();
+    server.await.unwrap();
+}
+
+#[test]
+fn std_blocking_transport() {
+    let (client_io, mut server_io) = UnixStream::pair().unwrap();
+    let server = thread::spawn(move || {
+        let mut head = vec![0u8; REQUEST.len()];
+        server_io.read_exact(&mut head).unwrap();
+        assert_eq!(head, REQUEST);
+        server_io
+            .write_all(b"GURT/1.0.0 200 OK\r\ncontent-length: 6\r\n\r\nabc")
+            .unwrap();
+    });
+
+    let mut client = GurtClient::new(FromStd(client_io));
+    block_on(async {
+        client
+            .request_no_body(Method::Get, "/hello", "example.com", None)
+            .await
+            .unwrap();
+        let mut response = client.response_reader();
+        let length = read_head(&mut response).await;
+        server.join().unwrap();
+
+        let mut body = Vec::new();
+        let err = response.body(length).read_to_end(&mut body).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+        assert_eq!(body, b"abc");
+    });
+}
+
+#[test]
+fn futures_truncated_body() {
+    use futures::io::{AsyncReadExt, Cursor};
+
+    let response = b"GURT/1.0.0 200 OK\r\ncontent-length: 4\r\n\r\nab".to_vec();
+    let mut client = GurtClient::new(FromFutures(Cursor::new(response)));
+    block_on(async {
+        let mut response = client.response_reader();
+        let length = read_head(&mut response).await;
+        let mut body = Vec::new();
+        let err = response
+            .body(length)
+            .read_to_end(&mut body)
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+    });
+
+    // Through the embedded-io-async interface the same condition is a GurtError
+    let response = b"GURT/1.0.0 200 OK\r\ncontent-length: 4\r\n\r\nab".to_vec();
+    let mut client = GurtClient::new(FromFutures(Cursor::new(response)));
+    block_on(async {
+        let mut response = client.response_reader();
+        let length = read_head(&mut response).await;
+        let mut out = [0u8; 4];
+        let result =
+            embedded_io_async::Read::read_exact(&mut response.body(length), &mut out).await;
+        assert!(matches!(
+            result,
+            Err(embedded_io_async::ReadExactError::Other(
+                GurtError::UnexpectedEof
+            ))
+        ));
+    });
+}
+
+#[test]
+fn transport_errors_are_shown_and_chained() {
+    let (client_io, server_io) = UnixStream::pair().unwrap();
+    drop(server_io);
+    let mut client = GurtClient::new(FromStd(client_io));
+    let error = block_on(client.request_no_body(Method::Get, "/", "example.com", None))
+        .err()
+        .unwrap();
+    let GurtError::Io(io) = &error else {
+        panic!("{error:?}")
+    };
+    // Shown by the transport error's message, which is also the source
+    assert_eq!(error.to_string(), format!("transport error: {io}"));
+    let source = std::error::Error::source(&error).unwrap();
+    let source = source.downcast_ref::<std::io::Error>().unwrap();
+    assert_eq!(source.kind(), ErrorKind::BrokenPipe);
+    assert!(std::error::Error::source(&GurtError::<std::io::Error>::Timeout).is_none());
+}
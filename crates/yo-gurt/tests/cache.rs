@@ -0,0 +1,170 @@
+//! Response cache: cache-control parsing, freshness, revalidation and the stores
+
+use portal_solutions_yo_gurt::StatusCode;
+use portal_solutions_yo_gurt::cache::{
+    CacheControl, CacheStore, CachedResponse, DiskCache, MemoryCache, parse_date,
+};
+
+/// 2015-10-21 07:28:00 UTC
+const NOW: u64 = 1_445_412_480;
+
+fn response(headers: &[(&str, &str)], body: &str) -> CachedResponse {
+    let headers: Vec<(String, String)> = headers
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+    CachedResponse::new(StatusCode::Ok, &headers, body.into(), NOW)
+}
+
+#[test]
+fn cache_control() {
+    assert_eq!(CacheControl::parse(""), CacheControl::default());
+    let mut directives = CacheControl::parse("public, Max-Age=\"600\", must-revalidate");
+    assert_eq!(directives.max_age, Some(600));
+    assert!(!directives.no_cache && !directives.no_store);
+    directives.add("max-age=60,no-cache, max-age=x");
+    directives.add("NO-STORE");
+    assert_eq!(
+        directives,
+        CacheControl {
+            max_age: Some(60),
+            no_cache: true,
+            no_store: true,
+        }
+    );
+    assert_eq!(parse_date("Wed, 21 Oct 2015 07:28:00 GMT"), Some(NOW));
+    assert_eq!(parse_date("tomorrow"), None);
+}
+
+#[test]
+fn freshness() {
+    let stored = response(&[("cache-control", "max-age=60"), ("age", "10")], "a");
+    assert_eq!(stored.stored_at, NOW - 10);
+    assert_eq!(stored.freshness_lifetime(), 60);
+    assert!(stored.is_fresh(NOW + 49));
+    assert!(!stored.is_fresh(NOW + 50));
+
+    // max-age wins over expires, which counts from date
+    let stored = response(
+        &[
+            ("date", "Wed, 21 Oct 2015 07:00:00 GMT"),
+            ("expires", "Wed, 21 Oct 2015 08:00:00 GMT"),
+        ],
+        "",
+    );
+    assert_eq!(stored.freshness_lifetime(), 3600);
+    let stored = response(&[("expires", "0"), ("cache-control", "max-age=5")], "");
+    assert_eq!(stored.freshness_lifetime(), 5);
+    assert_eq!(response(&[("expires", "0")], "").freshness_lifetime(), 0);
+
+    // Without either, a tenth of the time since last-modified
+    let stored = response(&[("last-modified", "Wed, 21 Oct 2015 06:28:00 GMT")], "");
+    assert_eq!(stored.freshness_lifetime(), 360);
+    assert!(!response(&[("cache-control", "no-cache, max-age=60")], "").is_fresh(NOW));
+}
+
+#[test]
+fn storing_and_revalidating() {
+    let none = CacheControl::default();
+    let stored = response(
+        &[
+            ("etag", "\"v1\""),
+            ("content-encoding", "gzip"),
+            ("content-length", "30"),
+            ("x-version", "1"),
+        ],
+        "body",
+    );
+    assert_eq!(stored.header("content-encoding"), None);
+    assert_eq!(stored.header("content-length"), None);
+    assert!(stored.is_storable(&none));
+    assert!(!stored.is_storable(&CacheControl::parse("no-store")));
+    assert_eq!(
+        stored.validators(),
+        [("if-none-match".to_string(), "\"v1\"".to_string())]
+    );
+
+    assert!(response(&[("cache-control", "max-age=1")], "").is_storable(&none));
+    assert!(response(&[("last-modified", "Wed, 21 Oct 2015 06:28:00 GMT")], "").is_storable(&none));
+    assert!(!response(&[], "").is_storable(&none));
+    assert!(!response(&[("etag", "x"), ("cache-control", "no-store")], "").is_storable(&none));
+    assert!(response(&[("etag", "x"), ("vary", "Accept-Encoding")], "").is_storable(&none));
+    assert!(
+        !response(&[("etag", "x"), ("vary", "accept-encoding, cookie")], "").is_storable(&none)
+    );
+    let mut not_found = response(&[("etag", "x")], "");
+    not_found.status = StatusCode::NotFound;
+    assert!(!not_found.is_storable(&none));
+
+    // A 304 replaces the headers it names and restarts the clock
+    let mut updated = stored.clone();
+    let headers = [
+        ("x-version".to_string(), "2".to_string()),
+        ("cache-control".to_string(), "max-age=30".to_string()),
+        ("content-length".to_string(), "0".to_string()),
+    ];
+    updated.update(&headers, NOW + 100);
+    assert_eq!(updated.header("x-version"), Some("2"));
+    assert_eq!(updated.header("etag"), Some("\"v1\""));
+    assert_eq!(updated.header("content-length"), None);
+    assert_eq!(updated.body, b"body");
+    assert!(updated.is_fresh(NOW + 129));
+}
+
+#[test]
+fn memory_cache() {
+    let mut cache = MemoryCache::new(100);
+    assert!(cache.is_empty());
+    let body = "x".repeat(30);
+    cache.put("a", response(&[], &body));
+    cache.put("b", response(&[], &body));
+    cache.put("c", response(&[], &body));
+    assert_eq!((cache.len(), cache.size()), (3, 90));
+    // Using "a" leaves "b" as the least recently used
+    assert!(cache.get("a").is_some());
+    cache.put("d", response(&[], &body));
+    assert!(cache.get("b").is_none());
+    assert_eq!(cache.len(), 3);
+
+    cache.put("a", response(&[], "short"));
+    assert_eq!(cache.get("a").unwrap().body, b"short");
+    assert_eq!(cache.size(), 65);
+    cache.put("huge", response(&[], &"x".repeat(101)));
+    assert!(cache.get("huge").is_none());
+    assert_eq!(cache.len(), 3);
+    cache.remove("a");
+    assert_eq!((cache.len(), cache.size()), (2, 60));
+    cache.clear();
+    assert!(cache.is_empty());
+}
+
+#[test]
+fn disk_cache() {
+    let dir = std::env::temp_dir().join(format!("yo-gurt-cache-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let mut cache = DiskCache::open(&dir).unwrap();
+    let key = "gurt://site.web/logo.png";
+    let stored = response(
+        &[("etag", "\"v1\""), ("x-note", "a: b")],
+        "\u{0}binary\nbody",
+    );
+    cache.put(key, stored.clone());
+    assert_eq!(cache.get(key), Some(stored.clone()));
+    assert_eq!(DiskCache::open(&dir).unwrap().get(key), Some(stored));
+    assert_eq!(cache.get("gurt://site.web/other"), None);
+
+    // Damaged files count as missing
+    let file = std::fs::read_dir(&dir)
+        .unwrap()
+        .next()
+        .unwrap()
+        .unwrap()
+        .path();
+    std::fs::write(&file, "gurt-cache 1\ngurt://site.web/logo.png\n999\n").unwrap();
+    assert_eq!(cache.get(key), None);
+    cache.put("gurt://site.web/\nbad", response(&[], ""));
+    assert_eq!(cache.get("gurt://site.web/\nbad"), None);
+    cache.remove(key);
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,104 @@
+//! Traffic capture through a teeing transport
+
+use embassy_futures::block_on;
+use embedded_io_async::{Read, Write};
+use portal_solutions_yo_gurt::capture::{
+    self, Capture, CaptureError, Direction, MAGIC, Record, Sink, Writer,
+};
+use portal_solutions_yo_gurt::testing::Scripted;
+use portal_solutions_yo_gurt::{GurtClient, Method, StatusCode};
+
+const RESPONSES: &[u8] = b"GURT/1.0.0 101 SWITCHING_PROTOCOLS\r\n\r\n\
+GURT/1.0.0 200 OK\r\ncontent-length: 5\r\n\r\nhello";
+
+fn joined(records: &[Record<'_>], direction: Direction) -> Vec<u8> {
+    records
+        .iter()
+        .filter(|record| record.direction == direction)
+        .flat_map(|record| record.bytes.iter().copied())
+        .collect()
+}
+
+#[test]
+fn capture_round_trip() {
+    let transport = Scripted::new(RESPONSES);
+    let mut client = GurtClient::new(Capture::new(transport, Writer::new(Vec::new()).unwrap()));
+    block_on(async {
+        let mut buf = [0u8; 256];
+        client.handshake("example.com", "test/1.0").await.unwrap();
+        client.read_handshake_response(&mut buf).await.unwrap();
+        client
+            .request_no_body(Method::Get, "/", "example.com", None)
+            .await
+            .unwrap();
+        let mut response = client.response_reader();
+        assert_eq!(
+            response.read_status_line(&mut buf).await.unwrap().status,
+            StatusCode::Ok
+        );
+        while response.read_header(&mut buf).await.unwrap().is_some() {}
+        let mut body = [0u8; 5];
+        response.read_body_exact(&mut body).await.unwrap();
+    });
+
+    let (transport, writer) = client.transport.into_parts();
+    let data = writer.finish().unwrap();
+    assert!(data.starts_with(MAGIC));
+    let records: Vec<Record<'_>> = capture::records(&data)
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(joined(&records, Direction::Sent), transport.written());
+    assert_eq!(joined(&records, Direction::Received), RESPONSES);
+    assert!(records.windows(2).all(|w| w[0].timestamp <= w[1].timestamp));
+}
+
+#[test]
+fn closure_sinks() {
+    let mut sent = 0;
+    let mut received = 0;
+    let transport = Scripted::new(b"abc");
+    let mut capture = Capture::new(
+        transport,
+        |direction: Direction, bytes: &[u8]| match direction {
+            Direction::Sent => sent += bytes.len(),
+            Direction::Received => received += bytes.len(),
+        },
+    );
+    block_on(async {
+        capture.write_all(b"hello").await.unwrap();
+        let mut buf = [0u8; 8];
+        assert_eq!(capture.read(&mut buf).await.unwrap(), 3);
+        // End of stream records nothing
+        assert_eq!(capture.read(&mut buf).await.unwrap(), 0);
+    });
+    capture.sink_mut().record(Direction::Sent, b"!");
+    drop(capture);
+    assert_eq!((sent, received), (6, 3));
+}
+
+#[test]
+fn malformed_captures() {
+    assert_eq!(
+        capture::records(b"GURT/1.0.0").unwrap_err(),
+        CaptureError::NotACapture
+    );
+
+    let mut writer = Writer::new(Vec::new()).unwrap();
+    writer.record(Direction::Sent, b"GET");
+    let data = writer.finish().unwrap();
+    let cut = &data[..data.len() - 1];
+    let mut records = capture::records(cut).unwrap();
+    assert_eq!(
+        records.next(),
+        Some(Err(CaptureError::Truncated(MAGIC.len())))
+    );
+    assert_eq!(records.next(), None);
+
+    let mut bad = data.clone();
+    bad[MAGIC.len()] = 7;
+    assert_eq!(
+        capture::records(&bad).unwrap().next(),
+        Some(Err(CaptureError::InvalidDirection(MAGIC.len())))
+    );
+}
@@ -0,0 +1,265 @@
+//! Recording exchanges into cassettes and replaying them offline
+
+use embassy_futures::block_on;
+use embassy_futures::join::join;
+use embedded_io_async::{Read, Write};
+use portal_solutions_yo_gurt::capture::{Capture, Direction, Sink};
+use portal_solutions_yo_gurt::cassette::{
+    CASSETTE_HEADER, Cassette, CassetteError, Exchange, Recording, Replay, ReplayError,
+};
+use portal_solutions_yo_gurt::testing::{MockPeer, Reply, duplex};
+use portal_solutions_yo_gurt::{GurtClient, GurtError, Method, StatusCode};
+
+/// Send a request on `client` and read the whole response
+async fn fetch<T: Read + Write>(
+    client: &mut GurtClient<T>,
+    method: Method,
+    path: &str,
+    body: Option<&[u8]>,
+) -> Result<(StatusCode, Vec<u8>), GurtError<T::Error>> {
+    let mut buf = [0u8; 256];
+    match body {
+        Some(body) => {
+            let headers = [("content-type", "text/plain")];
+            let mut writer = client
+                .request(method, path, "gurt.test", None, &headers, Some(body.len()))
+                .await?;
+            writer.write(body).await?;
+            writer.finish().await?;
+        }
+        None => {
+            client
+                .request_no_body(method, path, "gurt.test", None)
+                .await?
+        }
+    }
+    let mut response = client.response_reader();
+    let status = response.read_status_line(&mut buf).await?.status;
+    while response.read_header(&mut buf).await?.is_some() {}
+    let length = match method {
+        Method::Head => 0,
+        _ => response.content_length().unwrap_or(0),
+    };
+    let mut body = vec![0u8; length];
+    response.read_body_exact(&mut body).await?;
+    Ok((status, body))
+}
+
+/// The conversation under test, the same whether recorded or replayed
+async fn exercise<T: Read + Write>(client: &mut GurtClient<T>) -> Vec<(StatusCode, Vec<u8>)> {
+    client.handshake("gurt.test", "test/1.0").await.unwrap();
+    client
+        .read_handshake_response(&mut [0u8; 256])
+        .await
+        .unwrap();
+    let mut results = Vec::new();
+    for (method, path, body) in [
+        (Method::Get, "/a", None),
+        (Method::Post, "/b", Some(&b"data"[..])),
+        (Method::Head, "/c", None),
+    ] {
+        results.push(fetch(client, method, path, body).await.unwrap());
+    }
+    results
+}
+
+fn record() -> (Cassette, Vec<(StatusCode, Vec<u8>)>) {
+    let (client_io, server_io) = duplex(64);
+    let server = MockPeer::new()
+        .expect_handshake("gurt.test", "test/1.0")
+        .reply(Reply::new(StatusCode::SwitchingProtocols))
+        .expect_request(
+            Method::Get,
+            "/a",
+            &[("host", "gurt.test"), ("user-agent", "yo-gurt/0.1")],
+            b"",
+        )
+        .reply(Reply::new(StatusCode::Ok).body(b"alpha"))
+        .expect_request(
+            Method::Post,
+            "/b",
+            &[
+                ("host", "gurt.test"),
+                ("content-type", "text/plain"),
+                ("content-length", "4"),
+                ("user-agent", "yo-gurt/0.1"),
+            ],
+            b"data",
+        )
+        .reply(Reply::new(StatusCode::Created).body(b"stored"))
+        .expect_request(
+            Method::Head,
+            "/c",
+            &[("host", "gurt.test"), ("user-agent", "yo-gurt/0.1")],
+            b"",
+        )
+        .reply(Reply::new(StatusCode::Ok).header("content-length", "100"))
+        .run(server_io);
+    let mut client = GurtClient::new(Capture::new(client_io, Recording::new()));
+    let ((), results) = block_on(join(server, exercise(&mut client)));
+    (client.transport.sink().cassette().unwrap(), results)
+}
+
+#[test]
+fn record_and_replay() {
+    let (cassette, recorded) = record();
+    assert_eq!(
+        recorded,
+        [
+            (StatusCode::Ok, b"alpha".to_vec()),
+            (StatusCode::Created, b"stored".to_vec()),
+            (StatusCode::Ok, Vec::new()),
+        ]
+    );
+    let requests: Vec<_> = cassette
+        .exchanges()
+        .iter()
+        .map(|exchange| (exchange.method(), exchange.path()))
+        .collect();
+    assert_eq!(
+        requests,
+        [
+            (Method::Handshake, "/"),
+            (Method::Get, "/a"),
+            (Method::Post, "/b"),
+            (Method::Head, "/c"),
+        ]
+    );
+    let post = &cassette.exchanges()[2];
+    assert_eq!(post.header("content-type"), Some("text/plain"));
+    assert!(post.request().ends_with(b"\r\n\r\ndata"));
+    assert!(post.response().ends_with(b"\r\n\r\nstored"));
+
+    let bytes = cassette.to_bytes();
+    assert!(bytes.starts_with(format!("{CASSETTE_HEADER}\nrequest ").as_bytes()));
+    assert_eq!(Cassette::from_bytes(&bytes).unwrap(), cassette);
+    let path = std::env::temp_dir().join(format!("yo-gurt-{}.cassette", std::process::id()));
+    cassette.save(&path).unwrap();
+    let loaded = Cassette::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(loaded, cassette);
+
+    let mut client = GurtClient::new(Replay::new(loaded).match_header("host"));
+    assert!(!client.transport.is_finished());
+    let replayed = block_on(exercise(&mut client));
+    assert_eq!(replayed, recorded);
+    assert!(client.transport.is_finished());
+    assert_eq!(client.transport.unused().count(), 0);
+}
+
+#[test]
+fn replay_matching() {
+    let (cassette, _) = record();
+
+    // Requests are matched by method and path, not by order
+    let mut client = GurtClient::new(Replay::new(cassette.clone()));
+    block_on(async {
+        client.handshake("gurt.test", "test/1.0").await.unwrap();
+        client
+            .read_handshake_response(&mut [0u8; 256])
+            .await
+            .unwrap();
+        let head = fetch(&mut client, Method::Head, "/c", None).await.unwrap();
+        assert_eq!(head, (StatusCode::Ok, Vec::new()));
+        let get = fetch(&mut client, Method::Get, "/a", None).await.unwrap();
+        assert_eq!(get, (StatusCode::Ok, b"alpha".to_vec()));
+        // Each exchange is served once
+        assert_eq!(
+            fetch(&mut client, Method::Get, "/a", None).await,
+            Err(GurtError::Io(ReplayError::Unmatched))
+        );
+    });
+    let unused: Vec<_> = client.transport.unused().map(Exchange::path).collect();
+    assert_eq!(unused, ["/b"]);
+
+    // A header named for matching must agree with the recording
+    let mut client = GurtClient::new(Replay::new(cassette.clone()).match_header("Host"));
+    block_on(async {
+        client
+            .handshake("other.test", "test/1.0")
+            .await
+            .unwrap_err();
+        assert_eq!(
+            client
+                .request_no_body(Method::Get, "/a", "other.test", None)
+                .await,
+            Err(GurtError::Io(ReplayError::Unmatched))
+        );
+    });
+
+    // A request written a byte at a time is served once its body is complete
+    let post = cassette.exchanges()[2].clone();
+    let mut replay = Replay::new(cassette.clone());
+    let mut buf = vec![0u8; post.response().len()];
+    for byte in post.request() {
+        assert_eq!(
+            block_on(replay.read(&mut buf)),
+            Err(ReplayError::NoResponse)
+        );
+        block_on(replay.write(core::slice::from_ref(byte))).unwrap();
+    }
+    block_on(replay.read_exact(&mut buf)).unwrap();
+    assert_eq!(buf, post.response());
+    assert_eq!(replay.unused().count(), 3);
+
+    // Nothing to read before a request
+    let mut replay = Replay::new(cassette);
+    let mut buf = [0u8; 8];
+    assert_eq!(
+        block_on(replay.read(&mut buf)),
+        Err(ReplayError::NoResponse)
+    );
+    assert!(matches!(
+        block_on(replay.write(b"NOPE / GURT/1.0.0\r\n\r\n")),
+        Err(ReplayError::Invalid(_))
+    ));
+}
+
+#[test]
+fn unfinished_recordings_and_malformed_cassettes() {
+    // A request whose response never completed is left out
+    let mut recording = Recording::new();
+    recording.record(
+        Direction::Sent,
+        b"GET /a GURT/1.0.0\r\nhost: gurt.test\r\n\r\n",
+    );
+    recording.record(
+        Direction::Received,
+        b"GURT/1.0.0 200 OK\r\ncontent-length: 5\r\n\r\nab",
+    );
+    assert_eq!(recording.cassette().unwrap(), Cassette::new());
+    recording.record(Direction::Received, b"cde");
+    assert_eq!(recording.cassette().unwrap().exchanges().len(), 1);
+    // A bodiless status needs no length, but any other response does
+    recording.record(
+        Direction::Sent,
+        b"GET /b GURT/1.0.0\r\n\r\nGET /c GURT/1.0.0\r\n\r\n",
+    );
+    recording.record(
+        Direction::Received,
+        b"GURT/1.0.0 204 NO_CONTENT\r\n\r\nGURT/1.0.0 200 OK\r\n\r\nrest",
+    );
+    assert_eq!(recording.cassette(), Err(CassetteError::UnframedResponse));
+
+    assert_eq!(
+        Cassette::from_bytes(b"GURT/1.0.0 200 OK\r\n"),
+        Err(CassetteError::NotACassette)
+    );
+    let header = CASSETTE_HEADER.len() + 1;
+    let cut = format!("{CASSETTE_HEADER}\nrequest 30\nGET / GURT/1.0.0\r\n\r\n\n");
+    assert_eq!(
+        Cassette::from_bytes(cut.as_bytes()),
+        Err(CassetteError::Malformed(header))
+    );
+    let no_response = format!("{CASSETTE_HEADER}\nrequest 20\nGET / GURT/1.0.0\r\n\r\n\n");
+    assert_eq!(
+        Cassette::from_bytes(no_response.as_bytes()),
+        Err(CassetteError::Malformed(no_response.len()))
+    );
+    let partial = format!("{CASSETTE_HEADER}\nrequest 7\nGET / G\nresponse 0\n\n");
+    assert_eq!(
+        Cassette::from_bytes(partial.as_bytes()),
+        Err(CassetteError::InvalidRequest(GurtError::UnexpectedEof))
+    );
+    assert!(Exchange::new(b"GET / GURT/1.0.0\r\n\r\nGET".to_vec(), Vec::new()).is_err());
+}
@@ -0,0 +1,350 @@
+//! GurtClient and ResponseReader against the scripted mock peer
+
+use embassy_futures::block_on;
+use embassy_futures::join::join;
+
+use embedded_io_async::{ErrorKind, Read, ReadExactError, Write};
+use portal_solutions_yo_gurt::testing::{DuplexError, MockPeer, Reply, Scripted, duplex};
+use portal_solutions_yo_gurt::{
+    GurtClient, GurtError, Limit, MAX_MESSAGE_SIZE, Method, ProtocolError, RequestError,
+    RequestHead, StatusCode,
+};
+
+#[test]
+fn handshake_then_get() {
+    let (client_io, server_io) = duplex(64);
+    let server = MockPeer::new()
+        .expect_handshake("example.com", "test/1.0")
+        .reply(Reply::new(StatusCode::SwitchingProtocols).header("gurt-version", "1.0.0"))
+        .expect_request(
+            Method::Get,
+            "/api/data",
+            &[("host", "example.com"), ("user-agent", "yo-gurt/0.1")],
+            b"",
+        )
+        .reply(
+            Reply::new(StatusCode::Ok)
+                .header("content-type", "text/plain")
+                .body(b"hello"),
+        )
+        .run(server_io);
+
+    let client = async {
+        let mut client = GurtClient::new(client_io);
+        let mut buf = [0u8; 128];
+
+        client.handshake("example.com", "test/1.0").await.unwrap();
+        let mut response = client.response_reader();
+        let status = response.read_status_line(&mut buf).await.unwrap();
+        assert_eq!(status.status, StatusCode::SwitchingProtocols);
+        let header = response.read_header(&mut buf).await.unwrap().unwrap();
+        assert_eq!(header.name(&buf), b"gurt-version");
+        assert_eq!(header.value(&buf), b"1.0.0");
+        assert!(response.read_header(&mut buf).await.unwrap().is_none());
+
+        client
+            .request_no_body(Method::Get, "/api/data", "example.com", None)
+            .await
+            .unwrap();
+        let mut response = client.response_reader();
+        let status = response.read_status_line(&mut buf).await.unwrap();
+        assert_eq!(status.status, StatusCode::Ok);
+        assert_eq!(status.bytes_read, b"GURT/1.0.0 200 OK\r\n".len());
+        let mut content_length = None;
+        while let Some(header) = response.read_header(&mut buf).await.unwrap() {
+            if header.name(&buf) == b"content-length" {
+                content_length = Some(header.value(&buf).to_vec());
+            }
+        }
+        assert_eq!(content_length.as_deref(), Some(&b"5"[..]));
+
+        let mut body = response.body(5);
+        let mut out = [0u8; 16];
+        let mut len = 0;
+        while !body.is_done() {
+            len += body.read(&mut out[len..]).await.unwrap();
+        }
+        assert_eq!(&out[..len], b"hello");
+        assert_eq!(body.read(&mut out).await.unwrap(), 0);
+    };
+
+    block_on(join(client, server));
+}
+
+#[test]
+fn request_with_body_and_headers() {
+    let (client_io, server_io) = duplex(16);
+    let server = MockPeer::new()
+        .expect_request(
+            Method::Post,
+            "/submit",
+            &[
+                ("host", "example.com"),
+                ("content-type", "application/json"),
+                ("content-length", "11"),
+                ("user-agent", "yo-gurt/0.1"),
+            ],
+            b"{\"a\":true}\n",
+        )
+        .expect_request(
+            Method::Put,
+            "/item",
+            &[
+                ("host", "example.com"),
+                ("accept", "*/*"),
+                ("content-length", "0"),
+                ("user-agent", "agent"),
+            ],
+            b"",
+        )
+        .expect_eof()
+        .reply(Reply::new(StatusCode::Ok))
+        .run(server_io);
+
+    let client = async {
+        let mut client = GurtClient::new(client_io);
+        let mut body = client
+            .request_with_body(
+                Method::Post,
+                "/submit",
+                "example.com",
+                None,
+                Some("application/json"),
+                11,
+            )
+            .await
+            .unwrap();
+        body.write(b"{\"a\":true}\n").await.unwrap();
+        body.finish().await.unwrap();
+
+        client
+            .request(
+                Method::Put,
+                "/item",
+                "example.com",
+                Some("agent"),
+                &[("accept", "*/*")],
+                Some(0),
+            )
+            .await
+            .unwrap()
+            .finish()
+            .await
+            .unwrap();
+
+        // Half-close: the response still arrives after the write side is closed
+        client.close().await.unwrap();
+        let mut buf = [0u8; 64];
+        let mut response = client.response_reader();
+        let status = response.read_status_line(&mut buf).await.unwrap();
+        assert_eq!(status.status, StatusCode::Ok);
+    };
+
+    block_on(join(client, server));
+}
+
+#[test]
+fn status_line_errors() {
+    let (client_io, server_io) = duplex(64);
+    let server = MockPeer::new()
+        .send(b"HTTP/1.1 200 OK\r\n")
+        .send(b"GURT/1.0.0 299 ODD\r\n")
+        .send(b"GURT/1.0.0 200 O")
+        .run(server_io);
+
+    let client = async {
+        let mut client = GurtClient::new(client_io);
+        let mut buf = [0u8; 64];
+        let mut response = client.response_reader();
+        assert!(matches!(
+            response.read_status_line(&mut buf).await,
+            Err(GurtError::Protocol(ProtocolError::InvalidProtocol))
+        ));
+        assert!(matches!(
+            response.read_status_line(&mut buf).await,
+            Err(GurtError::Protocol(ProtocolError::InvalidStatusLine))
+        ));
+        assert!(matches!(
+            response.read_status_line(&mut buf).await,
+            Err(GurtError::UnexpectedEof)
+        ));
+    };
+
+    block_on(join(client, server));
+}
+
+#[test]
+fn invalid_requests_write_nothing() {
+    let (client_io, server_io) = duplex(64);
+    let server = MockPeer::new()
+        .expect_request(
+            Method::Post,
+            "/",
+            &[
+                ("host", "example.com"),
+                ("content-length", "2"),
+                ("user-agent", "yo-gurt/0.1"),
+            ],
+            b"ok",
+        )
+        .expect_eof()
+        .run(server_io);
+
+    let client = async {
+        let mut client = GurtClient::new(client_io);
+        let mut request = async |path, host, headers: &[(&str, &str)], length| {
+            client
+                .request(Method::Post, path, host, None, headers, length)
+                .await
+                .map(|_| ())
+        };
+        let invalid = |e| Err(GurtError::InvalidRequest(e));
+        assert_eq!(
+            request("/a b", "example.com", &[], None).await,
+            invalid(RequestError::InvalidPath)
+        );
+        assert_eq!(
+            request("/", "example.com\r\nx: y", &[], None).await,
+            invalid(RequestError::InvalidHost)
+        );
+        assert_eq!(
+            request("/", "example.com", &[("X-Upper", "v")], None).await,
+            invalid(RequestError::InvalidHeaderName)
+        );
+        assert_eq!(
+            request("/", "example.com", &[("x", "v\r\nhost: evil")], None).await,
+            invalid(RequestError::InvalidHeaderValue)
+        );
+        assert_eq!(
+            request("/", "example.com", &[("content-length", "0")], None).await,
+            invalid(RequestError::ReservedHeader)
+        );
+        assert_eq!(
+            request("/", "example.com", &[], Some(MAX_MESSAGE_SIZE + 1)).await,
+            Err(GurtError::LimitExceeded(Limit::MessageTooLarge))
+        );
+
+        let mut body = client
+            .request(Method::Post, "/", "example.com", None, &[], Some(2))
+            .await
+            .unwrap();
+        assert_eq!(
+            body.write(b"okay").await,
+            invalid(RequestError::BodyTooLong)
+        );
+        body.write(b"ok").await.unwrap();
+        body.finish().await.unwrap();
+    };
+
+    block_on(join(client, server));
+}
+
+#[test]
+fn handshake_rejected_and_truncated_body() {
+    let (client_io, server_io) = duplex(64);
+    let server = MockPeer::new()
+        .expect_handshake("example.com", "test/1.0")
+        .reply(Reply::new(StatusCode::Forbidden).header("x", "y"))
+        .send(b"GURT/1.0.0 200 OK\r\ncontent-length: 5\r\n\r\nabc")
+        .run(server_io);
+
+    let client = async {
+        let mut client = GurtClient::new(client_io);
+        let mut buf = [0u8; 64];
+        client.handshake("example.com", "test/1.0").await.unwrap();
+        assert_eq!(
+            client.read_handshake_response(&mut buf).await,
+            Err(GurtError::HandshakeRejected(StatusCode::Forbidden))
+        );
+
+        let mut response = client.response_reader();
+        response.read_status_line(&mut buf).await.unwrap();
+        while response.read_header(&mut buf).await.unwrap().is_some() {}
+        let length = response.content_length().unwrap();
+        let mut body = response.body(length);
+        let mut out = [0u8; 8];
+        assert_eq!(
+            body.read_exact(&mut out[..5]).await,
+            Err(ReadExactError::Other(GurtError::UnexpectedEof))
+        );
+    };
+
+    block_on(join(client, server));
+}
+
+#[test]
+fn head_written_in_one_write() {
+    let headers = [("accept", "*/*")];
+    let head = RequestHead::new(Method::Post, "/submit", "example.com")
+        .headers(&headers)
+        .content_length(42);
+    let expected: &[u8] = b"POST /submit GURT/1.0.0\r\nhost: example.com\r\naccept: */*\r\n\
+        content-length: 42\r\nuser-agent: yo-gurt/0.1\r\n\r\n";
+    assert_eq!(head.encoded_len(), expected.len());
+    assert_eq!(head.slices().collect::<Vec<_>>().concat(), expected);
+
+    block_on(async {
+        let mut client = GurtClient::new(Scripted::default());
+        client.handshake("example.com", "test/1.0").await.unwrap();
+        assert_eq!(client.transport.flushes(), 1);
+        let mut body = client
+            .request(
+                Method::Post,
+                "/submit",
+                "example.com",
+                None,
+                &headers,
+                Some(42),
+            )
+            .await
+            .unwrap();
+        body.write(&[b'x'; 40]).await.unwrap();
+        assert_eq!(
+            body.finish().await,
+            Err(GurtError::InvalidRequest(RequestError::BodyIncomplete))
+        );
+        let transport = &client.transport;
+        assert_eq!(transport.writes().len(), 3);
+        assert_eq!(transport.writes()[1], expected);
+        // The head is flushed together with the body once the request is complete
+        assert_eq!(transport.flushes(), 1);
+
+        // Streamed when the buffer is too small
+        let mut client = GurtClient::new(Scripted::default());
+        let mut buf = [0u8; 16];
+        let mut body = client.send_head(&head, &mut buf).await.unwrap();
+        body.write(&[b'x'; 42]).await.unwrap();
+        body.finish().await.unwrap();
+        let transport = &client.transport;
+        assert!(transport.writes().len() > 2);
+        assert_eq!(transport.flushes(), 1);
+        assert!(transport.written().starts_with(expected));
+    });
+}
+
+#[test]
+fn duplex_closed_peer() {
+    let (mut a, b) = duplex(4);
+    drop(b);
+    block_on(async {
+        let mut buf = [0u8; 4];
+        assert_eq!(a.read(&mut buf).await, Ok(0));
+        let err = a.write(b"x").await.unwrap_err();
+        assert_eq!(err, DuplexError::BrokenPipe);
+        assert_eq!(embedded_io_async::Error::kind(&err), ErrorKind::BrokenPipe);
+    });
+}
+
+#[test]
+#[should_panic(expected = "mock peer received unexpected bytes")]
+fn mock_rejects_unexpected_request() {
+    let (client_io, server_io) = duplex(64);
+    let server = MockPeer::new()
+        .expect_handshake("example.com", "test/1.0")
+        .run(server_io);
+    let client = async {
+        let mut client = GurtClient::new(client_io);
+        let _ = client.handshake("example.org", "test/1.0").await;
+    };
+    block_on(join(client, server));
+}
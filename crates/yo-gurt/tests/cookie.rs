@@ -0,0 +1,349 @@
+//! Cookies: set-cookie parsing, jar scoping and expiry, and the three stores
+
+use portal_solutions_yo_gurt::Limit;
+use portal_solutions_yo_gurt::cookie::{
+    Cookie, CookieJar, CookieStore, FileStore, FixedStore, MAX_COOKIE_SIZE, MemoryStore, SetCookie,
+};
+
+/// 2015-10-21 07:28:00 UTC
+const NOW: u64 = 1_445_412_480;
+
+fn header(jar: &CookieJar<impl CookieStore>, host: &str, path: &str, now: u64) -> String {
+    let mut buf = [0u8; 512];
+    let header = jar.cookie_header(host, path, now, &mut buf).unwrap();
+    assert_eq!(
+        header.map(str::to_owned),
+        jar.cookie_string(host, path, now)
+    );
+    header.unwrap_or_default().to_owned()
+}
+
+#[test]
+fn parse_attributes() {
+    let cookie = SetCookie::parse(
+        " id = a3fWa ; Expires=Wed, 21 Oct 2015 07:28:00 GMT; Max-Age=60; domain=.Example.web; \
+         Path=/docs; Secure; HttpOnly; SameSite=Lax",
+    )
+    .unwrap();
+    assert_eq!(
+        cookie,
+        SetCookie {
+            name: "id",
+            value: "a3fWa",
+            domain: Some("Example.web"),
+            path: Some("/docs"),
+            expires: Some(NOW),
+            max_age: Some(60),
+            secure: true,
+            http_only: true,
+        }
+    );
+
+    let cookie = SetCookie::parse("q=\"quoted\"; path=relative; max-age=soon; domain=").unwrap();
+    assert_eq!(cookie.value, "\"quoted\"");
+    assert_eq!(
+        (cookie.path, cookie.max_age, cookie.domain),
+        (None, None, None)
+    );
+    assert_eq!(SetCookie::parse("empty=").unwrap().value, "");
+    assert_eq!(
+        SetCookie::parse("a=1; max-age=-5; max-age=99999999999999999999")
+            .unwrap()
+            .max_age,
+        Some(i64::MAX)
+    );
+
+    for invalid in ["", "novalue", "=value", " =x", "a=b\u{7}", "a\nb=c"] {
+        assert_eq!(SetCookie::parse(invalid), None, "{invalid:?}");
+    }
+}
+
+#[test]
+fn expiry_dates() {
+    let expires = |date: &str| {
+        SetCookie::parse(&format!("a=b; expires={date}"))
+            .unwrap()
+            .expires
+    };
+    for date in [
+        "Wed, 21 Oct 2015 07:28:00 GMT",
+        "Wednesday, 21-Oct-15 07:28:00 GMT",
+        "Wed Oct 21 07:28:00 2015",
+        "21 oct 2015 7:28:0",
+    ] {
+        assert_eq!(expires(date), Some(NOW), "{date}");
+    }
+    assert_eq!(expires("Thu, 01 Jan 1970 00:00:00 GMT"), Some(0));
+    assert_eq!(expires("Sat, 01 Jan 1601 00:00:00 GMT"), Some(0));
+    assert_eq!(expires("Tue, 29 Feb 2000 12:00:00 GMT"), Some(951_825_600));
+    assert_eq!(
+        expires("Fri, 31 Dec 9999 23:59:59 GMT"),
+        Some(253_402_300_799)
+    );
+    for invalid in [
+        "yesterday",
+        "Wed, 32 Oct 2015 07:28:00 GMT",
+        "Wed, 21 Oct 2015 24:00:00 GMT",
+        "Wed, 21 Foo 2015 07:28:00 GMT",
+        "Wed, 21 Oct 1500 07:28:00 GMT",
+        "Wed, 21 Oct 2015",
+    ] {
+        assert_eq!(expires(invalid), None, "{invalid}");
+    }
+}
+
+#[test]
+fn domain_scoping() {
+    let mut jar = CookieJar::new(MemoryStore::new());
+    assert!(jar.set_cookie("www.example.web", "/", "host=1", NOW));
+    assert!(jar.set_cookie("www.example.web", "/", "site=2; Domain=example.web", NOW));
+    assert!(jar.set_cookie(
+        "www.example.web",
+        "/",
+        "self=3; Domain=WWW.example.web",
+        NOW
+    ));
+    // Not a parent of the host, a single label, or a sibling
+    assert!(!jar.set_cookie("www.example.web", "/", "tld=4; Domain=web", NOW));
+    assert!(!jar.set_cookie("www.example.web", "/", "other=5; Domain=other.web", NOW));
+    assert!(!jar.set_cookie("www.example.web", "/", "sub=6; Domain=api.example.web", NOW));
+
+    assert_eq!(
+        header(&jar, "www.example.web", "/", NOW),
+        "host=1; site=2; self=3"
+    );
+    assert_eq!(
+        header(&jar, "WWW.Example.web.", "/", NOW),
+        "host=1; site=2; self=3"
+    );
+    assert_eq!(header(&jar, "example.web", "/", NOW), "site=2");
+    assert_eq!(
+        header(&jar, "a.www.example.web", "/", NOW),
+        "site=2; self=3"
+    );
+    assert_eq!(header(&jar, "notexample.web", "/", NOW), "");
+    assert_eq!(jar.cookie_string("other.web", "/", NOW), None);
+
+    // Single-label hosts and addresses only get host-only cookies
+    assert!(jar.set_cookie("localhost", "/", "dev=1; Domain=localhost", NOW));
+    assert!(!jar.set_cookie("10.0.0.1", "/", "ip=1; Domain=0.0.1", NOW));
+    assert!(jar.set_cookie("10.0.0.1", "/", "ip=2; Domain=10.0.0.1", NOW));
+    assert_eq!(header(&jar, "localhost", "/", NOW), "dev=1");
+    assert_eq!(header(&jar, "sub.localhost", "/", NOW), "");
+    assert_eq!(header(&jar, "10.0.0.1", "/", NOW), "ip=2");
+}
+
+#[test]
+fn path_scoping_and_order() {
+    let mut jar = CookieJar::new(MemoryStore::new());
+    assert!(jar.set_cookie("example.web", "/", "root=1; Path=/", NOW));
+    assert!(jar.set_cookie("example.web", "/docs/api/v1?x=/y", "default=2", NOW));
+    assert!(jar.set_cookie("example.web", "/", "docs=3; Path=/docs", NOW));
+    assert!(jar.set_cookie("example.web", "/", "slash=4; Path=/docs/", NOW));
+    assert!(jar.set_cookie("example.web", "/file", "top=5", NOW));
+
+    let cookie = |path: &str| header(&jar, "example.web", path, NOW);
+    assert_eq!(
+        cookie("/docs/api/v1/x"),
+        "default=2; slash=4; docs=3; root=1; top=5"
+    );
+    assert_eq!(
+        cookie("/docs/api"),
+        "default=2; slash=4; docs=3; root=1; top=5"
+    );
+    assert_eq!(cookie("/docs/apiary"), "slash=4; docs=3; root=1; top=5");
+    assert_eq!(cookie("/docs"), "docs=3; root=1; top=5");
+    assert_eq!(cookie("/docs?page=2"), "docs=3; root=1; top=5");
+    assert_eq!(cookie("/docsearch"), "root=1; top=5");
+    assert_eq!(cookie("/"), "root=1; top=5");
+}
+
+#[test]
+fn expiry_and_replacement() {
+    let mut jar = CookieJar::new(MemoryStore::new());
+    assert!(jar.set_cookie("example.web", "/", "a=1; Max-Age=60", NOW));
+    assert!(jar.set_cookie(
+        "example.web",
+        "/",
+        "b=2; Expires=Wed, 21 Oct 2015 08:28:00 GMT",
+        NOW
+    ));
+    // Max-Age wins over Expires
+    assert!(jar.set_cookie(
+        "example.web",
+        "/",
+        "c=3; Max-Age=3600; Expires=Thu, 01 Jan 1970 00:00:00 GMT",
+        NOW
+    ));
+    assert!(jar.set_cookie("example.web", "/", "session=4", NOW));
+    assert_eq!(
+        header(&jar, "example.web", "/", NOW),
+        "a=1; b=2; c=3; session=4"
+    );
+    assert_eq!(
+        header(&jar, "example.web", "/", NOW + 60),
+        "b=2; c=3; session=4"
+    );
+    assert_eq!(header(&jar, "example.web", "/", NOW + 3600), "session=4");
+
+    // Same name, domain and path replaces; a different path does not
+    assert!(jar.set_cookie("example.web", "/", "session=5", NOW));
+    assert!(jar.set_cookie("example.web", "/", "session=6; Path=/app", NOW));
+    assert_eq!(jar.store().len(), 5);
+    assert_eq!(
+        header(&jar, "example.web", "/app", NOW),
+        "session=6; a=1; b=2; c=3; session=5"
+    );
+
+    // Expired cookies delete their namesake
+    assert!(jar.set_cookie("example.web", "/", "session=; Max-Age=0", NOW));
+    assert!(jar.set_cookie(
+        "example.web",
+        "/",
+        "b=; Expires=Thu, 01 Jan 1970 00:00:00 GMT",
+        NOW
+    ));
+    assert_eq!(
+        header(&jar, "example.web", "/app", NOW),
+        "session=6; a=1; c=3"
+    );
+
+    // Setting a cookie drops the ones that expired
+    assert!(jar.set_cookie("example.web", "/", "late=1", NOW + 120));
+    assert_eq!(jar.store().len(), 3);
+    jar.remove_expired(NOW + 3600);
+    assert_eq!(jar.store().len(), 2);
+}
+
+#[test]
+fn prefixes_and_size() {
+    let mut jar = CookieJar::new(MemoryStore::new());
+    let set = |jar: &mut CookieJar<MemoryStore>, header: &str| {
+        jar.set_cookie("www.example.web", "/app/page", header, NOW)
+    };
+    assert!(!set(&mut jar, "__Secure-a=1"));
+    assert!(set(&mut jar, "__Secure-a=1; Secure; Domain=example.web"));
+    assert!(!set(&mut jar, "__Host-b=1; Secure"));
+    assert!(!set(&mut jar, "__Host-b=1; Path=/"));
+    assert!(!set(
+        &mut jar,
+        "__Host-b=1; Secure; Path=/; Domain=www.example.web"
+    ));
+    assert!(set(&mut jar, "__host-b=1; Secure; Path=/"));
+    // Secure cookies go out over every GURT connection, which is always TLS
+    assert_eq!(
+        header(&jar, "www.example.web", "/app/", NOW),
+        "__Secure-a=1; __host-b=1"
+    );
+
+    let value = "v".repeat(MAX_COOKIE_SIZE - 1);
+    assert!(set(&mut jar, &format!("n={value}")));
+    assert!(!set(&mut jar, &format!("nn={value}")));
+
+    let mut buf = [0u8; 100];
+    assert_eq!(
+        jar.cookie_header("www.example.web", "/app/", NOW, &mut buf),
+        Err(Limit::BufferTooSmall)
+    );
+    assert_eq!(jar.cookie_header("nobody.web", "/", NOW, &mut []), Ok(None));
+}
+
+#[test]
+fn fixed_store() {
+    let mut jar = CookieJar::new(FixedStore::<3, 32>::new());
+    assert!(jar.store().is_empty());
+    assert!(jar.set_cookie("example.web", "/", "a=1", NOW));
+    assert!(jar.set_cookie("example.web", "/", "b=2; Max-Age=10", NOW));
+    assert!(jar.set_cookie("example.web", "/", "c=3", NOW));
+    // Name, value, domain and path must fit in 32 bytes
+    assert!(!jar.set_cookie("example.web", "/", &format!("d={}", "x".repeat(20)), NOW));
+    assert_eq!(jar.store().len(), 3);
+
+    // Replacing keeps the slot and age; a new cookie evicts the oldest
+    assert!(jar.set_cookie("example.web", "/", "a=4", NOW));
+    assert!(jar.set_cookie("example.web", "/", "e=5", NOW));
+    assert_eq!(header(&jar, "example.web", "/", NOW), "e=5; b=2; c=3");
+    // Expired cookies make room before anything is evicted
+    assert!(jar.set_cookie("example.web", "/", "f=6", NOW + 10));
+    assert_eq!(header(&jar, "example.web", "/", NOW + 10), "e=5; f=6; c=3");
+    assert!(jar.store_mut().remove("c", "EXAMPLE.web", "/"));
+    assert!(!jar.store_mut().remove("c", "example.web", "/"));
+    assert_eq!(jar.store().len(), 2);
+}
+
+#[test]
+fn file_store() {
+    let path = std::env::temp_dir().join(format!("yo-gurt-cookies-{}.txt", std::process::id()));
+    std::fs::write(
+        &path,
+        "# Netscape HTTP Cookie File\n\
+         # written by hand\n\
+         \n\
+         .example.web\tTRUE\t/\tFALSE\t2000000000\tsite\tone\n\
+         #HttpOnly_www.example.web\tFALSE\t/app\tTRUE\t2000000000\tsession\ttwo\n\
+         www.example.web\tFALSE\t/\tFALSE\t0\tempty\t\n",
+    )
+    .unwrap();
+    {
+        let mut jar = CookieJar::new(FileStore::open(&path).unwrap());
+        assert_eq!(jar.store().cookies().len(), 3);
+        let mut http_only = Vec::new();
+        jar.store().for_each(&mut |cookie: &Cookie<'_>| {
+            if cookie.http_only {
+                http_only.push((cookie.name.to_owned(), cookie.secure, cookie.host_only));
+            }
+        });
+        assert_eq!(http_only, [("session".to_owned(), true, true)]);
+        assert_eq!(
+            header(&jar, "www.example.web", "/app", NOW),
+            "session=two; site=one; empty="
+        );
+        assert!(jar.set_cookie("example.web", "/", "new=3; Max-Age=3600", NOW));
+        assert!(jar.set_cookie("example.web", "/", "temporary=4", NOW));
+        assert!(jar.set_cookie(
+            "www.example.web",
+            "/",
+            "site=; Domain=example.web; Max-Age=0",
+            NOW
+        ));
+        // Dropped here, saving the changes
+    }
+    let saved = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(
+        saved,
+        "# Netscape HTTP Cookie File\n\
+         #HttpOnly_www.example.web\tFALSE\t/app\tTRUE\t2000000000\tsession\ttwo\n\
+         example.web\tFALSE\t/\tFALSE\t1445416080\tnew\t3\n"
+    );
+    let jar = CookieJar::new(FileStore::open(&path).unwrap());
+    assert_eq!(header(&jar, "example.web", "/", NOW), "new=3");
+
+    // A malformed line is skipped, keeping the rest of the file
+    std::fs::write(
+        &path,
+        "example.web\tMAYBE\t/\tFALSE\t0\ta\tb\n\
+         example.web\tFALSE\t/\tFALSE\t2000000000\tkept\t1\n",
+    )
+    .unwrap();
+    {
+        let mut jar = CookieJar::new(FileStore::open(&path).unwrap());
+        assert_eq!(header(&jar, "example.web", "/", NOW), "kept=1");
+        // A tab would split the line, so such a cookie stays out of the file
+        assert!(jar.set_cookie("example.web", "/", "tab=a\tb; Max-Age=3600", NOW));
+        assert!(jar.set_cookie("example.web", "/", "tab\tname=1; Max-Age=3600", NOW));
+        assert!(jar.set_cookie("example.web", "/", "x=1; Max-Age=3600; Path=/a\tb", NOW));
+        assert_eq!(jar.store().cookies().len(), 4);
+    }
+    let saved = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(
+        saved,
+        "# Netscape HTTP Cookie File\n\
+         example.web\tFALSE\t/\tFALSE\t2000000000\tkept\t1\n"
+    );
+    std::fs::remove_file(&path).unwrap();
+
+    let missing = FileStore::open(&path).unwrap();
+    assert!(missing.cookies().is_empty());
+    drop(missing);
+    assert!(!path.exists());
+}
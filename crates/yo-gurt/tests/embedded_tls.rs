@@ -0,0 +1,479 @@
+//! embedded-tls connector against a rustls GURT server on localhost
+
+use std::io::{Read as _, Write as _};
+use std::net::{IpAddr, Ipv4Addr, TcpListener};
+use std::sync::Arc;
+use std::thread;
+
+use embassy_futures::block_on;
+use embedded_io_async::Read;
+use embedded_nal_async::{AddrType, Dns};
+use embedded_tls::{Aes128GcmSha256, Certificate, TlsError, UnsecureProvider};
+use p256::pkcs8::DecodePrivateKey;
+use portal_solutions_yo_gurt::embedded_tls::pin::{FilePins, SpkiHash, StaticPins};
+use portal_solutions_yo_gurt::embedded_tls::{ConnectError, Connector, TlsClient, TrustCa};
+use portal_solutions_yo_gurt::resolve::{Hosts, NalDns};
+use portal_solutions_yo_gurt::{ALPN_IDENTIFIER, GurtError, Method, StatusCode};
+use portal_solutions_yo_gurt_ca::{Issued, LocalCa};
+use rand_core::OsRng;
+use rcgen::{BasicConstraints, CertificateParams, DnType, IsCa, KeyPair};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{RootCertStore, ServerConfig, ServerConnection, StreamOwned};
+use std_embedded_nal_async::Stack;
+
+/// Resolves `gurt.test` to localhost
+const HOSTS: Hosts = Hosts::new("127.0.0.1 gurt.test");
+
+/// Resolves `gurt.test` to localhost through the `embedded-nal-async` interface
+struct TestDns;
+
+impl Dns for TestDns {
+    type Error = ();
+
+    async fn get_host_by_name(&self, host: &str, _addr_type: AddrType) -> Result<IpAddr, ()> {
+        match host {
+            "gurt.test" => Ok(Ipv4Addr::LOCALHOST.into()),
+            _ => Err(()),
+        }
+    }
+
+    async fn get_host_by_address(&self, _addr: IpAddr, _result: &mut [u8]) -> Result<usize, ()> {
+        Err(())
+    }
+}
+
+/// Serve one connection: answer the handshake with `handshake_status`, then one request
+///
+/// The server presents `identity`, or a self-signed certificate for `gurt.test`. With
+/// `client_ca`, client certificates issued by it are required. The server thread returns
+/// the client's certificate, if any.
+fn serve(
+    handshake_status: &'static [u8],
+    identity: Option<&Issued>,
+    client_ca: Option<CertificateDer<'static>>,
+) -> (u16, thread::JoinHandle<Option<CertificateDer<'static>>>) {
+    let (cert, key) = match identity {
+        Some(identity) => (identity.cert_der().clone(), identity.key_der()),
+        None => {
+            let cert = rcgen::generate_simple_self_signed(vec!["gurt.test".into()]).unwrap();
+            let key = PrivateKeyDer::Pkcs8(cert.key_pair.serialize_der().into());
+            (cert.cert.der().clone(), key)
+        }
+    };
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .unwrap();
+    let builder = match client_ca {
+        Some(ca) => {
+            let mut roots = RootCertStore::empty();
+            roots.add(ca).unwrap();
+            let verifier = WebPkiClientVerifier::builder_with_provider(roots.into(), provider)
+                .build()
+                .unwrap();
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+    let mut config = builder.with_single_cert(vec![cert], key).unwrap();
+    config.alpn_protocols = vec![ALPN_IDENTIFIER.as_bytes().to_vec()];
+
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = thread::spawn(move || {
+        let (tcp, _) = listener.accept().unwrap();
+        let conn = ServerConnection::new(Arc::new(config)).unwrap();
+        let mut tls = StreamOwned::new(conn, tcp);
+
+        let head = read_head(&mut tls);
+        assert!(head.starts_with(b"HANDSHAKE / GURT/1.0.0\r\nhost: gurt.test\r\n"));
+        assert_eq!(tls.conn.alpn_protocol(), Some(ALPN_IDENTIFIER.as_bytes()));
+        assert_eq!(tls.conn.server_name(), Some("gurt.test"));
+        let peer = tls
+            .conn
+            .peer_certificates()
+            .map(|chain| chain[0].clone().into_owned());
+        tls.write_all(handshake_status).unwrap();
+        if !handshake_status.starts_with(b"GURT/1.0.0 101") {
+            return peer;
+        }
+
+        let head = read_head(&mut tls);
+        assert!(head.starts_with(b"GET /hello GURT/1.0.0\r\n"));
+        tls.write_all(b"GURT/1.0.0 200 OK\r\ncontent-length: 5\r\n\r\nhello")
+            .unwrap();
+
+        // The client ends with close_notify, which rustls reports as a clean end of file
+        let mut rest = Vec::new();
+        tls.read_to_end(&mut rest).unwrap();
+        assert!(rest.is_empty());
+        peer
+    });
+    (port, server)
+}
+
+fn read_head(stream: &mut impl std::io::Read) -> Vec<u8> {
+    let mut head = Vec::new();
+    let mut byte = [0u8];
+    while !head.ends_with(b"\r\n\r\n") {
+        stream.read_exact(&mut byte).unwrap();
+        head.push(byte[0]);
+    }
+    head
+}
+
+/// Send the `/hello` request `serve` expects, read the response and close
+async fn hello<C, CipherSuite>(mut client: TlsClient<'_, C, CipherSuite>)
+where
+    C: embedded_io_async::Read + embedded_io_async::Write,
+    CipherSuite: embedded_tls::TlsCipherSuite + 'static,
+{
+    client
+        .request_no_body(Method::Get, "/hello", "gurt.test", None)
+        .await
+        .unwrap();
+    let mut buf = [0u8; 128];
+    let mut response = client.response_reader();
+    assert_eq!(
+        response.read_status_line(&mut buf).await.unwrap().status,
+        StatusCode::Ok
+    );
+    while response.read_header(&mut buf).await.unwrap().is_some() {}
+    let mut out = [0u8; 5];
+    response.body(5).read_exact(&mut out).await.unwrap();
+    client.transport.close().await.map_err(|(_, e)| e).unwrap();
+}
+
+#[test]
+fn connect_request_and_close() {
+    let (port, server) = serve(b"GURT/1.0.0 101 SWITCHING_PROTOCOLS\r\n\r\n", None, None);
+    let stack = Stack::default();
+    let connector = Connector::new(&stack, HOSTS).user_agent("embedded-test");
+    let address = format!("gurt://gurt.test:{port}/ignored");
+
+    block_on(async {
+        let mut read_buf = [0u8; 16640];
+        let mut write_buf = [0u8; 4096];
+        let provider = UnsecureProvider::new::<Aes128GcmSha256>(OsRng);
+        let mut client = connector
+            .connect(&address, provider, &mut read_buf, &mut write_buf)
+            .await
+            .unwrap();
+
+        client
+            .request_no_body(Method::Get, "/hello", "gurt.test", None)
+            .await
+            .unwrap();
+        let mut buf = [0u8; 128];
+        let mut response = client.response_reader();
+        let status = response.read_status_line(&mut buf).await.unwrap();
+        assert_eq!(status.status, StatusCode::Ok);
+        while response.read_header(&mut buf).await.unwrap().is_some() {}
+        let length = response.content_length().unwrap();
+        let mut body = response.body(length);
+        let mut out = [0u8; 5];
+        body.read_exact(&mut out).await.unwrap();
+        assert_eq!(&out, b"hello");
+
+        client.transport.close().await.map_err(|(_, e)| e).unwrap();
+    });
+    assert_eq!(server.join().unwrap(), None);
+}
+
+#[test]
+fn client_certificate() {
+    let ca_key = KeyPair::generate().unwrap();
+    let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+    params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    let ca = params.self_signed(&ca_key).unwrap();
+    let client_key = KeyPair::generate().unwrap();
+    let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+    params
+        .distinguished_name
+        .push(DnType::CommonName, "device-1");
+    let client_cert = params.signed_by(&client_key, &ca, &ca_key).unwrap();
+    // UnsecureProvider signs with a SEC1 encoded P-256 key
+    let sec1_key = p256::SecretKey::from_pkcs8_der(&client_key.serialize_der())
+        .unwrap()
+        .to_sec1_der()
+        .unwrap();
+
+    let (port, server) = serve(
+        b"GURT/1.0.0 101 SWITCHING_PROTOCOLS\r\n\r\n",
+        None,
+        Some(ca.der().clone()),
+    );
+    let stack = Stack::default();
+    let connector = Connector::new(&stack, HOSTS);
+    let address = format!("gurt.test:{port}");
+
+    block_on(async {
+        let mut read_buf = [0u8; 16640];
+        let mut write_buf = [0u8; 4096];
+        let provider = UnsecureProvider::new::<Aes128GcmSha256>(OsRng)
+            .with_cert(Certificate::X509(client_cert.der()))
+            .with_priv_key(&sec1_key);
+        let mut client = connector
+            .connect(&address, provider, &mut read_buf, &mut write_buf)
+            .await
+            .unwrap();
+        client
+            .request_no_body(Method::Get, "/hello", "gurt.test", None)
+            .await
+            .unwrap();
+        let mut buf = [0u8; 128];
+        let mut response = client.response_reader();
+        assert_eq!(
+            response.read_status_line(&mut buf).await.unwrap().status,
+            StatusCode::Ok
+        );
+        while response.read_header(&mut buf).await.unwrap().is_some() {}
+        let mut out = [0u8; 5];
+        response.body(5).read_exact(&mut out).await.unwrap();
+        client.transport.close().await.map_err(|(_, e)| e).unwrap();
+    });
+    assert_eq!(server.join().unwrap().as_ref(), Some(client_cert.der()));
+}
+
+#[test]
+fn rejected_handshake() {
+    let (port, server) = serve(b"GURT/1.0.0 403 FORBIDDEN\r\n\r\n", None, None);
+    let stack = Stack::default();
+    let connector = Connector::new(&stack, NalDns(TestDns));
+    let address = format!("gurt.test:{port}");
+
+    block_on(async {
+        let mut read_buf = [0u8; 16640];
+        let mut write_buf = [0u8; 4096];
+        let provider = UnsecureProvider::new::<Aes128GcmSha256>(OsRng);
+        let result = connector
+            .connect(&address, provider, &mut read_buf, &mut write_buf)
+            .await;
+        assert!(matches!(
+            result,
+            Err(ConnectError::Gurt(GurtError::HandshakeRejected(
+                StatusCode::Forbidden
+            )))
+        ));
+    });
+    server.join().unwrap();
+}
+
+#[test]
+fn trusted_local_ca() {
+    let ca = LocalCa::generate("test CA").unwrap();
+    let identity = ca.issue_server(&["gurt.test"]).unwrap();
+    let (port, server) = serve(
+        b"GURT/1.0.0 101 SWITCHING_PROTOCOLS\r\n\r\n",
+        Some(&identity),
+        None,
+    );
+    let stack = Stack::default();
+    let connector = Connector::new(&stack, HOSTS);
+    let address = format!("gurt.test:{port}");
+
+    block_on(async {
+        let mut read_buf = [0u8; 16640];
+        let mut write_buf = [0u8; 4096];
+        let provider = TrustCa::new(
+            UnsecureProvider::new::<Aes128GcmSha256>(OsRng),
+            ca.cert_der(),
+        );
+        let mut client = connector
+            .connect(&address, provider, &mut read_buf, &mut write_buf)
+            .await
+            .unwrap();
+        client
+            .request_no_body(Method::Get, "/hello", "gurt.test", None)
+            .await
+            .unwrap();
+        let mut buf = [0u8; 128];
+        let mut response = client.response_reader();
+        assert_eq!(
+            response.read_status_line(&mut buf).await.unwrap().status,
+            StatusCode::Ok
+        );
+        while response.read_header(&mut buf).await.unwrap().is_some() {}
+        let mut out = [0u8; 5];
+        response.body(5).read_exact(&mut out).await.unwrap();
+        client.transport.close().await.map_err(|(_, e)| e).unwrap();
+    });
+    server.join().unwrap();
+}
+
+#[test]
+fn untrusted_certificates() {
+    let ca = LocalCa::generate("test CA").unwrap();
+    let other = LocalCa::generate("other CA").unwrap();
+    let wrong_name = ca.issue_server(&["other.test"]).unwrap();
+    let wrong_ca = other.issue_server(&["gurt.test"]).unwrap();
+
+    for identity in [None, Some(&wrong_name), Some(&wrong_ca)] {
+        let (port, server) = serve(
+            b"GURT/1.0.0 101 SWITCHING_PROTOCOLS\r\n\r\n",
+            identity,
+            None,
+        );
+        let stack = Stack::default();
+        let connector = Connector::new(&stack, HOSTS);
+        let address = format!("gurt.test:{port}");
+
+        block_on(async {
+            let mut read_buf = [0u8; 16640];
+            let mut write_buf = [0u8; 4096];
+            let provider = TrustCa::new(
+                UnsecureProvider::new::<Aes128GcmSha256>(OsRng),
+                ca.cert_der(),
+            );
+            let result = connector
+                .connect(&address, provider, &mut read_buf, &mut write_buf)
+                .await;
+            assert!(matches!(result, Err(ConnectError::Tls(_))));
+        });
+        // The server sees the handshake fail
+        assert!(server.join().is_err());
+    }
+}
+
+#[test]
+fn pin_format() {
+    let cert = rcgen::generate_simple_self_signed(vec!["gurt.test".into()]).unwrap();
+    let pin = SpkiHash::of_certificate(cert.cert.der()).unwrap();
+    assert_eq!(pin, SpkiHash::of_spki(&cert.key_pair.public_key_der()));
+
+    let text = pin.to_string();
+    assert!(text.starts_with("sha256//") && text.ends_with('='));
+    assert_eq!(text.len(), 8 + 44);
+    assert_eq!(text.parse::<SpkiHash>(), Ok(pin));
+    assert_eq!(
+        SpkiHash([0; 32]).to_string(),
+        "sha256//AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA="
+    );
+    assert_eq!(
+        SpkiHash([0xff; 32]).to_string(),
+        "sha256////////////////////////////////////////////8="
+    );
+
+    for invalid in [
+        "",
+        "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=",
+        "sha256//AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA",
+        "sha256//AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA!=",
+        // Unused trailing bits set
+        "sha256//AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAB=",
+    ] {
+        assert!(invalid.parse::<SpkiHash>().is_err(), "{invalid}");
+    }
+    assert!(SpkiHash::of_certificate(b"\x30\x03\x02\x01\x00").is_none());
+}
+
+#[test]
+fn pinned_keys() {
+    let ca = LocalCa::generate("test CA").unwrap();
+    let identity = ca.issue_server(&["gurt.test"]).unwrap();
+    let pin = SpkiHash::of_certificate(identity.cert_der()).unwrap();
+    let other = SpkiHash([7; 32]);
+    let stack = Stack::default();
+    let connector = Connector::new(&stack, HOSTS);
+
+    #[derive(Debug, PartialEq)]
+    enum Outcome {
+        Connected,
+        Mismatch,
+        Unpinned,
+    }
+    let cases: [(&[(&str, SpkiHash)], Outcome); 3] = [
+        // Any of a host's pins may match, and hosts compare case-insensitively
+        (
+            &[("GURT.test", other), ("gurt.test", pin)],
+            Outcome::Connected,
+        ),
+        (&[("gurt.test", other)], Outcome::Mismatch),
+        (&[("other.test", pin)], Outcome::Unpinned),
+    ];
+    for (pins, expected) in cases {
+        let (port, server) = serve(
+            b"GURT/1.0.0 101 SWITCHING_PROTOCOLS\r\n\r\n",
+            Some(&identity),
+            None,
+        );
+        let address = format!("gurt.test:{port}");
+        let outcome = block_on(async {
+            let mut read_buf = [0u8; 16640];
+            let mut write_buf = [0u8; 4096];
+            let provider = UnsecureProvider::new::<Aes128GcmSha256>(OsRng);
+            let result = connector
+                .connect_pinned(
+                    &address,
+                    provider,
+                    StaticPins(pins),
+                    &mut read_buf,
+                    &mut write_buf,
+                )
+                .await;
+            match result {
+                Ok(client) => {
+                    hello(client).await;
+                    Outcome::Connected
+                }
+                // The error carries the key the server presented
+                Err(ConnectError::PinMismatch(seen)) if seen == pin => Outcome::Mismatch,
+                Err(ConnectError::Unpinned(seen)) if seen == pin => Outcome::Unpinned,
+                Err(e) => panic!("{e}"),
+            }
+        });
+        assert_eq!(outcome, expected);
+        assert_eq!(server.join().is_ok(), expected == Outcome::Connected);
+    }
+}
+
+#[test]
+fn trust_on_first_use() {
+    let path = std::env::temp_dir().join(format!("yo-gurt-pins-{}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let ca = LocalCa::generate("test CA").unwrap();
+    let first = ca.issue_server(&["gurt.test"]).unwrap();
+    let second = ca.issue_server(&["gurt.test"]).unwrap();
+    let stack = Stack::default();
+    let connector = Connector::new(&stack, HOSTS);
+
+    // First use records the key; it is then trusted, and a new key is a mismatch
+    for (identity, trusted) in [(&first, true), (&first, true), (&second, false)] {
+        let mut pins = FilePins::open(&path).unwrap();
+        let (port, server) = serve(
+            b"GURT/1.0.0 101 SWITCHING_PROTOCOLS\r\n\r\n",
+            Some(identity),
+            None,
+        );
+        let address = format!("gurt.test:{port}");
+        block_on(async {
+            let mut read_buf = [0u8; 16640];
+            let mut write_buf = [0u8; 4096];
+            let provider = UnsecureProvider::new::<Aes128GcmSha256>(OsRng);
+            let result = connector
+                .connect_pinned(&address, provider, &mut pins, &mut read_buf, &mut write_buf)
+                .await;
+            match result {
+                Ok(client) => hello(client).await,
+                Err(e @ ConnectError::PinMismatch(seen)) if !trusted => {
+                    assert_eq!(Some(seen), SpkiHash::of_certificate(second.cert_der()));
+                    // and stays a distinct error as the client's error type
+                    let e: GurtError<TlsError> = e.into();
+                    assert!(matches!(e, GurtError::PinMismatch(key) if key == seen.0));
+                }
+                Err(e) => panic!("{e}"),
+            }
+        });
+        assert_eq!(server.join().is_ok(), trusted);
+    }
+
+    let pin = SpkiHash::of_certificate(first.cert_der()).unwrap();
+    let pins = FilePins::open(&path).unwrap();
+    assert_eq!(pins.pins(), &[("gurt.test".to_owned(), pin)]);
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        format!("gurt.test {pin}\n")
+    );
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,248 @@
+//! Content encodings: codecs, bounded decoding and decoded bodies over in-memory input
+
+use core::convert::Infallible;
+
+use embassy_futures::block_on;
+use portal_solutions_yo_gurt::encoding::{
+    ACCEPT_ENCODING, ContentEncoding, DecodeError, Decoder, SUPPORTED, encode,
+};
+use portal_solutions_yo_gurt::{GurtError, Limit, ProtocolError, ResponseReader};
+
+/// Compressible but not trivial: numbered lines of text
+fn sample(len: usize) -> Vec<u8> {
+    let mut data = Vec::with_capacity(len + 32);
+    let mut i = 0u32;
+    while data.len() < len {
+        data.extend_from_slice(format!("line {i} of {}\n", i.wrapping_mul(2654435761)).as_bytes());
+        i += 1;
+    }
+    data.truncate(len);
+    data
+}
+
+/// Decode `encoded` feeding `step` input bytes at a time into `out_size` byte buffers
+fn decode_in_steps(
+    decoder: &mut Decoder,
+    encoded: &[u8],
+    step: usize,
+    out_size: usize,
+) -> Result<Vec<u8>, DecodeError> {
+    let mut out = Vec::new();
+    let mut buf = vec![0u8; out_size];
+    let mut pos = 0;
+    loop {
+        let end = (pos + step).min(encoded.len());
+        let last = end == encoded.len();
+        let progress = decoder.decode(&encoded[pos..end], &mut buf, last)?;
+        out.extend_from_slice(&buf[..progress.produced]);
+        pos += progress.consumed;
+        if progress.done {
+            return Ok(out);
+        }
+    }
+}
+
+/// Decode a whole response carrying `body` with the given `content-encoding` headers
+fn decoded_response(encodings: &[&str], body: &[u8]) -> Result<Vec<u8>, GurtError<Infallible>> {
+    let mut message = b"GURT/1.0.0 200 OK\r\n".to_vec();
+    for encoding in encodings {
+        message.extend_from_slice(format!("content-encoding: {encoding}\r\n").as_bytes());
+    }
+    message.extend_from_slice(format!("content-length: {}\r\n\r\n", body.len()).as_bytes());
+    message.extend_from_slice(body);
+    let mut transport = &message[..];
+    let mut buf = [0u8; 64];
+    let mut reader = ResponseReader::new(&mut transport);
+    block_on(async {
+        reader.read_status_line(&mut buf).await?;
+        while reader.read_header(&mut buf).await?.is_some() {}
+        let length = reader.content_length().unwrap_or(0);
+        let mut body = reader.decoded_body(length)?;
+        let mut out = Vec::new();
+        body.read_to_end(&mut out).await?;
+        assert!(body.is_done());
+        Ok(out)
+    })
+}
+
+#[test]
+fn names() {
+    assert_eq!(ACCEPT_ENCODING, "zstd, br, gzip, deflate");
+    for encoding in SUPPORTED {
+        assert!(encoding.is_supported());
+        assert_eq!(
+            ContentEncoding::from_bytes(encoding.as_str().as_bytes()),
+            Some(*encoding)
+        );
+    }
+    assert_eq!(
+        ContentEncoding::from_bytes(b" GZIP "),
+        Some(ContentEncoding::Gzip)
+    );
+    assert_eq!(
+        ContentEncoding::from_bytes(b"x-gzip"),
+        Some(ContentEncoding::Gzip)
+    );
+    assert_eq!(
+        ContentEncoding::from_bytes(b"Identity"),
+        Some(ContentEncoding::Identity)
+    );
+    assert_eq!(ContentEncoding::from_bytes(b"gzip, br"), None);
+    assert_eq!(ContentEncoding::from_bytes(b"compress"), None);
+    assert_eq!(ContentEncoding::Brotli.to_string(), "br");
+}
+
+#[test]
+fn round_trip_in_small_steps() {
+    let data = sample(300 * 1024);
+    for &encoding in SUPPORTED {
+        let encoded = encode(encoding, &data).unwrap();
+        assert!(
+            encoded.len() < data.len() / 2,
+            "{encoding} does not compress"
+        );
+        for (step, out_size) in [(1, 7), (13, 1000), (4096, 64 * 1024), (encoded.len(), 1)] {
+            let mut decoder = Decoder::new(encoding).unwrap();
+            let out = decode_in_steps(&mut decoder, &encoded, step, out_size)
+                .unwrap_or_else(|e| panic!("{encoding} step {step}: {e}"));
+            assert!(out == data, "{encoding} step {step} decodes differently");
+            assert_eq!(decoder.total_out(), data.len());
+        }
+    }
+}
+
+#[test]
+fn empty_bodies() {
+    for &encoding in SUPPORTED {
+        let encoded = encode(encoding, b"").unwrap();
+        let mut decoder = Decoder::new(encoding).unwrap();
+        assert_eq!(
+            decode_in_steps(&mut decoder, &encoded, 3, 16),
+            Ok(Vec::new())
+        );
+    }
+}
+
+#[test]
+fn truncated_and_corrupt() {
+    let data = sample(64 * 1024);
+    for &encoding in SUPPORTED {
+        let encoded = encode(encoding, &data).unwrap();
+        for len in [0, 1, encoded.len() / 2, encoded.len() - 1] {
+            let mut decoder = Decoder::new(encoding).unwrap();
+            assert!(
+                decode_in_steps(&mut decoder, &encoded[..len], 100, 1024).is_err(),
+                "{encoding} accepts {len} of {} bytes",
+                encoded.len()
+            );
+        }
+        let mut corrupt = encoded.clone();
+        corrupt[encoded.len() / 2] ^= 0x55;
+        corrupt[encoded.len() - 3] ^= 0x55;
+        let mut decoder = Decoder::new(encoding).unwrap();
+        assert!(
+            decode_in_steps(&mut decoder, &corrupt, 100, 1024).is_err(),
+            "{encoding} accepts a corrupt body"
+        );
+    }
+    let mut decoder = Decoder::new(ContentEncoding::Gzip).unwrap();
+    assert_eq!(
+        decode_in_steps(&mut decoder, b"not gzip at all", 100, 1024),
+        Err(DecodeError::Invalid)
+    );
+    let encoded = encode(ContentEncoding::Gzip, &data).unwrap();
+    let mut decoder = Decoder::new(ContentEncoding::Gzip).unwrap();
+    assert_eq!(
+        decode_in_steps(&mut decoder, &encoded[..encoded.len() - 4], 100, 1024),
+        Err(DecodeError::Truncated)
+    );
+}
+
+#[test]
+fn output_is_bounded() {
+    let zeros = vec![0u8; 4 * 1024 * 1024];
+    for &encoding in SUPPORTED {
+        let encoded = encode(encoding, &zeros).unwrap();
+        assert!(encoded.len() < 64 * 1024);
+        let mut decoder = Decoder::with_limit(encoding, 1024 * 1024).unwrap();
+        assert_eq!(
+            decode_in_steps(&mut decoder, &encoded, 4096, 16 * 1024),
+            Err(DecodeError::TooLarge),
+            "{encoding}"
+        );
+        assert!(decoder.total_out() <= 1024 * 1024 + 16 * 1024);
+    }
+}
+
+#[test]
+fn gzip_members_and_raw_deflate() {
+    let mut encoded = encode(ContentEncoding::Gzip, b"hello, ").unwrap();
+    encoded.extend(encode(ContentEncoding::Gzip, b"world").unwrap());
+    let mut decoder = Decoder::new(ContentEncoding::Gzip).unwrap();
+    assert_eq!(
+        decode_in_steps(&mut decoder, &encoded, 5, 3),
+        Ok(b"hello, world".to_vec())
+    );
+
+    // zlib header (2 bytes) and Adler-32 trailer (4 bytes) around raw DEFLATE
+    let zlib = encode(ContentEncoding::Deflate, b"raw deflate body").unwrap();
+    let raw = &zlib[2..zlib.len() - 4];
+    let mut decoder = Decoder::new(ContentEncoding::Deflate).unwrap();
+    assert_eq!(
+        decode_in_steps(&mut decoder, raw, 2, 5),
+        Ok(b"raw deflate body".to_vec())
+    );
+}
+
+#[test]
+fn decoded_response_bodies() {
+    let data = sample(50 * 1024);
+    for &encoding in SUPPORTED {
+        let encoded = encode(encoding, &data).unwrap();
+        let out = decoded_response(&[encoding.as_str()], &encoded).unwrap();
+        assert!(out == data, "{encoding} response decodes differently");
+    }
+    assert_eq!(decoded_response(&[], b"plain"), Ok(b"plain".to_vec()));
+    assert_eq!(
+        decoded_response(
+            &["identity", "gzip"],
+            &encode(ContentEncoding::Gzip, b"ok").unwrap()
+        ),
+        Ok(b"ok".to_vec())
+    );
+    // An empty body needs no decoding, whatever it claims
+    assert_eq!(decoded_response(&["compress"], b""), Ok(Vec::new()));
+    assert_eq!(
+        decoded_response(&["compress"], b"data"),
+        Err(GurtError::Protocol(
+            ProtocolError::UnsupportedContentEncoding
+        ))
+    );
+    assert_eq!(
+        decoded_response(&["gzip", "br"], b"data"),
+        Err(GurtError::Protocol(
+            ProtocolError::UnsupportedContentEncoding
+        ))
+    );
+
+    let mut trailing = encode(ContentEncoding::Zstd, b"body").unwrap();
+    trailing.extend_from_slice(b"junk");
+    assert_eq!(
+        decoded_response(&["zstd"], &trailing),
+        Err(GurtError::Protocol(ProtocolError::InvalidEncodedBody))
+    );
+    let encoded = encode(ContentEncoding::Brotli, &data).unwrap();
+    assert_eq!(
+        decoded_response(&["br"], &encoded[..encoded.len() / 2]),
+        Err(GurtError::Protocol(ProtocolError::InvalidEncodedBody))
+    );
+}
+
+#[test]
+fn decoded_response_is_bounded() {
+    let encoded = encode(ContentEncoding::Gzip, &vec![0u8; 11 * 1024 * 1024]).unwrap();
+    assert_eq!(
+        decoded_response(&["gzip"], &encoded),
+        Err(GurtError::LimitExceeded(Limit::MessageTooLarge))
+    );
+}
@@ -0,0 +1,839 @@
+//! High-level fetch client against a rustls GURT server on localhost
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use embedded_io_async::{Read, Write};
+use portal_solutions_yo_gurt::adapters::FromTokio;
+use portal_solutions_yo_gurt::cache::MemoryCache;
+use portal_solutions_yo_gurt::encoding::{ContentEncoding, encode};
+use portal_solutions_yo_gurt::fetch::{
+    Client, ClientBuilder, Connect, Connector, DOWNLOAD_CHUNK_SIZE, Error, Response, Retry, Url,
+};
+use portal_solutions_yo_gurt::range::ByteRange;
+use portal_solutions_yo_gurt::resolve::Hosts;
+use portal_solutions_yo_gurt::server::GurtServer;
+use portal_solutions_yo_gurt::{ALPN_IDENTIFIER, GurtError, Method, StatusCode};
+use portal_solutions_yo_gurt_ca::LocalCa;
+use rustls::ServerConfig;
+use rustls::pki_types::CertificateDer;
+use serde::{Deserialize, Serialize};
+use tokio::io::BufReader;
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+
+/// Resolves `gurt.test` to localhost
+const HOSTS: Hosts = Hosts::new("127.0.0.1 gurt.test");
+
+/// A GURT server on localhost with a few fixed routes
+struct TestServer {
+    port: u16,
+    ca: CertificateDer<'static>,
+    /// Connections accepted so far
+    connections: Arc<AtomicUsize>,
+    /// Requests received so far, by path
+    hits: Hits,
+}
+
+type Hits = Arc<Mutex<HashMap<String, usize>>>;
+
+impl TestServer {
+    async fn start() -> Self {
+        let ca = LocalCa::generate("test CA").unwrap();
+        let issued = ca.issue_server(&["gurt.test"]).unwrap();
+        let mut config =
+            ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+                .with_protocol_versions(&[&rustls::version::TLS13])
+                .unwrap()
+                .with_no_client_auth()
+                .with_single_cert(vec![issued.cert_der().clone()], issued.key_der())
+                .unwrap();
+        config.alpn_protocols = vec![ALPN_IDENTIFIER.as_bytes().to_vec()];
+        let acceptor = TlsAcceptor::from(Arc::new(config));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let connections = Arc::new(AtomicUsize::new(0));
+        let count = connections.clone();
+        let hits = Hits::default();
+        let server_hits = hits.clone();
+        tokio::spawn(async move {
+            loop {
+                let (tcp, _) = listener.accept().await.unwrap();
+                count.fetch_add(1, Ordering::SeqCst);
+                let acceptor = acceptor.clone();
+                let hits = server_hits.clone();
+                tokio::spawn(async move {
+                    if let Ok(tls) = acceptor.accept(tcp).await {
+                        let server = GurtServer::new(FromTokio(BufReader::new(tls)));
+                        let _ = serve_connection(server, hits).await;
+                    }
+                });
+            }
+        });
+        Self {
+            port,
+            ca: ca.cert_der().clone(),
+            connections,
+            hits,
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("gurt://gurt.test:{}{path}", self.port)
+    }
+
+    fn client(&self) -> ClientBuilder<Hosts> {
+        Client::builder()
+            .webpki_roots(false)
+            .add_root_certificate(self.ca.clone())
+            .resolver(HOSTS)
+    }
+
+    fn connections(&self) -> usize {
+        self.connections.load(Ordering::SeqCst)
+    }
+
+    fn hits(&self, path: &str) -> usize {
+        self.hits.lock().unwrap().get(path).copied().unwrap_or(0)
+    }
+}
+
+type Transport = FromTokio<BufReader<tokio_rustls::server::TlsStream<tokio::net::TcpStream>>>;
+
+/// Answer requests until the client goes away or a route closes the connection
+///
+/// `/flaky/<n>` answers `503` to its first `n` requests and `/reset/<n>` closes the
+/// connection without answering them.
+async fn serve_connection(
+    mut server: GurtServer<Transport>,
+    hits: Hits,
+) -> Result<(), GurtError<std::io::Error>> {
+    let mut buf = [0u8; 1024];
+    server.accept_handshake(&mut buf).await?;
+    loop {
+        let mut reader = server.request_reader();
+        let line = reader.read_request_line(&mut buf).await?;
+        let path = String::from_utf8_lossy(line.path(&buf)).into_owned();
+        let mut headers = Vec::new();
+        while let Some(header) = reader.read_header(&mut buf).await? {
+            headers.push(format!(
+                "{}={}",
+                String::from_utf8_lossy(header.name(&buf)),
+                String::from_utf8_lossy(header.value(&buf))
+            ));
+        }
+        let length = reader.content_length().unwrap_or(0);
+        let mut body = vec![0; length];
+        reader.body(length).read_exact(&mut body).await.unwrap();
+        let hit = {
+            let mut hits = hits.lock().unwrap();
+            let hit = hits.entry(path.clone()).or_default();
+            *hit += 1;
+            *hit
+        };
+        let failing = |prefix: &str| {
+            path.strip_prefix(prefix)
+                .is_some_and(|n| hit <= n.parse().unwrap())
+        };
+        if failing("/reset/") {
+            return Ok(());
+        }
+
+        let (status, extra, body, close): (_, Vec<(&str, String)>, Vec<u8>, bool) =
+            match path.as_str() {
+                "/hello" => (StatusCode::Ok, vec![], b"hello".to_vec(), false),
+                "/json" => (
+                    StatusCode::Ok,
+                    vec![("content-type", "application/json".into())],
+                    br#"{"name":"gurt","count":3}"#.to_vec(),
+                    false,
+                ),
+                "/gzip" => (
+                    StatusCode::Ok,
+                    vec![("content-encoding", "gzip".into())],
+                    encode(ContentEncoding::Gzip, &b"squeezed ".repeat(100)).unwrap(),
+                    false,
+                ),
+                "/login" => (
+                    StatusCode::Ok,
+                    vec![
+                        ("set-cookie", "session=abc; Path=/; HttpOnly".into()),
+                        ("set-cookie", "theme=dark; Path=/settings".into()),
+                    ],
+                    vec![],
+                    false,
+                ),
+                "/echo" => (
+                    StatusCode::Ok,
+                    vec![
+                        ("x-method", line.method.as_str().into()),
+                        ("x-headers", headers.join("; ")),
+                    ],
+                    body,
+                    false,
+                ),
+                "/close" => (StatusCode::Ok, vec![], b"bye".to_vec(), true),
+                "/slow" => {
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                    (StatusCode::Ok, vec![], vec![], false)
+                }
+                _ if failing("/flaky/") => (
+                    StatusCode::ServiceUnavailable,
+                    vec![("retry-after", "0".into())],
+                    b"try again".to_vec(),
+                    false,
+                ),
+                p if p.starts_with("/flaky/") || p.starts_with("/reset/") => {
+                    (StatusCode::Ok, vec![], b"recovered".to_vec(), false)
+                }
+                "/fresh" => (
+                    StatusCode::Ok,
+                    vec![("cache-control", "max-age=60".into())],
+                    format!("fresh {hit}").into_bytes(),
+                    false,
+                ),
+                "/tagged" if headers.iter().any(|h| h == "if-none-match=\"v1\"") => (
+                    StatusCode::NotModified,
+                    vec![("etag", "\"v1\"".into()), ("x-hit", hit.to_string())],
+                    vec![],
+                    false,
+                ),
+                "/tagged" => (
+                    StatusCode::Ok,
+                    vec![
+                        ("cache-control", "no-cache".into()),
+                        ("etag", "\"v1\"".into()),
+                        ("content-encoding", "gzip".into()),
+                        ("x-hit", hit.to_string()),
+                    ],
+                    encode(ContentEncoding::Gzip, b"tagged").unwrap(),
+                    false,
+                ),
+                "/padded" if headers.iter().any(|h| h == "if-none-match=\"p1\"") => (
+                    StatusCode::NotModified,
+                    vec![("etag", "\"p1\"".into())],
+                    b"stray".to_vec(),
+                    false,
+                ),
+                "/padded" => (
+                    StatusCode::Ok,
+                    vec![
+                        ("cache-control", "no-cache".into()),
+                        ("etag", "\"p1\"".into()),
+                    ],
+                    b"padded".to_vec(),
+                    false,
+                ),
+                "/dated-asset" if headers.iter().any(|h| h.starts_with("if-modified-since=")) => {
+                    (StatusCode::NotModified, vec![], vec![], false)
+                }
+                "/dated-asset" => (
+                    StatusCode::Ok,
+                    vec![("last-modified", "Wed, 01 Jan 2020 00:00:00 GMT".into())],
+                    b"dated".to_vec(),
+                    false,
+                ),
+                "/uncached" => (
+                    StatusCode::Ok,
+                    vec![("cache-control", "no-store, max-age=60".into())],
+                    b"uncached".to_vec(),
+                    false,
+                ),
+                // Clients must not validate ranges with weak tags
+                "/weak-blob" if headers.iter().any(|h| h.starts_with("if-range=")) => {
+                    (StatusCode::BadRequest, vec![], vec![], false)
+                }
+                "/blob" | "/weak-blob" => {
+                    let blob = blob();
+                    let etag = match path.as_str() {
+                        "/blob" => "\"b1\"",
+                        _ => "W/\"b1\"",
+                    };
+                    let if_range = headers.iter().find_map(|h| h.strip_prefix("if-range="));
+                    let range = headers
+                        .iter()
+                        .find_map(|h| h.strip_prefix("range="))
+                        .and_then(ByteRange::parse)
+                        .filter(|_| if_range.is_none_or(|tag| tag == etag));
+                    let etag = ("etag", etag.to_string());
+                    match range.map(|range| range.resolve(blob.len() as u64)) {
+                        None => (StatusCode::Ok, vec![etag], blob, false),
+                        Some(None) => (
+                            StatusCode::RangeNotSatisfiable,
+                            vec![etag, ("content-range", format!("bytes */{}", blob.len()))],
+                            vec![],
+                            false,
+                        ),
+                        Some(Some((first, last))) => (
+                            StatusCode::PartialContent,
+                            vec![
+                                etag,
+                                (
+                                    "content-range",
+                                    format!("bytes {first}-{last}/{}", blob.len()),
+                                ),
+                            ],
+                            blob[first as usize..=last as usize].to_vec(),
+                            false,
+                        ),
+                    }
+                }
+                "/empty" => (StatusCode::NoContent, vec![], vec![], false),
+                "/busy" => (
+                    StatusCode::ServiceUnavailable,
+                    vec![("retry-after", "3600".into())],
+                    vec![],
+                    false,
+                ),
+                "/dated" => (
+                    StatusCode::GatewayTimeout,
+                    vec![("retry-after", "Thu, 01 Jan 1970 00:00:00 GMT".into())],
+                    vec![],
+                    false,
+                ),
+                _ => (StatusCode::NotFound, vec![], b"not found".to_vec(), false),
+            };
+        let extra: Vec<(&str, &str)> = extra.iter().map(|(n, v)| (*n, v.as_str())).collect();
+        let mut writer = server.respond(status, &extra, body.len()).await?;
+        if line.method != Method::Head {
+            writer.write(&body).await?;
+            writer.finish().await?;
+        } else {
+            server.transport.flush().await.map_err(GurtError::Io)?;
+        }
+        if close {
+            return server.close().await;
+        }
+    }
+}
+
+/// The resource at `/blob`, a little over one download chunk
+fn blob() -> Vec<u8> {
+    (0..DOWNLOAD_CHUNK_SIZE + 1000)
+        .map(|i| (i % 251) as u8)
+        .collect()
+}
+
+/// The request headers `/echo` received
+fn echo(response: &Response<impl Connect>) -> String {
+    response.header("x-headers").unwrap().to_owned()
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Item {
+    name: String,
+    count: u32,
+}
+
+#[tokio::test]
+async fn requests_share_a_connection() {
+    let server = TestServer::start().await;
+    let client = server.client().build().unwrap();
+
+    let response = client.get(&server.url("/hello")).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::Ok);
+    assert_eq!(response.header("content-length"), Some("5"));
+    assert_eq!(response.url().path(), "/hello");
+    assert_eq!(response.text().await.unwrap(), "hello");
+
+    let response = client.get(&server.url("/json")).send().await.unwrap();
+    assert_eq!(response.header("content-type"), Some("application/json"));
+    let item: Item = response.json().await.unwrap();
+    assert_eq!(
+        item,
+        Item {
+            name: "gurt".into(),
+            count: 3
+        }
+    );
+
+    let response = client.get(&server.url("/gzip")).send().await.unwrap();
+    assert_eq!(response.header("content-encoding"), Some("gzip"));
+    let length = response.content_length();
+    assert!(length < 900);
+    let mut received = 0;
+    let body = response
+        .bytes_with_progress(|n, total| {
+            assert!(n > received && total == length);
+            received = n;
+        })
+        .await
+        .unwrap();
+    assert_eq!(body, b"squeezed ".repeat(100));
+    assert_eq!(received, length);
+
+    // HEAD responses carry a content-length but no body
+    let response = client.head(&server.url("/hello")).send().await.unwrap();
+    assert_eq!(response.header("content-length"), Some("5"));
+    assert_eq!(response.bytes().await.unwrap(), b"");
+
+    let url = Url::parse(&server.url("/")).unwrap();
+    assert_eq!(server.connections(), 1);
+    assert_eq!(client.idle_connections(&url), 1);
+
+    // A response dropped before its body is read takes its connection with it
+    drop(client.get(&server.url("/hello")).send().await.unwrap());
+    assert_eq!(client.idle_connections(&url), 0);
+    client.get(&server.url("/hello")).send().await.unwrap();
+    assert_eq!(server.connections(), 2);
+}
+
+#[tokio::test]
+async fn cookies_headers_and_bodies() {
+    let server = TestServer::start().await;
+    let client = server
+        .client()
+        .user_agent("fetch-test/1.0")
+        .default_header("X-Client", "tests")
+        .build()
+        .unwrap();
+
+    client.get(&server.url("/login")).send().await.unwrap();
+    let url = Url::parse(&server.url("/settings/page")).unwrap();
+    assert_eq!(
+        client.cookies_for(&url).as_deref(),
+        Some("theme=dark; session=abc")
+    );
+
+    let response = client
+        .get(&server.url("/echo"))
+        .header("Accept-Encoding", "identity")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(
+        echo(&response),
+        "host=gurt.test; accept-encoding=identity; x-client=tests; cookie=session=abc; \
+         user-agent=fetch-test/1.0"
+    );
+
+    let response = client
+        .post(&server.url("/echo"))
+        .form(&[("q", "a b"), ("n", "1")])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.header("x-method"), Some("POST"));
+    assert!(echo(&response).contains("content-type=application/x-www-form-urlencoded"));
+    assert_eq!(response.text().await.unwrap(), "q=a+b&n=1");
+
+    let item = Item {
+        name: "new".into(),
+        count: 1,
+    };
+    let response = client
+        .put(&server.url("/echo"))
+        .json(&item)
+        .send()
+        .await
+        .unwrap();
+    assert!(echo(&response).contains("content-type=application/json"));
+    assert!(echo(&response).contains("content-length=24"));
+    assert_eq!(response.json::<Item>().await.unwrap(), item);
+
+    let client = server.client().no_cookies().build().unwrap();
+    client.get(&server.url("/login")).send().await.unwrap();
+    let response = client.get(&server.url("/echo")).send().await.unwrap();
+    assert!(!echo(&response).contains("cookie"));
+    assert_eq!(client.cookies_for(&url), None);
+}
+
+#[tokio::test]
+async fn failures() {
+    let server = TestServer::start().await;
+    let client = server.client().build().unwrap();
+
+    for url in [
+        "http://gurt.test/",
+        "gurt://",
+        "gurt://user@gurt.test/",
+        "gurt://gurt.test:x/",
+    ] {
+        assert!(
+            matches!(client.get(url).send().await, Err(Error::InvalidUrl)),
+            "{url}"
+        );
+    }
+    let response = client.get(&server.url("/missing")).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::NotFound);
+    assert!(matches!(
+        response.error_for_status(),
+        Err(Error::Status(StatusCode::NotFound))
+    ));
+    let response = client
+        .get(&server.url("/echo"))
+        .header("bad header", "x")
+        .send()
+        .await;
+    assert!(matches!(
+        response,
+        Err(Error::Gurt(GurtError::InvalidRequest(_)))
+    ));
+
+    // A pooled connection the server closed is replaced transparently
+    let response = client.get(&server.url("/hello")).send().await.unwrap();
+    assert_eq!(response.text().await.unwrap(), "hello");
+    let before = server.connections();
+    let response = client.get(&server.url("/close")).send().await.unwrap();
+    assert_eq!(response.text().await.unwrap(), "bye");
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let response = client.get(&server.url("/hello")).send().await.unwrap();
+    assert_eq!(response.text().await.unwrap(), "hello");
+    assert_eq!(server.connections(), before + 1);
+
+    let error = client
+        .get(&server.url("/slow"))
+        .timeout(Duration::from_millis(200))
+        .send()
+        .await
+        .err()
+        .unwrap();
+    assert!(error.is_timeout(), "{error}");
+
+    // Without the test CA the server's certificate is not trusted
+    let untrusted = Client::builder().resolver(HOSTS).build().unwrap();
+    let error = untrusted
+        .get(&server.url("/hello"))
+        .send()
+        .await
+        .err()
+        .unwrap();
+    assert!(matches!(error, Error::Gurt(GurtError::Io(_))), "{error}");
+    let insecure = Client::builder()
+        .resolver(HOSTS)
+        .danger_accept_invalid_certs(true)
+        .build()
+        .unwrap();
+    let response = insecure.get(&server.url("/hello")).send().await.unwrap();
+    assert_eq!(response.text().await.unwrap(), "hello");
+}
+
+#[tokio::test]
+async fn retries() {
+    let server = TestServer::start().await;
+    let retry = Retry::new(3)
+        .backoff(Duration::from_millis(1), Duration::from_millis(10))
+        .jitter(false);
+    let client = server.client().retry(retry.clone()).build().unwrap();
+
+    let response = client.get(&server.url("/flaky/2")).send().await.unwrap();
+    assert_eq!(response.text().await.unwrap(), "recovered");
+    assert_eq!(server.hits("/flaky/2"), 3);
+    let response = client.delete(&server.url("/reset/2")).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::Ok);
+    assert_eq!(server.hits("/reset/2"), 3);
+
+    // Attempts run out with the last response or error
+    let response = client.get(&server.url("/flaky/5")).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::ServiceUnavailable);
+    assert_eq!(response.text().await.unwrap(), "try again");
+    assert_eq!(server.hits("/flaky/5"), 3);
+    let error = client
+        .get(&server.url("/reset/5"))
+        .send()
+        .await
+        .err()
+        .unwrap();
+    assert!(
+        matches!(
+            error,
+            Error::Gurt(GurtError::UnexpectedEof | GurtError::Io(_))
+        ),
+        "{error}"
+    );
+    // The first attempt went out on a pooled connection, so it was sent again at once on a
+    // new one in case the server had closed the pooled connection while idle
+    assert_eq!(server.hits("/reset/5"), 4);
+
+    // POST is sent once unless the policy says otherwise
+    let response = client
+        .post(&server.url("/flaky/1"))
+        .body("x")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::ServiceUnavailable);
+    assert_eq!(server.hits("/flaky/1"), 1);
+    let error = client
+        .post(&server.url("/reset/1"))
+        .send()
+        .await
+        .err()
+        .unwrap();
+    assert!(matches!(error, Error::Gurt(_)), "{error}");
+    let response = client
+        .post(&server.url("/reset/1"))
+        .body("again")
+        .retry(retry.clone().non_idempotent(true))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::Ok);
+    assert_eq!(server.hits("/reset/1"), 2);
+    // Nor again on a new connection when it fails on a pooled one
+    let response = client.get(&server.url("/hello")).send().await.unwrap();
+    assert_eq!(response.text().await.unwrap(), "hello");
+    client
+        .post(&server.url("/reset/3"))
+        .body("once")
+        .send()
+        .await
+        .err()
+        .unwrap();
+    assert_eq!(server.hits("/reset/3"), 1);
+
+    // retry-after: a past date means now, a long wait is not worth it
+    let response = client.get(&server.url("/dated")).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::GatewayTimeout);
+    assert_eq!(server.hits("/dated"), 3);
+    let response = client.get(&server.url("/busy")).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::ServiceUnavailable);
+    assert_eq!(server.hits("/busy"), 1);
+
+    // No wait runs past the timeout, and a policy can be switched off
+    let response = client
+        .get(&server.url("/flaky/9"))
+        .retry(
+            retry
+                .clone()
+                .backoff(Duration::from_secs(5), Duration::from_secs(5)),
+        )
+        .timeout(Duration::from_millis(500))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::ServiceUnavailable);
+    let response = client
+        .get(&server.url("/flaky/8"))
+        .retry(Retry::never())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::ServiceUnavailable);
+    assert_eq!(server.hits("/flaky/8"), 1);
+}
+
+#[tokio::test]
+async fn caching() {
+    let server = TestServer::start().await;
+    let client = server
+        .client()
+        .cache(MemoryCache::new(1 << 20))
+        .build()
+        .unwrap();
+    let get = |path: &str| client.get(&server.url(path)).send();
+
+    // Fresh responses are served from the cache
+    assert_eq!(
+        get("/fresh").await.unwrap().text().await.unwrap(),
+        "fresh 1"
+    );
+    assert_eq!(
+        get("/fresh").await.unwrap().text().await.unwrap(),
+        "fresh 1"
+    );
+    assert_eq!(server.hits("/fresh"), 1);
+    let response = client
+        .get(&server.url("/fresh"))
+        .header("cache-control", "no-cache")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.text().await.unwrap(), "fresh 2");
+    // Unsafe methods invalidate the stored response
+    let response = client.post(&server.url("/fresh")).send().await.unwrap();
+    assert_eq!(response.text().await.unwrap(), "fresh 3");
+    assert_eq!(
+        get("/fresh").await.unwrap().text().await.unwrap(),
+        "fresh 4"
+    );
+    assert_eq!(server.hits("/fresh"), 4);
+
+    // Stale responses are revalidated; a 304 brings back the stored body and status
+    let response = get("/tagged").await.unwrap();
+    assert_eq!(response.header("x-hit"), Some("1"));
+    assert_eq!(response.text().await.unwrap(), "tagged");
+    let response = get("/tagged").await.unwrap();
+    assert_eq!(response.status(), StatusCode::Ok);
+    assert_eq!(response.header("x-hit"), Some("2"));
+    assert_eq!(response.header("content-encoding"), None);
+    assert_eq!(response.text().await.unwrap(), "tagged");
+    assert_eq!(server.hits("/tagged"), 2);
+    // Requests with their own validators see the 304
+    let response = client
+        .get(&server.url("/tagged"))
+        .header("if-none-match", "\"v1\"")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NotModified);
+    // A 304 that declares a body anyway closes its connection rather than leave the body
+    // on it for the next response
+    for _ in 0..2 {
+        let response = get("/padded").await.unwrap();
+        assert_eq!(response.text().await.unwrap(), "padded");
+    }
+    assert_eq!(server.hits("/padded"), 2);
+    assert_eq!(get("/hello").await.unwrap().text().await.unwrap(), "hello");
+
+    // last-modified makes a response fresh for a while, and serves as a validator
+    assert_eq!(
+        get("/dated-asset").await.unwrap().text().await.unwrap(),
+        "dated"
+    );
+    assert_eq!(
+        get("/dated-asset").await.unwrap().text().await.unwrap(),
+        "dated"
+    );
+    assert_eq!(server.hits("/dated-asset"), 1);
+    let response = client
+        .get(&server.url("/dated-asset"))
+        .header("cache-control", "max-age=0")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.text().await.unwrap(), "dated");
+    assert_eq!(server.hits("/dated-asset"), 2);
+
+    get("/uncached").await.unwrap().text().await.unwrap();
+    get("/uncached").await.unwrap().text().await.unwrap();
+    assert_eq!(server.hits("/uncached"), 2);
+    // Connections went back to the pool throughout, except after the 304 with a body
+    assert_eq!(server.connections(), 2);
+}
+
+#[tokio::test]
+async fn downloads() {
+    let server = TestServer::start().await;
+    let client = server.client().build().unwrap();
+    let dir = std::env::temp_dir().join(format!("yo-gurt-downloads-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("blob");
+    let blob = blob();
+
+    let response = client
+        .get(&server.url("/blob"))
+        .range(ByteRange::Last(10))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::PartialContent);
+    let content_range = response.content_range().unwrap();
+    assert_eq!(content_range.complete_length, Some(blob.len() as u64));
+    assert_eq!(response.bytes().await.unwrap(), blob[blob.len() - 10..]);
+
+    // A download continues from what an earlier one left in the file
+    std::fs::write(&path, &blob[..100]).unwrap();
+    let download = client.get(&server.url("/blob")).download(&path);
+    assert_eq!(download.await.unwrap(), blob.len() as u64);
+    assert!(std::fs::read(&path).unwrap() == blob);
+    assert_eq!(server.hits("/blob"), 3);
+    // A complete file takes one request to confirm
+    let download = client.get(&server.url("/blob")).download(&path);
+    assert_eq!(download.await.unwrap(), blob.len() as u64);
+    assert_eq!(server.hits("/blob"), 4);
+    // A file longer than the resource cannot be continued
+    std::fs::write(&path, [&blob[..], b"extra"].concat()).unwrap();
+    let error = client
+        .get(&server.url("/blob"))
+        .download(&path)
+        .await
+        .err()
+        .unwrap();
+    assert!(
+        matches!(error, Error::Status(StatusCode::RangeNotSatisfiable)),
+        "{error}"
+    );
+
+    // Servers without ranges send the whole resource, which replaces the file
+    let download = client.get(&server.url("/hello")).download(&path);
+    assert_eq!(download.await.unwrap(), 5);
+    assert_eq!(std::fs::read(&path).unwrap(), b"hello");
+    // Ranges that fail in transit are asked for again
+    let download = client.get(&server.url("/reset/2")).download(&path);
+    assert_eq!(download.await.unwrap(), 9);
+    assert_eq!(std::fs::read(&path).unwrap(), b"recovered");
+    assert_eq!(server.hits("/reset/2"), 3);
+    // Under the same policy as other requests, so only idempotent methods are repeated
+    let download = client.post(&server.url("/reset/1")).download(&path);
+    assert!(download.await.is_err());
+    assert_eq!(server.hits("/reset/1"), 1);
+    let error = client
+        .get(&server.url("/missing"))
+        .download(&path)
+        .await
+        .err()
+        .unwrap();
+    assert!(
+        matches!(error, Error::Status(StatusCode::NotFound)),
+        "{error}"
+    );
+    // Nor does a bodiless status empty the file
+    let error = client
+        .get(&server.url("/empty"))
+        .download(&path)
+        .await
+        .err()
+        .unwrap();
+    assert!(
+        matches!(error, Error::Status(StatusCode::NoContent)),
+        "{error}"
+    );
+    assert_eq!(std::fs::read(&path).unwrap(), b"recovered");
+
+    // A download continues under the validator kept by the one that left the file
+    let validator = dir.join("blob.validator");
+    std::fs::write(&path, &blob[..100]).unwrap();
+    std::fs::write(&validator, "\"b1\"").unwrap();
+    let download = client.get(&server.url("/blob")).download(&path);
+    assert_eq!(download.await.unwrap(), blob.len() as u64);
+    assert!(std::fs::read(&path).unwrap() == blob);
+    assert!(!validator.exists());
+    assert_eq!(server.hits("/blob"), 7);
+    // and starts over when the resource has changed since
+    std::fs::write(&path, &blob[..100]).unwrap();
+    std::fs::write(&validator, "\"b0\"").unwrap();
+    let download = client.get(&server.url("/blob")).download(&path);
+    assert_eq!(download.await.unwrap(), blob.len() as u64);
+    assert!(std::fs::read(&path).unwrap() == blob);
+    assert_eq!(server.hits("/blob"), 8);
+    // Weak tags are not sent in `if-range`
+    std::fs::remove_file(&path).unwrap();
+    let download = client.get(&server.url("/weak-blob")).download(&path);
+    assert_eq!(download.await.unwrap(), blob.len() as u64);
+    assert!(std::fs::read(&path).unwrap() == blob);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn urls() {
+    let url = Url::parse("GURT://Example.WEB/a/b?c=d#frag").unwrap();
+    assert_eq!(
+        (url.host(), url.port(), url.path()),
+        ("example.web", 4878, "/a/b?c=d")
+    );
+    assert_eq!(url.to_string(), "gurt://example.web/a/b?c=d");
+    let url: Url = "gurt://[::1]:9000?x".parse().unwrap();
+    assert_eq!((url.host(), url.port(), url.path()), ("::1", 9000, "/?x"));
+    assert_eq!(url.to_string(), "gurt://[::1]:9000/?x");
+    assert_eq!(url.with_path("up").to_string(), "gurt://[::1]:9000/up");
+    assert_eq!(Url::parse("gurt://site.web").unwrap().path(), "/");
+    for invalid in [
+        "",
+        "site.web",
+        "gurt://[::1",
+        "gurt://:80/",
+        "gurt://a.web/b c",
+        "gurt://a:99999/",
+    ] {
+        assert!(Url::parse(invalid).is_err(), "{invalid}");
+    }
+    let _: Connector = Connector::new(
+        rustls::ClientConfig::builder()
+            .with_root_certificates(rustls::RootCertStore::empty())
+            .with_no_client_auth(),
+        portal_solutions_yo_gurt::resolve::System,
+    );
+}
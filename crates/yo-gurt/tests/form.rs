@@ -0,0 +1,365 @@
+//! Form encoders and incremental form parsers over in-memory transports
+
+use core::convert::Infallible;
+
+use embassy_futures::block_on;
+use embedded_io_async::Read;
+use portal_solutions_yo_gurt::form::{
+    Boundary, MultipartBody, MultipartReader, MultipartWriter, Part, UrlEncoded, UrlEncodedReader,
+    decode_urlencoded, multipart_boundary,
+};
+use portal_solutions_yo_gurt::testing::Scripted;
+use portal_solutions_yo_gurt::{
+    BodyReader, GurtClient, GurtError, Limit, Method, ProtocolError, RequestBodyWriter,
+    RequestError, ResponseReader,
+};
+
+type Error = GurtError<Infallible>;
+
+/// Write a request body of `len` bytes with `write`, returning just the body bytes
+fn body<F>(len: usize, write: F) -> Result<Vec<u8>, Error>
+where
+    F: AsyncFnOnce(&mut RequestBodyWriter<'_, Scripted<'_>>) -> Result<(), Error>,
+{
+    let mut client = GurtClient::new(Scripted::default());
+    block_on(async {
+        let mut body = client
+            .request(Method::Post, "/", "example.com", None, &[], Some(len))
+            .await?;
+        write(&mut body).await?;
+        body.finish().await
+    })?;
+    let written = client.transport.written();
+    let head = written.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+    Ok(written[head..].to_vec())
+}
+
+/// A body reader over `input`, delivering at most `step` bytes per read
+fn with_body<O>(
+    input: &[u8],
+    step: usize,
+    f: impl AsyncFnOnce(BodyReader<'_, Scripted<'_>>) -> O,
+) -> O {
+    let mut transport = Scripted::new(input).step(step);
+    block_on(f(ResponseReader::new(&mut transport).body(input.len())))
+}
+
+fn read_fields(input: &[u8], step: usize, buf_size: usize) -> Result<Vec<(String, String)>, Error> {
+    with_body(input, step, async |body| {
+        let mut buf = vec![0u8; buf_size];
+        let mut reader = UrlEncodedReader::new(body, &mut buf);
+        let mut fields = Vec::new();
+        while let Some((name, value)) = reader.next_field().await? {
+            fields.push((name.to_owned(), value.to_owned()));
+        }
+        Ok(fields)
+    })
+}
+
+/// A part as read back: name, file name, content type and contents
+type ReadPart = (String, Option<String>, Option<String>, Vec<u8>);
+
+fn read_parts(
+    input: &[u8],
+    boundary: &str,
+    step: usize,
+    buf_size: usize,
+) -> Result<Vec<ReadPart>, Error> {
+    with_body(input, step, async |body| {
+        let mut buf = vec![0u8; buf_size];
+        let mut reader = MultipartReader::new(body, boundary, &mut buf);
+        let mut parts = Vec::new();
+        while let Some(head) = reader.next_part().await? {
+            let mut part = (
+                head.name().to_owned(),
+                head.filename().map(str::to_owned),
+                head.content_type().map(str::to_owned),
+                Vec::new(),
+            );
+            let mut chunk = [0u8; 7];
+            loop {
+                let n = reader.read(&mut chunk).await?;
+                if n == 0 {
+                    break;
+                }
+                part.3.extend_from_slice(&chunk[..n]);
+            }
+            parts.push(part);
+        }
+        Ok(parts)
+    })
+}
+
+fn s(value: &str) -> String {
+    value.to_owned()
+}
+
+#[test]
+fn urlencoded_encoding() {
+    let fields = [("name", "Jane Doe"), ("q", "a&b=c/ü*-._~")];
+    let form = UrlEncoded::new(&fields);
+    let expected = b"name=Jane+Doe&q=a%26b%3Dc%2F%C3%BC*-._%7E";
+    assert_eq!(form.to_vec(), expected);
+    assert_eq!(form.len(), expected.len());
+    assert_eq!(
+        body(form.len(), async |body| form.write_to(body).await),
+        Ok(expected.to_vec())
+    );
+
+    // Longer than the encoder's stack buffer
+    let long = "x y".repeat(500);
+    let fields = [("long", long.as_str())];
+    let form = UrlEncoded::new(&fields);
+    let written = body(form.len(), async |body| form.write_to(body).await).unwrap();
+    assert_eq!(written, form.to_vec());
+    assert!(UrlEncoded::new(&[]).is_empty());
+}
+
+#[test]
+fn urlencoded_parsing() {
+    let input = b"name=Jane+Doe&&q=a%26b%3Dc%2F%C3%BC&bad=100%zz%&flag&=empty&";
+    let expected = vec![
+        (s("name"), s("Jane Doe")),
+        (s("q"), s("a&b=c/ü")),
+        (s("bad"), s("100%zz%")),
+        (s("flag"), s("")),
+        (s(""), s("empty")),
+    ];
+    for step in [1, 3, 100] {
+        assert_eq!(read_fields(input, step, 32), Ok(expected.clone()));
+    }
+    assert_eq!(decode_urlencoded(input), Ok(expected));
+    assert_eq!(read_fields(b"", 1, 32), Ok(vec![]));
+
+    assert_eq!(
+        read_fields(b"short=1&long=0123456789abcdef", 4, 16),
+        Err(GurtError::LimitExceeded(Limit::BufferTooSmall))
+    );
+    assert_eq!(
+        read_fields(b"name=%FF", 4, 16),
+        Err(GurtError::Protocol(ProtocolError::InvalidFormData))
+    );
+}
+
+#[test]
+fn boundaries() {
+    let boundary = Boundary::new("abc-123").unwrap();
+    assert_eq!(boundary.as_str(), "abc-123");
+    assert_eq!(
+        boundary.content_type(),
+        "multipart/form-data; boundary=abc-123"
+    );
+    assert!(Boundary::new("").is_none());
+    assert!(Boundary::new("has space").is_none());
+    assert!(Boundary::new(&"x".repeat(71)).is_none());
+    assert!(Boundary::new(&"x".repeat(70)).is_some());
+    assert_ne!(
+        Boundary::from_seed(1).as_str(),
+        Boundary::from_seed(2).as_str()
+    );
+    assert_ne!(Boundary::random().as_str(), Boundary::random().as_str());
+
+    assert_eq!(
+        multipart_boundary("multipart/form-data; boundary=abc-123"),
+        Some("abc-123")
+    );
+    assert_eq!(
+        multipart_boundary("Multipart/Form-Data;charset=utf-8; Boundary=\"a b\""),
+        Some("a b")
+    );
+    assert_eq!(multipart_boundary("multipart/mixed; boundary=abc"), None);
+    assert_eq!(multipart_boundary("multipart/form-data"), None);
+}
+
+#[test]
+fn multipart_encoding() {
+    let boundary = Boundary::new("XyZ").unwrap();
+    let parts = [
+        Part::new("title", 5),
+        Part::new("up\"load", 3)
+            .filename("a\r\nb.txt")
+            .content_type("text/plain"),
+    ];
+    let expected: &[u8] = b"--XyZ\r\n\
+        content-disposition: form-data; name=\"title\"\r\n\
+        \r\n\
+        hello\r\n\
+        --XyZ\r\n\
+        content-disposition: form-data; name=\"up%22load\"; filename=\"a%0D%0Ab.txt\"\r\n\
+        content-type: text/plain\r\n\
+        \r\n\
+        abc\r\n\
+        --XyZ--\r\n";
+    assert_eq!(boundary.content_length(&parts), expected.len());
+
+    let written = body(expected.len(), async |body| {
+        let mut form = MultipartWriter::new(body, &boundary);
+        form.field("title", b"hello").await?;
+        form.part(&parts[1]).await?;
+        form.write(b"a").await?;
+        form.write(b"bc").await?;
+        form.finish().await
+    });
+    assert_eq!(written.as_deref(), Ok(expected));
+
+    let mut collected = MultipartBody::new(boundary.clone());
+    collected
+        .field("title", b"hello")
+        .file("up\"load", "a\r\nb.txt", "text/plain", b"abc")
+        .unwrap();
+    assert_eq!(collected.content_type(), boundary.content_type());
+    assert_eq!(collected.finish(), expected);
+
+    assert_eq!(boundary.content_length(&[]), b"--XyZ--\r\n".len());
+}
+
+#[test]
+fn multipart_writer_checks_part_lengths() {
+    let boundary = Boundary::new("b").unwrap();
+    let too_long = body(100, async |body| {
+        let mut form = MultipartWriter::new(body, &boundary);
+        form.part(&Part::new("a", 2)).await?;
+        form.write(b"abc").await
+    });
+    assert_eq!(
+        too_long,
+        Err(GurtError::InvalidRequest(RequestError::BodyTooLong))
+    );
+
+    let short = body(100, async |body| {
+        let mut form = MultipartWriter::new(body, &boundary);
+        form.part(&Part::new("a", 2)).await?;
+        form.write(b"a").await?;
+        form.finish().await
+    });
+    assert_eq!(
+        short,
+        Err(GurtError::InvalidRequest(RequestError::BodyIncomplete))
+    );
+
+    let no_part = body(100, async |body| {
+        MultipartWriter::new(body, &boundary).write(b"a").await
+    });
+    assert_eq!(
+        no_part,
+        Err(GurtError::InvalidRequest(RequestError::BodyTooLong))
+    );
+
+    let bad_type = body(100, async |body| {
+        let mut form = MultipartWriter::new(body, &boundary);
+        form.part(&Part::new("a", 1).content_type("text/plain\r\nx: y"))
+            .await
+    });
+    assert_eq!(
+        bad_type,
+        Err(GurtError::InvalidRequest(RequestError::InvalidHeaderValue))
+    );
+}
+
+#[test]
+fn multipart_parsing() {
+    let boundary = Boundary::from_seed(7);
+    let big: Vec<u8> = (0..5000u32).map(|i| (i % 251) as u8).collect();
+    let mut form = MultipartBody::new(boundary.clone());
+    form.field("title", b"hello")
+        .field("empty", b"")
+        // Contents that look almost like a delimiter
+        .field(
+            "tricky",
+            format!("\r\n--{}x\r\n-", &boundary.as_str()[..10]).as_bytes(),
+        )
+        .file("upload", "photo.bin", "application/octet-stream", &big)
+        .unwrap();
+    let input = form.finish();
+    let expected = vec![
+        (s("title"), None, None, b"hello".to_vec()),
+        (s("empty"), None, None, Vec::new()),
+        (
+            s("tricky"),
+            None,
+            None,
+            format!("\r\n--{}x\r\n-", &boundary.as_str()[..10]).into_bytes(),
+        ),
+        (
+            s("upload"),
+            Some(s("photo.bin")),
+            Some(s("application/octet-stream")),
+            big,
+        ),
+    ];
+    for (step, buf_size) in [(1, 128), (5, 200), (1000, 4096)] {
+        assert_eq!(
+            read_parts(&input, boundary.as_str(), step, buf_size),
+            Ok(expected.clone()),
+            "step {step}, buffer {buf_size}"
+        );
+    }
+}
+
+#[test]
+fn multipart_framing_variants() {
+    // Preamble, padding after delimiters, header case, extra parameters and an epilogue
+    let input = b"preamble\r\n--b  \r\n\
+        Content-Disposition: form-data ; filename=\"x;y.txt\";name=field\r\n\
+        X-Ignored: yes\r\n\
+        \r\n\
+        one\r\n--b\t\r\n\
+        content-disposition: form-data; name=\"second\"\r\n\
+        \r\n\
+        two\r\n--b--\r\nepilogue";
+    let parts = read_parts(input, "b", 3, 128).unwrap();
+    assert_eq!(
+        parts,
+        vec![
+            (s("field"), Some(s("x;y.txt")), None, b"one".to_vec()),
+            (s("second"), None, None, b"two".to_vec()),
+        ]
+    );
+
+    // Part contents left unread are skipped
+    let skipped = with_body(input, 4, async |body| {
+        let mut buf = [0u8; 128];
+        let mut reader = MultipartReader::new(body, "b", &mut buf);
+        let mut names = Vec::new();
+        while let Some(head) = reader.next_part().await? {
+            names.push(head.name().to_owned());
+        }
+        Ok::<_, Error>(names)
+    });
+    assert_eq!(skipped, Ok(vec![s("field"), s("second")]));
+}
+
+#[test]
+fn malformed_multipart() {
+    let invalid = Err(GurtError::Protocol(ProtocolError::InvalidFormData));
+    for input in [
+        &b"--b\r\ncontent-disposition: form-data; name=a\r\n\r\nno close"[..],
+        b"--b\r\ncontent-disposition: form-data; name=a\r\n\r\nx\r\n--b",
+        b"--b\r\ncontent-disposition: attachment; name=a\r\n\r\nx\r\n--b--\r\n",
+        b"--b\r\ncontent-disposition: form-data\r\n\r\nx\r\n--b--\r\n",
+        b"--b\r\ncontent-disposition: form-data; name=\"a\r\n\r\nx\r\n--b--\r\n",
+        b"--b\r\nno colon\r\n\r\nx\r\n--b--\r\n",
+        b"--bx\r\ncontent-disposition: form-data; name=a\r\n\r\nx\r\n--b--\r\n",
+        b"no delimiter at all",
+    ] {
+        assert_eq!(
+            read_parts(input, "b", 3, 128),
+            invalid,
+            "{}",
+            String::from_utf8_lossy(input)
+        );
+    }
+    let long_header = format!(
+        "--b\r\ncontent-disposition: form-data; name=\"{}\"\r\n\r\nx\r\n--b--\r\n",
+        "n".repeat(200)
+    );
+    assert_eq!(
+        read_parts(long_header.as_bytes(), "b", 16, 128),
+        Err(GurtError::LimitExceeded(Limit::BufferTooSmall))
+    );
+    assert_eq!(
+        read_parts(b"--boundary--\r\n", "boundary", 16, 8),
+        Err(GurtError::LimitExceeded(Limit::BufferTooSmall))
+    );
+    assert_eq!(read_parts(b"--b--\r\n", "b", 1, 16), Ok(vec![]));
+}
@@ -0,0 +1,127 @@
+//! Progress observers on request and response bodies
+
+use embassy_futures::block_on;
+use embedded_io_async::Read;
+use portal_solutions_yo_gurt::adapters::FromTokio;
+use portal_solutions_yo_gurt::testing::Scripted;
+use portal_solutions_yo_gurt::{
+    GurtClient, GurtError, Limit, Method, RequestError, RequestHead, ResponseReader,
+};
+
+#[test]
+fn upload_progress() {
+    let mut client = GurtClient::new(Scripted::default());
+    let mut seen = Vec::new();
+    block_on(async {
+        let mut body = client
+            .request(Method::Post, "/up", "example.com", None, &[], Some(10))
+            .await
+            .unwrap()
+            .with_progress(|n, total| seen.push((n, total)));
+        body.write(b"hello").await.unwrap();
+        body.write(b"").await.unwrap();
+        // Only the declared length is taken from the source
+        let mut source: &[u8] = b"world!";
+        body.write_from(&mut source, &mut [0; 3]).await.unwrap();
+        assert_eq!(source, b"!");
+        body.finish().await.unwrap();
+    });
+    assert_eq!(seen, [(5, 10), (8, 10), (10, 10)]);
+    assert!(client.transport.written().ends_with(b"\r\n\r\nhelloworld"));
+
+    block_on(async {
+        let mut body = client
+            .request(Method::Post, "/up", "example.com", None, &[], Some(4))
+            .await
+            .unwrap();
+        let result = body.write_from(&mut &b"ab"[..], &mut []).await;
+        assert_eq!(result, Err(GurtError::LimitExceeded(Limit::BufferTooSmall)));
+        let result = body.write_from(&mut &b"ab"[..], &mut [0; 8]).await;
+        assert_eq!(
+            result,
+            Err(GurtError::InvalidRequest(RequestError::BodyIncomplete))
+        );
+        assert_eq!(body.remaining(), 2);
+    });
+}
+
+#[test]
+fn download_progress() {
+    let mut transport: &[u8] = b"GURT/1.0.0 200 OK\r\n\
+        content-length: 10\r\n\
+        \r\n\
+        0123456789\
+        GURT/1.0.0 200 OK\r\n\
+        content-length: 4\r\n\
+        content-encoding: identity\r\n\
+        \r\n\
+        abcd";
+    let mut buf = [0u8; 64];
+    let mut seen = Vec::new();
+    block_on(async {
+        let mut response = ResponseReader::new(&mut transport);
+        response.read_status_line(&mut buf).await.unwrap();
+        while response.read_header(&mut buf).await.unwrap().is_some() {}
+        let mut body = response
+            .body(10)
+            .with_progress(|n, total| seen.push((n, total)));
+        let mut chunk = [0u8; 4];
+        while body.read(&mut chunk).await.unwrap() > 0 {}
+        assert!(body.is_done());
+    });
+    assert_eq!(seen, [(4, 10), (8, 10), (10, 10)]);
+
+    seen.clear();
+    block_on(async {
+        let mut response = ResponseReader::new(&mut transport);
+        response.read_status_line(&mut buf).await.unwrap();
+        while response.read_header(&mut buf).await.unwrap().is_some() {}
+        let mut body = response
+            .decoded_body(4)
+            .unwrap()
+            .with_progress(|n, total| seen.push((n, total)));
+        let mut out = Vec::new();
+        body.read_to_end(&mut out).await.unwrap();
+        assert_eq!(out, b"abcd");
+    });
+    assert_eq!(seen, [(4, 4)]);
+}
+
+#[tokio::test]
+async fn send_file() {
+    use tokio::io::AsyncReadExt;
+
+    let path = std::env::temp_dir().join(format!("yo-gurt-send-file-{}", std::process::id()));
+    let contents: Vec<u8> = (0..40_000u32).map(|i| i as u8).collect();
+    std::fs::write(&path, &contents).unwrap();
+
+    let (client_io, mut server_io) = tokio::io::duplex(1024);
+    let server = tokio::spawn(async move {
+        let mut received = Vec::new();
+        server_io.read_to_end(&mut received).await.unwrap();
+        received
+    });
+    let mut client = GurtClient::new(FromTokio(client_io));
+    let mut seen = Vec::new();
+    let head = RequestHead::new(Method::Put, "/upload", "example.com");
+    client
+        .send_file(head, &path, |n, total| seen.push((n, total)))
+        .await
+        .unwrap();
+    drop(client);
+    std::fs::remove_file(&path).unwrap();
+
+    let received = server.await.unwrap();
+    let end = received.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+    let head = std::str::from_utf8(&received[..end]).unwrap();
+    assert!(head.starts_with("PUT /upload GURT/1.0.0\r\n"));
+    assert!(head.contains("\r\ncontent-length: 40000\r\n"));
+    assert_eq!(&received[end..], contents);
+    assert_eq!(seen.last(), Some(&(40_000, 40_000)));
+    assert!(seen.len() >= 3 && seen.windows(2).all(|w| w[0].0 < w[1].0));
+
+    let head = RequestHead::new(Method::Put, "/upload", "example.com");
+    let mut client = GurtClient::new(FromTokio(tokio::io::duplex(64).0));
+    let result = client.send_file(head, "/nonexistent/file", |_, _| {}).await;
+    assert!(matches!(result, Err(GurtError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound));
+}
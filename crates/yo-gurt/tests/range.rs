@@ -0,0 +1,111 @@
+//! Byte ranges: range and content-range headers, and their framing in responses
+
+use embassy_futures::block_on;
+use portal_solutions_yo_gurt::range::{ByteRange, ContentRange};
+use portal_solutions_yo_gurt::{ResponseReader, StatusCode};
+
+#[test]
+fn byte_ranges() {
+    for (value, range) in [
+        ("bytes=0-499", ByteRange::FromTo(0, 499)),
+        ("bytes=500-", ByteRange::From(500)),
+        ("bytes=-200", ByteRange::Last(200)),
+        (" bytes= 7 - 7 ", ByteRange::FromTo(7, 7)),
+    ] {
+        assert_eq!(ByteRange::parse(value), Some(range), "{value}");
+    }
+    assert_eq!(ByteRange::FromTo(0, 499).to_string(), "bytes=0-499");
+    assert_eq!(ByteRange::From(500).to_string(), "bytes=500-");
+    assert_eq!(ByteRange::Last(200).to_string(), "bytes=-200");
+    for invalid in [
+        "",
+        "bytes=",
+        "bytes=-",
+        "bytes=5-4",
+        "bytes=0-1,5-6",
+        "items=0-1",
+        "bytes=+1-2",
+        "bytes=a-",
+    ] {
+        assert_eq!(ByteRange::parse(invalid), None, "{invalid}");
+    }
+
+    assert_eq!(ByteRange::FromTo(0, 499).resolve(1000), Some((0, 499)));
+    assert_eq!(ByteRange::FromTo(900, 2000).resolve(1000), Some((900, 999)));
+    assert_eq!(ByteRange::From(999).resolve(1000), Some((999, 999)));
+    assert_eq!(ByteRange::From(1000).resolve(1000), None);
+    assert_eq!(ByteRange::Last(200).resolve(1000), Some((800, 999)));
+    assert_eq!(ByteRange::Last(2000).resolve(1000), Some((0, 999)));
+    assert_eq!(ByteRange::Last(0).resolve(1000), None);
+    assert_eq!(ByteRange::From(0).resolve(0), None);
+    assert_eq!(ByteRange::Last(5).resolve(0), None);
+}
+
+#[test]
+fn content_ranges() {
+    let range = ContentRange::parse("bytes 0-499/1234").unwrap();
+    assert_eq!(range.range, Some((0, 499)));
+    assert_eq!(range.complete_length, Some(1234));
+    assert_eq!((range.len(), range.is_empty()), (500, false));
+    assert_eq!(range.to_string(), "bytes 0-499/1234");
+    let unknown = ContentRange::parse("bytes 10-19/*").unwrap();
+    assert_eq!(
+        (unknown.range, unknown.complete_length),
+        (Some((10, 19)), None)
+    );
+    assert_eq!(unknown.to_string(), "bytes 10-19/*");
+    let unsatisfied = ContentRange::parse("bytes */1234").unwrap();
+    assert_eq!((unsatisfied.range, unsatisfied.len()), (None, 0));
+    assert_eq!(unsatisfied.to_string(), "bytes */1234");
+    for invalid in [
+        "bytes */*",
+        "bytes 5-4/10",
+        "bytes 0-10/10",
+        "bytes 0-1",
+        "items 0-1/2",
+        "bytes -1-2/3",
+        "bytes 0-1/x",
+    ] {
+        assert_eq!(ContentRange::parse(invalid), None, "{invalid}");
+    }
+}
+
+#[test]
+fn response_framing() {
+    let mut transport: &[u8] = b"GURT/1.0.0 206 PARTIAL_CONTENT\r\n\
+        content-range: bytes 4-7/12\r\n\
+        content-length: 4\r\n\
+        \r\n\
+        4567\
+        GURT/1.0.0 416 RANGE_NOT_SATISFIABLE\r\n\
+        content-range: bytes */12\r\n\
+        \r\n\
+        GURT/1.0.0 200 OK\r\n\
+        content-range: nonsense\r\n\
+        \r\n";
+    let mut buf = [0u8; 64];
+    let mut reader = ResponseReader::new(&mut transport);
+    block_on(async {
+        let status = reader.read_status_line(&mut buf).await.unwrap().status;
+        assert_eq!(status, StatusCode::PartialContent);
+        while reader.read_header(&mut buf).await.unwrap().is_some() {}
+        let range = reader.content_range().unwrap();
+        assert_eq!(
+            (range.range, range.complete_length),
+            (Some((4, 7)), Some(12))
+        );
+        let mut body = [0u8; 4];
+        reader.read_body_exact(&mut body).await.unwrap();
+        assert_eq!(&body, b"4567");
+
+        let status = reader.read_status_line(&mut buf).await.unwrap().status;
+        assert_eq!(status, StatusCode::RangeNotSatisfiable);
+        assert_eq!(reader.content_range(), None);
+        while reader.read_header(&mut buf).await.unwrap().is_some() {}
+        assert_eq!(reader.content_range().unwrap().complete_length, Some(12));
+
+        reader.read_status_line(&mut buf).await.unwrap();
+        while reader.read_header(&mut buf).await.unwrap().is_some() {}
+        assert_eq!(reader.content_range(), None);
+    });
+}
@@ -0,0 +1,65 @@
+//! Static name resolution
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use embassy_futures::block_on;
+use portal_solutions_yo_gurt::resolve::{Fallback, Hosts, NotFound, Resolver};
+
+const HOSTS: Hosts = Hosts::new(
+    "# local stand-ins\n\
+     127.0.0.1\tsite.web www.site.web # the main site\n\
+     ::1 api.site.web\n\
+     not-an-address broken.web\n\
+     10.0.0.2 broken.web site.web\n",
+);
+
+#[test]
+fn hosts_lookup() {
+    let localhost = IpAddr::V4(Ipv4Addr::LOCALHOST);
+    assert_eq!(HOSTS.lookup("site.web"), Some(localhost));
+    assert_eq!(HOSTS.lookup("WWW.Site.Web"), Some(localhost));
+    assert_eq!(
+        HOSTS.lookup("api.site.web"),
+        Some(IpAddr::V6(Ipv6Addr::LOCALHOST))
+    );
+    // Lines with unusable addresses are skipped
+    assert_eq!(
+        HOSTS.lookup("broken.web"),
+        Some(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)))
+    );
+    for missing in ["other.web", "main", "the", "", "127.0.0.1"] {
+        assert_eq!(HOSTS.lookup(missing), None, "{missing}");
+    }
+    assert_eq!(block_on(HOSTS.resolve("nope.web")), Err(NotFound));
+}
+
+#[test]
+fn hosts_file() {
+    let path = std::env::temp_dir().join(format!("yo-gurt-hosts-{}", std::process::id()));
+    std::fs::write(&path, "192.168.1.5 device.web\n").unwrap();
+    let hosts = Hosts::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(
+        block_on(hosts.resolve("device.web")),
+        Ok(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 5)))
+    );
+    assert!(Hosts::load(&path).is_err());
+}
+
+#[test]
+fn fallback_chain() {
+    let overrides = Hosts::new("10.0.0.1 site.web");
+    let resolver = Fallback(None::<Hosts>, Fallback(&overrides, HOSTS));
+    let resolve = |host| block_on(resolver.resolve(host));
+    assert_eq!(
+        resolve("site.web"),
+        Ok(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)))
+    );
+    assert_eq!(resolve("www.site.web"), Ok(IpAddr::V4(Ipv4Addr::LOCALHOST)));
+    assert_eq!(resolve("other.web"), Err(NotFound));
+    assert_eq!(block_on(None::<Hosts>.resolve("site.web")), Err(None));
+    assert_eq!(
+        block_on(Some(HOSTS).resolve("other.web")),
+        Err(Some(NotFound))
+    );
+}
@@ -0,0 +1,198 @@
+//! ResponseReader grammar over in-memory input
+
+use core::convert::Infallible;
+
+use embassy_futures::block_on;
+use portal_solutions_yo_gurt::{
+    GurtError, Limit, ParseMode, ProtocolError, ResponseReader, StatusCode,
+};
+
+fn status_line(input: &[u8]) -> Result<StatusCode, GurtError<Infallible>> {
+    let mut transport = input;
+    let mut buf = [0u8; 64];
+    block_on(ResponseReader::new(&mut transport).read_status_line(&mut buf)).map(|r| r.status)
+}
+
+type Header = (Vec<u8>, Vec<u8>);
+
+fn header(input: &[u8]) -> Result<Option<Header>, GurtError<Infallible>> {
+    let mut transport = input;
+    let mut buf = [0u8; 64];
+    let header = block_on(ResponseReader::new(&mut transport).read_header(&mut buf))?;
+    Ok(header.map(|h| (h.name(&buf).to_vec(), h.value(&buf).to_vec())))
+}
+
+/// Read a header section and return the framed content length
+fn framing(headers: &[u8], mode: ParseMode) -> Result<Option<usize>, GurtError<Infallible>> {
+    let mut transport = headers;
+    let mut buf = [0u8; 64];
+    let mut reader = ResponseReader::with_mode(&mut transport, mode);
+    block_on(async {
+        while reader.read_header(&mut buf).await?.is_some() {}
+        Ok(reader.content_length())
+    })
+}
+
+#[test]
+fn status_line_grammar() {
+    assert_eq!(status_line(b"GURT/1.0.0 200 OK\r\n"), Ok(StatusCode::Ok));
+    assert_eq!(status_line(b"GURT/1.0.0 404\r\n"), Ok(StatusCode::NotFound));
+    assert_eq!(
+        status_line(b"GURT/1.0.0 304 NOT_MODIFIED\r\n"),
+        Ok(StatusCode::NotModified)
+    );
+    assert_eq!(
+        status_line(b"GURT/1.0.0 200 OK\n"),
+        Err(GurtError::Protocol(ProtocolError::InvalidLineEnding))
+    );
+    assert_eq!(
+        status_line(b"GURT/1.0.0 200 O\rK\r\n"),
+        Err(GurtError::Protocol(ProtocolError::InvalidLineEnding))
+    );
+    assert_eq!(
+        status_line(b"GURT/1.0 200 OK\r\n"),
+        Err(GurtError::Protocol(ProtocolError::InvalidProtocol))
+    );
+    for line in [
+        &b"GURT/1.0.0 0200 OK\r\n"[..],
+        b"GURT/1.0.0 20 OK\r\n",
+        b"GURT/1.0.0  200 OK\r\n",
+        b"GURT/1.0.0 200OK\r\n",
+        b"GURT/1.0.0 200 O\x00K\r\n",
+        b"GURT/1.0.0\r\n",
+    ] {
+        assert_eq!(
+            status_line(line),
+            Err(GurtError::Protocol(ProtocolError::InvalidStatusLine)),
+            "{}",
+            line.escape_ascii()
+        );
+    }
+    assert_eq!(
+        status_line(&[b'x'; 80]),
+        Err(GurtError::LimitExceeded(Limit::BufferTooSmall))
+    );
+}
+
+#[test]
+fn header_grammar() {
+    let pair = |name: &[u8], value: &[u8]| Ok(Some((name.to_vec(), value.to_vec())));
+    assert_eq!(
+        header(b"content-length: 5\r\n"),
+        pair(b"content-length", b"5")
+    );
+    assert_eq!(
+        header(b"x-a:\t spaced out \t\r\n"),
+        pair(b"x-a", b"spaced out")
+    );
+    assert_eq!(header(b"x-empty:\r\n"), pair(b"x-empty", b""));
+    assert_eq!(header(b"\r\n"), Ok(None));
+    assert_eq!(
+        header(b"no colon\r\n"),
+        Err(GurtError::Protocol(ProtocolError::InvalidHeader))
+    );
+    assert_eq!(
+        header(b": value\r\n"),
+        Err(GurtError::Protocol(ProtocolError::EmptyHeaderName))
+    );
+    assert_eq!(
+        header(b"bad name: v\r\n"),
+        Err(GurtError::Protocol(ProtocolError::InvalidHeaderName))
+    );
+    assert_eq!(
+        header(b"x(y): v\r\n"),
+        Err(GurtError::Protocol(ProtocolError::InvalidHeaderName))
+    );
+    assert_eq!(
+        header(b"x: a\x7fb\r\n"),
+        Err(GurtError::Protocol(ProtocolError::InvalidHeaderValue))
+    );
+    assert_eq!(
+        header(b"x: v\n"),
+        Err(GurtError::Protocol(ProtocolError::InvalidLineEnding))
+    );
+    assert_eq!(
+        header(b"\n"),
+        Err(GurtError::Protocol(ProtocolError::InvalidLineEnding))
+    );
+    assert_eq!(header(b"x: v"), Err(GurtError::UnexpectedEof));
+}
+
+#[test]
+fn framing_strict() {
+    let strict = |headers: &[u8]| framing(headers, ParseMode::Strict);
+    assert_eq!(strict(b"content-length: 12\r\n\r\n"), Ok(Some(12)));
+    assert_eq!(strict(b"Content-Length: 12\r\n\r\n"), Ok(Some(12)));
+    assert_eq!(strict(b"x: y\r\n\r\n"), Ok(None));
+    for value in [
+        &b"+12"[..],
+        b"-1",
+        b"1 2",
+        b"0x10",
+        b"",
+        b"99999999999999999999999",
+    ] {
+        let mut headers = b"content-length: ".to_vec();
+        headers.extend_from_slice(value);
+        headers.extend_from_slice(b"\r\n\r\n");
+        assert_eq!(
+            strict(&headers),
+            Err(GurtError::Protocol(ProtocolError::InvalidContentLength)),
+            "{}",
+            value.escape_ascii()
+        );
+    }
+    assert_eq!(
+        strict(b"content-length: 5\r\ncontent-length: 5\r\n\r\n"),
+        Err(GurtError::Protocol(ProtocolError::DuplicateContentLength))
+    );
+    assert_eq!(
+        strict(b"content-length: 5, 5\r\n\r\n"),
+        Err(GurtError::Protocol(ProtocolError::DuplicateContentLength))
+    );
+    assert_eq!(
+        strict(b"content-length : 5\r\n\r\n"),
+        Err(GurtError::Protocol(ProtocolError::WhitespaceBeforeColon))
+    );
+    assert_eq!(
+        strict(b"x: a\r\n b\r\n\r\n"),
+        Err(GurtError::Protocol(ProtocolError::ObsoleteLineFolding))
+    );
+    assert_eq!(
+        strict(b"transfer-encoding: chunked\r\n\r\n"),
+        Err(GurtError::Protocol(
+            ProtocolError::TransferEncodingNotAllowed
+        ))
+    );
+    assert_eq!(
+        strict(b"content-length: 10485761\r\n\r\n"),
+        Err(GurtError::LimitExceeded(Limit::MessageTooLarge))
+    );
+}
+
+#[test]
+fn framing_lenient() {
+    let lenient = |headers: &[u8]| framing(headers, ParseMode::Lenient);
+    assert_eq!(
+        lenient(b"content-length: 5\r\ncontent-length: 5\r\n\r\n"),
+        Ok(Some(5))
+    );
+    assert_eq!(lenient(b"content-length: 5, 5\r\n\r\n"), Ok(Some(5)));
+    assert_eq!(lenient(b"content-length\t: 5\r\n\r\n"), Ok(Some(5)));
+    assert_eq!(
+        lenient(b"content-length: 5\r\ncontent-length: 6\r\n\r\n"),
+        Err(GurtError::Protocol(ProtocolError::ConflictingContentLength))
+    );
+    assert_eq!(
+        lenient(b"content-length: 5, 6\r\n\r\n"),
+        Err(GurtError::Protocol(ProtocolError::ConflictingContentLength))
+    );
+    assert_eq!(
+        lenient(b"content-length: 5,\r\n\r\n"),
+        Err(GurtError::Protocol(ProtocolError::InvalidContentLength))
+    );
+    assert_eq!(
+        lenient(b"x: a\r\n\tb\r\n\r\n"),
+        Err(GurtError::Protocol(ProtocolError::ObsoleteLineFolding))
+    );
+}
@@ -0,0 +1,177 @@
+//! GurtServer against GurtClient over the in-memory duplex transport
+
+use embassy_futures::block_on;
+use embassy_futures::join::join;
+
+use embedded_io_async::Read;
+use portal_solutions_yo_gurt::server::{GurtServer, RequestReader};
+use portal_solutions_yo_gurt::testing::{Scripted, duplex};
+use portal_solutions_yo_gurt::{
+    GurtClient, GurtError, Method, ProtocolError, RequestError, ResponseHead, StatusCode,
+};
+
+#[test]
+fn handshake_request_and_response() {
+    let (client_io, server_io) = duplex(64);
+
+    let server = async {
+        let mut server = GurtServer::new(server_io);
+        let mut buf = [0u8; 128];
+        server.accept_handshake(&mut buf).await.unwrap();
+
+        let mut request = server.request_reader();
+        let line = request.read_request_line(&mut buf).await.unwrap();
+        assert_eq!(line.method, Method::Post);
+        assert_eq!(line.path(&buf), b"/echo");
+        let mut host = Vec::new();
+        while let Some(header) = request.read_header(&mut buf).await.unwrap() {
+            if header.name(&buf) == b"host" {
+                host = header.value(&buf).to_vec();
+            }
+        }
+        assert_eq!(host, b"example.com");
+        let length = request.content_length().unwrap();
+        let mut body = vec![0u8; length];
+        request.body(length).read_exact(&mut body).await.unwrap();
+
+        let mut response = server
+            .respond(
+                StatusCode::Created,
+                &[("content-type", "text/plain")],
+                length,
+            )
+            .await
+            .unwrap();
+        response.write(&body).await.unwrap();
+        response.finish().await.unwrap();
+    };
+
+    let client = async {
+        let mut client = GurtClient::new(client_io);
+        let mut buf = [0u8; 128];
+        client.handshake("example.com", "test/1.0").await.unwrap();
+        client.read_handshake_response(&mut buf).await.unwrap();
+
+        let mut body = client
+            .request(Method::Post, "/echo", "example.com", None, &[], Some(4))
+            .await
+            .unwrap();
+        body.write(b"ping").await.unwrap();
+        body.finish().await.unwrap();
+
+        let mut response = client.response_reader();
+        let status = response.read_status_line(&mut buf).await.unwrap();
+        assert_eq!(status.status, StatusCode::Created);
+        while response.read_header(&mut buf).await.unwrap().is_some() {}
+        let length = response.content_length().unwrap();
+        let mut out = [0u8; 4];
+        response.body(length).read_exact(&mut out).await.unwrap();
+        assert_eq!(&out, b"ping");
+    };
+
+    block_on(join(client, server));
+}
+
+#[test]
+fn response_head_encoding() {
+    let headers = [("content-type", "text/plain")];
+    let head = ResponseHead::new(StatusCode::Forbidden)
+        .headers(&headers)
+        .content_length(12);
+    let expected: &[u8] =
+        b"GURT/1.0.0 403 FORBIDDEN\r\ncontent-type: text/plain\r\ncontent-length: 12\r\n\r\n";
+    assert_eq!(head.encoded_len(), expected.len());
+    let mut buf = [0u8; 128];
+    let len = head.encode(&mut buf).unwrap();
+    assert_eq!(&buf[..len], expected);
+
+    let empty = ResponseHead::new(StatusCode::NoContent);
+    assert_eq!(
+        empty.slices().collect::<Vec<_>>().concat(),
+        b"GURT/1.0.0 204 NO_CONTENT\r\ncontent-length: 0\r\n\r\n"
+    );
+
+    let reserved = [("content-length", "1")];
+    assert_eq!(
+        ResponseHead::new(StatusCode::Ok)
+            .headers(&reserved)
+            .validate::<()>(),
+        Err(GurtError::InvalidResponse(RequestError::ReservedHeader))
+    );
+}
+
+#[test]
+fn response_bodies_report_response_errors() {
+    block_on(async {
+        let mut server = GurtServer::new(Scripted::default());
+        let mut body = server.respond(StatusCode::Ok, &[], 3).await.unwrap();
+        assert_eq!(
+            body.write(b"four").await,
+            Err(GurtError::InvalidResponse(RequestError::BodyTooLong))
+        );
+        body.write(b"ab").await.unwrap();
+        assert_eq!(
+            body.finish().await,
+            Err(GurtError::InvalidResponse(RequestError::BodyIncomplete))
+        );
+        // The head went out in one write, ahead of the body
+        assert_eq!(
+            server.transport.writes(),
+            [
+                b"GURT/1.0.0 200 OK\r\ncontent-length: 3\r\n\r\n".to_vec(),
+                b"ab".to_vec()
+            ]
+        );
+
+        let headers = [("Content-Type", "text/plain")];
+        assert_eq!(
+            server.respond(StatusCode::Ok, &headers, 0).await.err(),
+            Some(GurtError::InvalidResponse(RequestError::InvalidHeaderName))
+        );
+        assert_eq!(server.transport.writes().len(), 2);
+    });
+}
+
+#[test]
+fn request_line_errors() {
+    let cases: [(&[u8], ProtocolError); 6] = [
+        (b"GET / HTTP/1.1\r\n", ProtocolError::InvalidProtocol),
+        (b"FETCH / GURT/1.0.0\r\n", ProtocolError::InvalidMethod),
+        (b"GET GURT/1.0.0\r\n", ProtocolError::InvalidRequestLine),
+        (b"GET  / GURT/1.0.0\r\n", ProtocolError::InvalidRequestLine),
+        (
+            b"GET path GURT/1.0.0\r\n",
+            ProtocolError::InvalidRequestLine,
+        ),
+        (
+            b"GET /a\x01 GURT/1.0.0\r\n",
+            ProtocolError::InvalidRequestLine,
+        ),
+    ];
+    block_on(async {
+        for (input, expected) in cases {
+            let mut transport = input;
+            let mut buf = [0u8; 64];
+            let result = RequestReader::new(&mut transport)
+                .read_request_line(&mut buf)
+                .await;
+            assert_eq!(result, Err(GurtError::Protocol(expected)), "{input:?}");
+        }
+    });
+}
+
+#[test]
+fn handshake_required() {
+    block_on(async {
+        let mut server = GurtServer::new(Scripted::new(
+            b"GET / GURT/1.0.0\r\nhost: example.com\r\n\r\n",
+        ));
+        let mut buf = [0u8; 64];
+        assert_eq!(
+            server.accept_handshake(&mut buf).await,
+            Err(GurtError::Protocol(ProtocolError::HandshakeRequired))
+        );
+        // Nothing is written, leaving the reply to the caller
+        assert!(server.transport.written().is_empty());
+    });
+}
@@ -0,0 +1,106 @@
+//! tracing events for client exchanges, with header values redacted
+
+use std::sync::{Arc, Mutex};
+
+use embassy_futures::block_on;
+use embedded_io_async::Read;
+use portal_solutions_yo_gurt::testing::Scripted;
+use portal_solutions_yo_gurt::trace::{
+    DEFAULT_REDACTED_HEADERS, redacted_headers, set_redacted_headers,
+};
+use portal_solutions_yo_gurt::{GurtClient, Method, RequestHead};
+use tracing_subscriber::fmt::MakeWriter;
+
+/// Log output collected from a subscriber
+#[derive(Clone, Default)]
+struct Logs(Arc<Mutex<Vec<u8>>>);
+
+impl Logs {
+    fn take(&self) -> String {
+        String::from_utf8(std::mem::take(&mut *self.0.lock().unwrap())).unwrap()
+    }
+}
+
+impl std::io::Write for Logs {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for Logs {
+    type Writer = Logs;
+
+    fn make_writer(&'a self) -> Logs {
+        self.clone()
+    }
+}
+
+/// Send a request with credentials and read a response setting a cookie
+fn exchange() {
+    let mut client = GurtClient::new(Scripted::new(
+        b"GURT/1.0.0 200 OK\r\n\
+        set-cookie: session=s3cret\r\n\
+        x-api-key: k3y\r\n\
+        content-length: 2\r\n\
+        \r\n\
+        okGURT/1.0.0 999 NOPE\r\n",
+    ));
+    let headers = [("authorization", "Bearer t0ken"), ("accept", "text/plain")];
+    let head = RequestHead::new(Method::Get, "/private", "example.com").headers(&headers);
+    block_on(async {
+        let mut buf = [0u8; 128];
+        client.send_head(&head, &mut buf).await.unwrap();
+        let mut response = client.response_reader();
+        response.read_status_line(&mut buf).await.unwrap();
+        while response.read_header(&mut buf).await.unwrap().is_some() {}
+        let mut body = response.body(2);
+        let mut out = [0u8; 2];
+        body.read_exact(&mut out).await.unwrap();
+        let mut response = client.response_reader();
+        assert!(response.read_status_line(&mut buf).await.is_err());
+    });
+}
+
+#[test]
+fn events_and_redaction() {
+    let logs = Logs::default();
+    let subscriber = tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::TRACE)
+        .with_ansi(false)
+        .without_time()
+        .with_writer(logs.clone())
+        .finish();
+    tracing::subscriber::with_default(subscriber, || {
+        exchange();
+        let output = logs.take();
+        for expected in [
+            "DEBUG portal_solutions_yo_gurt::head: request Get /private",
+            "TRACE portal_solutions_yo_gurt::head: host: example.com",
+            "authorization: <redacted>",
+            "accept: text/plain",
+            "DEBUG portal_solutions_yo_gurt: response Ok",
+            "set-cookie: <redacted>",
+            "x-api-key: k3y",
+            "content-length: 2",
+            "body received, 2 bytes",
+            "WARN portal_solutions_yo_gurt::log: reading status line failed: Protocol(InvalidStatusLine)",
+        ] {
+            assert!(output.contains(expected), "{expected:?} not in\n{output}");
+        }
+        assert!(!output.contains("t0ken") && !output.contains("s3cret"));
+
+        assert_eq!(redacted_headers(), DEFAULT_REDACTED_HEADERS);
+        set_redacted_headers(["X-Api-Key"]);
+        exchange();
+        let output = logs.take();
+        assert!(output.contains("x-api-key: <redacted>"), "{output}");
+        assert!(output.contains("authorization: Bearer t0ken"), "{output}");
+        assert_eq!(redacted_headers(), ["x-api-key"]);
+        set_redacted_headers(DEFAULT_REDACTED_HEADERS);
+    });
+}